@@ -0,0 +1,17 @@
+fn main() {
+    let mut a = 1;
+    let b = 2;
+    let d = {
+        let add = |c: i32| a + b + c;
+        add(3)
+    };
+    {
+        let mut inc = || {
+            a = a + 1;
+        };
+        inc();
+    };
+    let e = move |c: i32| b + c;
+    let f = e(d) + a;
+    f;
+}
@@ -0,0 +1,11 @@
+fn main() {
+    fn add_one(a: i32) -> i32 {
+        a + 1
+    };
+    fn apply(f: fn(i32) -> i32, x: i32) -> i32 {
+        f(x)
+    };
+    let f: fn(i32) -> i32 = add_one;
+    let a = apply(f, 1) + apply(|x: i32| x + 2, 1);
+    a;
+}
@@ -0,0 +1,16 @@
+mod math;
+
+mod shapes {
+    use super::math::square;
+
+    pub fn area(side: i32) -> i32 {
+        square(side)
+    }
+}
+
+use shapes::area;
+
+fn main() {
+    let a = area(3) + math::square(2);
+    a;
+}
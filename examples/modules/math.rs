@@ -0,0 +1,3 @@
+pub fn square(a: i32) -> i32 {
+    a * a
+}
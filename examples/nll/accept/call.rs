@@ -0,0 +1,11 @@
+// The returned reference only borrows the arguments that share its lifetime
+fn first<'a>(x: &'a i32, _y: &i32) -> &'a i32 {
+    x
+}
+fn main() {
+    let a = 1;
+    let mut b = 2;
+    let r = first(&a, &b);
+    b = 3;
+    *r + b;
+}
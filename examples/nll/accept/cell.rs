@@ -0,0 +1,11 @@
+// The value in a cell is changed through shared references while they are live
+fn main() {
+    let c = Cell::new(1);
+    let r = &c;
+    c.set(2);
+    r.set(r.get() + 1);
+    let s = RefCell::new(1);
+    let t = &s;
+    *s.borrow_mut() = 2;
+    *t.borrow();
+}
@@ -0,0 +1,10 @@
+// The closure borrows `a` until its last call
+fn main() {
+    let mut a = 1;
+    let mut f = || {
+        a = 2;
+    };
+    f();
+    let b = &a;
+    *b;
+}
@@ -0,0 +1,11 @@
+// A mutable reference is coerced to a shared reference at a call site
+fn read(x: &i32) -> i32 {
+    *x + 1
+}
+
+fn main() {
+    let mut a = 0;
+    let r = &mut a;
+    let b = read(r);
+    *r = b;
+}
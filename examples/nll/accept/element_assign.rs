@@ -0,0 +1,9 @@
+// An element is assigned a value computed from other elements
+fn main() {
+    let mut a = [1, 2, 3];
+    let mut v = vec![1, 2];
+    let i = 0;
+    let j = 1;
+    a[i] = a[j] + 1;
+    v[i] += v[j];
+}
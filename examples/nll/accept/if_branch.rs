@@ -0,0 +1,12 @@
+// `r` is only used in one branch, `a` is not borrowed in the other one
+fn main() {
+    let mut a = 0;
+    let c = a == 0;
+    let r = &mut a;
+    if c {
+        *r = 1;
+    } else {
+        a = 2;
+    };
+    a;
+}
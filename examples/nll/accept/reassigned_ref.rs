@@ -0,0 +1,11 @@
+// Assigning to `r` ends the borrow of `a`
+fn main() {
+    let mut a = 0;
+    let mut b = 0;
+    let mut r = &mut a;
+    *r = 1;
+    r = &mut b;
+    a = 2;
+    *r = 3;
+    a + b;
+}
@@ -0,0 +1,10 @@
+// The reborrow `s` is not used after `*s = 1` so `r` can be used again
+fn main() {
+    let mut a = 0;
+    let r = &mut a;
+    let s = &mut *r;
+    *s = 1;
+    *r = 2;
+    let t = &*r;
+    *t + 1;
+}
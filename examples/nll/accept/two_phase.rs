@@ -0,0 +1,7 @@
+// The mutable borrow of `v` by `push` is only activated once the argument has been evaluated
+fn main() {
+    let mut v = vec![1, 2];
+    v.push(v[0]);
+    let r = &v[1];
+    v.push(*r);
+}
@@ -0,0 +1,8 @@
+// `b` is not used after `*b = 4` so `a` can be borrowed again
+fn main() {
+    let mut a = 0;
+    let b = &mut a;
+    *b = 4;
+    let c = &a;
+    *c;
+}
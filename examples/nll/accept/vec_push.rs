@@ -0,0 +1,7 @@
+// `r` is not used after `r.len()` so `v` can be borrowed mutably by `push`
+fn main() {
+    let mut v = vec![1];
+    let r = &v;
+    r.len();
+    v.push(2);
+}
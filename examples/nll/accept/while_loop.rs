@@ -0,0 +1,12 @@
+// Each iteration borrows `a` anew, the borrow ends within the iteration
+fn main() {
+    let mut a = 0;
+    let mut i = 0;
+    while i < 3 {
+        let r = &mut a;
+        *r = *r + 1;
+        i = i + 1;
+    };
+    let c = &a;
+    *c;
+}
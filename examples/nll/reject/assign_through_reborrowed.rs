@@ -0,0 +1,8 @@
+// E0506, `*r` is assigned while the shared reborrow `s` is still used
+fn main() {
+    let mut a = 0;
+    let r = &mut a;
+    let s = &*r;
+    *r = 1;
+    *s + 1;
+}
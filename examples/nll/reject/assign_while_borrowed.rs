@@ -0,0 +1,7 @@
+// E0506, `r` is used after `a` is assigned
+fn main() {
+    let mut a = 0;
+    let r = &a;
+    a = 2;
+    *r;
+}
@@ -0,0 +1,10 @@
+// E0502, the closure holds a mutable borrow of `a` until it is called
+fn main() {
+    let mut a = 2;
+    let mut f = || {
+        a = 3;
+    };
+    let b = &a;
+    f();
+    *b;
+}
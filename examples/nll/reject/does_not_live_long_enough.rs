@@ -0,0 +1,9 @@
+// E0597, `x` is dropped while `r` is used later
+fn main() {
+    let mut r = &0;
+    {
+        let x = 1;
+        r = &x;
+    };
+    *r;
+}
@@ -0,0 +1,8 @@
+// E0503, `a[i]` may be any element so `a[1]` can not be used while it is borrowed
+fn main() {
+    let mut a = [1, 2, 3];
+    let i = 0;
+    let x = &mut a[i];
+    let b = a[1];
+    *x = b;
+}
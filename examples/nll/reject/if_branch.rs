@@ -0,0 +1,10 @@
+// E0506, `r` is used after the branch that assigns to `a`
+fn main() {
+    let mut a = 0;
+    let c = a == 0;
+    let r = &a;
+    if c {
+        a = 1;
+    };
+    *r;
+}
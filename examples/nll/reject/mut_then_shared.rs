@@ -0,0 +1,8 @@
+// E0502, `b` is used after `a` is borrowed again
+fn main() {
+    let mut a = 0;
+    let b = &mut a;
+    let c = &a;
+    *b = 4;
+    *c;
+}
@@ -0,0 +1,9 @@
+// E0499, `a` is borrowed while the reborrow `s` of `r` still holds the borrow of it
+fn main() {
+    let mut a = 0;
+    let r = &mut a;
+    let s = &*r;
+    let b = &mut a;
+    *b = 1;
+    *s + 1;
+}
@@ -0,0 +1,8 @@
+// E0506, `*r` is assigned while the reborrow `s` is still used
+fn main() {
+    let mut a = 0;
+    let r = &mut a;
+    let s = &mut *r;
+    *r = 1;
+    *s = 2;
+}
@@ -0,0 +1,7 @@
+// The guard borrows the cell so it cannot be assigned while the guard is live
+fn main() {
+    let mut c = RefCell::new(1);
+    let g = c.borrow();
+    c = RefCell::new(2);
+    *g;
+}
@@ -0,0 +1,8 @@
+// E0499, both mutable borrows are used
+fn main() {
+    let mut a = 0;
+    let r1 = &mut a;
+    let r2 = &mut a;
+    *r1 = 1;
+    *r2 = 2;
+}
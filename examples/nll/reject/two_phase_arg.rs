@@ -0,0 +1,9 @@
+// E0502, the shared borrow of `v` is used by the call that activates the mutable borrow
+fn add(v: &mut Vec<i32>, x: &i32) {
+    v.push(*x);
+}
+
+fn main() {
+    let mut v = vec![1, 2];
+    add(&mut v, &v[0]);
+}
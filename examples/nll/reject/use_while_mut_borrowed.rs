@@ -0,0 +1,7 @@
+// E0503, `a` is read while `r` is still used
+fn main() {
+    let mut a = 0;
+    let r = &mut a;
+    let b = a + 1;
+    *r = b;
+}
@@ -0,0 +1,7 @@
+// E0502, `r` is used after `push` borrows `v` mutably
+fn main() {
+    let mut v = vec![1];
+    let r = &v;
+    v.push(2);
+    r.len();
+}
@@ -0,0 +1,11 @@
+// E0506, the borrow from the previous iteration is used after `a` is assigned
+fn main() {
+    let mut a = 0;
+    let mut i = 0;
+    let mut r = &1;
+    while i < 2 {
+        a = i;
+        i = i + *r;
+        r = &a;
+    };
+}
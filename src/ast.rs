@@ -1,20 +1,24 @@
 pub mod block;
+pub mod closure;
 pub mod expr;
 pub mod format;
 pub mod func;
 pub mod globals;
 pub mod literal;
+pub mod module;
 pub mod op;
 pub mod program;
 pub mod statement;
 pub mod types;
 
 pub use block::*;
+pub use closure::*;
 pub use expr::*;
 pub use format::*;
 pub use func::*;
 pub use globals::*;
 pub use literal::*;
+pub use module::*;
 pub use op::*;
 pub use program::*;
 pub use statement::*;
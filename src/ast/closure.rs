@@ -0,0 +1,167 @@
+//! Defines closures, anonymous functions that can capture their environment.
+
+use crate::AstNode;
+
+use super::{Block, Expr, Statement, Type, UnaryOp};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosureArg {
+    pub id: Expr,
+    /// Closure arguments may omit their type, in that case it is inferred from usage.
+    pub ty: Option<Type>,
+}
+
+/// An anonymous function
+///
+/// ```rust
+/// let a = 2;
+/// let f = |b: i32| a + b;
+/// let g = move |b| a + b;
+/// g(1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Closure {
+    pub args: Vec<ClosureArg>,
+    /// Optional return type, requires the body to be a block.
+    pub ty: Option<Type>,
+    pub body: Box<Expr>,
+    /// Wether or not the closure was declared as `move`
+    pub move_semantics: bool,
+}
+
+/// Describes how a variable is captured by a [`Closure`].
+///
+/// The variants are ordered so that a stronger capture always compares greater.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capture {
+    /// The variable is only read, a `&` borrow is enough.
+    Ref,
+    /// The variable is assigned to or borrowed mutably, a `&mut` borrow is needed.
+    MutRef,
+    /// The closure is `move`, the value is copied in to the closure.
+    Value,
+}
+
+impl Closure {
+    /// Returns every identifier that the closure uses but does not declare, along with how it
+    /// needs to be captured.
+    ///
+    /// This is purely syntactical, it is up to the caller to filter out identifiers that do
+    /// not refer to variables, such as function names.
+    pub fn captures(&self) -> Vec<(String, Capture)> {
+        let mut bound: Vec<String> = self
+            .args
+            .iter()
+            .filter_map(|arg| match &arg.id {
+                Expr::Ident(i) => Some(i.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut captures = Vec::new();
+        visit_expr(&self.body, &mut bound, &mut captures, Capture::Ref);
+        if self.move_semantics {
+            for (_id, capture) in captures.iter_mut() {
+                *capture = Capture::Value;
+            }
+        }
+        captures
+    }
+}
+
+fn capture(id: &str, bound: &[String], captures: &mut Vec<(String, Capture)>, how: Capture) {
+    if bound.iter().any(|el| el == id) {
+        return;
+    }
+    match captures.iter_mut().find(|(captured, _)| *captured == id) {
+        Some((_, previous)) => *previous = (*previous).max(how),
+        None => captures.push((id.to_owned(), how)),
+    }
+}
+
+fn visit_expr(
+    expr: &Expr,
+    bound: &mut Vec<String>,
+    captures: &mut Vec<(String, Capture)>,
+    how: Capture,
+) {
+    match expr {
+        Expr::Ident(i) => capture(i, bound, captures, how),
+        Expr::Lit(_) => {}
+        Expr::BinOp(_, lhs, rhs) => {
+            visit_expr(lhs, bound, captures, Capture::Ref);
+            visit_expr(rhs, bound, captures, Capture::Ref);
+        }
+        Expr::UnOp(UnaryOp::BorrowMut, e) => visit_expr(e, bound, captures, Capture::MutRef),
+        Expr::UnOp(_, e) => visit_expr(e, bound, captures, Capture::Ref),
+        Expr::Par(e) | Expr::Try(e) | Expr::Cast(e, _) => visit_expr(e, bound, captures, how),
+        Expr::IfThenElse(cond, then_block, else_block) => {
+            visit_expr(cond, bound, captures, Capture::Ref);
+            visit_block(then_block, bound, captures);
+            if let Some(else_block) = else_block {
+                visit_block(else_block, bound, captures);
+            }
+        }
+        Expr::Array(elements) => {
+            for el in elements {
+                visit_expr(el, bound, captures, Capture::Ref);
+            }
+        }
+        Expr::Index(id, idx) => {
+            visit_expr(id, bound, captures, how);
+            visit_expr(idx, bound, captures, Capture::Ref);
+        }
+        Expr::IndexMut(id, idx) => {
+            visit_expr(id, bound, captures, Capture::MutRef);
+            visit_expr(idx, bound, captures, Capture::Ref);
+        }
+        Expr::FuncCall(call) => {
+            // The callee might be a closure stored in a variable
+            visit_expr(&call.id, bound, captures, Capture::Ref);
+            for arg in call.args.iter() {
+                visit_expr(arg, bound, captures, Capture::Ref);
+            }
+        }
+        Expr::Block(b) | Expr::Unsafe(b) => visit_block(b, bound, captures),
+        Expr::Closure(closure) => {
+            for (id, how) in closure.captures() {
+                capture(&id, bound, captures, how);
+            }
+        }
+    }
+}
+
+fn visit_block(block: &Block, bound: &mut Vec<String>, captures: &mut Vec<(String, Capture)>) {
+    // Anything declared in the block goes out of scope at the end of it
+    let len = bound.len();
+    for statement in &block.statements {
+        match statement {
+            Statement::Let(id, _, _, rhs) => {
+                if let Some(rhs) = rhs {
+                    visit_expr(rhs, bound, captures, Capture::Ref);
+                }
+                if let Expr::Ident(i) = id {
+                    bound.push(i.clone());
+                }
+            }
+            Statement::Assign(lhs, rhs) => {
+                visit_expr(rhs, bound, captures, Capture::Ref);
+                match lhs {
+                    // Writing through a reference only requires the reference to be readable
+                    Expr::UnOp(UnaryOp::Dereff, e) => visit_expr(e, bound, captures, Capture::Ref),
+                    lhs => visit_expr(lhs, bound, captures, Capture::MutRef),
+                }
+            }
+            Statement::While(cond, block) => {
+                visit_expr(cond, bound, captures, Capture::Ref);
+                visit_block(block, bound, captures);
+            }
+            Statement::Expr(e) => visit_expr(e, bound, captures, Capture::Ref),
+            Statement::Block(b) => visit_block(b, bound, captures),
+            // Nested functions cannot capture anything
+            Statement::FnDecleration(_) => {}
+        }
+    }
+    bound.truncate(len);
+}
+
+impl AstNode for Closure {}
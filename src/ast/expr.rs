@@ -1,6 +1,6 @@
 use crate::AstNode;
 
-use super::{BinaryOp, Block, FuncCall, Literal, UnaryOp};
+use super::{BinaryOp, Block, Closure, FuncCall, Literal, Type, UnaryOp};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expr {
@@ -83,6 +83,37 @@ pub enum Expr {
     /// Runs the given function with the given arguments
     FuncCall(FuncCall),
     Block(Block),
+    /// An anonymous function that captures the variables it uses
+    ///
+    /// ```rust
+    /// let a = 2;
+    /// let f = |b: i32| a + b;
+    /// ```
+    Closure(Closure),
+    /// Unwraps an `Option` or a `Result`, returning the `None` or the `Err` from the enclosing
+    /// function
+    ///
+    /// ```rust
+    /// fn add_one(a: Option<i32>) -> Option<i32> {
+    ///     Some(a? + 1)
+    /// }
+    /// ```
+    Try(Box<Expr>),
+    /// A block in which raw pointers may be dereferenced, mutable statics accessed and unsafe
+    /// functions called
+    ///
+    /// ```rust
+    /// static mut COUNT: i32 = 0;
+    /// unsafe { COUNT = COUNT + 1 };
+    /// ```
+    Unsafe(Block),
+    /// Converts a value to another type, a reference to a raw pointer or between integer types
+    ///
+    /// ```rust
+    /// let mut a = 1;
+    /// let p = &mut a as *mut i32;
+    /// ```
+    Cast(Box<Expr>, Type),
 }
 
 impl Expr {
@@ -102,6 +133,10 @@ impl Expr {
             Expr::IndexMut(_, _) => false,
             Expr::FuncCall(_) => false,
             Expr::Block(_) => false,
+            Expr::Closure(_) => false,
+            Expr::Try(_) => false,
+            Expr::Unsafe(_) => false,
+            Expr::Cast(_, _) => true,
         }
     }
 }
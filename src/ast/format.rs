@@ -1,4 +1,4 @@
-use super::{Arg, Func, FuncCall, Prog, Static};
+use super::{Arg, Closure, Func, FuncCall, Module, Prog, Static, Use};
 use crate::ast::{BinaryOp, Block, Expr, Literal, Statement, Type, UnaryOp};
 use std::fmt::{self};
 
@@ -7,6 +7,13 @@ enum KeyWords {
     Fn,
     While,
     Static,
+    Const,
+    Move,
+    Pub,
+    Mod,
+    Use,
+    Unsafe,
+    As,
 }
 
 #[cfg(test)]
@@ -19,6 +26,13 @@ pub mod color_test {
                 super::KeyWords::Fn => "fn",
                 super::KeyWords::While => "while",
                 super::KeyWords::Static => "static",
+                super::KeyWords::Const => "const",
+                super::KeyWords::Move => "move",
+                super::KeyWords::Pub => "pub",
+                super::KeyWords::Mod => "mod",
+                super::KeyWords::Use => "use",
+                super::KeyWords::Unsafe => "unsafe",
+                super::KeyWords::As => "as",
             }
             .to_string();
             write!(f, "{}", s)
@@ -56,6 +70,13 @@ pub mod color_normal {
                 super::KeyWords::Fn => Purple.paint("fn"),
                 super::KeyWords::While => Purple.paint("while"),
                 super::KeyWords::Static => Purple.paint("static"),
+                super::KeyWords::Const => Purple.paint("const"),
+                super::KeyWords::Move => Purple.paint("move"),
+                super::KeyWords::Pub => Purple.paint("pub"),
+                super::KeyWords::Mod => Purple.paint("mod"),
+                super::KeyWords::Use => Purple.paint("use"),
+                super::KeyWords::Unsafe => Purple.paint("unsafe"),
+                super::KeyWords::As => Purple.paint("as"),
             }
             .to_string();
             write!(f, "{}", s)
@@ -137,12 +158,67 @@ impl InteralFormat for Prog {
     }
 }
 
+fn visibility(public: bool) -> String {
+    match public {
+        true => format!("{} ", KeyWords::Pub),
+        false => "".to_owned(),
+    }
+}
+
+impl InteralFormat for Module {
+    fn fmt_internal(&self, indent: usize) -> String {
+        let items = self
+            .items
+            .iter()
+            .flat_map(|el| {
+                el.to_string()
+                    .lines()
+                    .map(|line| format!("{}{line}", " ".repeat(indent + 4)))
+                    .collect::<Vec<String>>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "{}{}{} {} {{\n{items}\n{}}}",
+            " ".repeat(indent),
+            visibility(self.public),
+            KeyWords::Mod,
+            identifier(&self.id),
+            " ".repeat(indent)
+        )
+    }
+}
+
+impl InteralFormat for Use {
+    fn fmt_internal(&self, indent: usize) -> String {
+        format!(
+            "{}{}{} {}{}{};",
+            " ".repeat(indent),
+            visibility(self.public),
+            KeyWords::Use,
+            self.path.join("::"),
+            match self.glob {
+                true => "::*",
+                false => "",
+            },
+            match &self.alias {
+                Some(alias) => format!(" as {alias}"),
+                None => "".to_owned(),
+            }
+        )
+    }
+}
+
 impl InteralFormat for Static {
     fn fmt_internal(&self, indent: usize) -> String {
         format!(
-            "{}{} {} {}:{}={};",
+            "{}{}{} {} {}:{}={};",
             " ".repeat(indent),
-            KeyWords::Static,
+            visibility(self.public),
+            match self.constant {
+                true => KeyWords::Const,
+                false => KeyWords::Static,
+            },
             match self.mutable {
                 true => ty("mut".to_string()).to_string(),
                 _ => "".to_string(),
@@ -204,8 +280,17 @@ impl InteralFormat for Func {
             Expr::Ident(i) => i,
             _ => unreachable!(),
         };
+        let lifetimes = match self.lifetimes.is_empty() {
+            true => String::new(),
+            false => format!("<{}>", self.lifetimes.join(",")),
+        };
+        let unsafety = match self.unsafety {
+            true => format!("{} ", KeyWords::Unsafe),
+            false => String::new(),
+        };
         format!(
-            "{} {}({}) -> {} {}",
+            "{}{unsafety}{} {}{lifetimes}({}) -> {} {}",
+            visibility(self.public),
             KeyWords::Fn,
             fn_identifier(id.as_str()),
             self.args
@@ -270,9 +355,37 @@ impl InteralFormat for Expr {
             Expr::IndexMut(id, idx) => format!("{id}[{idx}]"),
             Expr::FuncCall(func) => format!("{func}"),
             Expr::Block(block) => block.fmt_internal(indent),
+            Expr::Closure(closure) => closure.fmt_internal(indent),
+            Expr::Try(e) => format!("{}?", e.fmt_internal(indent)),
+            Expr::Unsafe(block) => format!("{} {}", KeyWords::Unsafe, block.fmt_internal(indent)),
+            Expr::Cast(e, typ) => format!("{} {} {typ}", e.fmt_internal(indent), KeyWords::As),
         }
     }
 }
+impl InteralFormat for Closure {
+    fn fmt_internal(&self, indent: usize) -> String {
+        format!(
+            "{}|{}|{} {}",
+            match self.move_semantics {
+                true => format!("{} ", KeyWords::Move),
+                false => "".to_owned(),
+            },
+            self.args
+                .iter()
+                .map(|arg| match &arg.ty {
+                    Some(ty) => format!("{}:{ty}", arg.id),
+                    None => format!("{}", arg.id),
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+            match &self.ty {
+                Some(ty) => format!(" -> {ty}"),
+                None => "".to_owned(),
+            },
+            self.body.fmt_internal(indent)
+        )
+    }
+}
 impl fmt::Display for BinaryOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
@@ -289,18 +402,22 @@ impl fmt::Display for BinaryOp {
         write!(f, "{}", s)
     }
 }
-fmt!(Prog, Block, Func, Expr, Statement, Static,);
+fmt!(Prog, Block, Func, Expr, Statement, Static, Closure, Module, Use,);
 
 impl fmt::Display for FuncCall {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let id = match *self.id.clone() {
-            Expr::Ident(i) => i,
-            _ => unreachable!(),
+            // `None` is parsed as a call without arguments
+            Expr::Ident(i) if i == "None" && self.args.is_empty() => {
+                return write!(f, "{}", fn_identifier("None"))
+            }
+            Expr::Ident(i) => fn_identifier(i.as_str()),
+            e => e.to_string(),
         };
         write!(
             f,
             "{}({})",
-            fn_identifier(id.as_str()),
+            id,
             self.args
                 .iter()
                 .map(|v| format!("{}", v))
@@ -364,9 +481,41 @@ impl fmt::Display for Type {
             Type::Unit => ty("()".to_owned()),
             Type::Usize => ty("usize".to_owned()),
             Type::Array(typ, size) => format!("[{};{size}]", ty(typ.to_string())),
-            Type::Ref(crate::ast::types::Ref(ty, _, _)) => format!("& {ty}"),
+            Type::ArrayConst(typ, size) => format!("[{};{size}]", ty(typ.to_string())),
+            Type::Ref(crate::ast::types::Ref(ty, None)) => format!("& {ty}"),
+            Type::Ref(crate::ast::types::Ref(ty, Some(lifetime))) => format!("&{lifetime} {ty}"),
             Type::String => ty("String".to_string()),
-            Type::MutRef(crate::ast::types::Ref(ty, _, _)) => format!("&mut {ty}"),
+            Type::MutRef(crate::ast::types::Ref(ty, None)) => format!("&mut {ty}"),
+            Type::MutRef(crate::ast::types::Ref(ty, Some(lifetime))) => {
+                format!("&{lifetime} mut {ty}")
+            }
+            Type::Closure(args, ret, _) => format!(
+                "|{}| -> {ret}",
+                args.iter()
+                    .map(|el| el.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            Type::FnPtr(args, ret) => format!(
+                "{}({}) -> {ret}",
+                ty("fn".to_owned()),
+                args.iter()
+                    .map(|el| el.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            Type::Box(inner) => format!("{}<{inner}>", ty("Box".to_owned())),
+            Type::Rc(inner) => format!("{}<{inner}>", ty("Rc".to_owned())),
+            Type::Vec(inner) => format!("{}<{inner}>", ty("Vec".to_owned())),
+            Type::Cell(inner) => format!("{}<{inner}>", ty("Cell".to_owned())),
+            Type::RefCell(inner) => format!("{}<{inner}>", ty("RefCell".to_owned())),
+            Type::CellRef(inner) => format!("{}<{inner}>", ty("Ref".to_owned())),
+            Type::CellRefMut(inner) => format!("{}<{inner}>", ty("RefMut".to_owned())),
+            Type::Option(inner) => format!("{}<{inner}>", ty("Option".to_owned())),
+            Type::Result(inner, err) => format!("{}<{inner}, {err}>", ty("Result".to_owned())),
+            Type::Never => ty("!".to_owned()),
+            Type::ConstPtr(inner) => format!("*{} {inner}", ty("const".to_owned())),
+            Type::MutPtr(inner) => format!("*{} {inner}", ty("mut".to_owned())),
         };
         write!(f, "{}", s)
     }
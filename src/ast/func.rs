@@ -10,9 +10,15 @@ pub struct Arg {
 pub struct Func {
     pub id: super::Expr,
     pub ty: super::Type,
+    /// The lifetime parameters, `'a` in `fn f<'a>(x: &'a i32) -> &'a i32`
+    pub lifetimes: Vec<String>,
     /// A vector of argument identifiers
     pub args: Vec<Arg>,
     pub body: super::Block,
+    /// Wether or not the function is visible outside of its module
+    pub public: bool,
+    /// Wether or not the function is an `unsafe fn`, it may only be called in an unsafe context
+    pub unsafety: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
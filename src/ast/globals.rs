@@ -2,12 +2,18 @@ use crate::AstNode;
 
 use super::{Expr, Prio, TopLevel, Type};
 
+/// A global, either a `static` or a `const` item.
+///
+/// Both are evaluated at compile time so they share a representation, a `const` is simply an
+/// immutable global that may not refer to statics.
 #[derive(Debug)]
 pub struct Static {
     pub(crate) ty: Type,
     pub(crate) mutable: bool,
     pub(crate) value: Expr,
     pub(crate) id: String,
+    pub(crate) public: bool,
+    pub(crate) constant: bool,
 }
 impl Prio for Static {
     fn prio(&self) -> usize {
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use crate::AstNode;
+
+use super::{Prio, TopLevel};
+
+/// A module, either declared inline, `mod a { .. }`, or loaded from a file, `mod a;`.
+#[derive(Debug)]
+pub struct Module {
+    pub(crate) id: String,
+    pub(crate) public: bool,
+    pub(crate) items: Vec<Box<dyn TopLevel>>,
+    /// True if the items were declared inline rather than in `a.rs` or `a/mod.rs`.
+    pub(crate) inline: bool,
+    /// The file the items were read from, if known.
+    pub(crate) file: Option<PathBuf>,
+}
+
+/// A single import, `use a::b;`, `use a::b as c;` or `use a::*;`.
+///
+/// Nested imports like `use a::{b, c};` are split in to one [`Use`] per leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Use {
+    pub(crate) public: bool,
+    pub(crate) path: Vec<String>,
+    pub(crate) alias: Option<String>,
+    pub(crate) glob: bool,
+}
+
+impl Module {
+    /// Adds the module, and the file it was read from, to an error message.
+    pub(crate) fn locate(&self, err: String) -> String {
+        match &self.file {
+            Some(file) => format!("{err}\n    in module `{}` ({})", self.id, file.display()),
+            None => format!("{err}\n    in module `{}`", self.id),
+        }
+    }
+}
+
+impl Prio for Module {
+    fn prio(&self) -> usize {
+        2
+    }
+}
+impl TopLevel for Module {
+    fn is_main(&self) -> bool {
+        false
+    }
+}
+
+impl Prio for Use {}
+impl TopLevel for Use {
+    fn is_main(&self) -> bool {
+        false
+    }
+}
+
+impl AstNode for Module {}
+impl AstNode for Use {}
@@ -13,6 +13,8 @@ pub trait TopLevel:
     + crate::type_check::TypeCheck
     + crate::borrow_checker::Linearize
     + crate::borrow_checker::PreDeclareTop
+    + crate::resolve::Resolve
+    + crate::const_eval::ConstEval
     + Display
     + Prio
     + CodeGen
@@ -71,4 +73,9 @@ impl From<Vec<Box<dyn TopLevel>>> for Prog {
         Self { statements: value }
     }
 }
-impl AstNode for Prog {}
+impl AstNode for Prog {
+    fn resolve_modules(&mut self, source: Option<&std::path::Path>) -> Result<(), String> {
+        crate::resolve::resolve(self, source)?;
+        crate::const_eval::evaluate(self)
+    }
+}
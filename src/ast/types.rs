@@ -7,16 +7,143 @@ pub enum Type {
     Unit,
     Usize,
     Array(Box<Type>, usize),
+    /// An array whose length is a constant expression, e.g. `[i32; N]`, this is replaced by a
+    /// [`Type::Array`] once the constant has been evaluated
+    ArrayConst(Box<Type>, Box<super::Expr>),
     Ref(Ref),
     MutRef(Ref),
+    /// A raw pointer, `*const T`
+    ConstPtr(Box<Type>),
+    /// A raw pointer through which the value may be written, `*mut T`
+    MutPtr(Box<Type>),
     String,
+    /// The type of a closure, argument types followed by the return type and whether it mutates
+    /// a variable it captures, in which case it has to be called through a mutable binding
+    Closure(Vec<Type>, Box<Type>, bool),
+    /// A function pointer, `fn(A) -> B`, argument types followed by the return type
+    FnPtr(Vec<Type>, Box<Type>),
+    /// An owned heap allocation, `Box<T>`
+    Box(Box<Type>),
+    /// A reference counted heap allocation, `Rc<T>`
+    Rc(Box<Type>),
+    /// A growable array, `Vec<T>`
+    Vec(Box<Type>),
+    /// A value that may be replaced through a shared reference, `Cell<T>`
+    Cell(Box<Type>),
+    /// A value with borrows that are checked at runtime, `RefCell<T>`
+    RefCell(Box<Type>),
+    /// A shared borrow of the value in a `RefCell<T>`, `Ref<T>`
+    CellRef(Box<Type>),
+    /// A mutable borrow of the value in a `RefCell<T>`, `RefMut<T>`
+    CellRefMut(Box<Type>),
+    /// An optional value, `Option<T>`
+    Option(Box<Type>),
+    /// A value or an error, `Result<T, E>`
+    Result(Box<Type>, Box<Type>),
+    /// The type of expressions that never produce a value, e.g. `panic!()`
+    Never,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Ref(pub Box<Type>, pub usize, pub usize);
+impl Type {
+    /// Returns true if values of this type own a heap allocation.
+    pub fn is_heap(&self) -> bool {
+        matches!(self, Type::Box(_) | Type::Rc(_))
+    }
+
+    /// Returns true if values of this type are copied when they are used, values of other types
+    /// are moved out of the variable that holds them.
+    ///
+    /// Closures are treated as `Copy` as their type does not tell how they capture.
+    pub fn is_copy(&self) -> bool {
+        match self {
+            Type::I32 | Type::Bool | Type::Unit | Type::Usize | Type::Never => true,
+            Type::Ref(_) | Type::FnPtr(..) | Type::Closure(..) => true,
+            Type::ConstPtr(_) | Type::MutPtr(_) => true,
+            Type::Array(ty, _) | Type::ArrayConst(ty, _) | Type::Option(ty) => ty.is_copy(),
+            Type::Result(ty, err) => ty.is_copy() && err.is_copy(),
+            Type::MutRef(_) | Type::String | Type::Box(_) | Type::Rc(_) | Type::Vec(_) => false,
+            Type::Cell(_) | Type::RefCell(_) | Type::CellRef(_) | Type::CellRefMut(_) => false,
+        }
+    }
+
+    /// Returns true if a value of type `other` can be used where this type is expected.
+    ///
+    /// The element type of an empty `Vec` is not known, it is `()` until something is pushed to
+    /// it, so it is accepted as any `Vec<T>`. The same goes for the types that `None`, `Ok(v)`
+    /// and `Err(e)` do not mention. An expression that never returns is accepted as any type.
+    pub fn accepts(&self, other: &Type) -> bool {
+        let unknown = |ty: &Type| *ty == Type::Unit;
+        match (self, other) {
+            (_, Type::Never) => true,
+            (Type::Vec(_), Type::Vec(other)) if unknown(other) => true,
+            (Type::Option(expected), Type::Option(other)) => {
+                unknown(other) || expected.accepts(other)
+            }
+            (Type::Result(ty, err), Type::Result(other_ty, other_err)) => {
+                (unknown(other_ty) || ty.accepts(other_ty))
+                    && (unknown(other_err) || err.accepts(other_err))
+            }
+            (expected, other) => expected == other,
+        }
+    }
+
+    /// Returns true if a value of type `other` can be passed where this type is expected, like
+    /// [`accepts`](Self::accepts) but a `&mut T` is coerced to a `&T`.
+    pub fn coerces(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Ref(Ref(expected, _)), Type::MutRef(Ref(other, _))) => expected.accepts(other),
+            (expected, other) => expected.accepts(other),
+        }
+    }
+
+    /// Returns true if the type is a raw pointer, `*const T` or `*mut T`.
+    pub fn is_raw(&self) -> bool {
+        matches!(self, Type::ConstPtr(_) | Type::MutPtr(_))
+    }
+
+    /// Returns the type behind any number of references, e.g. `i32` for `&&mut i32`.
+    pub fn peel_refs(&self) -> &Type {
+        match self {
+            Type::Ref(Ref(ty, _)) | Type::MutRef(Ref(ty, _)) => ty.peel_refs(),
+            ty => ty,
+        }
+    }
+
+    /// Returns the most specific type that accepts both types, e.g. the type of an `if` where
+    /// one branch is `Ok(1)` and the other is `Err(false)`.
+    pub fn unify(&self, other: &Type) -> Option<Type> {
+        let inner = |ty: &Type, other: &Type| match (ty, other) {
+            (Type::Unit, other) | (other, Type::Unit) => Some(Box::new(other.clone())),
+            (ty, other) => ty.unify(other).map(Box::new),
+        };
+        match (self, other) {
+            (Type::Never, other) | (other, Type::Never) => Some(other.clone()),
+            (Type::Vec(ty), Type::Vec(other)) => Some(Type::Vec(inner(ty, other)?)),
+            (Type::Option(ty), Type::Option(other)) => Some(Type::Option(inner(ty, other)?)),
+            (Type::Result(ty, err), Type::Result(other_ty, other_err)) => {
+                Some(Type::Result(inner(ty, other_ty)?, inner(err, other_err)?))
+            }
+            (ty, other) if ty == other => Some(ty.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The referenced type of a reference along with its lifetime, `'a` in `&'a i32`.
+///
+/// The lifetime is `None` if it is elided. Lifetimes are checked by the
+/// [region checker](crate::borrow_checker::regions) so they are ignored when types are compared.
+#[derive(Debug, Clone, Eq)]
+pub struct Ref(pub Box<Type>, pub Option<String>);
+
+impl PartialEq for Ref {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
 
 impl From<Type> for Ref {
     fn from(value: Type) -> Self {
-        Ref(Box::new(value), 0, 0)
+        Ref(Box::new(value), None)
     }
 }
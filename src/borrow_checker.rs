@@ -1,13 +1,20 @@
 //! This modlue is responsible for linearization of programs, this requires that each node can be
 //! found recursivly in an enviornment.
 
+pub mod cfg;
 pub mod env;
+pub mod explain;
 pub mod linearize_and_borrow;
+pub mod nll;
 pub mod pre_decleration;
+pub mod regions;
 
 pub use env::*;
+pub use explain::{Explanation, LoanReport};
 pub use linearize_and_borrow::*;
+pub use nll::BorrowErr;
 pub use pre_decleration::*;
+pub use regions::{Region, RegionErr, Signature};
 use std::collections::HashMap;
 
 use crate::ast::{Expr, Statement};
@@ -17,10 +24,20 @@ pub enum BCError {
     EnvError(EnvErr),
     InvalidIdentifierType(Expr),
     NeverUsed(String),
-    MultipleRefWhileMutRefAlive,
-    TryingToBorrowWhileMutBorrow(String),
-    TryingToBorrowMutWhileImmut(String),
-    DerrefOfOutOfScope,
+    /// A place that is used while it is borrowed, see [`nll`]
+    Borrow(BorrowErr),
+    /// A reference that outlives what it borrows, see [`regions`]
+    Region(RegionErr),
+}
+
+impl std::fmt::Display for BCError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BCError::Borrow(e) => write!(f, "{e}"),
+            BCError::Region(e) => write!(f, "{e}"),
+            e => write!(f, "{e:?}"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -67,6 +84,12 @@ pub trait PreDeclareTop {
 
 pub trait Linearize {
     fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError>;
+
+    /// Declares the signatures of functions and the globals before any item is linearized, a
+    /// function may call functions that are declared after it.
+    fn declare_top<'a>(&self, _env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        Ok(())
+    }
 }
 
 pub trait UnOpPreDeclaration {
@@ -83,17 +106,6 @@ pub struct BCScope<'a> {
     scope: HashMap<String, BCMeta<'a>>,
 }
 
-#[derive(Debug, Clone)]
-struct BorrowMap {
-    map: HashMap<String, (bool, Vec<Box<BorrowValue>>)>,
-}
-
-#[derive(Debug, Clone)]
-pub struct BorrowValue {
-    id: String,
-    mutable: bool,
-}
-
 impl<'a> MetaVariable for BCMeta<'a> {
     fn access(&mut self) {
         self.usage_counter += 1;
@@ -126,9 +138,6 @@ impl<'a> Env<BCScope<'a>> {
         // No need to decrement the counter here, not doing so allows us to linearize the program
         // much faster.
         if let Some(mut env) = self.vars.pop() {
-            for el in env.to_vec() {
-                self.destroy_ref(&el.hash());
-            }
             return match env.validate() {
                 Ok(_) => Ok(()),
                 Err(e) => Err(EnvErr::ScopeError(e)),
@@ -186,7 +195,7 @@ impl<'a> BCMeta<'a> {
 
 #[cfg(test)]
 mod test {
-    use super::Env;
+    use super::{BCError, BorrowErr, Env, LoanReport, Region, RegionErr};
     use crate::{
         borrow_checker::PreDeclareTop, check, eval, parse, prelude::*, vm::Eval, vm::VarEnv, Ast,
     };
@@ -234,4 +243,268 @@ mod test {
         println!("{:?}", check!(prog));
         println!("{:?}", eval!(prog, iter));
     }
+
+    #[test]
+    fn test_closure_holds_borrow() {
+        let prog = "fn main(){
+            let mut a:i32 = 2;
+            let mut f = || { a = 3; };
+            let b = &a;
+            f();
+            *b;
+        }"
+        .to_string();
+        let mut prog: Ast<Prog> = parse!(prog, Prog);
+        prog.pre_declare_top(&mut 0, &mut 0).unwrap();
+        let mut env = Env::new();
+        let l = prog.linearize(&mut env);
+        println!("l : {l:?}");
+        assert!(l.is_err());
+    }
+
+    #[test]
+    fn test_vec_push_while_borrowed() {
+        let prog = "fn main(){
+            let mut v = vec![1];
+            v.push(2);
+            let r = &v;
+            v.push(3);
+            Vec::len(r);
+        }"
+        .to_string();
+        let mut prog: Ast<Prog> = parse!(prog, Prog);
+        prog.pre_declare_top(&mut 0, &mut 0).unwrap();
+        let mut env = Env::new();
+        let l = prog.linearize(&mut env);
+        println!("l : {l:?}");
+        assert!(l.is_err());
+    }
+
+    fn borrow_check(prog: &str) -> Result<(), BCError> {
+        let prog = prog.to_string();
+        let mut prog: Ast<Prog> = parse!(prog, Prog);
+        prog.pre_declare_top(&mut 0, &mut 0).unwrap();
+        let mut env = Env::new();
+        prog.linearize(&mut env)
+    }
+
+    #[test]
+    fn test_lifetimes() {
+        let prog = "
+        static G: i32 = 3;
+        fn longest<'a>(x: &'a i32, y: &'a i32) -> &'a i32 {
+            if *x > *y { x } else { y }
+        }
+        fn id(x: &i32) -> &i32 {
+            x
+        }
+        fn global(_x: &i32) -> &'static i32 {
+            &G
+        }
+        fn main() -> i32 {
+            let a = 1;
+            let b = 2;
+            let l = longest(&a, &b);
+            let i = id(l);
+            let g = global(&a);
+            *i + *g
+        }";
+        assert!(borrow_check(prog).is_ok());
+        let prog = prog.to_string();
+        let mut prog: Ast<Prog> = parse!(prog, Prog);
+        // Lifetimes are not part of the types
+        assert!(check!(prog).is_ok());
+
+        let err = |prog: &str| match borrow_check(prog) {
+            Err(BCError::Region(e)) => e,
+            res => unreachable!("Expected a region error but got {res:?}"),
+        };
+        let e = err("fn f() -> &'static i32 { let x = 1; &x } fn main() {}");
+        assert!(matches!(e, RegionErr::DoesNotLiveLongEnough { .. }), "{e}");
+        assert_eq!(
+            e.to_string(),
+            "`x` does not live long enough, it is dropped at the end of the body of `f` but the \
+             reference to it has to live for `'static`, the lifetime of the return value of `f`"
+        );
+        let e = err("fn f(x: i32) -> &i32 { &x } fn main() {}");
+        assert_eq!(
+            e,
+            RegionErr::MissingLifetime {
+                func: "f".to_owned(),
+                inputs: 0
+            }
+        );
+        let e = err("fn f(x: &i32, y: &i32) -> &i32 { x } fn main() {}");
+        assert!(
+            matches!(e, RegionErr::MissingLifetime { inputs: 2, .. }),
+            "{e}"
+        );
+        let e = err("fn f(x: &'a i32) -> &'a i32 { x } fn main() {}");
+        assert!(matches!(e, RegionErr::Undeclared { .. }), "{e}");
+        let e = err("fn f<'a, 'b>(x: &'a i32, y: &'b i32) -> &'a i32 { y } fn main() {}");
+        assert_eq!(
+            e,
+            RegionErr::DoesNotOutlive {
+                func: "f".to_owned(),
+                region: Region::Param("'b".to_owned()),
+                required: Region::Param("'a".to_owned()),
+            }
+        );
+        let e = err("fn f(x: &i32) -> &i32 { let y = 1; longest(x, &y) }
+            fn longest<'a>(x: &'a i32, y: &'a i32) -> &'a i32 { x }
+            fn main() {}");
+        assert_eq!(
+            e.to_string(),
+            "`y` does not live long enough, it is dropped at the end of the body of `f` but the \
+             reference to it has to live for `'1`, the lifetime of the return value of `f`"
+        );
+        let e = err("fn main() { let r = { let x = 1; &x }; *r; }");
+        assert!(matches!(e, RegionErr::DoesNotLiveLongEnough { .. }), "{e}");
+
+        // The value returned by a call borrows the arguments
+        let l = borrow_check(
+            "fn id<'a>(x: &'a i32) -> &'a i32 { x }
+            fn main() {
+                let r = &0;
+                {
+                    let x = 1;
+                    r = id(&x);
+                };
+                *r;
+            }",
+        );
+        assert!(
+            matches!(
+                l,
+                Err(BCError::Borrow(BorrowErr::DoesNotLiveLongEnough { .. }))
+            ),
+            "{l:?}"
+        );
+    }
+
+    #[test]
+    fn test_element_places() {
+        // Borrows of distinct constant indices are disjoint
+        let ok = borrow_check(
+            "fn main() {
+                let mut a = [1, 2, 3];
+                let x = &mut a[0];
+                let y = &mut a[1];
+                *x = a[2];
+                *y = 1;
+            }",
+        );
+        assert!(ok.is_ok(), "{ok:?}");
+        // A dynamic index may be any element
+        let e = borrow_check(
+            "fn main() {
+                let mut a = [1, 2, 3];
+                let i = 1;
+                let x = &mut a[i];
+                let y = &mut a[1];
+                *x = 1;
+                *y = 1;
+            }",
+        );
+        match e {
+            Err(BCError::Borrow(e)) => assert_eq!(
+                e.to_string(),
+                "cannot borrow `a[1]` as mutable more than once at a time, the first mutable \
+                 borrow is used later by `x`"
+            ),
+            e => unreachable!("Expected a borrow error but got {e:?}"),
+        }
+        // A borrow of the whole array conflicts with its elements
+        let e = borrow_check(
+            "fn main() {
+                let mut a = [1, 2, 3];
+                let x = &a;
+                a[0] = 2;
+                x;
+            }",
+        );
+        assert!(matches!(e, Err(BCError::Borrow(_))), "{e:?}");
+        // Two phase borrows, the mutable borrow of `v` starts after the arguments are evaluated
+        let ok = borrow_check(
+            "fn main() {
+                let mut v = vec![1, 2];
+                v.push(v.len());
+                v[0] += v[1];
+            }",
+        );
+        assert!(ok.is_ok(), "{ok:?}");
+    }
+
+    /// The examples in `examples/nll` are accepted and rejected by rustc in the same way, the
+    /// accepted examples also run without panicking.
+    #[test]
+    fn test_nll() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/nll");
+        for (kind, accepted) in [("accept", true), ("reject", false)] {
+            let mut paths: Vec<_> = std::fs::read_dir(dir.join(kind))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            paths.sort();
+            assert!(!paths.is_empty());
+            for path in paths {
+                let src = std::fs::read_to_string(&path).unwrap();
+                let res = borrow_check(&src);
+                println!("{} : {res:?}", path.display());
+                match accepted {
+                    true => {
+                        assert!(res.is_ok(), "{}: {res:?}", path.display());
+                        let ran = crate::Engine::new(&src).and_then(|e| e.call("main", &[]));
+                        assert!(ran.is_ok(), "{}: {ran:?}", path.display());
+                    }
+                    false => assert!(
+                        matches!(res, Err(BCError::Borrow(_))),
+                        "{}: {res:?}",
+                        path.display()
+                    ),
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_explain_borrows() {
+        let prog = "
+        fn main() -> i32 {
+            let mut a = 1;
+            let b = &mut a;
+            let c = &a;
+            *b = 2;
+            let f = || *c;
+            f()
+        }"
+        .to_string();
+        let mut prog: Ast<Prog> = parse!(prog, Prog);
+        prog.pre_declare_top(&mut 0, &mut 0).unwrap();
+        let mut env = Env::new().explaining();
+        let res = prog.linearize(&mut env);
+        assert!(matches!(res, Err(BCError::Borrow(_))));
+
+        let explanations = env.explanations();
+        assert_eq!(explanations.len(), 2);
+        assert_eq!(explanations[0].func, "main");
+        assert_eq!(
+            explanations[0].loans[0],
+            LoanReport {
+                place: "`a`".to_owned(),
+                mutable: true,
+                holders: vec!["`b`".to_owned()],
+                created: "`let b = &mut a`".to_owned(),
+                last_use: Some("`*b = 2`".to_owned()),
+                conflict: Some("a shared borrow of `a` at `let c = &a`".to_owned()),
+            }
+        );
+        assert_eq!(explanations[0].loans[1].holders, ["`c`", "`f`"]);
+        assert_eq!(explanations[1].func, "main::{closure#0}");
+        assert!(explanations[1].loans.is_empty());
+
+        let graph = env.borrow_graph();
+        assert!(graph.starts_with("digraph borrows {"));
+        assert!(graph.contains(r#""main::b" -> "main::a" [label = "&mut", color = red];"#));
+        assert!(graph.contains(r#""main::c" -> "main::a" [label = "&"];"#));
+    }
 }
@@ -0,0 +1,665 @@
+//! The control flow graph of a function body, the input of the [non lexical
+//! lifetimes](super::nll) analysis.
+//!
+//! Statements are lowered to nodes in evaluation order, each node uses a few variables, accesses
+//! at most one place and assigns at most one variable. Every variable gets a [`Var`] of its own,
+//! shadowed variables are distinct, and the values of borrow expressions, blocks and `if`s are
+//! held by temporaries until they are used.
+//!
+//! Places are tracked per element, `a[0]` and `a[1]` can be borrowed mutably at the same time
+//! while an element at an index that is only known at runtime, `a[i]`, may be any of them.
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::{Region, Signature};
+use crate::ast::{Block, Capture, Closure, Expr, Func, FuncCall, Literal, Statement, UnaryOp};
+
+pub type Var = usize;
+pub type Node = usize;
+pub type LoanId = usize;
+
+/// How a node accesses a place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// The value is read, copied or moved
+    Read,
+    /// The value is overwritten
+    Write,
+    /// The value behind the reference is overwritten, `*place = value`
+    WriteThrough,
+    /// `&place`
+    Borrow,
+    /// `&mut place`
+    BorrowMut,
+    /// The variable goes out of scope at the end of its block
+    Drop,
+}
+
+/// A projection from a place to a part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proj {
+    /// The element at a constant index, `a[1]`
+    Index(usize),
+    /// The element at an index that is only known at runtime, it may be any of them
+    Any,
+}
+
+/// A variable or a part of it, e.g. `a` or `a[1]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Place {
+    pub var: Var,
+    pub path: Vec<Proj>,
+}
+
+impl Place {
+    /// Returns true if the places may refer to the same value, `a` overlaps all of its elements
+    /// and `a[i]` overlaps `a[0]` and `a[1]` which do not overlap each other.
+    pub fn overlaps(&self, other: &Place) -> bool {
+        self.var == other.var
+            && self
+                .path
+                .iter()
+                .zip(other.path.iter())
+                .all(|projs| match projs {
+                    (Proj::Index(a), Proj::Index(b)) => a == b,
+                    _ => true,
+                })
+    }
+}
+
+impl From<Var> for Place {
+    fn from(var: Var) -> Self {
+        Self { var, path: vec![] }
+    }
+}
+
+/// A borrow of `place` created by a `&place` or `&mut place` expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loan {
+    pub place: Place,
+    pub mutable: bool,
+    /// The nodes in which a two-phase borrow is only reserved, it acts like a shared borrow
+    /// until the call that it is passed to activates it at the end of the range
+    pub reserved: Option<Range<Node>>,
+}
+
+/// An assignment, `var` holds the loans held by `from` along with `loans` afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct Def {
+    pub var: Var,
+    pub from: Vec<Var>,
+    pub loans: Vec<LoanId>,
+    /// False if only a part of `var` is assigned, it keeps the loans it held before
+    pub kill: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Action {
+    /// The variables whose values are used by the node
+    pub uses: Vec<Var>,
+    pub access: Option<(Place, Access)>,
+    pub def: Option<Def>,
+}
+
+#[derive(Debug, Default)]
+pub struct Cfg {
+    pub nodes: Vec<Action>,
+    pub succs: Vec<Vec<Node>>,
+    /// The name of each variable, temporaries do not have one
+    pub names: Vec<Option<String>>,
+    pub loans: Vec<Loan>,
+    /// The statement that each node was lowered from, see [`explain`](super::explain)
+    pub labels: Vec<String>,
+    /// The graphs of the closures defined in the body, they are checked on their own
+    pub closures: Vec<Cfg>,
+}
+
+impl Cfg {
+    /// Builds the graph of the body of `func`, `signatures` tells which arguments the values
+    /// returned by calls borrow.
+    pub fn of(func: &Func, signatures: &HashMap<String, Signature>) -> Self {
+        let args: Vec<&Expr> = func.args.iter().map(|arg| &arg.id).collect();
+        Builder::new(signatures).build(&args, &func.body)
+    }
+
+    /// The name of `var` in diagnostics.
+    pub fn name(&self, var: Var) -> String {
+        self.place_name(&Place::from(var))
+    }
+
+    /// The name of `place` in diagnostics, an index that is only known at runtime is `_`.
+    pub fn place_name(&self, place: &Place) -> String {
+        match &self.names[place.var] {
+            // Temporaries are introduced by the pre declaration pass as well
+            Some(name) if !name.starts_with('#') => {
+                let path: String = place
+                    .path
+                    .iter()
+                    .map(|proj| match proj {
+                        Proj::Index(idx) => format!("[{idx}]"),
+                        Proj::Any => "[_]".to_owned(),
+                    })
+                    .collect();
+                format!("`{name}{path}`")
+            }
+            _ => "a temporary value".to_owned(),
+        }
+    }
+
+    pub fn preds(&self) -> Vec<Vec<Node>> {
+        let mut preds = vec![vec![]; self.nodes.len()];
+        for (node, succs) in self.succs.iter().enumerate() {
+            for succ in succs {
+                preds[*succ].push(node);
+            }
+        }
+        preds
+    }
+}
+
+/// The first line of `statement`, loops and blocks are labeled by their head.
+fn label(statement: &Statement) -> String {
+    let statement = statement.to_string();
+    let line = statement.lines().next().unwrap_or_default().trim();
+    format!("`{}`", line.strip_suffix(';').unwrap_or(line))
+}
+
+struct Builder<'a> {
+    cfg: Cfg,
+    /// The variables declared in each block
+    scopes: Vec<Vec<(String, Var)>>,
+    /// The nodes that the next node follows
+    current: Vec<Node>,
+    /// The statement that is being lowered
+    label: String,
+    signatures: &'a HashMap<String, Signature>,
+}
+
+/// Intrinsics that wrap their arguments, the value holds what the arguments hold.
+///
+/// The guard returned by `RefCell::borrow` and `RefCell::borrow_mut` holds the shared borrow of
+/// the cell, the borrow of the value in it is checked at runtime.
+const WRAPPERS: [&str; 9] = [
+    "Some",
+    "Ok",
+    "Err",
+    "Box::new",
+    "Rc::new",
+    "Cell::new",
+    "RefCell::new",
+    "RefCell::borrow",
+    "RefCell::borrow_mut",
+];
+
+impl<'a> Builder<'a> {
+    fn new(signatures: &'a HashMap<String, Signature>) -> Self {
+        Self {
+            cfg: Cfg::default(),
+            scopes: vec![vec![]],
+            current: vec![],
+            label: "the arguments".to_owned(),
+            signatures,
+        }
+    }
+
+    fn build(mut self, args: &[&Expr], body: &Block) -> Cfg {
+        self.push(Action::default());
+        for arg in args {
+            if let Expr::Ident(id) = arg {
+                let var = self.declare(id);
+                self.def(var, vec![], vec![]);
+            }
+        }
+        // The returned value is used by the caller
+        self.label = "the end of the function".to_owned();
+        let ret = self.block(body);
+        self.label = "the return value".to_owned();
+        self.push(Action {
+            uses: ret,
+            ..Action::default()
+        });
+        self.cfg
+    }
+
+    fn push(&mut self, action: Action) -> Node {
+        let node = self.cfg.nodes.len();
+        self.cfg.nodes.push(action);
+        self.cfg.succs.push(vec![]);
+        self.cfg.labels.push(self.label.clone());
+        for pred in self.current.drain(..) {
+            self.cfg.succs[pred].push(node);
+        }
+        self.current = vec![node];
+        node
+    }
+
+    fn uses(&mut self, uses: Vec<Var>) {
+        if !uses.is_empty() {
+            self.push(Action {
+                uses,
+                ..Action::default()
+            });
+        }
+    }
+
+    fn def(&mut self, var: Var, from: Vec<Var>, loans: Vec<LoanId>) {
+        self.push(Action {
+            uses: from.clone(),
+            def: Some(Def {
+                var,
+                from,
+                loans,
+                kill: true,
+            }),
+            ..Action::default()
+        });
+    }
+
+    fn var(&mut self, name: Option<String>) -> Var {
+        self.cfg.names.push(name);
+        self.cfg.names.len() - 1
+    }
+
+    fn temp(&mut self) -> Var {
+        self.var(None)
+    }
+
+    fn declare(&mut self, id: &str) -> Var {
+        let var = self.var(Some(id.to_owned()));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((id.to_owned(), var));
+        }
+        var
+    }
+
+    fn lookup(&self, id: &str) -> Option<Var> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(declared, _)| declared == id)
+            .map(|(_, var)| *var)
+    }
+
+    /// Lowers a block and returns the temporary holding its value.
+    fn block(&mut self, block: &Block) -> Vec<Var> {
+        self.scopes.push(vec![]);
+        let tail = match block.semi {
+            true => None,
+            false => block.statements.len().checked_sub(1),
+        };
+        let mut value = vec![];
+        for (idx, statement) in block.statements.iter().enumerate() {
+            let label = std::mem::replace(&mut self.label, label(statement));
+            match (statement, Some(idx) == tail) {
+                (Statement::Expr(e), true) => value = self.expr(e),
+                (statement, _) => self.statement(statement),
+            }
+            self.label = label;
+        }
+        // The value is moved out of the block before its variables are dropped
+        let ret = match value.is_empty() {
+            true => vec![],
+            false => {
+                let temp = self.temp();
+                self.def(temp, value, vec![]);
+                vec![temp]
+            }
+        };
+        let declared = self.scopes.pop().unwrap_or_default();
+        for (_, var) in declared.into_iter().rev() {
+            self.push(Action {
+                access: Some((var.into(), Access::Drop)),
+                ..Action::default()
+            });
+        }
+        ret
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(id, _, _, rhs) => {
+                // The value is evaluated before the variable shadows anything
+                let value = rhs.as_ref().map(|rhs| self.expr(rhs));
+                if let Expr::Ident(id) = id {
+                    let var = self.declare(id);
+                    if let Some(value) = value {
+                        self.def(var, value, vec![]);
+                    }
+                }
+            }
+            Statement::Assign(lhs, rhs) => {
+                let value = self.expr(rhs);
+                self.assign(lhs, value);
+            }
+            Statement::While(cond, block) => {
+                let head = self.push(Action::default());
+                let cond = self.expr(cond);
+                self.uses(cond);
+                let exit = self.current.clone();
+                self.block(block);
+                for pred in self.current.drain(..) {
+                    self.cfg.succs[pred].push(head);
+                }
+                self.current = exit;
+            }
+            Statement::Expr(e) => {
+                let value = self.expr(e);
+                self.uses(value);
+            }
+            Statement::Block(block) => {
+                self.block(block);
+            }
+            // Nested functions are checked on their own
+            Statement::FnDecleration(_) => {}
+        }
+    }
+
+    fn assign(&mut self, lhs: &Expr, value: Vec<Var>) {
+        match lhs {
+            Expr::Ident(id) => match self.lookup(id) {
+                Some(var) => {
+                    self.push(Action {
+                        uses: value.clone(),
+                        access: Some((var.into(), Access::Write)),
+                        def: Some(Def {
+                            var,
+                            from: value,
+                            loans: vec![],
+                            kill: true,
+                        }),
+                    });
+                }
+                // Globals are not tracked
+                None => self.uses(value),
+            },
+            // A write through a reference uses the reference, it conflicts with reborrows of it
+            Expr::UnOp(UnaryOp::Dereff, target) => match &**target {
+                Expr::Ident(id) if self.lookup(id).is_some() => {
+                    let var = self.lookup(id).unwrap_or_default();
+                    let mut uses = vec![var];
+                    uses.extend(value);
+                    self.push(Action {
+                        uses,
+                        access: Some((var.into(), Access::WriteThrough)),
+                        def: None,
+                    });
+                }
+                target => {
+                    let mut uses = self.expr(target);
+                    uses.extend(value);
+                    self.uses(uses);
+                }
+            },
+            // An element is assigned, the rest of the array is kept
+            Expr::Index(..) | Expr::IndexMut(..) => match self.place(lhs) {
+                Some(place) => {
+                    let var = place.var;
+                    self.push(Action {
+                        uses: value.clone(),
+                        access: Some((place, Access::Write)),
+                        def: Some(Def {
+                            var,
+                            from: value,
+                            loans: vec![],
+                            kill: false,
+                        }),
+                    });
+                }
+                None => self.uses(value),
+            },
+            lhs => {
+                let value = self.expr(lhs);
+                self.uses(value);
+            }
+        }
+    }
+
+    /// Lowers an expression and returns the variables that hold the references in its value.
+    fn expr(&mut self, e: &Expr) -> Vec<Var> {
+        match e {
+            Expr::Ident(id) => match self.lookup(id) {
+                Some(var) => {
+                    self.push(Action {
+                        uses: vec![var],
+                        access: Some((var.into(), Access::Read)),
+                        def: None,
+                    });
+                    vec![var]
+                }
+                // Functions and globals
+                None => vec![],
+            },
+            Expr::UnOp(UnaryOp::Borrow, place) => self.borrow(place, false),
+            Expr::UnOp(UnaryOp::BorrowMut, place) => self.borrow(place, true),
+            // The value is read through the reference
+            Expr::UnOp(_, e) => {
+                let value = self.expr(e);
+                self.uses(value);
+                vec![]
+            }
+            Expr::BinOp(_, lhs, rhs) => {
+                let mut uses = self.expr(lhs);
+                uses.extend(self.expr(rhs));
+                self.uses(uses);
+                vec![]
+            }
+            Expr::Par(e) | Expr::Try(e) => self.expr(e),
+            Expr::IfThenElse(cond, then, otherwise) => {
+                let cond = self.expr(cond);
+                self.uses(cond);
+                let temp = self.temp();
+                let branch = self.current.clone();
+                let value = self.block(then);
+                self.def(temp, value, vec![]);
+                let mut joined = std::mem::replace(&mut self.current, branch);
+                if let Some(otherwise) = otherwise {
+                    let value = self.block(otherwise);
+                    self.def(temp, value, vec![]);
+                }
+                joined.append(&mut self.current);
+                self.current = joined;
+                vec![temp]
+            }
+            Expr::Array(elements) => {
+                let mut value = vec![];
+                for el in elements {
+                    value.extend(self.expr(el));
+                }
+                value
+            }
+            // Only the element is read, the value holds what the array holds
+            Expr::Index(..) | Expr::IndexMut(..) => match self.place(e) {
+                Some(place) => {
+                    let var = place.var;
+                    self.push(Action {
+                        uses: vec![var],
+                        access: Some((place, Access::Read)),
+                        def: None,
+                    });
+                    vec![var]
+                }
+                None => vec![],
+            },
+            Expr::FuncCall(call) => self.call(call),
+            Expr::Block(block) | Expr::Unsafe(block) => self.block(block),
+            Expr::Closure(closure) => self.closure(closure),
+            // A raw pointer holds no loans, the borrow it is cast from ends here
+            Expr::Cast(e, _) => {
+                let value = self.expr(e);
+                self.uses(value);
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Returns the place `e` refers to if it is a local variable or an element of one, the
+    /// indices are lowered on the way.
+    fn place(&mut self, e: &Expr) -> Option<Place> {
+        match e {
+            Expr::Ident(id) => self.lookup(id).map(Place::from),
+            Expr::Index(target, idx) | Expr::IndexMut(target, idx) => {
+                let proj = match &**idx {
+                    Expr::Lit(Literal::Int(idx)) => {
+                        usize::try_from(*idx).map_or(Proj::Any, Proj::Index)
+                    }
+                    _ => Proj::Any,
+                };
+                let idx = self.expr(idx);
+                self.uses(idx);
+                let mut place = self.place(target)?;
+                place.path.push(proj);
+                Some(place)
+            }
+            Expr::Par(e) => self.place(e),
+            _ => None,
+        }
+    }
+
+    /// Lowers `&place` or `&mut place` and returns the temporary holding the reference.
+    fn borrow(&mut self, place: &Expr, mutable: bool) -> Vec<Var> {
+        match place {
+            // A reborrow, `&mut *r`, borrows the reference itself so that `r` can not be used
+            // while the reborrow is live, like a borrow of a reference it holds the loans of `r`
+            // as well
+            Expr::UnOp(UnaryOp::Dereff, target) => match &**target {
+                Expr::Ident(id) if self.lookup(id).is_some() => self.borrow(target, mutable),
+                target => self.expr(target),
+            },
+            Expr::Ident(_) | Expr::Index(..) | Expr::IndexMut(..) | Expr::Par(_) => {
+                let place = match self.place(place) {
+                    Some(place) => place,
+                    // Globals live for the entire program
+                    None => return vec![],
+                };
+                let var = place.var;
+                let loan = self.cfg.loans.len();
+                self.cfg.loans.push(Loan {
+                    place: place.clone(),
+                    mutable,
+                    reserved: None,
+                });
+                let temp = self.temp();
+                let access = match mutable {
+                    true => Access::BorrowMut,
+                    false => Access::Borrow,
+                };
+                // A reference to a reference keeps what it points to borrowed as well
+                self.push(Action {
+                    uses: vec![var],
+                    access: Some((place, access)),
+                    def: Some(Def {
+                        var: temp,
+                        from: vec![var],
+                        loans: vec![loan],
+                        kill: true,
+                    }),
+                });
+                vec![temp]
+            }
+            e => self.expr(e),
+        }
+    }
+
+    /// Lowers `&mut place` passed to a call as a two-phase borrow. The borrow is only reserved
+    /// while the other arguments are evaluated, so they may still read the place as in
+    /// `v.push(v.len())`. Returns the reserved loan along with the node that reserves it.
+    fn reserve(&mut self, place: &Expr) -> (Vec<Var>, Option<(LoanId, Node)>) {
+        let loan = self.cfg.loans.len();
+        let value = self.borrow(place, true);
+        if self.cfg.loans.len() == loan {
+            return (value, None);
+        }
+        // Reserving the place only requires that it is not borrowed mutably
+        let node = self.cfg.nodes.len() - 1;
+        if let Some((_, access)) = &mut self.cfg.nodes[node].access {
+            *access = Access::Borrow;
+        }
+        (value, Some((loan, node)))
+    }
+
+    /// Activates a loan reserved by [`reserve`](Self::reserve), from here on it is a mutable
+    /// borrow held by `temp`.
+    fn activate(&mut self, loan: LoanId, reserved_at: Node, temp: Var) {
+        let place = self.cfg.loans[loan].place.clone();
+        let node = self.push(Action {
+            uses: vec![temp],
+            access: Some((place, Access::BorrowMut)),
+            def: None,
+        });
+        self.cfg.loans[loan].reserved = Some(reserved_at + 1..node);
+    }
+
+    fn call(&mut self, call: &FuncCall) -> Vec<Var> {
+        let mut args = vec![];
+        let mut reserved = vec![];
+        for arg in call.args.iter() {
+            args.push(match arg {
+                Expr::UnOp(UnaryOp::BorrowMut, place) => {
+                    let (value, loan) = self.reserve(place);
+                    reserved.extend(loan.zip(value.first().copied()));
+                    value
+                }
+                arg => self.expr(arg),
+            });
+        }
+        for ((loan, reserved_at), temp) in reserved {
+            self.activate(loan, reserved_at, temp);
+        }
+        let mut value = vec![];
+        match &*call.id {
+            // Closures and function pointers
+            Expr::Ident(id) if self.lookup(id).is_some() => {
+                self.expr(&call.id);
+            }
+            Expr::Ident(id) if WRAPPERS.contains(&id.as_str()) => {
+                value = args.iter().flatten().copied().collect();
+            }
+            Expr::Ident(id) => {
+                // The value borrows the arguments that share a lifetime with the return type
+                if let Some(signature) = self.signatures.get(id) {
+                    for (arg, lifetimes) in args.iter().zip(signature.args.iter()) {
+                        let shared = |l: &Region| *l != Region::Static && signature.ret.contains(l);
+                        if lifetimes.iter().any(shared) {
+                            value.extend(arg.iter().copied());
+                        }
+                    }
+                }
+            }
+            callee => {
+                let callee = self.expr(callee);
+                self.uses(callee);
+            }
+        }
+        // The arguments are used by the call
+        self.uses(args.into_iter().flatten().collect());
+        value
+    }
+
+    /// A closure holds references to the variables it captures by reference.
+    fn closure(&mut self, closure: &Closure) -> Vec<Var> {
+        let builder = Builder::new(self.signatures);
+        let args: Vec<&Expr> = closure.args.iter().map(|arg| &arg.id).collect();
+        let body = match &*closure.body {
+            Expr::Block(body) => body.clone(),
+            body => Block {
+                statements: vec![Statement::Expr(body.clone())],
+                semi: false,
+            },
+        };
+        // The captured variables are not declared in the closure so they are not tracked there
+        self.cfg.closures.push(builder.build(&args, &body));
+
+        let mut value = vec![];
+        for (id, capture) in closure.captures() {
+            let captured = match capture {
+                Capture::Value => self.expr(&Expr::Ident(id)),
+                Capture::Ref => self.borrow(&Expr::Ident(id), false),
+                Capture::MutRef => self.borrow(&Expr::Ident(id), true),
+            };
+            value.extend(captured);
+        }
+        value
+    }
+}
@@ -1,17 +1,26 @@
 //! Defines the environment used for borrow checking
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 use crate::{ast::Expr, type_check::FunctionMeta};
 
-use super::{BCError, BCMeta, BorrowMap, BorrowValue, EnvErr, MetaVariable, Rename, Scope};
+use super::{
+    explain, BCError, BCMeta, EnvErr, Explanation, MetaVariable, Rename, Scope, Signature,
+};
 
 #[derive(Debug)]
 pub struct Env<Meta: Scope> {
     pub(crate) vars: Vec<Meta>,
     fns: Vec<HashMap<String, FunctionMeta>>,
-    borrows: BorrowMap,
-    borrowers: HashMap<String, String>,
+    /// The lifetimes in the signatures of the functions in the program
+    pub(crate) signatures: HashMap<String, Signature>,
+    /// The globals of the program, these live for `'static`
+    pub(crate) statics: HashSet<String>,
+    /// The borrows of the functions checked so far, only collected if requested with
+    /// [`explaining`](Self::explaining), nested functions share it with the enclosing one
+    pub(crate) explanations: Option<Rc<RefCell<Vec<Explanation>>>>,
     scope_counter: usize,
 }
 
@@ -54,6 +63,10 @@ where
         }
         return Err(EnvErr::NoSuchIdentifier(target.to_owned()));
     }
+    /// Returns true if `target` is a live variable visible from the current scope.
+    pub(crate) fn is_declared(&self, target: &String) -> bool {
+        self.traverse_imut(target).is_ok()
+    }
     fn traverse_imut<'a>(&'a self, target: &String) -> Result<&'a Meta::Meta, EnvErr> {
         let mut len: usize = self.vars.len();
         while let Some(idx) = len.checked_sub(1) {
@@ -82,23 +95,39 @@ where
         Self {
             vars: Vec::new(),
             fns: Vec::new(),
-            borrows: BorrowMap {
-                map: HashMap::new(),
-            },
-            borrowers: HashMap::new(),
+            signatures: HashMap::new(),
+            statics: HashSet::new(),
+            explanations: None,
             scope_counter: 0,
         }
     }
+    /// Collects an [`Explanation`] of the borrows in every function that is checked.
+    pub fn explaining(mut self) -> Self {
+        self.explanations = Some(Rc::default());
+        self
+    }
+    /// The explanations collected so far, in the order the functions were checked.
+    pub fn explanations(&self) -> Vec<Explanation> {
+        match &self.explanations {
+            Some(explanations) => explanations.borrow().clone(),
+            None => vec![],
+        }
+    }
+    /// The graph from the borrowers to what they borrow as Graphviz DOT, see [`explain::dot`].
+    pub fn borrow_graph(&self) -> String {
+        explain::dot(&self.explanations())
+    }
     pub fn enter_function(&self) -> Self {
         let fns = self.fns.clone();
-        // TODO:
-        // Lacks access to global scope
+        // Globals are not declared in the scopes, they live for the entire program and are
+        // tracked in `statics` instead
         let vars = vec![];
         let mut new = Self {
             vars,
             fns,
-            borrows: self.borrows.clone(),
-            borrowers: self.borrowers.clone(),
+            signatures: self.signatures.clone(),
+            statics: self.statics.clone(),
+            explanations: self.explanations.clone(),
             scope_counter: self.scope_counter.clone(),
         };
         new.push();
@@ -146,112 +175,4 @@ where
 
         Ok(())
     }
-
-    /// This assumes that the typechecking does some basic borrow checking to see that all borrows
-    /// go to valid variables.
-    pub(crate) fn borrow(
-        &mut self,
-        target_id: &String,
-        borrow_value: BorrowValue,
-    ) -> Result<(), BCError> {
-        let is_mutable = borrow_value.mutable.clone();
-        let mut targets = Vec::new();
-        let mut any_mut = false;
-        if let Some((prev_any_mut, borrows)) = self.borrows.map.get(target_id) {
-            targets = borrows.clone();
-            any_mut = *prev_any_mut || is_mutable;
-        }
-        //println!("{:?}", (is_mutable, any_mut, targets.len()));
-        match (is_mutable, any_mut, targets.len()) {
-            (_, true, _) => return Err(BCError::MultipleRefWhileMutRefAlive),
-            (true, false, 0) => {}
-            (true, false, _) => return Err(BCError::MultipleRefWhileMutRefAlive),
-            (_, _, _) => {}
-        };
-        self.borrowers
-            .insert(borrow_value.id.clone(), target_id.clone());
-        targets.push(Box::new(borrow_value));
-        self.borrows
-            .map
-            .insert(target_id.clone(), (any_mut | is_mutable, targets));
-        Ok(())
-    }
-
-    fn remove_refferands(&mut self, target_id: &String) {
-        match self.borrows.map.remove(target_id) {
-            Some((_mutable, borrowers)) => borrowers,
-            _ => return,
-        };
-    }
-
-    pub(crate) fn destroy_ref(&mut self, target_id: &String) {
-        // First we check if we are borrowers
-        match self.borrowers.remove(target_id) {
-            Some(target) => match self.borrows.map.get(&target).clone() {
-                Some(targets) => {
-                    let mut id = None;
-                    for (idx, el) in targets.1.iter().enumerate() {
-                        if el.id == *target_id {
-                            id = Some(idx);
-                        }
-                    }
-                    let targets = self.borrows.map.get_mut(&target).unwrap();
-                    if let Some(id) = id {
-                        targets.1.remove(id);
-                    }
-                    if targets.1.len() == 0 {
-                        self.borrows.map.remove(&target);
-                    }
-                }
-                _ => {}
-            },
-            None => self.remove_refferands(target_id),
-        }
-    }
-
-    pub(crate) fn format_ident(&self, e: Expr) -> Result<String, EnvErr> {
-        match e {
-            Expr::Ident(i) => match self.traverse_imut(&i) {
-                Ok(meta) => Ok(meta.hash()),
-                Err(EnvErr::NoSuchIdentifier(id)) => {
-                    let scope_info: (usize, usize) = self.counter();
-                    let (id, count, depth, reassign) = (id.clone(), scope_info.0, scope_info.1, 0);
-
-                    Ok(format!(
-                        "{}_depth_{}_count_{}_reassign_{}",
-                        id, depth, count, reassign
-                    )
-                    .to_owned())
-                }
-                Err(e) => Err(e),
-            },
-            Expr::UnOp(_op, expr) => self.format_ident(*expr),
-            e => Err(EnvErr::CannotTreatAsIdentifier(e)),
-        }
-    }
-
-    pub fn dereff(&mut self, target_id: &String, dereff_depth: usize) -> Result<(), BCError> {
-        //println!("{:?},{:?},{dereff_depth:?}", self.borrowers, target_id);
-        let ref_id = match self.borrowers.get(target_id) {
-            Some(ref_id) => Ok(ref_id.clone()),
-            None => Err(BCError::DerrefOfOutOfScope),
-        }?;
-
-        // Validate on dereference
-        match self.borrows.map.get(&ref_id) {
-            Some((_, r)) => {
-                for el in r {
-                    if el.id == *target_id {
-                        //println!("Was borrowing {ref_id:?}");
-                        if dereff_depth == 0 {
-                            return Ok(());
-                        }
-                        return self.dereff(&ref_id, dereff_depth - 1);
-                    }
-                }
-                return Err(BCError::DerrefOfOutOfScope);
-            }
-            None => return Err(BCError::DerrefOfOutOfScope),
-        }
-    }
 }
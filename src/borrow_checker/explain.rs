@@ -0,0 +1,228 @@
+//! Explanations of the borrows in each function, printed by `rnr --explain-borrows`.
+//!
+//! Every loan in the [control flow graph](super::cfg) of a function is reported with the
+//! statement that creates it, the last statement that uses a variable holding it and the first
+//! access that conflicts with it, if any. The explanation is built before the identifiers are
+//! linearized so it only refers to the names in the source.
+//!
+//! The loans also form a graph from the variables holding them to the places they borrow, it can
+//! be rendered with Graphviz, see [`dot`].
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::cfg::{Access, Cfg, LoanId, Node, Var};
+use super::nll::{self, Conflict};
+
+/// A single `&place` or `&mut place` and how long it lasts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoanReport {
+    /// The borrowed place, `` `a` `` or `` `a[1]` ``
+    pub place: String,
+    pub mutable: bool,
+    /// The variables that hold the loan, temporaries are left out
+    pub holders: Vec<String>,
+    /// The statement that creates the loan
+    pub created: String,
+    /// The last statement that uses a variable holding the loan
+    pub last_use: Option<String>,
+    /// The first access that conflicts with the loan, it is reported as an error
+    pub conflict: Option<String>,
+}
+
+/// The loans of a function or of a closure defined in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub func: String,
+    pub loans: Vec<LoanReport>,
+}
+
+impl Explanation {
+    /// Explains the loans in `cfg` and in the closures it defines, the closures are named after
+    /// `func` and their position in it.
+    pub fn of(func: &str, cfg: &Cfg) -> Vec<Self> {
+        let holds_in = nll::holds(cfg);
+        let conflicts = nll::conflicts(cfg);
+        let loans = (0..cfg.loans.len())
+            .map(|loan| report(cfg, &holds_in, &conflicts, loan))
+            .collect();
+        let mut explanations = vec![Self {
+            func: func.to_owned(),
+            loans,
+        }];
+        for (idx, closure) in cfg.closures.iter().enumerate() {
+            explanations.extend(Self::of(&format!("{func}::{{closure#{idx}}}"), closure));
+        }
+        explanations
+    }
+}
+
+/// Returns true if `var` is a variable in the source rather than a temporary.
+fn named(cfg: &Cfg, var: Var) -> bool {
+    matches!(&cfg.names[var], Some(name) if !name.starts_with('#'))
+}
+
+fn holds(holds_in: &[nll::Holds], node: Node, var: Var, loan: LoanId) -> bool {
+    holds_in[node]
+        .get(&var)
+        .is_some_and(|loans| loans.contains(&loan))
+}
+
+fn report(cfg: &Cfg, holds_in: &[nll::Holds], conflicts: &[Conflict], loan: LoanId) -> LoanReport {
+    let defines = |node: &Node| {
+        let def = cfg.nodes[*node].def.as_ref();
+        def.is_some_and(|def| def.loans.contains(&loan))
+    };
+    let created = (0..cfg.nodes.len()).find(defines).unwrap_or_default();
+
+    let mut holders = BTreeSet::new();
+    let mut last_use = None;
+    for (node, action) in cfg.nodes.iter().enumerate() {
+        let held: Vec<Var> = holds_in[node]
+            .keys()
+            .copied()
+            .filter(|var| holds(holds_in, node, *var, loan))
+            .collect();
+        holders.extend(held.into_iter().filter(|var| named(cfg, *var)));
+        if let Some(def) = action.def.as_ref().filter(|_| node == created) {
+            if named(cfg, def.var) {
+                holders.insert(def.var);
+            }
+        }
+        let used = action
+            .uses
+            .iter()
+            .any(|var| holds(holds_in, node, *var, loan));
+        if used && node != created {
+            last_use = Some(node);
+        }
+    }
+
+    let conflict = conflicts.iter().find(|conflict| conflict.loan == loan);
+    LoanReport {
+        place: cfg.place_name(&cfg.loans[loan].place),
+        mutable: cfg.loans[loan].mutable,
+        holders: holders.into_iter().map(|var| cfg.name(var)).collect(),
+        created: cfg.labels[created].clone(),
+        last_use: last_use.map(|node| cfg.labels[node].clone()),
+        conflict: conflict.map(|conflict| access(cfg, conflict.node)),
+    }
+}
+
+/// Describes the access of `node`, e.g. "a mutable borrow of `a` at `let b = &mut a`".
+fn access(cfg: &Cfg, node: Node) -> String {
+    let (place, access) = match &cfg.nodes[node].access {
+        Some((place, access)) => (cfg.place_name(place), access),
+        None => return cfg.labels[node].clone(),
+    };
+    let label = &cfg.labels[node];
+    match access {
+        Access::Read => format!("a use of {place} at {label}"),
+        Access::Write => format!("an assignment to {place} at {label}"),
+        Access::WriteThrough => format!("an assignment through {place} at {label}"),
+        Access::Borrow => format!("a shared borrow of {place} at {label}"),
+        Access::BorrowMut => format!("a mutable borrow of {place} at {label}"),
+        Access::Drop => format!("{place} going out of scope at {label}"),
+    }
+}
+
+impl fmt::Display for LoanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.mutable {
+            true => "mutable",
+            false => "shared",
+        };
+        let holders = match self.holders.is_empty() {
+            true => "a temporary value".to_owned(),
+            false => self.holders.join(", "),
+        };
+        writeln!(f, "{kind} borrow of {} held by {holders}", self.place)?;
+        writeln!(f, "    created at {}", self.created)?;
+        match &self.last_use {
+            Some(last_use) => writeln!(f, "    last used at {last_use}")?,
+            None => writeln!(f, "    never used")?,
+        }
+        if let Some(conflict) = &self.conflict {
+            writeln!(f, "    conflicts with {conflict}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.loans.is_empty() {
+            return writeln!(f, "fn {}: no borrows", self.func);
+        }
+        writeln!(f, "fn {}:", self.func)?;
+        for loan in &self.loans {
+            for line in loan.to_string().lines() {
+                writeln!(f, "    {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of a place or a variable in the graph, without the quotes of the diagnostics.
+fn node_name(name: &str) -> String {
+    match name
+        .strip_prefix('`')
+        .and_then(|name| name.strip_suffix('`'))
+    {
+        Some(name) => name.to_owned(),
+        None => "temporary".to_owned(),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Renders the borrows as a Graphviz graph with a cluster per function, an edge goes from each
+/// holder of a loan to the place it borrows. Edges of loans that conflict with an access are red.
+pub fn dot(explanations: &[Explanation]) -> String {
+    let mut out = String::from("digraph borrows {\n");
+    for (idx, explanation) in explanations.iter().enumerate() {
+        out.push_str(&format!("    subgraph cluster_{idx} {{\n"));
+        out.push_str(&format!("        label = {};\n", quote(&explanation.func)));
+        let id = |name: &str| quote(&format!("{}::{}", explanation.func, node_name(name)));
+
+        let mut nodes = BTreeSet::new();
+        let mut edges = vec![];
+        for loan in &explanation.loans {
+            let holders = match loan.holders.is_empty() {
+                true => vec!["a temporary value".to_owned()],
+                false => loan.holders.clone(),
+            };
+            nodes.insert(node_name(&loan.place));
+            let label = match loan.mutable {
+                true => "&mut",
+                false => "&",
+            };
+            let color = match loan.conflict {
+                Some(_) => ", color = red",
+                None => "",
+            };
+            for holder in holders {
+                nodes.insert(node_name(&holder));
+                edges.push(format!(
+                    "        {} -> {} [label = {}{color}];\n",
+                    id(&holder),
+                    id(&loan.place),
+                    quote(label)
+                ));
+            }
+        }
+        for node in nodes {
+            out.push_str(&format!(
+                "        {} [label = {}];\n",
+                quote(&format!("{}::{node}", explanation.func)),
+                quote(&node)
+            ));
+        }
+        edges.iter().for_each(|edge| out.push_str(edge));
+        out.push_str("    }\n");
+    }
+    out.push_str("}\n");
+    out
+}
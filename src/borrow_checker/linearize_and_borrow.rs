@@ -1,153 +1,115 @@
 use crate::{
-    ast::{Arg, Block, Expr, Func, FuncCall, Statement, Static, UnaryOp},
+    ast::{Arg, Block, Expr, Func, FuncCall, Module, Statement, Static, Use},
     prelude::Prog,
     AstNode,
 };
 
-use super::{BCError, BCScope, BorrowValue, Env, Linearize};
+use super::{
+    cfg::Cfg, nll, regions, BCError, BCScope, Env, EnvErr, Explanation, Linearize, Signature,
+};
 impl Expr {
-    fn linearize<'a>(
-        &'a mut self,
-        env: &mut Env<BCScope<'a>>,
-        dereff_depth: &mut usize,
-    ) -> Result<Option<(String, BorrowValue)>, BCError> {
+    fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
         match self {
-            Expr::Ident(i) => {
-                let meta = env.traverse(&i.clone()).map_err(BCError::EnvError)?;
-                *i = meta.hash();
-            }
+            Expr::Ident(i) => match env.traverse(&i.clone()) {
+                Ok(meta) => *i = meta.hash(),
+                // Function names can be used as values
+                Err(EnvErr::NoSuchIdentifier(_)) => {}
+                Err(e) => return Err(BCError::EnvError(e)),
+            },
             Expr::BinOp(_op, lhs, rhs) => {
-                let _ = lhs.linearize(env, dereff_depth)?;
-                let _ = rhs.linearize(env, dereff_depth)?;
-            }
-            Expr::UnOp(UnaryOp::BorrowMut, e) => {
-                let e_clone = e.clone();
-                let _ = e.linearize(env, dereff_depth)?;
-                let id = match *e_clone.clone() {
-                    Expr::Ident(_id) => env.format_ident(*e_clone).map_err(BCError::EnvError)?,
-                    e => Err(BCError::InvalidIdentifierType(e.clone()))?,
-                };
-                return Ok(Some((
-                    id,
-                    BorrowValue {
-                        id: "".to_string(),
-                        mutable: true,
-                    },
-                )));
-            }
-            Expr::UnOp(UnaryOp::Borrow, e) => {
-                let e_clone = e.clone();
-                e.linearize(env, dereff_depth)?;
-                let id = env.format_ident(*e_clone).map_err(BCError::EnvError)?;
-
-                return Ok(Some((
-                    id,
-                    BorrowValue {
-                        id: "".to_string(),
-                        mutable: false,
-                    },
-                )));
-            }
-            Expr::UnOp(UnaryOp::Dereff, rhs) => {
-                let e_clone = rhs.clone();
-                rhs.linearize(env, dereff_depth)?;
-                match *e_clone.clone() {
-                    Expr::Ident(_) => {
-                        let id = env.format_ident(*e_clone).map_err(BCError::EnvError)?;
-                        *dereff_depth += 1;
-                        env.dereff(&id, *dereff_depth)?;
-                    }
-                    // This is already handled
-                    Expr::UnOp(UnaryOp::Borrow, _)
-                    | Expr::UnOp(UnaryOp::BorrowMut, _)
-                    | Expr::UnOp(UnaryOp::Dereff, _) => {}
-                    e => return Err(BCError::InvalidIdentifierType(e)),
-                }
+                lhs.linearize(env)?;
+                rhs.linearize(env)?;
             }
             Expr::UnOp(_op, rhs) => {
-                rhs.linearize(env, dereff_depth)?;
+                rhs.linearize(env)?;
             }
-            Expr::Par(e) => {
-                e.linearize(env, dereff_depth)?;
+            Expr::Par(e) | Expr::Try(e) | Expr::Cast(e, _) => {
+                e.linearize(env)?;
             }
             Expr::IfThenElse(condition, block, other_block) => {
-                let _ = condition.linearize(env, dereff_depth)?;
-                let _ = block.linearize(env)?;
+                condition.linearize(env)?;
+                block.linearize(env)?;
                 if let Some(block) = other_block {
-                    let _ = block.linearize(env);
+                    block.linearize(env)?;
                 }
             }
             Expr::Array(e) => {
                 for el in e {
-                    let _ = el.linearize(env, dereff_depth)?;
+                    el.linearize(env)?;
                 }
             }
             Expr::Index(id, value) | Expr::IndexMut(id, value) => {
-                id.linearize(env, dereff_depth)?;
-                value.linearize(env, dereff_depth)?;
+                id.linearize(env)?;
+                value.linearize(env)?;
             }
-            Expr::FuncCall(f) => f.linearize(env)?,
-            Expr::Block(b) => {
+            Expr::FuncCall(f) => {
+                f.linearize(env)?;
+            }
+            Expr::Block(b) | Expr::Unsafe(b) => {
                 b.linearize(env)?;
             }
+            Expr::Closure(closure) => {
+                env.push();
+                for arg in closure.args.iter_mut() {
+                    env.declare(Box::new(&mut arg.id))?;
+                }
+                closure.body.linearize(env)?;
+                env.pop().map_err(BCError::EnvError)?;
+            }
             _ => {}
         };
-        Ok(None)
+        Ok(())
     }
 }
 
 impl Linearize for Statement {
     fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
         match self {
-            Statement::Let(ident, _mutable, _, Some(rhs)) => {
-                let is_borrow = rhs.linearize(env, &mut 0)?;
-                let ident_clone = ident.clone();
-                env.declare(Box::new(ident))?;
-                if let Some((target, mut borrow_value)) = is_borrow {
-                    let id = env.format_ident(ident_clone).map_err(BCError::EnvError)?;
-                    borrow_value.id = id;
-                    env.borrow(&target, borrow_value)?;
+            Statement::Let(ident, _mutable, _, rhs) => {
+                if let Some(rhs) = rhs {
+                    rhs.linearize(env)?;
                 }
-                Ok(())
-            }
-            Statement::Let(ident, _mutable, _, None) => {
                 let _ = match *ident {
                     Expr::Ident(_) => Ok(()),
                     _ => Err(BCError::InvalidIdentifierType(ident.clone())),
                 }?;
-                env.declare(Box::new(ident))?;
-                Ok(())
+                env.declare(Box::new(ident))
             }
             Statement::Assign(ident, rhs) => {
-                let is_borrow = rhs.linearize(env, &mut 0)?;
-                let ident_clone = ident.clone();
-                ident.linearize(env, &mut 0)?;
-                if let Some((target, mut borrow_value)) = is_borrow {
-                    let id = env.format_ident(ident_clone).map_err(BCError::EnvError)?;
-                    borrow_value.id = id;
-                    env.borrow(&target, borrow_value)?;
-                }
-                Ok(())
+                rhs.linearize(env)?;
+                ident.linearize(env)
             }
             Statement::While(stmt, block) => {
-                stmt.linearize(env, &mut 0)?;
-                block.linearize(env)?;
-                Ok(())
-            }
-            Statement::Expr(e) => {
-                e.linearize(env, &mut 0)?;
-                Ok(())
+                stmt.linearize(env)?;
+                block.linearize(env)
             }
+            Statement::Expr(e) => e.linearize(env),
             Statement::Block(b) => b.linearize(env),
-            Statement::FnDecleration(f) => f.linearize(env),
+            Statement::FnDecleration(f) => {
+                f.declare_top(env)?;
+                f.linearize(env)
+            }
         }
     }
 }
 
 impl Linearize for FuncCall {
     fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        // Calls to closures and function pointers refer to a variable, these need to be renamed
+        // as well
+        match &mut *self.id {
+            Expr::Ident(id) => {
+                if env.is_declared(id) {
+                    let meta = env.traverse(&id.clone()).map_err(BCError::EnvError)?;
+                    *id = meta.hash();
+                }
+            }
+            e => {
+                e.linearize(env)?;
+            }
+        }
         for arg in self.args.iter_mut() {
-            arg.linearize(env, &mut 0)?;
+            arg.linearize(env)?;
         }
         Ok(())
     }
@@ -155,6 +117,14 @@ impl Linearize for FuncCall {
 
 impl Linearize for Func {
     fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        // The regions and borrows are checked before the identifiers are renamed
+        let cfg = Cfg::of(self, &env.signatures);
+        if let Some(explanations) = &env.explanations {
+            let explanation = Explanation::of(&regions::name(self), &cfg);
+            explanations.borrow_mut().extend(explanation);
+        }
+        regions::check_regions(self, &env.signatures, &env.statics).map_err(BCError::Region)?;
+        nll::check_cfg(&cfg).map_err(BCError::Borrow)?;
         let env = &mut env.enter_function();
         for arg in &mut self.args {
             arg.linearize(env)?;
@@ -163,6 +133,12 @@ impl Linearize for Func {
         self.body.linearize(env)?;
         env.pop().map_err(BCError::EnvError)
     }
+
+    fn declare_top<'a>(&self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        let signature = Signature::of(self).map_err(BCError::Region)?;
+        env.signatures.insert(regions::name(self), signature);
+        Ok(())
+    }
 }
 impl Linearize for Arg {
     fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
@@ -184,12 +160,42 @@ impl Linearize for Block {
 }
 impl Linearize for Static {
     fn linearize<'a>(&'a mut self, _env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
-        todo!()
+        // Globals live for the entire program so they can not be borrowed past their lifetime
+        Ok(())
+    }
+
+    fn declare_top<'a>(&self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        env.statics.insert(self.id.clone());
+        Ok(())
+    }
+}
+
+impl Linearize for Module {
+    fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        for el in self.items.iter_mut() {
+            el.linearize(env)?;
+        }
+        Ok(())
+    }
+
+    fn declare_top<'a>(&self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        for el in self.items.iter() {
+            el.declare_top(env)?;
+        }
+        Ok(())
+    }
+}
+impl Linearize for Use {
+    fn linearize<'a>(&'a mut self, _env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        Ok(())
     }
 }
 
 impl Linearize for Prog {
     fn linearize<'a>(&'a mut self, env: &mut Env<BCScope<'a>>) -> Result<(), BCError> {
+        for el in self.statements.iter() {
+            el.declare_top(env)?;
+        }
         for el in self.statements.iter_mut() {
             el.linearize(env)?;
         }
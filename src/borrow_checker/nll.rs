@@ -0,0 +1,235 @@
+//! Non lexical lifetimes, borrow checking over the [control flow graph](super::cfg) of a function.
+//!
+//! A borrow lasts for as long as a variable that holds it may be used later, not until the end
+//! of the scope of the reference. Liveness of the variables is computed backwards over the graph
+//! and the loans that each variable may hold are computed forwards, a loan is live at a node if
+//! a variable that is live there may hold it. Every access to a place is checked against the live
+//! loans of that place, like rustc a reference that is never used again does not keep the value
+//! borrowed.
+//!
+//! ```rust
+//! let mut a = 0;
+//! let b = &mut a;
+//! // `b` is not used after this point so `a` can be borrowed again
+//! let c = &a;
+//! ```
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use super::cfg::{Access, Cfg, LoanId, Node, Var};
+use super::Signature;
+use crate::ast::Func;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BorrowErr {
+    /// `place` is accessed while `holder`, which is used later, holds a conflicting borrow of it
+    Conflict {
+        place: String,
+        access: Access,
+        /// Wether or not the live borrow is mutable
+        mutable: bool,
+        holder: String,
+    },
+    /// `place` goes out of scope while `holder`, which is used later, still borrows it
+    DoesNotLiveLongEnough { place: String, holder: String },
+}
+
+impl fmt::Display for BorrowErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BorrowErr::Conflict {
+                place,
+                access,
+                mutable,
+                holder,
+            } => {
+                let (first, kind) = match mutable {
+                    true => ("first mutable borrow", "mutable"),
+                    false => ("first borrow", "immutable"),
+                };
+                match (access, mutable) {
+                    (Access::Read, _) => write!(
+                        f,
+                        "cannot use {place} because it was mutably borrowed, the borrow is \
+                         used later by {holder}"
+                    ),
+                    (Access::Borrow, _) => write!(
+                        f,
+                        "cannot borrow {place} as immutable because it is also borrowed as \
+                         mutable, the mutable borrow is used later by {holder}"
+                    ),
+                    (Access::BorrowMut, true) => write!(
+                        f,
+                        "cannot borrow {place} as mutable more than once at a time, the {first} \
+                         is used later by {holder}"
+                    ),
+                    (Access::BorrowMut, false) => write!(
+                        f,
+                        "cannot borrow {place} as mutable because it is also borrowed as {kind}, \
+                         the {kind} borrow is used later by {holder}"
+                    ),
+                    (Access::WriteThrough, _) => write!(
+                        f,
+                        "cannot assign through {place} because it is borrowed, the borrow is used \
+                         later by {holder}"
+                    ),
+                    (_, _) => write!(
+                        f,
+                        "cannot assign to {place} because it is borrowed, the borrow is used \
+                         later by {holder}"
+                    ),
+                }
+            }
+            BorrowErr::DoesNotLiveLongEnough { place, holder } => write!(
+                f,
+                "{place} does not live long enough, it is dropped while still borrowed, the \
+                 borrow is used later by {holder}"
+            ),
+        }
+    }
+}
+
+/// The loans each variable may hold.
+pub(super) type Holds = BTreeMap<Var, BTreeSet<LoanId>>;
+
+/// The variables that may be used after each node.
+fn liveness(cfg: &Cfg) -> Vec<BTreeSet<Var>> {
+    let mut live_in: Vec<BTreeSet<Var>> = vec![BTreeSet::new(); cfg.nodes.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in (0..cfg.nodes.len()).rev() {
+            let action = &cfg.nodes[node];
+            let mut live: BTreeSet<Var> = cfg.succs[node]
+                .iter()
+                .flat_map(|succ| live_in[*succ].iter().copied())
+                .collect();
+            if let Some(def) = action.def.as_ref().filter(|def| def.kill) {
+                live.remove(&def.var);
+            }
+            live.extend(action.uses.iter().copied());
+            if live != live_in[node] {
+                live_in[node] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+/// The loans each variable may hold before each node.
+pub(super) fn holds(cfg: &Cfg) -> Vec<Holds> {
+    let preds = cfg.preds();
+    let mut holds_in: Vec<Holds> = vec![Holds::new(); cfg.nodes.len()];
+    let mut holds_out: Vec<Holds> = vec![Holds::new(); cfg.nodes.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in 0..cfg.nodes.len() {
+            let mut holds = Holds::new();
+            for pred in preds[node].iter() {
+                for (var, loans) in holds_out[*pred].iter() {
+                    holds.entry(*var).or_default().extend(loans);
+                }
+            }
+            let mut out = holds.clone();
+            if let Some(def) = &cfg.nodes[node].def {
+                let mut loans: BTreeSet<LoanId> = def.loans.iter().copied().collect();
+                for from in def.from.iter() {
+                    loans.extend(holds.get(from).into_iter().flatten());
+                }
+                if !def.kill {
+                    loans.extend(holds.get(&def.var).into_iter().flatten());
+                }
+                out.insert(def.var, loans);
+            }
+            if holds != holds_in[node] || out != holds_out[node] {
+                holds_in[node] = holds;
+                holds_out[node] = out;
+                changed = true;
+            }
+        }
+    }
+    holds_in
+}
+
+/// An access to a place while a live variable holds a conflicting loan of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub node: Node,
+    pub loan: LoanId,
+    pub holder: Var,
+}
+
+impl Conflict {
+    /// The error reported for the conflict.
+    pub fn error(&self, cfg: &Cfg) -> BorrowErr {
+        let (place, access) = cfg.nodes[self.node].access.as_ref().unwrap();
+        match access {
+            Access::Drop => BorrowErr::DoesNotLiveLongEnough {
+                place: cfg.place_name(place),
+                holder: cfg.name(self.holder),
+            },
+            access => BorrowErr::Conflict {
+                place: cfg.place_name(place),
+                access: *access,
+                mutable: cfg.loans[self.loan].mutable,
+                holder: cfg.name(self.holder),
+            },
+        }
+    }
+}
+
+/// Returns every access in `cfg` that conflicts with a live loan, in the order of the nodes.
+pub fn conflicts(cfg: &Cfg) -> Vec<Conflict> {
+    let live_in = liveness(cfg);
+    let holds_in = holds(cfg);
+    let mut conflicts = vec![];
+    for (node, action) in cfg.nodes.iter().enumerate() {
+        let (place, access) = match &action.access {
+            Some((place, access)) => (place, *access),
+            None => continue,
+        };
+        for holder in live_in[node].iter() {
+            for loan in holds_in[node].get(holder).into_iter().flatten() {
+                if !cfg.loans[*loan].place.overlaps(place) {
+                    continue;
+                }
+                // A two-phase borrow acts like a shared borrow until it is activated
+                let mutable = match &cfg.loans[*loan].reserved {
+                    Some(nodes) if nodes.end == node => continue,
+                    Some(nodes) if nodes.contains(&node) => false,
+                    _ => cfg.loans[*loan].mutable,
+                };
+                let conflict = match access {
+                    Access::Read | Access::Borrow => mutable,
+                    Access::BorrowMut | Access::Write | Access::WriteThrough | Access::Drop => true,
+                };
+                if conflict {
+                    conflicts.push(Conflict {
+                        node,
+                        loan: *loan,
+                        holder: *holder,
+                    });
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+/// Checks every access in `cfg` and in the closures it defines against the live loans.
+pub fn check_cfg(cfg: &Cfg) -> Result<(), BorrowErr> {
+    if let Some(conflict) = conflicts(cfg).first() {
+        return Err(conflict.error(cfg));
+    }
+    cfg.closures.iter().try_for_each(check_cfg)
+}
+
+/// Borrow checks the body of `func`, `signatures` are the signatures of the functions in scope.
+pub fn check_borrows(
+    func: &Func,
+    signatures: &HashMap<String, Signature>,
+) -> Result<(), BorrowErr> {
+    check_cfg(&Cfg::of(func, signatures))
+}
@@ -1,5 +1,5 @@
 use crate::{
-    ast::{Block, Expr, Func, Statement, Static, UnaryOp},
+    ast::{Block, Expr, Func, Module, Statement, Static, UnaryOp, Use},
     prelude::Prog,
     Ast, AstNode,
 };
@@ -19,22 +19,61 @@ impl UnOpPreDeclaration for Expr {
                 if let Expr::Ident(_) = **e {
                     return Ok(());
                 }
+                // A reborrow refers to the value behind the reference and a borrow of an element
+                // to the element in the array, copying them in to a temporary would borrow the
+                // copy instead
+                if let (
+                    UnaryOp::Borrow | UnaryOp::BorrowMut,
+                    Expr::UnOp(UnaryOp::Dereff, inner)
+                    | Expr::Index(inner, _)
+                    | Expr::IndexMut(inner, _),
+                ) = (&*op, &**e)
+                {
+                    match **inner {
+                        Expr::Ident(_) => return Ok(()),
+                        // The place behind nested dereferences, e.g. `&**b`, is borrowed as well
+                        Expr::UnOp(UnaryOp::Dereff, _) => {
+                            return e.pre_declare(counter, block, index)
+                        }
+                        _ => {}
+                    }
+                }
                 e.pre_declare(counter, block, index)?;
                 let new_ident = Expr::Ident(format!("#{}_unary_op", *counter).to_string());
                 let needs_mut = match op {
                     UnaryOp::BorrowMut => true,
                     _ => false,
                 };
-                let new_declaration =
-                    Statement::Let(new_ident.clone(), needs_mut, None, Some(*e.clone()));
+                // A dereference of a dereference, e.g. `**b`, refers to the place behind the
+                // inner one, the temporary borrows it rather than moving it out of a box
+                let (rhs, expr) = match (&*op, &**e) {
+                    (UnaryOp::Dereff, Expr::UnOp(UnaryOp::Dereff, _)) => (
+                        Expr::UnOp(UnaryOp::Borrow, e.clone()),
+                        Expr::UnOp(
+                            UnaryOp::Dereff,
+                            Box::new(Expr::UnOp(UnaryOp::Dereff, Box::new(new_ident.clone()))),
+                        ),
+                    ),
+                    _ => (
+                        *e.clone(),
+                        Expr::UnOp(op.clone(), Box::new(new_ident.clone())),
+                    ),
+                };
+                let new_declaration = Statement::Let(new_ident, needs_mut, None, Some(rhs));
                 block.insert(*index, new_declaration);
-                *self = Expr::UnOp(op.clone(), Box::new(new_ident));
+                *self = expr;
 
                 *counter += 1;
                 *index += 1;
                 Ok(())
             }
-            Expr::Block(b) => b.pre_declare(counter, block, index),
+            Expr::Block(b) | Expr::Unsafe(b) => b.pre_declare(counter, block, index),
+            Expr::Cast(e, _) => e.pre_declare(counter, block, index),
+            // Temporaries in a closure body must be declared inside of the closure
+            Expr::Closure(closure) => match &mut *closure.body {
+                Expr::Block(b) => b.pre_declare(counter, block, index),
+                _ => Ok(()),
+            },
             Expr::BinOp(_op, l, r) => {
                 l.pre_declare(counter, block, index)?;
                 r.pre_declare(counter, block, index)
@@ -98,7 +137,8 @@ impl PreDeclareTop for Static {
         _counter: &mut usize,
         _index: &mut usize,
     ) -> Result<(), EnvErr> {
-        todo!()
+        // The initializer is a literal after constant evaluation
+        Ok(())
     }
 }
 impl PreDeclareTop for Func {
@@ -111,6 +151,27 @@ impl PreDeclareTop for Func {
             .pre_declare(counter, &mut self.body.statements.clone(), index)
     }
 }
+impl PreDeclareTop for Module {
+    fn pre_declare_top<'a>(
+        &mut self,
+        counter: &mut usize,
+        index: &mut usize,
+    ) -> Result<(), EnvErr> {
+        for item in self.items.iter_mut() {
+            item.pre_declare_top(counter, index)?;
+        }
+        Ok(())
+    }
+}
+impl PreDeclareTop for Use {
+    fn pre_declare_top<'a>(
+        &mut self,
+        _counter: &mut usize,
+        _index: &mut usize,
+    ) -> Result<(), EnvErr> {
+        Ok(())
+    }
+}
 impl<T: PreDeclareTop + AstNode> PreDeclareTop for Ast<T> {
     fn pre_declare_top<'a>(
         &mut self,
@@ -0,0 +1,482 @@
+//! Region checking of references that cross function boundaries.
+//!
+//! Every reference in the signature of a function has a lifetime, either a named one declared by
+//! the function, `fn f<'a>(x: &'a i32) -> &'a i32`, `'static` or an elided one. Elided lifetimes
+//! follow the rules of rust, every elided lifetime in the arguments is a lifetime of its own and
+//! if the arguments mention exactly one lifetime it is the lifetime of every elided reference in
+//! the return type. Elided lifetimes are named `'1`, `'2` and so on in diagnostics.
+//!
+//! The body of a function is checked against its [`Signature`], the [`Region`]s of the returned
+//! value are inferred from the expressions it is built from. A reference to a variable of the
+//! function lives until the end of the block that declares it, a reference passed to the
+//! function lives for the lifetime in the type of the argument and the value returned by a call
+//! borrows the arguments that share a lifetime with its return type.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{Block, Expr, Func, FuncCall, Statement, Type, UnaryOp};
+
+const STATIC: &str = "'static";
+
+/// A region that a reference is valid for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Region {
+    /// The entire program, globals and references with the `'static` lifetime
+    Static,
+    /// A lifetime in the signature of the function, named or elided
+    Param(String),
+    /// The variable `id` of the function, it is dropped at the end of the block that declares it
+    Local(String),
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Static => write!(f, "`{STATIC}`"),
+            Region::Param(lifetime) => write!(f, "`{lifetime}`"),
+            // Temporaries are introduced by the pre declaration pass
+            Region::Local(id) if id.starts_with('#') => write!(f, "a temporary value"),
+            Region::Local(id) => write!(f, "`{id}`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionErr {
+    /// A lifetime in the signature of `func` that it does not declare
+    Undeclared { func: String, lifetime: String },
+    /// The return type of `func` has an elided lifetime that can not be inferred from the
+    /// `inputs` lifetimes of its arguments
+    MissingLifetime { func: String, inputs: usize },
+    /// `borrowed` is dropped at the end of `region` while a reference to it is required to live
+    /// for `required`
+    DoesNotLiveLongEnough {
+        borrowed: Region,
+        region: String,
+        required: String,
+    },
+    /// `func` returns a reference that lives for `region` where its return type requires
+    /// `required`
+    DoesNotOutlive {
+        func: String,
+        region: Region,
+        required: Region,
+    },
+}
+
+impl fmt::Display for RegionErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionErr::Undeclared { func, lifetime } => write!(
+                f,
+                "use of undeclared lifetime `{lifetime}` in the signature of `{func}`, declare it \
+                 with `fn {func}<{lifetime}>`"
+            ),
+            RegionErr::MissingLifetime { func, inputs: 0 } => write!(
+                f,
+                "missing lifetime in the return type of `{func}`, it has no references in its \
+                 arguments to borrow from, consider `{STATIC}`"
+            ),
+            RegionErr::MissingLifetime { func, inputs } => write!(
+                f,
+                "missing lifetime in the return type of `{func}`, its arguments have {inputs} \
+                 lifetimes, name the one that the return value borrows from"
+            ),
+            RegionErr::DoesNotLiveLongEnough {
+                borrowed,
+                region,
+                required,
+            } => write!(
+                f,
+                "{borrowed} does not live long enough, it is dropped at the end of {region} but \
+                 the reference to it has to live for {required}"
+            ),
+            RegionErr::DoesNotOutlive {
+                func,
+                region,
+                required,
+            } => write!(
+                f,
+                "lifetime {region} does not outlive {required}, `{func}` returns a reference \
+                 that lives for {region} but its return type requires {required}"
+            ),
+        }
+    }
+}
+
+/// The lifetimes of the references in the signature of a function after elision, these are
+/// either [`Region::Param`] or [`Region::Static`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Signature {
+    /// The lifetimes of each argument
+    pub args: Vec<Vec<Region>>,
+    /// The lifetimes of the return value
+    pub ret: Vec<Region>,
+}
+
+/// The lifetimes of the references in `ty` in order, `None` for elided lifetimes. References in
+/// closure and function pointer types have lifetimes of their own.
+fn lifetimes(ty: &Type, out: &mut Vec<Option<String>>) {
+    match ty {
+        Type::Ref(r) | Type::MutRef(r) => {
+            out.push(r.1.clone());
+            lifetimes(&r.0, out);
+        }
+        Type::Array(ty, _)
+        | Type::ArrayConst(ty, _)
+        | Type::Box(ty)
+        | Type::Rc(ty)
+        | Type::Vec(ty)
+        | Type::Cell(ty)
+        | Type::RefCell(ty)
+        | Type::CellRef(ty)
+        | Type::CellRefMut(ty)
+        | Type::Option(ty) => lifetimes(ty, out),
+        Type::Result(ty, err) => {
+            lifetimes(ty, out);
+            lifetimes(err, out);
+        }
+        _ => {}
+    }
+}
+
+/// The name of a function, identifiers are formatted with colors.
+pub(crate) fn name(func: &Func) -> String {
+    match &func.id {
+        Expr::Ident(id) => id.clone(),
+        id => id.to_string(),
+    }
+}
+
+impl Signature {
+    /// Returns the signature of `func` with every elided lifetime filled in.
+    pub fn of(func: &Func) -> Result<Self, RegionErr> {
+        let id = name(func);
+        let region = |lifetime: String| match lifetime.as_str() {
+            STATIC => Ok(Region::Static),
+            _ if func.lifetimes.contains(&lifetime) => Ok(Region::Param(lifetime)),
+            _ => Err(RegionErr::Undeclared {
+                func: id.clone(),
+                lifetime,
+            }),
+        };
+
+        let mut elided = 0;
+        let mut inputs: Vec<Region> = vec![];
+        let mut args = vec![];
+        for arg in func.args.iter() {
+            let mut found = vec![];
+            lifetimes(&arg.ty, &mut found);
+            let mut regions = vec![];
+            for lifetime in found {
+                let lifetime = match lifetime {
+                    Some(lifetime) => region(lifetime)?,
+                    None => {
+                        elided += 1;
+                        Region::Param(format!("'{elided}"))
+                    }
+                };
+                if !inputs.contains(&lifetime) && lifetime != Region::Static {
+                    inputs.push(lifetime.clone());
+                }
+                regions.push(lifetime);
+            }
+            args.push(regions);
+        }
+
+        let mut found = vec![];
+        lifetimes(&func.ty, &mut found);
+        let mut ret = vec![];
+        for lifetime in found {
+            let lifetime = match (lifetime, &inputs[..]) {
+                (Some(lifetime), _) => region(lifetime)?,
+                (None, [input]) => input.clone(),
+                (None, inputs) => {
+                    return Err(RegionErr::MissingLifetime {
+                        func: id,
+                        inputs: inputs.len(),
+                    })
+                }
+            };
+            if !ret.contains(&lifetime) {
+                ret.push(lifetime);
+            }
+        }
+        Ok(Self { args, ret })
+    }
+}
+
+/// The regions of the references held by each variable in a block.
+type Scope = HashMap<String, Vec<Region>>;
+
+/// Infers the regions of the values in the body of a function.
+struct RegionCheck<'a> {
+    func: String,
+    signatures: HashMap<String, Signature>,
+    statics: &'a HashSet<String>,
+    /// The regions of the references held by each variable in scope
+    scopes: Vec<Scope>,
+}
+
+/// Adds the regions in `other` that are not already in `regions`.
+fn union(mut regions: Vec<Region>, other: Vec<Region>) -> Vec<Region> {
+    for region in other {
+        if !regions.contains(&region) {
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+impl<'a> RegionCheck<'a> {
+    fn lookup(&self, id: &str) -> Option<&Vec<Region>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(id))
+    }
+
+    fn declare(&mut self, id: &str, regions: Vec<Region>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(id.to_owned(), regions);
+        }
+    }
+
+    /// The regions of a block, references to the variables of the block may not escape it.
+    fn block(&mut self, block: &Block) -> Result<Vec<Region>, RegionErr> {
+        let (regions, scope) = self.scoped(block)?;
+        for region in regions.iter() {
+            if let Region::Local(id) = region {
+                if scope.contains_key(id) {
+                    return Err(RegionErr::DoesNotLiveLongEnough {
+                        borrowed: region.clone(),
+                        region: "its block".to_owned(),
+                        required: "the value of the block".to_owned(),
+                    });
+                }
+            }
+        }
+        Ok(regions)
+    }
+
+    /// The regions of a block along with the variables it declares.
+    fn scoped(&mut self, block: &Block) -> Result<(Vec<Region>, Scope), RegionErr> {
+        self.scopes.push(HashMap::new());
+        let mut regions = vec![];
+        let tail = match block.semi {
+            true => None,
+            false => block.statements.len().checked_sub(1),
+        };
+        for (idx, statement) in block.statements.iter().enumerate() {
+            match (statement, Some(idx) == tail) {
+                (Statement::Expr(e), true) => regions = self.expr(e)?,
+                (statement, _) => self.statement(statement)?,
+            }
+        }
+        let scope = self.scopes.pop().unwrap_or_default();
+        Ok((regions, scope))
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), RegionErr> {
+        match statement {
+            Statement::Let(id, _, _, rhs) => {
+                let regions = match rhs {
+                    Some(rhs) => self.expr(rhs)?,
+                    None => vec![],
+                };
+                if let Expr::Ident(id) = id {
+                    self.declare(id, regions);
+                }
+            }
+            Statement::Assign(lhs, rhs) => {
+                let regions = self.expr(rhs)?;
+                if let Expr::Ident(id) = lhs {
+                    // A variable may hold any of the references assigned to it
+                    if let Some(scope) = self.scopes.iter_mut().rev().find(|s| s.contains_key(id)) {
+                        let held = scope.remove(id).unwrap_or_default();
+                        scope.insert(id.clone(), union(held, regions));
+                    }
+                }
+            }
+            Statement::While(cond, block) => {
+                self.expr(cond)?;
+                self.block(block)?;
+            }
+            Statement::Expr(e) => {
+                self.expr(e)?;
+            }
+            Statement::Block(block) => {
+                self.block(block)?;
+            }
+            // Nested functions are checked on their own
+            Statement::FnDecleration(func) => {
+                let signature = Signature::of(func)?;
+                self.signatures.insert(name(func), signature);
+            }
+        }
+        Ok(())
+    }
+
+    /// The regions of the references held by the value of `e`.
+    fn expr(&mut self, e: &Expr) -> Result<Vec<Region>, RegionErr> {
+        Ok(match e {
+            Expr::Ident(id) => self.lookup(id).cloned().unwrap_or_default(),
+            Expr::UnOp(UnaryOp::Borrow | UnaryOp::BorrowMut, place) => self.place(place)?,
+            // The value is read through the reference
+            Expr::UnOp(_, e) => {
+                self.expr(e)?;
+                vec![]
+            }
+            Expr::BinOp(_, lhs, rhs) => {
+                self.expr(lhs)?;
+                self.expr(rhs)?;
+                vec![]
+            }
+            Expr::Par(e) | Expr::Try(e) => self.expr(e)?,
+            Expr::IfThenElse(cond, then, otherwise) => {
+                self.expr(cond)?;
+                let regions = self.block(then)?;
+                match otherwise {
+                    Some(otherwise) => union(regions, self.block(otherwise)?),
+                    None => regions,
+                }
+            }
+            Expr::Array(elements) => {
+                let mut regions = vec![];
+                for el in elements {
+                    regions = union(regions, self.expr(el)?);
+                }
+                regions
+            }
+            Expr::Index(array, idx) | Expr::IndexMut(array, idx) => {
+                self.expr(idx)?;
+                self.expr(array)?
+            }
+            Expr::FuncCall(call) => self.call(call)?,
+            Expr::Block(block) | Expr::Unsafe(block) => self.block(block)?,
+            Expr::Cast(e, _) => {
+                self.expr(e)?;
+                vec![]
+            }
+            _ => vec![],
+        })
+    }
+
+    /// The regions of a reference to the place `e`.
+    fn place(&mut self, e: &Expr) -> Result<Vec<Region>, RegionErr> {
+        Ok(match e {
+            Expr::Ident(id) if self.lookup(id).is_some() => vec![Region::Local(id.clone())],
+            Expr::Ident(id) if self.statics.contains(id) => vec![Region::Static],
+            // A reborrow lives as long as the reference it is borrowed from
+            Expr::UnOp(UnaryOp::Dereff, e) => self.expr(e)?,
+            Expr::Index(array, idx) | Expr::IndexMut(array, idx) => {
+                self.expr(idx)?;
+                self.place(array)?
+            }
+            Expr::Par(e) => self.place(e)?,
+            e => {
+                self.expr(e)?;
+                vec![]
+            }
+        })
+    }
+
+    /// The regions of the value returned by `call`, it borrows the arguments that share a
+    /// lifetime with the return type.
+    fn call(&mut self, call: &FuncCall) -> Result<Vec<Region>, RegionErr> {
+        let mut args = vec![];
+        for arg in call.args.iter() {
+            args.push(self.expr(arg)?);
+        }
+        let id = match &*call.id {
+            // Closures and function pointers do not have a known signature
+            Expr::Ident(id) if self.lookup(id).is_none() => id,
+            e => {
+                self.expr(e)?;
+                return Ok(vec![]);
+            }
+        };
+        // Intrinsics that wrap their arguments
+        if matches!(
+            id.as_str(),
+            "Some"
+                | "Ok"
+                | "Err"
+                | "Box::new"
+                | "Rc::new"
+                | "Cell::new"
+                | "RefCell::new"
+                | "RefCell::borrow"
+                | "RefCell::borrow_mut"
+        ) {
+            return Ok(args.into_iter().fold(vec![], union));
+        }
+        let signature = match self.signatures.get(id) {
+            Some(signature) => signature,
+            None => return Ok(vec![]),
+        };
+        let mut regions = vec![];
+        for lifetime in signature.ret.iter() {
+            if *lifetime == Region::Static {
+                regions = union(regions, vec![Region::Static]);
+                continue;
+            }
+            for (arg, lifetimes) in args.iter().zip(signature.args.iter()) {
+                if lifetimes.contains(lifetime) {
+                    regions = union(regions, arg.clone());
+                }
+            }
+        }
+        Ok(regions)
+    }
+}
+
+/// Checks that every reference returned by `func` lives for the lifetimes of its return type.
+///
+/// `signatures` are the signatures of the functions that `func` may call and `statics` are the
+/// globals of the program.
+pub fn check_regions(
+    func: &Func,
+    signatures: &HashMap<String, Signature>,
+    statics: &HashSet<String>,
+) -> Result<(), RegionErr> {
+    let signature = Signature::of(func)?;
+    let id = name(func);
+    let mut check = RegionCheck {
+        func: id.clone(),
+        signatures: signatures.clone(),
+        statics,
+        scopes: vec![HashMap::new()],
+    };
+    for (arg, lifetimes) in func.args.iter().zip(signature.args.iter()) {
+        if let Expr::Ident(arg) = &arg.id {
+            check.declare(arg, lifetimes.clone());
+        }
+    }
+    // The arguments and the variables of the body are dropped at the end of the function
+    let (regions, _) = check.scoped(&func.body)?;
+
+    let required = match signature.ret.first() {
+        Some(required) => required,
+        // Nothing that is returned borrows anything
+        None => return Ok(()),
+    };
+    for region in regions {
+        match region {
+            Region::Static => {}
+            Region::Param(_) if signature.ret.contains(&region) => {}
+            Region::Param(_) => {
+                return Err(RegionErr::DoesNotOutlive {
+                    func: check.func,
+                    region,
+                    required: required.clone(),
+                })
+            }
+            Region::Local(_) => {
+                return Err(RegionErr::DoesNotLiveLongEnough {
+                    borrowed: region,
+                    region: format!("the body of `{id}`"),
+                    required: format!("{required}, the lifetime of the return value of `{id}`"),
+                })
+            }
+        }
+    }
+    Ok(())
+}
@@ -38,15 +38,19 @@ impl BinaryOp {
 impl UnaryOp {
     pub fn priority(&self) -> u8 {
         match self {
-            UnaryOp::Not => 3,
-            UnaryOp::Subtract => 3,
-            UnaryOp::Borrow => 4,
-            UnaryOp::BorrowMut => 4,
-            UnaryOp::Dereff => 4,
+            UnaryOp::Not => 4,
+            UnaryOp::Subtract => 4,
+            UnaryOp::Borrow => 5,
+            UnaryOp::BorrowMut => 5,
+            UnaryOp::Dereff => 5,
         }
     }
 }
 
+// A cast binds harder than any binary operator but looser than the unary operators,
+// `-a as usize * 2` is `((-a) as usize) * 2`
+const CAST_PRIORITY: u8 = 3;
+
 // Flattens an Expr into a vector of ExprItems
 fn to_vec(e: Expr) -> Vec<ExprItems> {
     match e {
@@ -80,6 +84,12 @@ fn to_vec(e: Expr) -> Vec<ExprItems> {
         }
         Par(block) => vec![ExprItems::Par(to_vec(*block))],
         Ident(i) => vec![ExprItems::Ident(i)],
+        // The items are in reverse order, the cast comes before its operand
+        Cast(e, ty) => {
+            let mut ret = vec![ExprItems::Cast(ty)];
+            ret.append(&mut to_vec(*e));
+            ret
+        }
         Array(els) => {
             let len = els.len();
             let mut exprs: Vec<ExprItems> = vec![];
@@ -99,6 +109,7 @@ where
     match scanner.peek() {
         Some(ExprItems::Op(op)) => f(op.priority()),
         Some(ExprItems::UnOp(op)) => f(op.priority()),
+        Some(ExprItems::Cast(_)) => f(CAST_PRIORITY),
         _ => false,
     }
 }
@@ -107,6 +118,10 @@ fn climb_rec(mut lhs: Expr, min_precedence: u8, scanner: &mut Scanner) -> Expr {
     while peek_precedence(scanner, |op_precedence| op_precedence >= min_precedence) {
         // op := lookahead
         let expr_item = scanner.next().unwrap();
+        if let ExprItems::Cast(ty) = expr_item {
+            lhs = Cast(Box::new(lhs), ty.clone());
+            continue;
+        }
         let bin_op: Result<&BinaryOp, ()> = expr_item.try_into();
 
         if let Ok(op) = bin_op {
@@ -1,4 +1,4 @@
-use crate::ast::{BinaryOp, Expr, Literal, UnaryOp};
+use crate::ast::{BinaryOp, Expr, Literal, Type, UnaryOp};
 use std::convert::TryInto;
 
 use super::climb_rec;
@@ -39,6 +39,8 @@ pub enum ExprItems {
     Ident(String),
     Array((Vec<ExprItems>, usize)),
     Expr(Expr),
+    /// The `as T` after an operand
+    Cast(Type),
 }
 
 impl<'a> TryInto<&'a BinaryOp> for &'a ExprItems {
@@ -1,6 +1,15 @@
+pub mod cell;
+pub mod heap;
+pub mod input;
 pub mod llvm;
+pub mod option;
+pub mod panic;
+pub mod vec;
 // codegen for a simple MIPS 3k in single cycle mode.
 use crate::ast::*;
+use crate::intrinsics::{
+    CellIntrinsic, HeapIntrinsic, InputIntrinsic, OptionIntrinsic, PanicIntrinsic, VecIntrinsic,
+};
 use crate::{ast::BinaryOp, Ast};
 
 use mips::{
@@ -9,7 +18,7 @@ use mips::{
     rf::Reg::{self, *},
 };
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::str::FromStr;
 #[derive(Debug)]
 pub enum CompileTarget {
@@ -27,15 +36,43 @@ impl FromStr for CompileTarget {
 
 #[derive(Debug, Clone)]
 enum Target {
-    Var(i16), // offset on stack
-    Fn,       // address to function in memory
+    Var(i16),            // offset on stack
+    Fn,                  // address to function in memory
+    Captured(i16, bool), // offset in closure record, captured by reference
+    Data(i16),           // offset in the data section
 }
 
+// base address of the data section, statics and constants are stored here
+const DATA_BASE: u16 = 0x1001;
+
 #[derive(Debug, Clone)]
 pub struct Env {
     offset: i16,
     // (name_space, HashMap)
     scope: VecDeque<(String, HashMap<String, Target>)>,
+    // size of the data section and the code initializing it
+    data_offset: i16,
+    data: Instrs,
+    // variables owning a heap pointer, one entry per scope
+    owners: VecDeque<Vec<String>>,
+    // variables holding a guard of a `RefCell`, true for a `RefMut`, one entry
+    // per scope
+    guards: VecDeque<Vec<(String, bool)>>,
+    // labels of functions returning an owned heap pointer
+    heap_fns: HashSet<String>,
+    // true if the heap runtime is used
+    runtime: bool,
+    // true if the vector runtime is used
+    vec_runtime: bool,
+    // true if the cell runtime is used
+    cell_runtime: bool,
+    // true if the panic handler is used
+    panic_runtime: bool,
+    // labels of the exits of the enclosing functions and closures, `?` returns
+    // through the innermost one
+    exits: Vec<String>,
+    // number of closure exits, used to make their labels unique
+    closure_exits: usize,
 }
 
 impl Env {
@@ -43,6 +80,17 @@ impl Env {
         Env {
             offset: 0,
             scope: VecDeque::new(),
+            data_offset: 0,
+            data: Instrs::new(),
+            owners: VecDeque::new(),
+            guards: VecDeque::new(),
+            heap_fns: HashSet::new(),
+            runtime: false,
+            vec_runtime: false,
+            cell_runtime: false,
+            panic_runtime: false,
+            exits: vec![],
+            closure_exits: 0,
         }
     }
 
@@ -56,15 +104,13 @@ impl Env {
     // returns true if id already in current scope
     fn push_var(&mut self, id: &str) -> bool {
         if self.scope.is_empty() {
-            self.scope
-                .push_front(("GLOBAL_SCOPE".to_string(), HashMap::new()))
+            self.push_scope("GLOBAL_SCOPE");
         }
-        match self.scope[0].1.contains_key(id) {
-            true => match self.scope[0].1.get(id).unwrap() {
-                Target::Var(_) => true,
-                Target::Fn => panic!("expecting var, found fn"),
-            },
-            false => {
+        match self.scope[0].1.get(id) {
+            Some(Target::Var(_)) | Some(Target::Captured(_, _)) => true,
+            Some(Target::Fn) => panic!("expecting var, found fn"),
+            // locals shadow statics
+            Some(Target::Data(_)) | None => {
                 self.offset -= 4;
                 self.scope[0]
                     .1
@@ -89,17 +135,44 @@ impl Env {
         }
     }
 
+    // allocate `words` words in the data section
+    fn insert_data(&mut self, id: &str, words: i16) -> i16 {
+        if self.scope.is_empty() {
+            self.push_scope("GLOBAL_SCOPE");
+        }
+        let offset = self.data_offset;
+        self.data_offset += 4 * words;
+        self.scope[0].1.insert(id.to_owned(), Target::Data(offset));
+        offset
+    }
+
     // set argument offset relative to fp
     fn set_arg_offset(&mut self, id: &str, offset: i16) {
         self.scope[0].1.insert(id.to_owned(), Target::Var(offset));
     }
 
+    // offset of the (tagged) closure record relative to fp, only valid in closures
+    fn get_record_offset(&self) -> i16 {
+        self.get_var_offset("#closure_record")
+    }
+
+    // set the offset of a captured variable relative to the closure record
+    fn set_captured(&mut self, id: &str, offset: i16, by_ref: bool) {
+        self.scope[0]
+            .1
+            .insert(id.to_owned(), Target::Captured(offset, by_ref));
+    }
+
     fn push_scope(&mut self, name_space: &str) {
         self.scope.push_front((name_space.into(), HashMap::new()));
+        self.owners.push_front(vec![]);
+        self.guards.push_front(vec![]);
     }
 
     fn pop_scope(&mut self) {
         self.scope.pop_front();
+        self.owners.pop_front();
+        self.guards.pop_front();
     }
 
     // get_var, traverse the scopes
@@ -112,6 +185,14 @@ impl Env {
         None
     }
 
+    // true if id refers to a variable rather than a function
+    fn is_var(&self, id: &str) -> bool {
+        matches!(
+            self.get_var(id),
+            Some(Target::Var(_)) | Some(Target::Captured(_, _))
+        )
+    }
+
     // get_fn, traverse the scopes
     fn get_fn(&self, id: &str) -> Option<String> {
         // get_fn_scope, traverse the scopes
@@ -129,7 +210,8 @@ impl Env {
         }
 
         if found {
-            Some(format!("{}{}", id, name))
+            // paths, e.g. `a::f`, are not valid labels
+            Some(format!("{}{}", id.replace("::", "__"), name))
         } else {
             None
         }
@@ -176,20 +258,74 @@ impl Prog {
         });
         let mut entry_point = entry_point.codegen(env, &mut Instrs::new());
         entry_point.push(halt().comment("Main exit"));
+        // the panic messages of the cell runtime are stored in the data section
+        let mut cell_runtime = match env.cell_runtime {
+            true => cell::runtime(env),
+            false => Instrs::new(),
+        };
+        // initialize the data section before entering main
+        let mut data = std::mem::replace(&mut env.data, Instrs::new()).comment(".data");
+        if env.runtime {
+            data.append(&mut heap::heap_init());
+        }
+        // cells are allocated in the vector region
+        if env.vec_runtime || env.cell_runtime {
+            data.append(&mut vec::vec_init());
+        }
+        data.append(&mut entry_point);
+        let mut entry_point = data;
         entry_point.append(&mut fns);
+        if env.runtime {
+            entry_point.append(&mut heap::runtime());
+        }
+        if env.vec_runtime {
+            entry_point.append(&mut vec::runtime());
+        }
+        entry_point.append(&mut cell_runtime);
+        if env.panic_runtime {
+            entry_point.append(&mut panic::runtime());
+        }
         entry_point
     }
 }
-impl CodeGen for Static {
+impl CodeGen for Module {
+    // the items have already been renamed to their full paths
     fn codegen(&self, env: &mut Env, fns: &mut Instrs) {
-        let replacement = Statement::Let(
-            Expr::Ident(self.id.clone()),
-            self.mutable,
-            Some(self.ty.clone()),
-            Some(self.value.clone()),
-        );
-        let mut new_instrs = replacement.codegen(env, fns, 0, &mut false);
-        fns.append(&mut new_instrs)
+        for item in self.items.iter() {
+            item.codegen(env, fns)
+        }
+    }
+}
+impl CodeGen for Use {
+    fn codegen(&self, _env: &mut Env, _fns: &mut Instrs) {}
+}
+impl CodeGen for Static {
+    // the value has already been evaluated to a literal, it is stored in the data section
+    fn codegen(&self, env: &mut Env, _fns: &mut Instrs) {
+        fn words(l: &Literal, v: &mut Vec<u32>) {
+            match l {
+                Literal::Int(i) => v.push(*i as u32),
+                Literal::Bool(b) => v.push(*b as u32),
+                Literal::Array(elements) => elements.iter().for_each(|el| words(el, v)),
+                Literal::Unit => {}
+                _ => unreachable!("ICE, cannot store {} in the data section", l),
+            }
+        }
+        let mut values = vec![];
+        match &self.value {
+            Expr::Lit(l) => words(l, &mut values),
+            e => unreachable!("ICE, static {} is not evaluated, found {}", self.id, e),
+        }
+
+        let offset = env.insert_data(&self.id, values.len() as i16);
+        let mut asm = Instrs::new();
+        for (idx, value) in values.into_iter().enumerate() {
+            asm.append(&mut li(t0, value));
+            asm.push(lui(t1, DATA_BASE));
+            asm.push(sw(t0, offset + 4 * idx as i16, t1));
+        }
+        env.data
+            .append(&mut asm.comment(&format!("{} at data offset {}", self.id, offset)));
     }
 }
 
@@ -197,13 +333,41 @@ impl CodeGen for Static {
 impl Expr {
     fn codegen(&self, env: &mut Env, fns: &mut Instrs) -> Instrs {
         match self {
-            Expr::Ident(id) => {
-                let offset = env.get_var_offset(id);
-                let mut asm = Instrs::new();
-                asm.push(lw(t0, offset, fp));
-                asm.append(&mut push(t0));
-                asm.comment(&format!("load '{}' at offset {}", id, offset))
-            }
+            Expr::Ident(id) => match env.get_var(id) {
+                Some(Target::Captured(offset, by_ref)) => {
+                    let mut asm = Instrs::new();
+                    asm.push(lw(t1, env.get_record_offset(), fp).comment("closure record"));
+                    asm.push(lw(t0, offset - 1, t1));
+                    if by_ref {
+                        asm.push(lw(t0, 0, t0));
+                    }
+                    asm.append(&mut push(t0));
+                    asm.comment(&format!("load captured '{}' at offset {}", id, offset))
+                }
+                Some(Target::Fn) => {
+                    // a function used as a value, this is the address of a trampoline
+                    let ns = env.get_fn(id).unwrap();
+                    let mut asm = Instrs::new();
+                    asm.push(bal(1).comment("get trampoline address"));
+                    asm.push(b_label(&ns).comment(&format!("trampoline to {}", id)));
+                    asm.append(&mut push(ra));
+                    asm.comment(&format!("fn {} as a value", id))
+                }
+                Some(Target::Data(offset)) => {
+                    let mut asm = Instrs::new();
+                    asm.push(lui(t1, DATA_BASE));
+                    asm.push(lw(t0, offset, t1));
+                    asm.append(&mut push(t0));
+                    asm.comment(&format!("load static '{}' at data offset {}", id, offset))
+                }
+                _ => {
+                    let offset = env.get_var_offset(id);
+                    let mut asm = Instrs::new();
+                    asm.push(lw(t0, offset, fp));
+                    asm.append(&mut push(t0));
+                    asm.comment(&format!("load '{}' at offset {}", id, offset))
+                }
+            },
             Expr::Lit(l) => match l {
                 Literal::Bool(b) => {
                     let mut v = li(t0, *b as u32);
@@ -252,8 +416,31 @@ impl Expr {
             Expr::FuncCall(call) => {
                 let (id, args) = (call.id.clone(), call.args.clone());
                 let id = match *id.clone() {
-                    Expr::Ident(i) => i,
-                    _ => unreachable!(),
+                    Expr::Ident(i) if !env.is_var(&i) && HeapIntrinsic::from_id(&i).is_some() => {
+                        return HeapIntrinsic::from_id(&i).unwrap().codegen(&args, env, fns)
+                    }
+                    Expr::Ident(i) if !env.is_var(&i) && VecIntrinsic::from_id(&i).is_some() => {
+                        return VecIntrinsic::from_id(&i).unwrap().codegen(&args, env, fns)
+                    }
+                    Expr::Ident(i) if !env.is_var(&i) && OptionIntrinsic::from_id(&i).is_some() => {
+                        return OptionIntrinsic::from_id(&i)
+                            .unwrap()
+                            .codegen(&args, env, fns)
+                    }
+                    Expr::Ident(i) if !env.is_var(&i) && PanicIntrinsic::from_id(&i).is_some() => {
+                        return PanicIntrinsic::from_id(&i)
+                            .unwrap()
+                            .codegen(&args, env, fns)
+                    }
+                    Expr::Ident(i) if !env.is_var(&i) && InputIntrinsic::from_id(&i).is_some() => {
+                        return InputIntrinsic::from_id(&i).unwrap().codegen(env)
+                    }
+                    Expr::Ident(i) if !env.is_var(&i) && CellIntrinsic::from_id(&i).is_some() => {
+                        return CellIntrinsic::from_id(&i).unwrap().codegen(&args, env, fns)
+                    }
+                    // variables shadow functions
+                    Expr::Ident(i) if !env.is_var(&i) => i,
+                    callee => return indirect_call(&callee, &args, env, fns),
                 };
                 let mut call_asm = Instrs::new();
 
@@ -298,6 +485,43 @@ impl Expr {
                 ite_asm
             }
             Expr::Block(b) => b.codegen(env, fns, "expr"),
+            Expr::Unsafe(b) => b.codegen(env, fns, "unsafe"),
+            // Integers and booleans are all words, a cast does not change the value
+            Expr::Cast(e, _) => e.codegen(env, fns),
+            Expr::Closure(closure) => closure.codegen(env, fns),
+            Expr::Try(e) => option::try_expr(e, env, fns),
+            // arrays are not yet supported, so this is a Vec
+            Expr::Index(vec, idx) => {
+                let mut asm = vec::element(vec, idx, env, fns);
+                asm.push(lw(t0, 0, t0).comment("load element"));
+                asm.append(&mut push(t0));
+                asm.comment(&format!("{}[{}]", vec, idx))
+            }
+            // references are not yet supported, so this is a Box, an Rc or a
+            // guard of a RefCell
+            Expr::UnOp(UnaryOp::Dereff, e) => {
+                let mut asm = e.codegen(env, fns);
+                asm.append(&mut pop(t0));
+                match cell::guard(e) {
+                    Some(mutable) => asm.append(&mut cell::read_temporary(mutable)),
+                    None => {
+                        asm.push(lw(t0, 4, t0).comment("load heap value"));
+                        asm.append(&mut push(t0));
+                    }
+                }
+                asm.comment(&format!("*{}", e))
+            }
+            // a borrow of the value in a box, e.g. the `&*b` the borrow checker
+            // declares for `**b`, is the box itself as dereferencing it loads the
+            // value
+            Expr::UnOp(UnaryOp::Borrow | UnaryOp::BorrowMut, expr)
+                if matches!(&**expr, Expr::UnOp(UnaryOp::Dereff, _)) =>
+            {
+                match *expr.clone() {
+                    Expr::UnOp(_, inner) => inner.codegen(env, fns),
+                    _ => unreachable!(),
+                }
+            }
             // Since we assume type checking has been done before this we simply
             // treat mut an imutable borrows equally
             #[allow(unreachable_code, unused_variables)]
@@ -338,14 +562,55 @@ impl Statement {
         fn assign(id: &String, e: &Expr, env: &mut Env, fns: &mut Instrs) -> Instrs {
             let mut asm = e.codegen(env, fns);
             asm.append(&mut pop(t0));
-            let offset = env.get_var_offset(id);
-            asm.push(sw(t0, offset, fp).comment(&format!("store '{}' at offset {}", id, offset)));
+            match env.get_var(id) {
+                Some(Target::Captured(offset, by_ref)) => {
+                    asm.push(lw(t1, env.get_record_offset(), fp).comment("closure record"));
+                    if by_ref {
+                        asm.push(lw(t1, offset - 1, t1));
+                        asm.push(sw(t0, 0, t1));
+                    } else {
+                        asm.push(sw(t0, offset - 1, t1));
+                    }
+                    asm = asm.comment(&format!("store captured '{}' at offset {}", id, offset));
+                }
+                Some(Target::Data(offset)) => {
+                    asm.push(lui(t1, DATA_BASE));
+                    asm.push(
+                        sw(t0, offset, t1)
+                            .comment(&format!("store static '{}' at data offset {}", id, offset)),
+                    );
+                }
+                _ => {
+                    let offset = env.get_var_offset(id);
+                    asm.push(
+                        sw(t0, offset, fp).comment(&format!("store '{}' at offset {}", id, offset)),
+                    );
+                }
+            }
             asm.comment(&format!("'{} = {}'", id, e))
         }
 
         match self {
             // for now we don't support assignments on references
             Statement::Assign(Expr::Ident(id), e) => assign(id, e, env, fns),
+            Statement::Assign(Expr::IndexMut(vec, idx), e) => {
+                let mut asm = e.codegen(env, fns);
+                asm.append(&mut vec::element(vec, idx, env, fns));
+                asm.append(&mut pop(t1));
+                asm.push(sw(t1, 0, t0).comment("store element"));
+                asm.comment(&format!("'{}[{}] = {}'", vec, idx, e))
+            }
+            Statement::Assign(Expr::UnOp(UnaryOp::Dereff, ptr), e) => {
+                let mut asm = e.codegen(env, fns);
+                asm.append(&mut ptr.codegen(env, fns));
+                asm.append(&mut pop(t1));
+                asm.append(&mut pop(t0));
+                match cell::guard(ptr) {
+                    Some(_) => asm.append(&mut cell::write_temporary()),
+                    None => asm.push(sw(t0, 4, t1).comment("store heap value")),
+                }
+                asm.comment(&format!("'*{} = {}'", ptr, e))
+            }
             Statement::Assign(_, _) => {
                 panic!("only assignments directly to variables supported")
             }
@@ -358,6 +623,13 @@ impl Statement {
                     _ => unreachable!(),
                 };
 
+                // decided in the old environment, as the expression may refer to a shadowed variable
+                let owner = match (_type, opt_e) {
+                    (Some(ty), _) => ty.is_heap(),
+                    (None, Some(e)) => env.is_heap_expr(e),
+                    (None, None) => false,
+                };
+
                 if !env.push_var(id) {
                     // update env with new allocation
                     let_asm.push(addiu(sp, sp, -4).comment(&format!("allocate '{}'", id,)));
@@ -368,6 +640,8 @@ impl Statement {
                     // evaluate expression in old environment
                     let_asm.append(&mut assign(id, e, env, fns));
                 }
+                env.set_owner(id, owner);
+                env.set_guard(id, opt_e.as_ref().and_then(cell::guard));
                 let_asm
             }
             Statement::While(while_cond, while_body) => {
@@ -443,6 +717,11 @@ impl Block {
                 stmts_asm.append(&mut push(t0));
             }
 
+            // drop the heap pointers owned by the block and release the guards held by
+            // it, the block result is on top of the stack
+            stmts_asm.append(&mut env.drop_owned(self));
+            stmts_asm.append(&mut env.release_guards(self));
+
             if enter_offset != env.offset {
                 // we have local variables
                 stmts_asm.append(&mut pop(t0).comment("exit block, pop block result"));
@@ -463,25 +742,167 @@ pub trait CodeGen {
     fn codegen(&self, env: &mut Env, fns: &mut Instrs);
 }
 
+fn enter_frame() -> Instrs {
+    let mut enter_asm = Instrs::new();
+    enter_asm.append(&mut push(ra)); //
+    enter_asm.append(&mut push(fp)); //
+    enter_asm.push(mov(fp, sp)); //
+    enter_asm
+}
+
+fn exit_frame() -> Instrs {
+    let mut asm = Instrs::new();
+    asm.append(&mut pop(t0).comment("pop return value"));
+
+    asm.push(mov(sp, fp));
+    asm.append(&mut pop(fp)); //
+    asm.append(&mut pop(ra)); //
+    asm.append(&mut push(t0).comment("push back return value"));
+    asm.push(jr(ra)); //
+    asm
+}
+
 impl crate::ast::func::Func {
     fn enter(&self) -> Instrs {
-        let mut enter_asm = Instrs::new();
-        enter_asm.append(&mut push(ra)); //
-        enter_asm.append(&mut push(fp)); //
-        enter_asm.push(mov(fp, sp)); //
-        enter_asm
+        enter_frame()
     }
 
     fn exit(&self) -> Instrs {
+        exit_frame().comment(&format!("exit frame 'fn {}'", self.id))
+    }
+}
+
+// function values
+//
+// a function value is either a code address, for functions and closures that
+// capture nothing, or the address of a closure record tagged by setting the
+// lowest bit. the value is always pushed before the arguments so that the
+// arguments end up where a function expects them.
+//
+// 16[fp]    function value
+// 12[fp]    arg 1
+//  8[fp]    arg 2
+//  4[fp]    ra
+//  0[fp]    old_fp
+fn indirect_call(callee: &Expr, args: &[Expr], env: &mut Env, fns: &mut Instrs) -> Instrs {
+    let mut asm = callee.codegen(env, fns).comment("function value");
+    for arg in args.iter() {
+        asm.append(&mut arg.codegen(env, fns).comment(&format!("arg {}", arg)));
+    }
+    asm.push(lw(t0, 4 * args.len() as i16, sp).comment("function value"));
+    asm.push(andi(t1, t0, 1));
+    asm.push(beq(t1, zero, 1).comment("not a closure"));
+    asm.push(lw(t0, -1, t0).comment("closure code address"));
+    asm.push(jalr(t0).comment(&format!("call {}", callee)));
+    asm.append(&mut pop(t0).comment("pop result"));
+    asm.push(
+        addiu(sp, sp, 4 * (args.len() as i16 + 1)).comment("remove arguments and function value"),
+    );
+    asm.append(&mut push(t0).comment("push back result"));
+    asm
+}
+
+impl Closure {
+    // closure record layout, relative to the record address
+    //
+    //  0[rec]    code address
+    // -4[rec]    capture 1, value or address
+    // -8[rec]    capture 2, etc.
+    //
+    // the record is allocated as locals of the enclosing block, so it lives
+    // until the end of that block. closures that capture nothing have no
+    // record, they are represented by their code address.
+    fn codegen(&self, env: &mut Env, fns: &mut Instrs) -> Instrs {
+        let captures: Vec<(String, Capture)> = self
+            .captures()
+            .into_iter()
+            .filter(|(id, _)| env.is_var(id))
+            .collect();
+
         let mut asm = Instrs::new();
-        asm.append(&mut pop(t0).comment("pop return value"));
+        if captures.is_empty() {
+            let mut code = self.code(&captures, 0, &[], env, fns);
+            // skip the code, ra is left pointing at its first instruction
+            asm.push(bal(code.len() as i16).comment("get closure code address"));
+            asm.append(&mut code);
+            asm.append(&mut push(ra).comment("push closure code address"));
+            return asm.comment(&format!("closure {}", self));
+        }
 
-        asm.push(mov(sp, fp));
-        asm.append(&mut pop(fp)); //
-        asm.append(&mut pop(ra)); //
-        asm.append(&mut push(t0).comment("push back return value"));
-        asm.push(jr(ra)); //
-        asm.comment(&format!("exit frame 'fn {}'", self.id))
+        // allocate the record
+        let mut slots = vec![];
+        for idx in 0..=captures.len() {
+            let slot = format!("#closure@{}_{}", env.offset, idx);
+            env.push_var(&slot);
+            asm.push(addiu(sp, sp, -4).comment(&format!("allocate '{}'", slot)));
+            slots.push(env.get_var_offset(&slot));
+        }
+        let record = slots[0];
+
+        // fill in the captures
+        for ((id, capture), slot) in captures.iter().zip(slots.iter().skip(1)) {
+            match (capture, env.get_var(id)) {
+                (Capture::Value, _) => {
+                    asm.append(&mut Expr::Ident(id.clone()).codegen(env, fns));
+                    asm.append(&mut pop(t0));
+                }
+                (_, Some(Target::Captured(offset, true))) => {
+                    asm.push(lw(t0, env.get_record_offset(), fp).comment("closure record"));
+                    asm.push(lw(t0, offset - 1, t0));
+                }
+                (_, Some(Target::Captured(offset, false))) => {
+                    asm.push(lw(t0, env.get_record_offset(), fp).comment("closure record"));
+                    asm.push(addiu(t0, t0, offset - 1));
+                }
+                (_, _) => asm.push(addiu(t0, fp, env.get_var_offset(id))),
+            }
+            asm.push(sw(t0, *slot, fp).comment(&format!("capture '{}'", id)));
+        }
+
+        let mut code = self.code(&captures, record, &slots, env, fns);
+        // skip the code, ra is left pointing at its first instruction
+        asm.push(bal(code.len() as i16).comment("get closure code address"));
+        asm.append(&mut code);
+        asm.push(sw(ra, record, fp).comment("store closure code address"));
+
+        asm.push(addiu(t0, fp, record + 1).comment("tag closure record"));
+        asm.append(&mut push(t0).comment("push closure record"));
+        asm.comment(&format!("closure {}", self))
+    }
+
+    // generates the closure code in a new scope, see `indirect_call` for the frame layout
+    fn code(
+        &self,
+        captures: &[(String, Capture)],
+        record: i16,
+        slots: &[i16],
+        env: &mut Env,
+        fns: &mut Instrs,
+    ) -> Instrs {
+        env.push_scope("closure");
+        for ((id, capture), slot) in captures.iter().zip(slots.iter().skip(1)) {
+            env.set_captured(id, slot - record, *capture != Capture::Value);
+        }
+        for (offset, arg) in self.args.iter().rev().enumerate() {
+            let id = match arg.id.clone() {
+                Expr::Ident(i) => i,
+                _ => unreachable!(),
+            };
+            env.set_arg_offset(&id, (2 + offset as i16) * 4); // last argument at offset + 2
+        }
+        env.set_arg_offset("#closure_record", (2 + self.args.len() as i16) * 4);
+        let offset = env.offset;
+        env.offset = 0;
+        let exit = format!("closure_exit_{}", env.closure_exits);
+        env.closure_exits += 1;
+        env.exits.push(exit.clone());
+        let mut code = enter_frame();
+        code.append(&mut self.body.codegen(env, fns));
+        code.append(&mut exit_frame().comment("exit closure frame").label(&exit));
+        env.exits.pop();
+        env.offset = offset;
+        env.pop_scope();
+        code
     }
 }
 impl CodeGen for Func {
@@ -504,6 +925,9 @@ impl CodeGen for Func {
         env.insert_fn(&id);
         // enter a new scope for the function
         let fn_ns = &env.get_fn(&id).unwrap();
+        if self.ty.is_heap() {
+            env.heap_fns.insert(fn_ns.clone());
+        }
 
         for (offset, parameter) in self.args.iter().rev().enumerate() {
             let id = match parameter.id.clone() {
@@ -526,10 +950,25 @@ impl CodeGen for Func {
         // generate code for the body
         let offset = env.offset;
         env.offset = 0;
+        let exit = format!("{}_exit", fn_ns);
+        env.exits.push(exit.clone());
         asm.append(&mut self.body.codegen(env, fns, &id));
+        env.exits.pop();
         env.offset = offset;
-        // the exit block
-        asm.append(&mut self.exit());
+        // drop the heap pointers passed as arguments
+        let mut exit_asm = Instrs::new();
+        for (offset, arg) in self.args.iter().rev().enumerate() {
+            match &arg.id {
+                Expr::Ident(i) if arg.ty.is_heap() && !heap::moved(i, &self.body.statements) => {
+                    exit_asm.append(&mut heap::drop_var((2 + offset as i16) * 4, i));
+                    env.runtime = true;
+                }
+                _ => (),
+            }
+        }
+        // the exit block, `?` branches here with the return value on the stack
+        exit_asm.append(&mut self.exit());
+        asm.append(&mut exit_asm.label(&exit));
         fns.append(&mut asm);
     }
 }
@@ -608,6 +1047,238 @@ fn main(){
         )
     }
 
+    #[test]
+    fn test_module_program() {
+        mips_test_prog(
+            "
+mod a {
+    pub fn two() -> i32 {
+        2
+    }
+}
+use a::two;
+fn main(){
+    let x = two() + a::two();
+}
+",
+        )
+    }
+
+    #[test]
+    fn test_static_program() {
+        let prog: Ast<Prog> = "
+const STEP: i32 = 2 * 3;
+static mut COUNT: i32 = STEP + 1;
+fn main() -> i32 {
+    unsafe {
+        COUNT = COUNT + STEP;
+        COUNT
+    }
+}
+"
+        .to_string()
+        .into();
+        let asm = prog.codegen();
+        println!("codegen\n{}", asm);
+        let mut mips = Mips::new(Instrs::new_from_slice(&asm));
+        let _ = mips.run();
+        assert_eq!(mips.rf.get(t0) as i32, 13);
+    }
+
+    #[test]
+    fn test_heap_program() {
+        let prog: Ast<Prog> = "
+fn make(v: i32) -> Box<i32> {
+    Box::new(v)
+}
+fn add(a: Box<i32>, b: i32) -> i32 {
+    *a + b
+}
+fn main() -> i32 {
+    let mut a = Box::new(Box::new(3));
+    **a = 4;
+    let r = Rc::new(5);
+    let s = Rc::clone(&r);
+    let mut i = 0;
+    while i < 3 {
+        let t = make(1);
+        i = add(t, i);
+    };
+    **a + *r + *s + i
+}
+"
+        .to_string()
+        .into();
+        let asm = prog.codegen();
+        println!("codegen\n{}", asm);
+        let mut mips = Mips::new(Instrs::new_from_slice(&asm));
+        let _ = mips.run();
+        assert_eq!(mips.rf.get(t0) as i32, 17);
+        assert_eq!(mips.rf.get(sp), 0x7fff_fffc);
+    }
+
+    #[test]
+    fn test_vec_program() {
+        let prog: Ast<Prog> = "
+fn main() -> i32 {
+    let mut v: Vec<i32> = Vec::new();
+    let mut i = 0;
+    while i < 10 {
+        v.push(i);
+        i = i + 1;
+    };
+    v[9] = 20;
+    let w = vec![1, 2, 3];
+    let mut s = 0;
+    for x in &w {
+        s = s + x;
+    };
+    let mut e: Vec<i32> = Vec::new();
+    if e.pop().is_none() {
+        s = s + 100;
+    };
+    s + v.pop().unwrap() + v[8] + v.len()
+}
+"
+        .to_string()
+        .into();
+        let asm = prog.codegen();
+        println!("codegen\n{}", asm);
+        let mut mips = Mips::new(Instrs::new_from_slice(&asm));
+        let _ = mips.run();
+        assert_eq!(mips.rf.get(t0) as i32, 143);
+        assert_eq!(mips.rf.get(sp), 0x7fff_fffc);
+    }
+
+    #[test]
+    fn test_option_program() {
+        let prog: Ast<Prog> = "
+fn check(x: i32) -> Result<i32, i32> {
+    if x < 10 { Ok(x + 1) } else { Err(x) }
+}
+fn sum(a: i32, b: i32) -> Result<i32, i32> {
+    let x = check(a)?;
+    let y = check(b)?;
+    Ok(x + y)
+}
+fn first(v: Option<i32>) -> Option<i32> {
+    let f = |v: Option<i32>| -> Option<i32> { Some(v? + 100) };
+    f(v)
+}
+fn main() -> i32 {
+    let mut r = sum(1, 2).unwrap() + first(Some(1)).expect(\"some\");
+    let e = sum(1, 20);
+    if e.is_err() { r = r + 10 };
+    if first(None).is_none() { r = r + 1000 };
+    r
+}
+"
+        .to_string()
+        .into();
+        let asm = prog.codegen();
+        println!("codegen\n{}", asm);
+        let mut mips = Mips::new(Instrs::new_from_slice(&asm));
+        let _ = mips.run();
+        assert_eq!(mips.rf.get(t0) as i32, 1116);
+        assert_eq!(mips.rf.get(sp), 0x7fff_fffc);
+    }
+
+    #[test]
+    fn test_panic_program() {
+        let prog = "
+fn check(a: i32) -> i32 {
+    assert!(a < 10, \"a was {}\", a);
+    if a > 5 { a } else { unreachable!() }
+}
+fn main() -> i32 {
+    assert_eq!(check(6), 6);
+    assert_ne!(check(7), 6);
+    check(MAIN_ARG)
+}
+";
+        let panicked = panic::PANIC_CODE as i32;
+        for (arg, expected) in [("8", 8), ("3", panicked), ("12", panicked)] {
+            let prog: Ast<Prog> = prog.replace("MAIN_ARG", arg).into();
+            let asm = prog.codegen();
+            println!("codegen\n{}", asm);
+            let mut mips = Mips::new(Instrs::new_from_slice(&asm));
+            let _ = mips.run();
+            assert_eq!(mips.rf.get(t0) as i32, expected);
+        }
+    }
+
+    #[test]
+    fn test_cell_program() {
+        let prog = "
+fn main() -> i32 {
+    let c = Cell::new(1);
+    c.set(c.get() + 1);
+    let r = Rc::new(RefCell::new(0));
+    let s = Rc::clone(&r);
+    *r.borrow_mut() = 10;
+    {
+        let mut g = s.borrow_mut();
+        *g = *g + c.get();
+    };
+    let a = r.borrow();
+    *a + *s.borrow() + MAIN_ARG
+}
+";
+        let panicked = panic::PANIC_CODE as i32;
+        for (arg, expected) in [("0", 24), ("*s.borrow_mut()", panicked)] {
+            let prog: Ast<Prog> = prog.replace("MAIN_ARG", arg).into();
+            let asm = prog.codegen();
+            println!("codegen\n{}", asm);
+            let mut mips = Mips::new(Instrs::new_from_slice(&asm));
+            let _ = mips.run();
+            assert_eq!(mips.rf.get(t0) as i32, expected);
+        }
+    }
+
+    #[test]
+    fn test_read_line_program() {
+        use crate::vm::{Buffer, Eval, Io, VarEnv};
+        for line in ["rnr\n", "hi\n", "\n", "four\n", "no newline"] {
+            // the line printed by the vm
+            let bl: Block =
+                syn::parse2("{ print!(\"{}\", read_line()); }".parse().unwrap()).unwrap();
+            let out = Buffer::new();
+            let mut env = VarEnv::new();
+            env.io = Io::default()
+                .with_stdout(out.clone())
+                .with_stdin(line.as_bytes());
+            bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+
+            // the line as SPIM stores it in the buffer, trimmed by the runtime
+            let mut bytes = line.as_bytes().to_vec();
+            bytes.resize(12, 0);
+            let mut asm = Instrs(vec![lui(a0, vec::VEC_BASE), addiu(a0, a0, 0x100)]);
+            for (idx, word) in bytes.chunks(4).enumerate() {
+                let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                asm.push(lui(t0, (word >> 16) as u16));
+                asm.push(ori(t0, t0, word as u16));
+                asm.push(sw(t0, idx as i16 * 4, a0));
+            }
+            asm.append(&mut input::trim_newline());
+            asm.push(lw(t0, 0, a0));
+            asm.push(lw(t1, 4, a0));
+            asm.push(lw(t2, 8, a0));
+            asm.push(halt());
+            let mut mips = Mips::new(Instrs::new_from_slice(&asm));
+            let _ = mips.run();
+            let trimmed: Vec<u8> = [t0, t1, t2]
+                .into_iter()
+                .flat_map(|reg| (mips.rf.get(reg) as u32).to_le_bytes())
+                .take_while(|byte| *byte != 0)
+                .collect();
+            assert_eq!(
+                String::from_utf8(trimmed).unwrap(),
+                out.contents(),
+                "{line:?}"
+            );
+        }
+    }
+
     // helper to test expressions
     fn mips_test_prog(prog: &str) {
         let prog: Ast<Prog> = prog.to_string().into();
@@ -1448,4 +2119,45 @@ fn main(){
             3 + 7,
         );
     }
+
+    #[test]
+    fn mips_closure_capture() {
+        mips_test_fn(
+            "
+            {
+                fn f() -> i32 {
+                    let mut a = 1;
+                    let b = 2;
+                    let add = |c: i32| a + b + c;
+                    let d = add(3);
+                    let mut inc = || { a = a + 1; };
+                    inc();
+                    d + a
+                };
+
+                f()
+            }
+        ",
+            8,
+        )
+    }
+
+    #[test]
+    fn mips_fn_ptr() {
+        mips_test_fn(
+            "
+            {
+                fn add_one(x: i32) -> i32 {
+                    x + 1
+                };
+                fn apply(f: fn(i32) -> i32, x: i32) -> i32 {
+                    f(x)
+                };
+
+                apply(add_one, 2) + apply(|x: i32| x - 1, 3)
+            }
+        ",
+            5,
+        )
+    }
 }
@@ -0,0 +1,227 @@
+// runtime library for `Cell<T>` and `RefCell<T>`
+//
+// a cell is the address of a block of two words, allocated in the vector
+// region like the vectors
+//
+// 0[cell]   borrow flag, the number of live `Ref<T>` or -1 while a `RefMut<T>`
+//           is live, unused by a `Cell<T>`
+// 4[cell]   the value
+//
+// the value is at the same offset as in a heap block, so the guards returned
+// by `borrow` and `borrow_mut`, which are the address of the cell, are
+// dereferenced and assigned through like a `Box<T>`. a guard is released at
+// the end of the scope of the variable that holds it, or once it is read or
+// written through if it is never stored in a variable. a conflicting borrow
+// panics.
+use super::{heap::moved, panic::panic, pop, push, vec::VEC_BASE, Env};
+use crate::ast::{Block, Expr, UnaryOp};
+use crate::intrinsics::CellIntrinsic;
+
+use mips::{asm::*, instrs::Instrs, rf::Reg::*};
+
+const NEW: &str = "rt_cell_new";
+const BORROW: &str = "rt_cell_borrow";
+const BORROW_MUT: &str = "rt_cell_borrow_mut";
+const RELEASE: &str = "rt_cell_release";
+
+// the runtime routines, these only use t0-t2 and return through ra. the panic
+// messages are stored in the data section so this is generated before it is
+// emitted
+pub(super) fn runtime(env: &mut Env) -> Instrs {
+    let mut asm = new();
+    asm.append(&mut borrow(env));
+    asm.append(&mut borrow_mut(env));
+    asm.append(&mut release());
+    asm
+}
+
+// t0 = value, returns a new cell in t0
+fn new() -> Instrs {
+    Instrs(vec![
+        lui(t1, VEC_BASE),
+        lw(t2, 0, t1),
+        sw(zero, 0, t2).comment("borrow flag"),
+        sw(t0, 4, t2).comment("value"),
+        addiu(t0, t2, 8),
+        sw(t0, 0, t1).comment("bump pointer"),
+        mov(t0, t2),
+        jr(ra),
+    ])
+    .label(NEW)
+    .comment("rt_cell_new(t0 value) -> t0")
+}
+
+// t0 = cell, panics if the cell is mutably borrowed
+fn borrow(env: &mut Env) -> Instrs {
+    let mut panic = panic("already mutably borrowed: BorrowError", env);
+    let mut asm = Instrs(vec![
+        lw(t1, 0, t0).comment("borrow flag"),
+        slt(t2, t1, zero),
+        beq(t2, zero, panic.len() as i16).comment("not mutably borrowed"),
+    ]);
+    asm.append(&mut panic);
+    asm.push(addiu(t1, t1, 1));
+    asm.push(sw(t1, 0, t0).comment("borrow flag"));
+    asm.push(jr(ra));
+    asm.label(BORROW).comment("rt_cell_borrow(t0 cell)")
+}
+
+// t0 = cell, panics if the cell is borrowed
+fn borrow_mut(env: &mut Env) -> Instrs {
+    let mut panic = panic("already borrowed: BorrowMutError", env);
+    let mut asm = Instrs(vec![
+        lw(t1, 0, t0).comment("borrow flag"),
+        beq(t1, zero, panic.len() as i16).comment("not borrowed"),
+    ]);
+    asm.append(&mut panic);
+    asm.push(addiu(t1, zero, -1));
+    asm.push(sw(t1, 0, t0).comment("borrow flag"));
+    asm.push(jr(ra));
+    asm.label(BORROW_MUT).comment("rt_cell_borrow_mut(t0 cell)")
+}
+
+// t0 = cell, t1 = 1 if the guard is a `RefMut<T>`
+fn release() -> Instrs {
+    Instrs(vec![
+        bne(t1, zero, 3).comment("RefMut"),
+        lw(t1, 0, t0),
+        addiu(t1, t1, -1),
+        b(1),
+        mov(t1, zero),
+        sw(t1, 0, t0).comment("borrow flag"),
+        jr(ra),
+    ])
+    .label(RELEASE)
+    .comment("rt_cell_release(t0 cell, t1 mutable)")
+}
+
+// the cell is the referenced value, which is its address. a cell that is
+// shared by an `Rc` or owned by a `Box` is the value of the heap block
+fn receiver(e: &Expr, env: &mut Env, fns: &mut Instrs) -> Instrs {
+    let e = match e {
+        Expr::UnOp(UnaryOp::Borrow | UnaryOp::BorrowMut, e) => &**e,
+        e => e,
+    };
+    let mut asm = e.codegen(env, fns);
+    if matches!(e, Expr::Ident(id) if env.is_owner(id)) {
+        asm.append(&mut pop(t0));
+        asm.push(lw(t0, 4, t0).comment("load heap value"));
+        asm.append(&mut push(t0));
+    }
+    asm
+}
+
+// Some(mutable) if the expression returns a new guard, a `Ref<T>` or a
+// `RefMut<T>` if mutable
+pub(super) fn guard(e: &Expr) -> Option<bool> {
+    match e {
+        Expr::Par(e) => guard(e),
+        Expr::FuncCall(call) => match &*call.id {
+            Expr::Ident(id) => match CellIntrinsic::from_id(id) {
+                Some(CellIntrinsic::Borrow) => Some(false),
+                Some(CellIntrinsic::BorrowMut) => Some(true),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// t0 = guard that is not stored in a variable, pushes the value and releases
+// the guard
+pub(super) fn read_temporary(mutable: bool) -> Instrs {
+    let mut asm = Instrs(vec![lw(t1, 4, t0).comment("load cell value")]);
+    asm.append(&mut push(t1));
+    asm.push(ori(t1, zero, mutable as u16));
+    asm.push(bal_label(RELEASE));
+    asm.comment("release temporary guard")
+}
+
+// t0 = value, t1 = `RefMut<T>` that is not stored in a variable, stores the
+// value and releases the guard
+pub(super) fn write_temporary() -> Instrs {
+    Instrs(vec![
+        sw(t0, 4, t1).comment("store cell value"),
+        mov(t0, t1),
+        ori(t1, zero, 1),
+        bal_label(RELEASE),
+    ])
+    .comment("release temporary guard")
+}
+
+impl CellIntrinsic {
+    pub(super) fn codegen(&self, args: &[Expr], env: &mut Env, fns: &mut Instrs) -> Instrs {
+        env.cell_runtime = true;
+        let mut asm = Instrs::new();
+        match (self, args) {
+            (Self::CellNew | Self::RefCellNew, [value]) => {
+                asm.append(&mut value.codegen(env, fns));
+                asm.append(&mut pop(t0));
+                asm.push(bal_label(NEW));
+                asm.append(&mut push(t0));
+            }
+            (Self::Get, [cell]) => {
+                asm.append(&mut receiver(cell, env, fns));
+                asm.append(&mut pop(t0));
+                asm.push(lw(t0, 4, t0).comment("load cell value"));
+                asm.append(&mut push(t0));
+            }
+            (Self::Set, [cell, value]) => {
+                asm.append(&mut receiver(cell, env, fns));
+                asm.append(&mut value.codegen(env, fns));
+                asm.append(&mut pop(t1));
+                asm.append(&mut pop(t0));
+                asm.push(sw(t1, 4, t0).comment("store cell value"));
+                asm.append(&mut push(zero).comment("() return value"));
+            }
+            (Self::Borrow | Self::BorrowMut, [cell]) => {
+                asm.append(&mut receiver(cell, env, fns));
+                asm.append(&mut pop(t0));
+                match self {
+                    Self::Borrow => asm.push(bal_label(BORROW)),
+                    _ => asm.push(bal_label(BORROW_MUT)),
+                }
+                asm.append(&mut push(t0).comment("guard"));
+            }
+            (_, args) => unreachable!("ICE, invalid arguments to {}: {:?}", self.id(), args),
+        }
+        asm.comment(self.id())
+    }
+}
+
+impl Env {
+    // records wether or not the variable `id` in the current scope holds a
+    // guard, and if it is a `RefMut<T>`
+    pub(super) fn set_guard(&mut self, id: &str, guard: Option<bool>) {
+        if let Some(guards) = self.guards.front_mut() {
+            guards.retain(|(el, _)| el != id);
+            if let Some(mutable) = guard {
+                guards.push((id.to_owned(), mutable));
+            }
+        }
+    }
+
+    // true if the variable `id` holds a guard
+    pub(super) fn is_guard(&self, id: &str) -> bool {
+        self.guards.iter().flatten().any(|(el, _)| el == id)
+    }
+
+    // releases the guards held by the current scope, unless they have been
+    // moved out of
+    pub(super) fn release_guards(&mut self, block: &Block) -> Instrs {
+        let mut asm = Instrs::new();
+        let guards = self.guards.front().cloned().unwrap_or_default();
+        for (id, mutable) in guards.iter() {
+            if !moved(id, &block.statements) {
+                let release = Instrs(vec![
+                    lw(t0, self.get_var_offset(id), fp),
+                    ori(t1, zero, *mutable as u16),
+                    bal_label(RELEASE),
+                ]);
+                asm.append(&mut release.comment(&format!("release '{}'", id)));
+            }
+        }
+        asm
+    }
+}
@@ -0,0 +1,211 @@
+// heap allocation for `Box<T>` and `Rc<T>`
+//
+// the heap starts at HEAP_BASE, the first two words hold the bump pointer and
+// the head of the free list. every allocation is a block of three words
+//
+// 0[ptr]    reference count, or the next free block once freed
+// 4[ptr]    the value
+// 8[ptr]    1 if the value is itself a pointer that is owned by the block
+//
+// since all values are a single word every block has the same size, freed
+// blocks are put on the free list and reused before the heap is grown.
+use super::{pop, push, Env};
+use crate::ast::{Block, Expr, Statement, UnaryOp};
+use crate::intrinsics::HeapIntrinsic;
+
+use mips::{asm::*, instrs::Instrs, rf::Reg::*};
+
+const HEAP_BASE: u16 = 0x1004;
+const BLOCK_SIZE: i16 = 12;
+
+pub(super) const ALLOC: &str = "rt_alloc";
+const DROP: &str = "rt_drop";
+
+// sets up the bump pointer and an empty free list
+pub(super) fn heap_init() -> Instrs {
+    Instrs(vec![
+        lui(t1, HEAP_BASE),
+        addiu(t0, t1, 8),
+        sw(t0, 0, t1),
+        sw(zero, 4, t1),
+    ])
+    .comment("heap init")
+}
+
+// the runtime routines, these only use t0-t2 and return through ra
+pub(super) fn runtime() -> Instrs {
+    let mut asm = alloc();
+    asm.append(&mut drop());
+    asm
+}
+
+// t0 = value, t1 = 1 if the value is an owned pointer, returns the block in t0
+fn alloc() -> Instrs {
+    let mut asm = push(t1);
+    asm.append(&mut push(t0));
+    asm.push(lui(t1, HEAP_BASE));
+    asm.push(lw(t2, 4, t1).comment("free list head"));
+    asm.push(beq(t2, zero, 3).comment("free list empty"));
+    asm.push(lw(t0, 0, t2));
+    asm.push(sw(t0, 4, t1).comment("unlink free block"));
+    asm.push(b(3));
+    asm.push(lw(t2, 0, t1).comment("bump pointer"));
+    asm.push(addiu(t0, t2, BLOCK_SIZE));
+    asm.push(sw(t0, 0, t1));
+    asm.push(ori(t0, zero, 1));
+    asm.push(sw(t0, 0, t2).comment("reference count"));
+    asm.append(&mut pop(t0));
+    asm.push(sw(t0, 4, t2).comment("value"));
+    asm.append(&mut pop(t1));
+    asm.push(sw(t1, 8, t2).comment("owns value"));
+    asm.push(mov(t0, t2));
+    asm.push(jr(ra));
+    asm.label(ALLOC)
+        .comment("rt_alloc(t0 value, t1 owned) -> t0")
+}
+
+// t0 = block, decrements the reference count and frees the block, and any
+// owned blocks it points to, once the count reaches zero
+fn drop() -> Instrs {
+    let asm = Instrs(vec![
+        lw(t1, 0, t0),
+        addiu(t1, t1, -1),
+        sw(t1, 0, t0).comment("decrement reference count"),
+        bne(t1, zero, 7).comment("still referenced"),
+        lui(t1, HEAP_BASE),
+        lw(t2, 4, t1),
+        sw(t2, 0, t0),
+        sw(t0, 4, t1).comment("push block on free list"),
+        lw(t1, 8, t0),
+        lw(t0, 4, t0).comment("drop owned value"),
+        bne(t1, zero, -11),
+        jr(ra),
+    ]);
+    asm.label(DROP).comment("rt_drop(t0 block)")
+}
+
+// drops the owned pointer stored at offset[fp]
+pub(super) fn drop_var(offset: i16, id: &str) -> Instrs {
+    Instrs(vec![lw(t0, offset, fp), bal_label(DROP)]).comment(&format!("drop '{}'", id))
+}
+
+impl HeapIntrinsic {
+    pub(super) fn codegen(&self, args: &[Expr], env: &mut Env, fns: &mut Instrs) -> Instrs {
+        env.runtime = true;
+        match (self, args) {
+            (Self::BoxNew | Self::RcNew, [arg]) => {
+                let owned = env.is_heap_expr(arg);
+                let mut asm = arg.codegen(env, fns);
+                asm.append(&mut pop(t0));
+                asm.push(ori(t1, zero, owned as u16));
+                asm.push(bal_label(ALLOC));
+                asm.append(&mut push(t0));
+                asm.comment(&format!("{}({})", self.id(), arg))
+            }
+            // the argument is a reference, we simply use the pointer itself
+            (Self::RcClone, [Expr::UnOp(UnaryOp::Borrow, arg)]) => {
+                let mut asm = arg.codegen(env, fns);
+                asm.append(&mut pop(t0));
+                asm.push(lw(t1, 0, t0));
+                asm.push(addiu(t1, t1, 1));
+                asm.push(sw(t1, 0, t0).comment("increment reference count"));
+                asm.append(&mut push(t0));
+                asm.comment(&format!("Rc::clone(&{})", arg))
+            }
+            (_, args) => unreachable!("ICE, invalid arguments to {}: {:?}", self.id(), args),
+        }
+    }
+}
+
+impl Env {
+    // true if the expression evaluates to a pointer that is owned by whoever
+    // receives it
+    pub(super) fn is_heap_expr(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Ident(id) => self.is_owner(id),
+            Expr::Par(e) => self.is_heap_expr(e),
+            Expr::FuncCall(call) => match &*call.id {
+                Expr::Ident(id) => {
+                    HeapIntrinsic::from_id(id).is_some()
+                        || self
+                            .get_fn(id)
+                            .is_some_and(|label| self.heap_fns.contains(&label))
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    pub(super) fn is_owner(&self, id: &str) -> bool {
+        for (owners, (_, scope)) in self.owners.iter().zip(self.scope.iter()) {
+            if scope.contains_key(id) {
+                return owners.iter().any(|el| el == id);
+            }
+        }
+        false
+    }
+
+    // records wether or not the variable `id` in the current scope owns a pointer
+    pub(super) fn set_owner(&mut self, id: &str, owner: bool) {
+        if let Some(owners) = self.owners.front_mut() {
+            owners.retain(|el| el != id);
+            if owner {
+                owners.push(id.to_owned());
+            }
+        }
+    }
+
+    // drops the pointers owned by the current scope, unless they have been
+    // moved out of
+    pub(super) fn drop_owned(&mut self, block: &Block) -> Instrs {
+        let mut asm = Instrs::new();
+        let owners = self.owners.front().cloned().unwrap_or_default();
+        for id in owners.iter() {
+            if !moved(id, &block.statements) {
+                self.runtime = true;
+                asm.append(&mut drop_var(self.get_var_offset(id), id));
+            }
+        }
+        asm
+    }
+}
+
+// true if the value of `id` is moved anywhere in the statements, this is
+// conservative, a value that might be moved is never dropped
+pub(super) fn moved(id: &str, statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Let(_, _, _, Some(e)) | Statement::Assign(_, e) | Statement::Expr(e) => {
+            moved_expr(id, e)
+        }
+        Statement::Let(_, _, _, None) | Statement::FnDecleration(_) => false,
+        Statement::While(cond, block) => moved_expr(id, cond) || moved(id, &block.statements),
+        Statement::Block(block) => moved(id, &block.statements),
+    })
+}
+
+fn moved_expr(id: &str, e: &Expr) -> bool {
+    match e {
+        Expr::Ident(i) => i == id,
+        Expr::Lit(_) => false,
+        // dereferencing and borrowing does not move the pointer
+        Expr::UnOp(UnaryOp::Dereff | UnaryOp::Borrow | UnaryOp::BorrowMut, e) => {
+            !matches!(**e, Expr::Ident(_)) && moved_expr(id, e)
+        }
+        Expr::UnOp(_, e) | Expr::Par(e) | Expr::Try(e) | Expr::Cast(e, _) => moved_expr(id, e),
+        Expr::BinOp(_, lhs, rhs) | Expr::Index(lhs, rhs) | Expr::IndexMut(lhs, rhs) => {
+            moved_expr(id, lhs) || moved_expr(id, rhs)
+        }
+        Expr::IfThenElse(cond, then_block, else_block) => {
+            moved_expr(id, cond)
+                || moved(id, &then_block.statements)
+                || else_block
+                    .as_ref()
+                    .is_some_and(|block| moved(id, &block.statements))
+        }
+        Expr::Array(elements) => elements.iter().any(|el| moved_expr(id, el)),
+        Expr::FuncCall(call) => call.args.iter().any(|arg| moved_expr(id, arg)),
+        Expr::Block(block) | Expr::Unsafe(block) => moved(id, &block.statements),
+        Expr::Closure(closure) => moved_expr(id, &closure.body),
+    }
+}
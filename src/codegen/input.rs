@@ -0,0 +1,156 @@
+// program input through the SPIM syscalls
+//
+// `read_i32()` is read_int (5). `read_line()` is read_string (8) into a new
+// buffer of LINE_LEN bytes in the vector region, a string is the address of
+// its zero terminated bytes. like the vm the newline is cut off.
+//
+// `args()` builds a vector of the arguments passed to SPIM after the program,
+// SPIM starts the program with argc in a0 and argv in a1. these are stored in
+// the data section before main is entered, argv[0] is the program itself.
+use super::{
+    push,
+    vec::{NEW, PUSH, VEC_BASE},
+    Env, Target, DATA_BASE,
+};
+use crate::intrinsics::InputIntrinsic;
+
+use mips::{asm::*, instrs::Instrs, rf::Reg::*};
+
+const READ_INT: u16 = 5;
+const READ_STRING: u16 = 8;
+
+const LINE_LEN: i16 = 256;
+
+const ARGS: &str = "#args";
+
+// offset of argc followed by argv in the data section
+fn args_offset(env: &mut Env) -> i16 {
+    if let Some(Target::Data(offset)) = env.get_var(ARGS) {
+        return offset;
+    }
+    let offset = env.insert_data(ARGS, 2);
+    let data = Instrs(vec![
+        lui(t1, DATA_BASE),
+        sw(a0, offset, t1).comment("argc"),
+        sw(a1, offset + 4, t1).comment("argv"),
+    ]);
+    env.data
+        .append(&mut data.comment(&format!("args at data offset {}", offset)));
+    offset
+}
+
+// tests byte k of the word in t3 at t2, rest is the number of instructions
+// after the test until the end of the loop. the string ends at a zero byte and
+// the newline is replaced by one
+fn byte(k: u16, rest: i16) -> Instrs {
+    let (mut asm, newline) = match k {
+        0 | 1 => (
+            Instrs(vec![andi(t4, t3, 0xff << (8 * k))]),
+            ori(t5, zero, 0x0a << (8 * k)),
+        ),
+        k => (
+            Instrs(vec![lui(t5, 0xff << (8 * (k - 2))), and(t4, t3, t5)]),
+            lui(t5, 0x0a << (8 * (k - 2))),
+        ),
+    };
+    // keeps the bytes before the newline
+    let mut cut = match k {
+        0 => Instrs(vec![mov(t3, zero)]),
+        1 => Instrs(vec![andi(t3, t3, 0xff)]),
+        2 => Instrs(vec![andi(t3, t3, 0xffff)]),
+        _ => Instrs(vec![lui(t5, 0xff), ori(t5, t5, 0xffff), and(t3, t3, t5)]),
+    };
+    let len = cut.len() as i16;
+    asm.push(beq(t4, zero, len + 4 + rest).comment("end of the line"));
+    asm.push(newline);
+    asm.push(bne(t4, t5, len + 2).comment("newline"));
+    asm.append(&mut cut);
+    asm.push(sw(t3, 0, t2));
+    asm.push(b(rest));
+    asm
+}
+
+// cuts the line at a0 off at its newline. SPIM keeps the newline before the
+// terminating zero, the words of the line are read in turn and tested a byte
+// at a time, SPIM is little endian. uses t2-t5
+pub(super) fn trim_newline() -> Instrs {
+    let mut bytes = vec![];
+    // the increment and the branch back follow the last byte
+    let mut rest = 2;
+    for k in (0..4).rev() {
+        let byte = byte(k, rest);
+        rest += byte.len() as i16;
+        bytes.push(byte);
+    }
+    let mut body = Instrs(vec![lw(t3, 0, t2)]);
+    for mut byte in bytes.into_iter().rev() {
+        body.append(&mut byte);
+    }
+    body.push(addiu(t2, t2, 4));
+    let len = body.len() as i16 + 1;
+    body.push(b(-len));
+
+    let mut asm = Instrs(vec![mov(t2, a0)]);
+    asm.append(&mut body);
+    asm.comment("trim the newline")
+}
+
+impl InputIntrinsic {
+    pub(super) fn codegen(&self, env: &mut Env) -> Instrs {
+        let mut asm = Instrs::new();
+        match self {
+            Self::ReadI32 => {
+                asm.push(ori(v0, zero, READ_INT));
+                asm.push(syscall());
+                asm.append(&mut push(v0));
+            }
+            Self::ReadLine => {
+                env.vec_runtime = true;
+                asm.push(lui(t1, VEC_BASE));
+                asm.push(lw(a0, 0, t1).comment("buffer"));
+                asm.push(addiu(t2, a0, LINE_LEN));
+                asm.push(sw(t2, 0, t1).comment("bump pointer"));
+                asm.push(ori(a1, zero, LINE_LEN as u16));
+                asm.push(ori(v0, zero, READ_STRING));
+                asm.push(syscall());
+                asm.append(&mut trim_newline());
+                asm.append(&mut push(a0));
+            }
+            Self::Args => {
+                env.vec_runtime = true;
+                let offset = args_offset(env);
+                // 8[sp] = vector, 4[sp] = arguments left, 0[sp] = next argument
+                asm.push(bal_label(NEW));
+                asm.append(&mut push(t0));
+                asm.push(lui(t1, DATA_BASE));
+                asm.push(lw(t2, offset, t1));
+                asm.push(addiu(t2, t2, -1));
+                asm.append(&mut push(t2));
+                asm.push(lw(t2, offset + 4, t1));
+                asm.push(addiu(t2, t2, 4));
+                asm.append(&mut push(t2));
+
+                let mut body = Instrs(vec![
+                    addiu(t2, t2, -1),
+                    sw(t2, 4, sp),
+                    lw(t2, 0, sp),
+                    lw(t1, 0, t2).comment("argument"),
+                    addiu(t2, t2, 4),
+                    sw(t2, 0, sp),
+                    lw(t0, 8, sp).comment("vector"),
+                    bal_label(PUSH),
+                ]);
+                let mut test = Instrs(vec![
+                    lw(t2, 4, sp),
+                    blez(t2, body.len() as i16 + 1).comment("all arguments pushed"),
+                ]);
+                let len = (test.len() + body.len()) as i16;
+                asm.append(&mut test);
+                asm.append(&mut body);
+                asm.push(b(-len - 1));
+                asm.push(addiu(sp, sp, 8).comment("the vector is left on the stack"));
+            }
+        }
+        asm.comment(self.id())
+    }
+}
@@ -0,0 +1,89 @@
+// `Option<T>` and `Result<T, E>`
+//
+// `None` is the null pointer, every other value is a heap block, see `heap.rs`,
+// holding the contained value. an `Err` is tagged by setting the lowest bit of
+// the pointer, like a closure record, so that `Ok` and `Err` share a layout.
+//
+// the blocks are never freed, unwrapping a value leaks its block along with any
+// pointer it owns. `unwrap` and `expect` on a `None` or an `Err` halt the program.
+use super::{heap::ALLOC, pop, push, vec::receiver, Env};
+use crate::ast::Expr;
+use crate::intrinsics::OptionIntrinsic;
+
+use mips::{asm::*, instrs::Instrs, rf::Reg::*};
+
+// t0 = value, branches to `invalid` instructions ahead for a `None` or an `Err`
+fn check_valid(invalid: i16) -> Instrs {
+    Instrs(vec![
+        beq(t0, zero, 2 + invalid).comment("None"),
+        andi(t1, t0, 1),
+        bne(t1, zero, invalid).comment("Err"),
+    ])
+}
+
+// leaves the contained value of `e` on the stack, or returns the `None` or the
+// `Err` from the enclosing function
+pub(super) fn try_expr(e: &Expr, env: &mut Env, fns: &mut Instrs) -> Instrs {
+    let exit = match env.exits.last() {
+        Some(exit) => exit.clone(),
+        None => unreachable!("ICE, `?` outside of a function"),
+    };
+    let mut ret = push(t0);
+    ret.push(b_label(&exit).comment("return early"));
+
+    let mut asm = e.codegen(env, fns);
+    asm.append(&mut pop(t0));
+    asm.append(&mut check_valid(1));
+    asm.push(b(ret.len() as i16));
+    asm.append(&mut ret);
+    asm.push(lw(t0, 4, t0).comment("contained value"));
+    asm.append(&mut push(t0));
+    asm.comment(&format!("{}?", e))
+}
+
+impl OptionIntrinsic {
+    pub(super) fn codegen(&self, args: &[Expr], env: &mut Env, fns: &mut Instrs) -> Instrs {
+        let mut asm = Instrs::new();
+        match (self, args) {
+            (Self::None, _) => asm.append(&mut push(zero)),
+            (Self::Some | Self::Ok | Self::Err, [value]) => {
+                env.runtime = true;
+                asm.append(&mut value.codegen(env, fns));
+                asm.append(&mut pop(t0));
+                asm.push(ori(t1, zero, 0).comment("the value is never dropped"));
+                asm.push(bal_label(ALLOC));
+                if *self == Self::Err {
+                    asm.push(ori(t0, t0, 1).comment("tag Err"));
+                }
+                asm.append(&mut push(t0));
+            }
+            // the message can not be printed, so it is not evaluated
+            (Self::Unwrap | Self::Expect, [value, ..]) => {
+                asm.append(&mut value.codegen(env, fns));
+                asm.append(&mut pop(t0));
+                asm.append(&mut check_valid(1));
+                asm.push(b(1));
+                asm.push(halt().comment(&format!("{} failed", self.id())));
+                asm.push(lw(t0, 4, t0).comment("contained value"));
+                asm.append(&mut push(t0));
+            }
+            (Self::IsSome | Self::IsNone | Self::IsOk | Self::IsErr, [value]) => {
+                asm.append(&mut receiver(value, env, fns));
+                asm.append(&mut pop(t0));
+                match self {
+                    Self::IsSome | Self::IsNone => {
+                        asm.push(beq(t0, zero, 1));
+                        asm.push(ori(t0, zero, 1).comment("Some"));
+                    }
+                    _ => asm.push(andi(t0, t0, 1).comment("1 if Err")),
+                }
+                if matches!(self, Self::IsNone | Self::IsOk) {
+                    asm.push(xori(t0, t0, 1));
+                }
+                asm.append(&mut push(t0));
+            }
+            (_, args) => unreachable!("ICE, invalid arguments to {}: {:?}", self.id(), args),
+        }
+        asm.comment(self.id())
+    }
+}
@@ -0,0 +1,96 @@
+// runtime panic handler
+//
+// the message of a panic is stored in the data section, one character per
+// word. `rt_panic` copies it to the panic output starting at PANIC_BASE, the
+// length followed by the characters, and halts with PANIC_CODE in t0.
+//
+// the message is known at compile time, the arguments of a format string are
+// not formatted, the format string is output as is.
+use super::{pop, push, Env, DATA_BASE};
+use crate::ast::{Expr, Literal};
+use crate::intrinsics::{unquote, PanicIntrinsic};
+
+use mips::{asm::*, instrs::Instrs, rf::Reg::*};
+
+const PANIC_BASE: u16 = 0x100c;
+pub(super) const PANIC_CODE: u16 = 0xdead;
+
+const PANIC: &str = "rt_panic";
+
+// t0 = message, t1 = length, never returns
+pub(super) fn runtime() -> Instrs {
+    Instrs(vec![
+        lui(t2, PANIC_BASE),
+        sw(t1, 0, t2).comment("message length"),
+        beq(t1, zero, 6).comment("message copied"),
+        lw(t3, 0, t0),
+        addiu(t2, t2, 4),
+        sw(t3, 0, t2).comment("output character"),
+        addiu(t0, t0, 4),
+        addiu(t1, t1, -1),
+        b(-7),
+        ori(t0, zero, PANIC_CODE),
+        halt(),
+    ])
+    .label(PANIC)
+    .comment("rt_panic(t0 message, t1 length)")
+}
+
+// stores the message in the data section and calls the panic handler
+pub(super) fn panic(msg: &str, env: &mut Env) -> Instrs {
+    env.panic_runtime = true;
+    let chars: Vec<char> = msg.chars().collect();
+    let offset = env.insert_data(&format!("#panic_{}", env.data_offset), chars.len() as i16);
+    let mut data = Instrs::new();
+    data.push(lui(t1, DATA_BASE));
+    for (idx, c) in chars.iter().enumerate() {
+        data.push(ori(t0, zero, *c as u16));
+        data.push(sw(t0, offset + 4 * idx as i16, t1));
+    }
+    env.data
+        .append(&mut data.comment(&format!("panic message at data offset {}", offset)));
+
+    Instrs(vec![
+        lui(t0, DATA_BASE),
+        addiu(t0, t0, offset),
+        ori(t1, zero, chars.len() as u16),
+        bal_label(PANIC),
+    ])
+    .comment(&format!("panic '{}'", msg))
+}
+
+impl PanicIntrinsic {
+    pub(super) fn codegen(&self, args: &[Expr], env: &mut Env, fns: &mut Instrs) -> Instrs {
+        let (operands, msg) = args.split_at(self.operands());
+        let msg = match msg.first() {
+            Some(Expr::Lit(Literal::String(fmt))) => Some(unquote(fmt)),
+            Some(fmt) => unreachable!("ICE, invalid format string {}", fmt),
+            None => None,
+        };
+        let mut panic = panic(&self.message(msg), env);
+
+        let mut asm = Instrs::new();
+        for operand in operands {
+            asm.append(&mut operand.codegen(env, fns));
+        }
+        match self {
+            Self::Assert => {
+                asm.append(&mut pop(t0));
+                asm.push(bne(t0, zero, panic.len() as i16).comment("assertion holds"));
+            }
+            Self::AssertEq | Self::AssertNe => {
+                asm.append(&mut pop(t1));
+                asm.append(&mut pop(t0));
+                match self {
+                    Self::AssertEq => asm.push(beq(t0, t1, panic.len() as i16)),
+                    _ => asm.push(bne(t0, t1, panic.len() as i16)),
+                }
+            }
+            _ => {}
+        }
+        asm.append(&mut panic);
+        // the unit value, never reached if the intrinsic diverges
+        asm.append(&mut push(zero));
+        asm.comment(self.id())
+    }
+}
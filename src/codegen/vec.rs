@@ -0,0 +1,231 @@
+// runtime library for `Vec<T>`
+//
+// a vector is the address of a header of three words, allocated in the vector
+// region starting at VEC_BASE, its first word holds the bump pointer
+//
+// 0[vec]    length
+// 4[vec]    capacity
+// 8[vec]    address of the elements
+//
+// the elements are reallocated with twice the capacity when the vector is full.
+// the region is never freed, vectors and the buffers they outgrow are leaked.
+// out of bounds accesses halt the program, popping an empty vector gives `None`.
+use super::{cell, heap::ALLOC, pop, push, Env};
+use crate::ast::{Expr, UnaryOp};
+use crate::intrinsics::VecIntrinsic;
+
+use mips::{asm::*, instrs::Instrs, rf::Reg::*};
+
+pub(super) const VEC_BASE: u16 = 0x1008;
+
+pub(super) const NEW: &str = "rt_vec_new";
+pub(super) const PUSH: &str = "rt_vec_push";
+const POP: &str = "rt_vec_pop";
+const ADDR: &str = "rt_vec_addr";
+
+// sets up the bump pointer
+pub(super) fn vec_init() -> Instrs {
+    Instrs(vec![lui(t1, VEC_BASE), addiu(t0, t1, 4), sw(t0, 0, t1)]).comment("vec init")
+}
+
+// the runtime routines, these only use t0-t2 and return through ra
+pub(super) fn runtime() -> Instrs {
+    let mut asm = new();
+    asm.append(&mut push_value());
+    asm.append(&mut pop_value());
+    asm.append(&mut addr());
+    asm
+}
+
+// returns a new empty vector in t0
+fn new() -> Instrs {
+    Instrs(vec![
+        lui(t1, VEC_BASE),
+        lw(t0, 0, t1),
+        addiu(t2, t0, 12),
+        sw(t2, 0, t1).comment("bump pointer"),
+        sw(zero, 0, t0).comment("length"),
+        sw(zero, 4, t0).comment("capacity"),
+        sw(zero, 8, t0).comment("elements"),
+        jr(ra),
+    ])
+    .label(NEW)
+    .comment("rt_vec_new() -> t0")
+}
+
+// t0 = vector, t1 = value
+fn push_value() -> Instrs {
+    // t0 = new elements, t2 = old elements, 0[sp] = number of elements left
+    let mut copy = Instrs(vec![
+        lw(t1, 0, t2),
+        sw(t1, 0, t0),
+        addiu(t0, t0, 4),
+        addiu(t2, t2, 4),
+        lw(t1, 0, sp),
+        addiu(t1, t1, -1),
+        sw(t1, 0, sp),
+    ]);
+    copy.push(bne(t1, zero, -(copy.len() as i16) - 1));
+
+    // the vector is full, reallocate with capacity 2 * capacity + 4
+    let mut grow = Instrs(vec![
+        addu(t2, t2, t2),
+        addiu(t2, t2, 4),
+        sw(t2, 4, t0).comment("capacity"),
+    ]);
+    grow.append(&mut push(t0));
+    grow.push(addu(t2, t2, t2));
+    grow.push(addu(t2, t2, t2));
+    grow.push(lui(t1, VEC_BASE));
+    grow.push(lw(t0, 0, t1).comment("new elements"));
+    grow.push(addu(t2, t0, t2));
+    grow.push(sw(t2, 0, t1).comment("bump pointer"));
+    grow.push(lw(t1, 0, sp));
+    grow.push(lw(t2, 8, t1).comment("old elements"));
+    grow.push(sw(t0, 8, t1));
+    grow.push(lw(t1, 0, t1).comment("length"));
+    grow.append(&mut push(t1));
+    grow.push(beq(t1, zero, copy.len() as i16).comment("nothing to copy"));
+    grow.append(&mut copy.comment("copy elements"));
+    grow.push(addiu(sp, sp, 4));
+    grow.append(&mut pop(t0));
+
+    let mut asm = push(t1);
+    asm.push(lw(t1, 0, t0).comment("length"));
+    asm.push(lw(t2, 4, t0).comment("capacity"));
+    asm.push(bne(t1, t2, grow.len() as i16));
+    asm.append(&mut grow.comment("grow"));
+    asm.push(lw(t1, 0, t0));
+    asm.push(lw(t2, 8, t0));
+    asm.push(addu(t1, t1, t1));
+    asm.push(addu(t1, t1, t1));
+    asm.push(addu(t2, t2, t1));
+    asm.append(&mut pop(t1));
+    asm.push(sw(t1, 0, t2).comment("store value"));
+    asm.push(lw(t1, 0, t0));
+    asm.push(addiu(t1, t1, 1));
+    asm.push(sw(t1, 0, t0).comment("length"));
+    asm.push(jr(ra));
+    asm.label(PUSH).comment("rt_vec_push(t0 vec, t1 value)")
+}
+
+// t0 = vector, returns the last value in t0 and t1 = 1, or t0 = t1 = 0 if
+// the vector is empty
+fn pop_value() -> Instrs {
+    Instrs(vec![
+        lw(t1, 0, t0),
+        bne(t1, zero, 2),
+        mov(t0, zero),
+        jr(ra).comment("empty"),
+        addiu(t1, t1, -1),
+        sw(t1, 0, t0).comment("length"),
+        lw(t2, 8, t0),
+        addu(t1, t1, t1),
+        addu(t1, t1, t1),
+        addu(t2, t2, t1),
+        lw(t0, 0, t2),
+        ori(t1, zero, 1),
+        jr(ra),
+    ])
+    .label(POP)
+    .comment("rt_vec_pop(t0 vec) -> t0, t1")
+}
+
+// t0 = vector, t1 = index, returns the address of the element in t0
+fn addr() -> Instrs {
+    Instrs(vec![
+        lw(t2, 0, t0),
+        slt(t2, t1, t2),
+        bne(t2, zero, 1),
+        halt().comment("index out of bounds"),
+        slt(t2, t1, zero),
+        beq(t2, zero, 1),
+        halt().comment("negative index"),
+        lw(t0, 8, t0),
+        addu(t1, t1, t1),
+        addu(t1, t1, t1),
+        addu(t0, t0, t1),
+        jr(ra),
+    ])
+    .label(ADDR)
+    .comment("rt_vec_addr(t0 vec, t1 index) -> t0")
+}
+
+// the vector is the referenced value, which is its address. a vector behind a
+// guard is the value of the cell the guard points to
+pub(super) fn receiver(e: &Expr, env: &mut Env, fns: &mut Instrs) -> Instrs {
+    let e = match e {
+        Expr::UnOp(UnaryOp::Borrow | UnaryOp::BorrowMut, e) => &**e,
+        e => e,
+    };
+    let mut asm = e.codegen(env, fns);
+    match (cell::guard(e), e) {
+        (Some(mutable), _) => {
+            asm.append(&mut pop(t0));
+            asm.append(&mut cell::read_temporary(mutable));
+        }
+        (None, Expr::Ident(id)) if env.is_guard(id) => {
+            asm.append(&mut pop(t0));
+            asm.push(lw(t0, 4, t0).comment("load cell value"));
+            asm.append(&mut push(t0));
+        }
+        _ => (),
+    }
+    asm
+}
+
+// leaves the address of `vec[idx]` in t0
+pub(super) fn element(vec: &Expr, idx: &Expr, env: &mut Env, fns: &mut Instrs) -> Instrs {
+    env.vec_runtime = true;
+    let mut asm = vec.codegen(env, fns);
+    asm.append(&mut idx.codegen(env, fns));
+    asm.append(&mut pop(t1));
+    asm.append(&mut pop(t0));
+    asm.push(bal_label(ADDR));
+    asm
+}
+
+impl VecIntrinsic {
+    pub(super) fn codegen(&self, args: &[Expr], env: &mut Env, fns: &mut Instrs) -> Instrs {
+        env.vec_runtime = true;
+        let mut asm = Instrs::new();
+        match (self, args) {
+            (Self::New | Self::From, elements) => {
+                asm.push(bal_label(NEW));
+                asm.append(&mut push(t0));
+                for el in elements {
+                    asm.append(&mut el.codegen(env, fns));
+                    asm.append(&mut pop(t1));
+                    asm.push(lw(t0, 0, sp).comment("vec"));
+                    asm.push(bal_label(PUSH));
+                }
+            }
+            (Self::Push, [vec, value]) => {
+                asm.append(&mut receiver(vec, env, fns));
+                asm.append(&mut value.codegen(env, fns));
+                asm.append(&mut pop(t1));
+                asm.append(&mut pop(t0));
+                asm.push(bal_label(PUSH));
+                asm.append(&mut push(zero).comment("() return value"));
+            }
+            (Self::Pop, [vec]) => {
+                asm.append(&mut receiver(vec, env, fns));
+                asm.append(&mut pop(t0));
+                env.runtime = true;
+                asm.push(bal_label(POP));
+                asm.push(beq(t1, zero, 2).comment("None"));
+                asm.push(ori(t1, zero, 0).comment("the value is never dropped"));
+                asm.push(bal_label(ALLOC).comment("Some"));
+                asm.append(&mut push(t0));
+            }
+            (Self::Len, [vec]) => {
+                asm.append(&mut receiver(vec, env, fns));
+                asm.append(&mut pop(t0));
+                asm.push(lw(t0, 0, t0).comment("length"));
+                asm.append(&mut push(t0));
+            }
+            (_, args) => unreachable!("ICE, invalid arguments to {}: {:?}", self.id(), args),
+        }
+        asm.comment(self.id())
+    }
+}
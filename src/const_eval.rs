@@ -0,0 +1,421 @@
+//! Compile time evaluation of `const` and `static` initializers.
+//!
+//! Initializers are evaluated using the same operator semantics as the [vm](crate::vm) and are
+//! replaced by the resulting literal. Array lengths given as constant expressions, `[i32; N]`,
+//! are replaced by their values.
+pub mod item;
+
+use std::collections::HashMap;
+
+use crate::ast::{BinaryOp, Block, Expr, Literal, Ref, Statement, Type, UnaryOp};
+use crate::vm::{op::Operation, Values};
+
+pub type ConstErr = String;
+
+/// Denotes that a top level item may contain constant expressions.
+pub trait ConstEval {
+    /// Registers the globals that this node declares.
+    fn declare_consts(&self, _env: &mut ConstEnv) {}
+    /// Evaluates all constant expressions in the node.
+    fn const_eval(&mut self, env: &mut ConstEnv) -> Result<(), ConstErr>;
+}
+
+/// A global that can be referenced in constant expressions.
+#[derive(Debug, Clone)]
+struct Global {
+    value: Expr,
+    constant: bool,
+    mutable: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ConstEnv {
+    /// All globals, indexed by their full path.
+    globals: HashMap<String, Global>,
+    /// Globals that have been evaluated.
+    values: HashMap<String, Literal>,
+    /// The globals that are currently being evaluated, used to detect cycles.
+    evaluating: Vec<String>,
+}
+
+impl ConstEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn declare(&mut self, id: &str, value: Expr, constant: bool, mutable: bool) {
+        self.globals.insert(
+            id.to_owned(),
+            Global {
+                value,
+                constant,
+                mutable,
+            },
+        );
+    }
+
+    /// Returns the value of the global `id`, evaluating it if needed.
+    pub fn global(&mut self, id: &str) -> Result<Literal, ConstErr> {
+        if let Some(idx) = self.evaluating.iter().position(|el| el == id) {
+            let mut cycle = self.evaluating[idx..].to_vec();
+            cycle.push(id.to_owned());
+            return Err(format!(
+                "Cycle detected when evaluating `{id}`: {}",
+                cycle.join(" -> ")
+            ));
+        }
+        let global = match self.globals.get(id) {
+            Some(global) => global.clone(),
+            None => return Err(format!("`{id}` is not a constant expression")),
+        };
+        if let Some(user) = self.evaluating.last() {
+            let user_is_const = self.globals.get(user).is_some_and(|el| el.constant);
+            if user_is_const && !global.constant {
+                return Err(format!("Constant `{user}` cannot refer to static `{id}`"));
+            }
+            if global.mutable {
+                return Err(format!(
+                    "Use of mutable static `{id}` is not a constant expression"
+                ));
+            }
+        }
+
+        if let Some(value) = self.values.get(id) {
+            return Ok(value.clone());
+        }
+
+        self.evaluating.push(id.to_owned());
+        let value = self.eval(&global.value);
+        self.evaluating.pop();
+        let value = value.map_err(|e| format!("{e}\n    when evaluating `{id}`"))?;
+        self.values.insert(id.to_owned(), value.clone());
+        Ok(value)
+    }
+
+    /// Evaluates a constant expression.
+    pub fn eval(&mut self, expr: &Expr) -> Result<Literal, ConstErr> {
+        match expr {
+            Expr::Lit(l) => Ok(l.clone()),
+            Expr::Ident(id) => self.global(id),
+            Expr::Par(e) => self.eval(e),
+            Expr::BinOp(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs)?, self.eval(rhs)?);
+                // Overflows and division by zero are errors rather than panics
+                if let (Literal::Int(l), Literal::Int(r)) = (&lhs, &rhs) {
+                    if *op == BinaryOp::Div && *r == 0 {
+                        return Err(format!(
+                            "Cannot compute `{l} {op} {r}`: attempt to divide by zero"
+                        ));
+                    }
+                    let result = match op {
+                        BinaryOp::Add => Some(l.checked_add(*r)),
+                        BinaryOp::Sub => Some(l.checked_sub(*r)),
+                        BinaryOp::Mul => Some(l.checked_mul(*r)),
+                        BinaryOp::Div => Some(l.checked_div(*r)),
+                        _ => None,
+                    };
+                    if let Some(None) = result {
+                        return Err(format!(
+                            "Attempt to compute `{l} {op} {r}` which would overflow"
+                        ));
+                    }
+                }
+                match op.eval(Values::Lit(lhs), Values::Lit(rhs)) {
+                    Ok(value) => Ok(value.lit()),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+            Expr::UnOp(op @ (UnaryOp::Not | UnaryOp::Subtract), e) => {
+                let value = self.eval(e)?;
+                match op.eval(Values::Lit(value)) {
+                    Ok(value) => Ok(value.lit()),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+            Expr::Array(elements) => {
+                let mut values = vec![];
+                for el in elements.iter() {
+                    values.push(Box::new(self.eval(el)?));
+                }
+                Ok(Literal::Array(values))
+            }
+            Expr::Index(array, idx) => match (self.eval(array)?, self.eval(idx)?) {
+                (Literal::Array(values), Literal::Int(idx)) => match values.get(idx as usize) {
+                    Some(value) if idx >= 0 => Ok(*value.clone()),
+                    _ => Err(format!(
+                        "Index {idx} is out of bounds for an array of length {}",
+                        values.len()
+                    )),
+                },
+                (array, idx) => Err(format!("Cannot index in to {array} with {idx}")),
+            },
+            Expr::IfThenElse(cond, then_block, else_block) => match self.eval(cond)? {
+                Literal::Bool(true) => self.eval_block(then_block),
+                Literal::Bool(false) => match else_block {
+                    Some(else_block) => self.eval_block(else_block),
+                    None => Ok(Literal::Unit),
+                },
+                cond => Err(format!("Expected a boolean condition, got {cond}")),
+            },
+            Expr::Block(block) => self.eval_block(block),
+            Expr::Cast(e, ty) => {
+                let value = self.eval(e)?;
+                match Values::Lit(value).cast(ty) {
+                    Ok(value) => Ok(value.lit()),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+            e => Err(format!("`{e}` is not a constant expression")),
+        }
+    }
+
+    /// Only blocks consisting of a single expression are constant.
+    fn eval_block(&mut self, block: &Block) -> Result<Literal, ConstErr> {
+        match (block.statements.as_slice(), block.semi) {
+            ([], _) => Ok(Literal::Unit),
+            ([Statement::Expr(e)], semi) => {
+                let value = self.eval(e)?;
+                Ok(match semi {
+                    true => Literal::Unit,
+                    false => value,
+                })
+            }
+            _ => Err(format!("`{block}` is not a constant expression")),
+        }
+    }
+
+    /// Replaces constant array lengths with their values.
+    pub fn eval_type(&mut self, ty: &mut Type) -> Result<(), ConstErr> {
+        match ty {
+            Type::ArrayConst(inner, len) => {
+                self.eval_type(inner)?;
+                match self.eval(len)? {
+                    Literal::Int(len) if len >= 0 => {
+                        *ty = Type::Array(inner.clone(), len as usize);
+                        Ok(())
+                    }
+                    len => Err(format!("Expected array length to be a usize, got {len}")),
+                }
+            }
+            Type::Array(ty, _)
+            | Type::Ref(Ref(ty, _))
+            | Type::MutRef(Ref(ty, _))
+            | Type::Box(ty)
+            | Type::Rc(ty)
+            | Type::Vec(ty)
+            | Type::Cell(ty)
+            | Type::RefCell(ty)
+            | Type::CellRef(ty)
+            | Type::CellRefMut(ty)
+            | Type::ConstPtr(ty)
+            | Type::MutPtr(ty)
+            | Type::Option(ty) => self.eval_type(ty),
+            Type::Result(ty, err) => {
+                self.eval_type(ty)?;
+                self.eval_type(err)
+            }
+            Type::Closure(args, ret, _) | Type::FnPtr(args, ret) => {
+                for arg in args.iter_mut() {
+                    self.eval_type(arg)?;
+                }
+                self.eval_type(ret)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Evaluates the array lengths of all types in an expression.
+    pub(crate) fn eval_types(&mut self, expr: &mut Expr) -> Result<(), ConstErr> {
+        match expr {
+            Expr::Ident(_) | Expr::Lit(_) => Ok(()),
+            Expr::BinOp(_, lhs, rhs) | Expr::Index(lhs, rhs) | Expr::IndexMut(lhs, rhs) => {
+                self.eval_types(lhs)?;
+                self.eval_types(rhs)
+            }
+            Expr::UnOp(_, e) | Expr::Par(e) | Expr::Try(e) => self.eval_types(e),
+            Expr::Cast(e, ty) => {
+                self.eval_types(e)?;
+                self.eval_type(ty)
+            }
+            Expr::IfThenElse(cond, then_block, else_block) => {
+                self.eval_types(cond)?;
+                self.eval_block_types(then_block)?;
+                match else_block {
+                    Some(else_block) => self.eval_block_types(else_block),
+                    None => Ok(()),
+                }
+            }
+            Expr::Array(elements) => {
+                for el in elements.iter_mut() {
+                    self.eval_types(el)?;
+                }
+                Ok(())
+            }
+            Expr::FuncCall(call) => {
+                self.eval_types(&mut call.id)?;
+                for arg in call.args.iter_mut() {
+                    self.eval_types(arg)?;
+                }
+                Ok(())
+            }
+            Expr::Block(block) | Expr::Unsafe(block) => self.eval_block_types(block),
+            Expr::Closure(closure) => {
+                for ty in closure
+                    .args
+                    .iter_mut()
+                    .filter_map(|arg| arg.ty.as_mut())
+                    .chain(closure.ty.as_mut())
+                {
+                    self.eval_type(ty)?;
+                }
+                self.eval_types(&mut closure.body)
+            }
+        }
+    }
+
+    pub(crate) fn eval_block_types(&mut self, block: &mut Block) -> Result<(), ConstErr> {
+        for statement in block.statements.iter_mut() {
+            match statement {
+                Statement::Let(_, _, ty, rhs) => {
+                    if let Some(ty) = ty {
+                        self.eval_type(ty)?;
+                    }
+                    if let Some(rhs) = rhs {
+                        self.eval_types(rhs)?;
+                    }
+                }
+                Statement::Assign(lhs, rhs) => {
+                    self.eval_types(lhs)?;
+                    self.eval_types(rhs)?;
+                }
+                Statement::While(cond, block) => {
+                    self.eval_types(cond)?;
+                    self.eval_block_types(block)?;
+                }
+                Statement::Expr(e) => self.eval_types(e)?,
+                Statement::Block(block) => self.eval_block_types(block)?,
+                Statement::FnDecleration(f) => self.eval_fn_types(f)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn eval_fn_types(&mut self, f: &mut crate::ast::Func) -> Result<(), ConstErr> {
+        for arg in f.args.iter_mut() {
+            self.eval_type(&mut arg.ty)?;
+        }
+        self.eval_type(&mut f.ty)?;
+        self.eval_block_types(&mut f.body)
+    }
+}
+
+/// Evaluates all `const` and `static` initializers and constant array lengths in the program.
+pub fn evaluate(prog: &mut crate::ast::Prog) -> Result<(), ConstErr> {
+    let mut env = ConstEnv::new();
+    for item in prog.statements.iter() {
+        item.declare_consts(&mut env);
+    }
+    for item in prog.statements.iter_mut() {
+        item.const_eval(&mut env)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+
+    fn resolve(prog: &str) -> Result<Ast<Prog>, String> {
+        Ast::from_source(prog.to_string(), None)
+    }
+
+    #[test]
+    fn test_const_eval() {
+        let prog = resolve(
+            "
+        const B: i32 = A * 2 + 1;
+        const A: i32 = 3;
+        static C: bool = B > A && !false;
+        fn main() {
+            let a: [i32; A] = [1, 2, 3];
+        }
+        ",
+        )
+        .unwrap();
+        let prog = prog.to_string();
+        assert!(prog.contains("B:i32=7;"));
+        assert!(prog.contains("C:bool=true;"));
+        assert!(prog.contains("[i32;3]"));
+    }
+
+    #[test]
+    fn test_const_cycle() {
+        let prog = resolve(
+            "
+        const A: i32 = B;
+        const B: i32 = A + 1;
+        fn main() {}
+        ",
+        );
+        assert!(prog.unwrap_err().contains("Cycle detected"));
+    }
+
+    #[test]
+    fn test_not_const() {
+        let prog = resolve(
+            "
+        fn f() -> i32 { 1 }
+        const A: i32 = f();
+        fn main() {}
+        ",
+        );
+        assert!(prog.unwrap_err().contains("is not a constant expression"));
+        let prog = resolve(
+            "
+        static S: i32 = 1;
+        const A: i32 = S;
+        fn main() {}
+        ",
+        );
+        assert!(prog.unwrap_err().contains("cannot refer to static"));
+    }
+
+    #[test]
+    fn test_const_usize() {
+        let prog = "
+        const N: usize = 2 + 1;
+        static M: usize = N * 2;
+        fn main() -> usize {
+            let a: [i32; N] = [1, 2, 3];
+            let last = a[2];
+            if last == 3 {
+                M
+            } else {
+                0 as usize
+            }
+        }
+        ";
+        let ret = crate::Engine::new(prog).unwrap().call("main", &[]);
+        assert!(
+            matches!(ret, Ok(crate::vm::Values::Lit(crate::ast::Literal::Int(6)))),
+            "{ret:?}"
+        );
+
+        let prog = "
+        const N: usize = 1 - 2;
+        fn main() {}
+        ";
+        assert!(crate::Engine::new(prog).is_err());
+    }
+
+    #[test]
+    fn test_const_arithmetic() {
+        for (init, err) in [
+            ("10 / 0", "attempt to divide by zero"),
+            ("2147483647 + 1", "which would overflow"),
+        ] {
+            let prog = resolve(&format!("const A: i32 = {init}; fn main() {{}}"));
+            assert!(prog.unwrap_err().contains(err), "{init}");
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use super::{ConstEnv, ConstErr, ConstEval};
+use crate::ast::{Expr, Func, Module, Static, Use};
+
+impl ConstEval for Func {
+    fn const_eval(&mut self, env: &mut ConstEnv) -> Result<(), ConstErr> {
+        env.eval_fn_types(self)
+    }
+}
+
+impl ConstEval for Static {
+    fn declare_consts(&self, env: &mut ConstEnv) {
+        env.declare(&self.id, self.value.clone(), self.constant, self.mutable);
+    }
+
+    fn const_eval(&mut self, env: &mut ConstEnv) -> Result<(), ConstErr> {
+        env.eval_type(&mut self.ty)?;
+        self.value = Expr::Lit(env.global(&self.id)?);
+        Ok(())
+    }
+}
+
+impl ConstEval for Module {
+    fn declare_consts(&self, env: &mut ConstEnv) {
+        for item in self.items.iter() {
+            item.declare_consts(env);
+        }
+    }
+
+    fn const_eval(&mut self, env: &mut ConstEnv) -> Result<(), ConstErr> {
+        let mut ret = Ok(());
+        for item in self.items.iter_mut() {
+            ret = item.const_eval(env);
+            if ret.is_err() {
+                break;
+            }
+        }
+        ret.map_err(|e| self.locate(e))
+    }
+}
+
+impl ConstEval for Use {
+    fn const_eval(&mut self, _env: &mut ConstEnv) -> Result<(), ConstErr> {
+        Ok(())
+    }
+}
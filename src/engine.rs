@@ -0,0 +1,232 @@
+//! An embeddable engine, a program is parsed, checked and loaded once after which its functions
+//! can be called by name any number of times.
+//!
+//! ```
+//! use rnr::ast::Literal;
+//! use rnr::vm::Values;
+//! use rnr::Engine;
+//!
+//! let engine = Engine::new("fn add(a: i32, b: i32) -> i32 { a + b }").unwrap();
+//! for i in 0..3 {
+//!     let sum = engine.call("add", &[Literal::Int(i), Literal::Int(2)]).unwrap();
+//!     assert_eq!(sum, Values::Lit(Literal::Int(i + 2)));
+//! }
+//! assert!(engine.call("add", &[Literal::Bool(true), Literal::Int(2)]).is_err());
+//! ```
+use crate::ast::{Expr, FuncCall, Literal, Prog};
+use crate::borrow_checker::{BCError, Env, PreDeclareTop};
+use crate::intrinsics::IntrinsicRegistry;
+use crate::parse::Items;
+use crate::type_check::{TypeCheck, TypeEnv, TypeErr};
+use crate::vm::{Eval, Io, Values, VarEnv, VmErr};
+use crate::{parse_source, Ast, AstNode};
+
+/// The maximum number of statements executed by a call unless set by
+/// [`set_max_iter`](Engine::set_max_iter).
+pub const DEFAULT_MAX_ITER: usize = 10_000;
+
+#[derive(Debug)]
+pub enum EngineErr {
+    /// The program could not be parsed or a name could not be resolved
+    Parse(String),
+    Type(TypeErr),
+    Borrow(BCError),
+    Eval(VmErr),
+}
+
+impl std::fmt::Display for EngineErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineErr::Parse(e) => write!(f, "{e}"),
+            EngineErr::Type(e) => write!(f, "type error: {e}"),
+            EngineErr::Borrow(e) => write!(f, "borrow error: {e}"),
+            EngineErr::Eval(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// A checked and loaded program.
+#[derive(Debug)]
+pub struct Engine {
+    prog: Ast<Prog>,
+    /// The global scope after type checking, calls are checked in it
+    types: TypeEnv,
+    /// The global scope after loading the program, each call gets a copy of it
+    env: VarEnv,
+    max_iter: usize,
+}
+
+impl Engine {
+    /// Parses, checks and loads `source`, the items of a program.
+    pub fn new(source: &str) -> Result<Self, EngineErr> {
+        Self::with_host(source, IntrinsicRegistry::new())
+    }
+
+    /// Parses, checks and loads `source`, the program may call the host functions in `host`.
+    pub fn with_host(source: &str, host: IntrinsicRegistry) -> Result<Self, EngineErr> {
+        // Unlike a program run by `rnr` the source does not have to define `main`
+        let items: Items = parse_source(source, None).map_err(EngineErr::Parse)?;
+        let mut prog = Prog::from(items.0);
+        prog.resolve_modules(None)
+            .map_err(|e| EngineErr::Parse(format!("Error {e} ocurred while resolving names")))?;
+        let mut prog = Ast { t: prog };
+
+        let mut types = host.type_env();
+        prog.check(&mut types, 0).map_err(EngineErr::Type)?;
+        prog.pre_declare_top(&mut 0, &mut 0)
+            .map_err(|e| EngineErr::Borrow(BCError::EnvError(e)))?;
+        prog.linearize(&mut Env::new()).map_err(EngineErr::Borrow)?;
+
+        let mut env = VarEnv::with_host(host);
+        prog.t
+            .load(&mut env, 0, DEFAULT_MAX_ITER, &mut 0)
+            .map_err(EngineErr::Eval)?;
+        Ok(Self {
+            prog,
+            types,
+            env,
+            max_iter: DEFAULT_MAX_ITER,
+        })
+    }
+
+    /// Sets the maximum number of statements executed by a call.
+    pub fn set_max_iter(&mut self, max_iter: usize) {
+        self.max_iter = max_iter;
+    }
+
+    /// Sets the streams and arguments of the program for the following calls, see [`Io`].
+    pub fn set_io(&mut self, io: Io) {
+        self.env.io = io;
+    }
+
+    /// The checked program, identifiers are renamed by the borrow checker.
+    pub fn prog(&self) -> &Ast<Prog> {
+        &self.prog
+    }
+
+    /// Returns a new environment holding the loaded program.
+    pub fn env(&self) -> VarEnv {
+        self.env.clone()
+    }
+
+    /// Calls the function `id` with the arguments `args` in a new environment, no state is
+    /// shared between calls.
+    pub fn call(&self, id: &str, args: &[Literal]) -> Result<Values, EngineErr> {
+        self.call_in(&mut self.env(), id, args)
+    }
+
+    /// Calls the function `id` with the arguments `args` in `env`, an environment returned by
+    /// [`env`](Self::env). Changes to the globals are kept in `env` for the next call.
+    pub fn call_in(
+        &self,
+        env: &mut VarEnv,
+        id: &str,
+        args: &[Literal],
+    ) -> Result<Values, EngineErr> {
+        let call = Expr::FuncCall(FuncCall {
+            id: Box::new(Expr::Ident(id.to_owned())),
+            args: Box::new(args.iter().cloned().map(Expr::Lit).collect()),
+        });
+        // The arguments are checked like a call in the program
+        call.check(&mut self.types.clone(), 0)
+            .map_err(EngineErr::Type)?;
+        let value = call
+            .eval(env, 0, self.max_iter, &mut 0)
+            .map_err(EngineErr::Eval)?;
+        env.heap.resolve(value).map_err(EngineErr::Eval)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::Type;
+
+    #[test]
+    fn test_engine() {
+        let mut host = IntrinsicRegistry::new();
+        host.register("host_max", vec![Type::I32], Type::I32, |_| {
+            Ok(Values::Lit(Literal::Int(10)))
+        })
+        .unwrap();
+        let mut engine = Engine::with_host(
+            "
+            static mut COUNT: i32 = 0;
+            fn bump(by: i32) -> i32 {
+                unsafe {
+                    COUNT = COUNT + by;
+                    COUNT
+                }
+            }
+            fn fact(n: i32) -> i32 {
+                if n < 2 { 1 } else { n * fact(n - 1) }
+            }
+            fn clamped(n: i32) -> Option<i32> {
+                if n < host_max(n) { Some(n) } else { None }
+            }
+            fn greet(n: i32) {
+                println!(\"hello {}\", n);
+            }
+            ",
+            host,
+        )
+        .unwrap();
+        let int = |i| Values::Lit(Literal::Int(i));
+
+        assert_eq!(engine.call("fact", &[Literal::Int(5)]).unwrap(), int(120));
+        assert_eq!(
+            engine.call("clamped", &[Literal::Int(5)]).unwrap(),
+            Values::Option(Some(Box::new(int(5))))
+        );
+        assert_eq!(
+            engine.call("clamped", &[Literal::Int(15)]).unwrap(),
+            Values::Option(None)
+        );
+
+        // Every call gets a new environment unless one is passed to it
+        assert_eq!(engine.call("bump", &[Literal::Int(2)]).unwrap(), int(2));
+        assert_eq!(engine.call("bump", &[Literal::Int(2)]).unwrap(), int(2));
+        let mut env = engine.env();
+        assert_eq!(
+            engine
+                .call_in(&mut env, "bump", &[Literal::Int(2)])
+                .unwrap(),
+            int(2)
+        );
+        assert_eq!(
+            engine
+                .call_in(&mut env, "bump", &[Literal::Int(2)])
+                .unwrap(),
+            int(4)
+        );
+
+        assert!(matches!(
+            engine.call("fact", &[Literal::Bool(true)]),
+            Err(EngineErr::Type(_))
+        ));
+        assert!(matches!(
+            engine.call("missing", &[]),
+            Err(EngineErr::Type(_))
+        ));
+
+        let out = crate::vm::Buffer::new();
+        engine.set_io(Io::default().with_stdout(out.clone()));
+        engine.call("greet", &[Literal::Int(1)]).unwrap();
+        engine.call("greet", &[Literal::Int(2)]).unwrap();
+        assert_eq!(out.contents(), "hello 1\nhello 2\n");
+    }
+
+    #[test]
+    fn test_engine_errors() {
+        assert!(matches!(Engine::new("fn f( {}"), Err(EngineErr::Parse(_))));
+        assert!(matches!(
+            Engine::new("fn f() -> i32 { true }"),
+            Err(EngineErr::Type(_))
+        ));
+
+        let mut engine =
+            Engine::new("fn spin() { let mut i = 0; while true { i = i + 1; } }").unwrap();
+        engine.set_max_iter(10);
+        assert!(matches!(engine.call("spin", &[]), Err(EngineErr::Eval(_))));
+    }
+}
@@ -1,68 +1,30 @@
-use crate::ast::{Arg, Block, Expr, Func, Type};
-use regex::Regex;
-// Implementation of intrinsics for the vm
-use crate::ast::Literal;
-pub type Intrinsic = fn(Vec<crate::vm::Values>) -> crate::vm::Values;
+pub mod cell;
+pub mod format;
+pub mod fs;
+pub mod heap;
+pub mod host;
+pub mod input;
+pub mod option;
+pub mod panic;
+pub mod vec;
 
-pub fn vm_println() -> (Func, Intrinsic) {
-    (
-        Func {
-            id: Expr::Ident("println!".to_string()),
-            args: vec![
-                Arg {
-                    mutable: false,
-                    id: Expr::Ident("str".to_string()),
-                    ty: Type::String,
-                },
-                Arg {
-                    mutable: false,
-                    id: Expr::Ident("i".to_string()),
-                    ty: Type::I32,
-                },
-            ],
-            ty: Type::Unit,
-            body: Block {
-                statements: vec![],
-                semi: false,
-            },
-        },
-        |lit_vec: Vec<crate::vm::Values>| {
-            match &lit_vec[0] {
-                crate::vm::Values::Lit(Literal::String(s)) => {
-                    // this regex will find either '{}' or '{:?}'
-                    let re = Regex::new(r"\{(:\?)?\}").unwrap();
+pub use cell::*;
+pub use format::*;
+pub use fs::*;
+pub use heap::*;
+pub use host::*;
+pub use input::*;
+pub use option::*;
+pub use panic::*;
+pub use vec::*;
 
-                    // we split at these points
-                    let split = re.split(s);
-                    // and collect into vector
-                    let vec: Vec<&str> = split.collect();
-
-                    // first print the leading part
-                    print!("{}", vec[0]);
-                    // then print each matching pair
-                    // the value followed by the trailing part
-                    for (text, lit) in vec[1..].iter().zip(lit_vec[1..].iter()) {
-                        print!("{}{}", lit, text);
-                    }
-
-                    println!();
-                }
-                _ => panic!("ICE - no formatting string in println!"),
-            }
-            crate::vm::Values::Lit(Literal::Unit)
-        },
-    )
-}
-
-#[test]
-fn regex_test() {
-    // this regex will find either '{}' or '{:?}'
-    let re = Regex::new(r"\{(:\?)?\}").unwrap();
-
-    // we split at these points
-    let split = re.split("a {} b {:?} c");
-
-    // and collect into vector
-    let vec: Vec<&str> = split.collect();
-    println!("{:?}", vec);
+/// Returns true if `id` names a built in function rather than an item in the program.
+pub fn is_intrinsic(id: &str) -> bool {
+    id.ends_with('!')
+        || HeapIntrinsic::from_id(id).is_some()
+        || VecIntrinsic::from_id(id).is_some()
+        || OptionIntrinsic::from_id(id).is_some()
+        || InputIntrinsic::from_id(id).is_some()
+        || FsIntrinsic::from_id(id).is_some()
+        || CellIntrinsic::from_id(id).is_some()
 }
@@ -0,0 +1,67 @@
+//! The interior mutability intrinsics, `Cell::new`, `get` and `set` along with `RefCell::new`,
+//! `borrow` and `borrow_mut`.
+//!
+//! All of the methods take the receiver by shared reference, the value in a cell may be changed
+//! through a `&Cell<T>` or a `&RefCell<T>`. A `RefCell` counts its borrows at runtime instead,
+//! `borrow` and `borrow_mut` return a guard, `Ref<T>` or `RefMut<T>`, that releases the borrow
+//! when it is dropped and a borrow that conflicts with a live guard panics.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellIntrinsic {
+    /// `Cell::new(value)`
+    CellNew,
+    /// `Cell::get(&cell)`, a copy of the value
+    Get,
+    /// `Cell::set(&cell, value)`, replaces the value
+    Set,
+    /// `RefCell::new(value)`
+    RefCellNew,
+    /// `RefCell::borrow(&cell)`, a `Ref<T>` that reads the value
+    Borrow,
+    /// `RefCell::borrow_mut(&cell)`, a `RefMut<T>` that may write the value
+    BorrowMut,
+}
+
+impl CellIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "Cell::new" => Some(Self::CellNew),
+            "Cell::get" => Some(Self::Get),
+            "Cell::set" => Some(Self::Set),
+            "RefCell::new" => Some(Self::RefCellNew),
+            "RefCell::borrow" => Some(Self::Borrow),
+            "RefCell::borrow_mut" => Some(Self::BorrowMut),
+            _ => None,
+        }
+    }
+
+    /// Returns the intrinsic called by the method `name`.
+    pub fn from_method(name: &str) -> Option<Self> {
+        match name {
+            "get" => Some(Self::Get),
+            "set" => Some(Self::Set),
+            "borrow" => Some(Self::Borrow),
+            "borrow_mut" => Some(Self::BorrowMut),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::CellNew => "Cell::new",
+            Self::Get => "Cell::get",
+            Self::Set => "Cell::set",
+            Self::RefCellNew => "RefCell::new",
+            Self::Borrow => "RefCell::borrow",
+            Self::BorrowMut => "RefCell::borrow_mut",
+        }
+    }
+
+    /// Returns `Some(false)` for the methods, their receiver is always borrowed immutably.
+    pub fn receiver(&self) -> Option<bool> {
+        match self {
+            Self::CellNew | Self::RefCellNew => None,
+            _ => Some(false),
+        }
+    }
+}
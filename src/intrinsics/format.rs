@@ -0,0 +1,410 @@
+//! Format strings, shared by `print!`, `println!`, `eprint!`, `eprintln!`, `format!` and the
+//! messages of the [panicking intrinsics](super::PanicIntrinsic).
+//!
+//! A format string is parsed in to [`Piece`]s, the text between the arguments and the
+//! [`Spec`] of each argument, `{[arg][:[[fill]align][#][0][width][type]]}`. The argument is
+//! either a position, `{0}`, or the name of a variable, `{name}`, arguments without a position
+//! take the next one. The alignment is one of `<`, `^` and `>`, the type is one of `?`, `x`,
+//! `X` and `b`. `{{` and `}}` are a literal `{` and `}`.
+//!
+//! The parser turns the names in to positions of arguments that read the variables, see
+//! [`inline_named`], `format!("{a}")` is `format!("{0}", a)`.
+
+use crate::ast::Expr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatIntrinsic {
+    /// `print!(fmt, args..)`
+    Print,
+    /// `println!(fmt, args..)`
+    Println,
+    /// `eprint!(fmt, args..)`, prints to stderr
+    Eprint,
+    /// `eprintln!(fmt, args..)`, prints to stderr
+    Eprintln,
+    /// `format!(fmt, args..)`, the formatted `String`
+    Format,
+}
+
+impl FormatIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "print!" => Some(Self::Print),
+            "println!" => Some(Self::Println),
+            "eprint!" => Some(Self::Eprint),
+            "eprintln!" => Some(Self::Eprintln),
+            "format!" => Some(Self::Format),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Print => "print!",
+            Self::Println => "println!",
+            Self::Eprint => "eprint!",
+            Self::Eprintln => "eprintln!",
+            Self::Format => "format!",
+        }
+    }
+
+    /// Returns true if the output ends with a newline, these may be called without a format
+    /// string.
+    pub fn newline(&self) -> bool {
+        matches!(self, Self::Println | Self::Eprintln)
+    }
+}
+
+/// The argument that is formatted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Argument {
+    /// The argument at the index, `{0}`, or the next argument, `{}`
+    Index(usize),
+    /// A variable, `{name}`
+    Named(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// How an argument is formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `{}`
+    Display,
+    /// `{:?}`
+    Debug,
+    /// `{:x}`
+    LowerHex,
+    /// `{:X}`
+    UpperHex,
+    /// `{:b}`
+    Binary,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    pub arg: Argument,
+    pub fill: char,
+    /// The alignment, numbers are aligned to the right and everything else to the left by
+    /// default
+    pub align: Option<Align>,
+    /// The `#` flag, prefixes hexadecimal and binary numbers with `0x` and `0b`
+    pub alternate: bool,
+    /// The `0` flag, pads numbers with zeros after the sign
+    pub zero: bool,
+    pub width: usize,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Piece {
+    Text(String),
+    Arg(Spec),
+}
+
+/// Returns the value of a string literal, string literals keep their quotes and escapes.
+pub fn unquote(lit: &str) -> String {
+    match syn::parse_str::<syn::LitStr>(lit) {
+        Ok(lit) => lit.value(),
+        Err(_) => lit.trim_matches('"').to_owned(),
+    }
+}
+
+/// Returns the string literal holding `value`, the inverse of [`unquote`].
+pub fn quote(value: &str) -> String {
+    format!("{value:?}")
+}
+
+/// Replaces the named arguments of the format string `fmt` with the positions of arguments
+/// following the `given` ones, the variables are added to `named` once per name. Returns the
+/// new format string, invalid format strings are left for [`parse_format`] to report.
+pub fn inline_named(fmt: &str, given: usize, named: &mut Vec<Expr>) -> String {
+    let mut out = String::new();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                out.push(c);
+                out.push(c);
+            }
+            ('{', _) => {
+                let mut inner = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    closed = c == '}';
+                    if closed {
+                        break;
+                    }
+                    inner.push(c);
+                }
+                if !closed {
+                    out.push('{');
+                    out.push_str(&inner);
+                    break;
+                }
+                let (arg, format) = match inner.split_once(':') {
+                    Some((arg, format)) => (arg.trim(), Some(format)),
+                    None => (inner.trim(), None),
+                };
+                let ident = arg.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                    && arg.chars().all(|c| c.is_alphanumeric() || c == '_');
+                let arg = match ident {
+                    true => {
+                        let var = Expr::Ident(arg.to_owned());
+                        let idx = match named.iter().position(|e| *e == var) {
+                            Some(idx) => idx,
+                            None => {
+                                named.push(var);
+                                named.len() - 1
+                            }
+                        };
+                        (given + idx).to_string()
+                    }
+                    false => arg.to_owned(),
+                };
+                match format {
+                    Some(format) => out.push_str(&format!("{{{arg}:{format}}}")),
+                    None => out.push_str(&format!("{{{arg}}}")),
+                }
+            }
+            (c, _) => out.push(c),
+        }
+    }
+    out
+}
+
+/// Parses a format string, `fmt` is the value of the string literal.
+pub fn parse_format(fmt: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut next = 0;
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                text.push(c);
+            }
+            ('}', _) => return Err(format!("Unmatched `}}` in format string \"{fmt}\"")),
+            ('{', _) => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => {
+                            return Err(format!("Unterminated `{{` in format string \"{fmt}\""))
+                        }
+                    }
+                }
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Arg(parse_spec(&inner, &mut next)?));
+            }
+            (c, _) => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// Parses the inside of `{..}`, `next` is the index of the next implicit argument.
+fn parse_spec(spec: &str, next: &mut usize) -> Result<Spec, String> {
+    let (arg, format) = match spec.split_once(':') {
+        Some((arg, format)) => (arg.trim(), format),
+        None => (spec.trim(), ""),
+    };
+    let arg = if arg.is_empty() {
+        *next += 1;
+        Argument::Index(*next - 1)
+    } else if let Ok(idx) = arg.parse() {
+        Argument::Index(idx)
+    } else if arg.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Argument::Named(arg.to_owned())
+    } else {
+        return Err(format!("Invalid argument `{arg}` in format string"));
+    };
+
+    let align = |c: char| match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    };
+    let mut chars: Vec<char> = format.chars().collect();
+    let (fill, align) = match chars.as_slice() {
+        [fill, c, ..] if align(*c).is_some() => {
+            let ret = (*fill, align(*c));
+            chars.drain(..2);
+            ret
+        }
+        [c, ..] if align(*c).is_some() => {
+            let ret = (' ', align(*c));
+            chars.drain(..1);
+            ret
+        }
+        _ => (' ', None),
+    };
+    let mut flag = |flag: char| match chars.first() {
+        Some(c) if *c == flag => {
+            chars.remove(0);
+            true
+        }
+        _ => false,
+    };
+    let alternate = flag('#');
+    let zero = flag('0');
+    let digits: String = chars.iter().take_while(|c| c.is_ascii_digit()).collect();
+    let width = match digits.is_empty() {
+        true => 0,
+        false => digits
+            .parse()
+            .map_err(|_| format!("Invalid width `{digits}` in format string"))?,
+    };
+    let kind: String = chars[digits.len()..].iter().collect();
+    let kind = match kind.as_str() {
+        "" => Kind::Display,
+        "?" => Kind::Debug,
+        "x" => Kind::LowerHex,
+        "X" => Kind::UpperHex,
+        "b" => Kind::Binary,
+        kind => return Err(format!("Unknown format type `{kind}`")),
+    };
+    Ok(Spec {
+        arg,
+        fill,
+        align,
+        alternate,
+        zero,
+        width,
+        kind,
+    })
+}
+
+/// Returns the number of positional arguments used by the format string, all of them have to
+/// be used.
+pub fn positional_args(pieces: &[Piece]) -> Result<usize, String> {
+    let used: Vec<usize> = pieces
+        .iter()
+        .filter_map(|piece| match piece {
+            Piece::Arg(Spec {
+                arg: Argument::Index(idx),
+                ..
+            }) => Some(*idx),
+            _ => None,
+        })
+        .collect();
+    let count = used.iter().map(|idx| idx + 1).max().unwrap_or(0);
+    match (0..count).find(|idx| !used.contains(idx)) {
+        Some(idx) => Err(format!("Argument {idx} is never used in the format string")),
+        None => Ok(count),
+    }
+}
+
+impl Spec {
+    /// Pads the formatted argument to the width of the spec.
+    pub fn pad(&self, s: String, numeric: bool) -> String {
+        let len = s.chars().count();
+        if len >= self.width {
+            return s;
+        }
+        let padding = self.width - len;
+        if self.zero && numeric {
+            // the zeros go between the sign, or the prefix, and the digits
+            let split = match (s.strip_prefix('-'), self.alternate) {
+                (Some(_), _) => 1,
+                (None, true) => 2,
+                (None, false) => 0,
+            };
+            let (sign, digits) = s.split_at(split);
+            return format!("{sign}{}{digits}", "0".repeat(padding));
+        }
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        let align = match (self.align, numeric) {
+            (Some(align), _) => align,
+            (None, true) => Align::Right,
+            (None, false) => Align::Left,
+        };
+        match align {
+            Align::Left => format!("{s}{}", fill(padding)),
+            Align::Right => format!("{}{s}", fill(padding)),
+            Align::Center => format!("{}{s}{}", fill(padding / 2), fill(padding - padding / 2)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        let pieces = parse_format("a {} {{b}} {1:*^7} {name:#06x}").unwrap();
+        assert_eq!(
+            pieces,
+            vec![
+                Piece::Text("a ".to_owned()),
+                Piece::Arg(Spec {
+                    arg: Argument::Index(0),
+                    fill: ' ',
+                    align: None,
+                    alternate: false,
+                    zero: false,
+                    width: 0,
+                    kind: Kind::Display,
+                }),
+                Piece::Text(" {b} ".to_owned()),
+                Piece::Arg(Spec {
+                    arg: Argument::Index(1),
+                    fill: '*',
+                    align: Some(Align::Center),
+                    alternate: false,
+                    zero: false,
+                    width: 7,
+                    kind: Kind::Display,
+                }),
+                Piece::Text(" ".to_owned()),
+                Piece::Arg(Spec {
+                    arg: Argument::Named("name".to_owned()),
+                    fill: ' ',
+                    align: None,
+                    alternate: true,
+                    zero: true,
+                    width: 6,
+                    kind: Kind::LowerHex,
+                }),
+            ]
+        );
+        assert_eq!(positional_args(&pieces), Ok(2));
+
+        assert!(positional_args(&parse_format("{1}").unwrap()).is_err());
+        for fmt in ["{", "}", "{:q}", "{a b}"] {
+            assert!(parse_format(fmt).is_err(), "{fmt}");
+        }
+    }
+
+    #[test]
+    fn test_inline_named() {
+        let mut named = vec![];
+        let fmt = inline_named("{a} {} {b:>3} {{c}} {a:?} {0}", 1, &mut named);
+        assert_eq!(fmt, "{1} {} {2:>3} {{c}} {1:?} {0}");
+        assert_eq!(
+            named,
+            vec![Expr::Ident("a".to_owned()), Expr::Ident("b".to_owned())]
+        );
+        assert_eq!(positional_args(&parse_format(&fmt).unwrap()), Ok(3));
+
+        let mut named = vec![];
+        assert_eq!(inline_named("{a", 0, &mut named), "{a");
+        assert!(named.is_empty());
+    }
+}
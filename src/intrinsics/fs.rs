@@ -0,0 +1,48 @@
+//! Sandboxed file access, `fs::read_to_string(path)` and `fs::write(path, contents)`.
+//!
+//! A program may only access files in the directories allowed by its
+//! [`Io`](crate::vm::Io::with_allowed_dir), `--allow-fs DIR` on the command line. Both return a
+//! `Result` with a `String` error, a denied access is an `Err` that the program can handle like
+//! any other error. Files can only be accessed in the vm.
+use crate::ast::Type;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsIntrinsic {
+    /// `fs::read_to_string(path)`, the contents of the file
+    ReadToString,
+    /// `fs::write(path, contents)`, creates or truncates the file
+    Write,
+}
+
+impl FsIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id.strip_prefix("std::").unwrap_or(id) {
+            "fs::read_to_string" => Some(Self::ReadToString),
+            "fs::write" => Some(Self::Write),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::ReadToString => "fs::read_to_string",
+            Self::Write => "fs::write",
+        }
+    }
+
+    /// The number of `String` arguments.
+    pub fn operands(&self) -> usize {
+        match self {
+            Self::ReadToString => 1,
+            Self::Write => 2,
+        }
+    }
+
+    pub fn ty(&self) -> Type {
+        let ty = match self {
+            Self::ReadToString => Type::String,
+            Self::Write => Type::Unit,
+        };
+        Type::Result(Box::new(ty), Box::new(Type::String))
+    }
+}
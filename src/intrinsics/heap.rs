@@ -0,0 +1,57 @@
+//! Heap allocation intrinsics, `Box::new`, `Rc::new` and `Rc::clone`.
+//!
+//! These are generic over the allocated type so they can not be declared as regular functions,
+//! instead each pass handles them explicitly.
+use crate::ast::{Ref, Type};
+use crate::type_check::TypeErr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapIntrinsic {
+    /// `Box::new(value)`, moves the value to a new heap allocation
+    BoxNew,
+    /// `Rc::new(value)`, moves the value to a new reference counted heap allocation
+    RcNew,
+    /// `Rc::clone(&rc)`, increments the reference count
+    RcClone,
+}
+
+impl HeapIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "Box::new" => Some(Self::BoxNew),
+            "Rc::new" => Some(Self::RcNew),
+            "Rc::clone" => Some(Self::RcClone),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::BoxNew => "Box::new",
+            Self::RcNew => "Rc::new",
+            Self::RcClone => "Rc::clone",
+        }
+    }
+
+    /// Returns the type of a call with arguments of type `args`.
+    pub fn return_type(&self, args: &[Type]) -> Result<Type, TypeErr> {
+        let arg = match args {
+            [arg] => arg.clone(),
+            _ => {
+                return Err(format!(
+                    "Expected 1 argument to {} but got {}",
+                    self.id(),
+                    args.len()
+                ))
+            }
+        };
+        match (self, arg) {
+            (Self::BoxNew, ty) => Ok(Type::Box(Box::new(ty))),
+            (Self::RcNew, ty) => Ok(Type::Rc(Box::new(ty))),
+            (Self::RcClone, Type::Ref(Ref(ty, _))) if matches!(*ty, Type::Rc(_)) => Ok(*ty),
+            (Self::RcClone, ty) => Err(format!(
+                "Expected argument to Rc::clone to be a &Rc<T> but got {ty}"
+            )),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Host functions, Rust closures registered by the embedding application and called from RnR
+//! programs like any other function.
+//!
+//! Each host function declares its signature, the type checker declares it in the global
+//! [`FunctionScope`](crate::type_check::FunctionScope) so calls are checked like calls to
+//! functions in the program. The vm evaluates the arguments and passes their values to the
+//! closure, references and heap pointers are replaced by the values they point to. An error
+//! returned by the closure, or a value that does not have the declared return type, aborts the
+//! evaluation with a [`VmErr::Host`]. Host functions are only available in the vm, they can not
+//! be compiled.
+//!
+//! Closures taking and returning [`HostType`]s are registered with
+//! [`register_fn`](IntrinsicRegistry::register_fn), their signature is derived from the Rust
+//! types and the values are converted for them.
+//!
+//! ```
+//! use rnr::ast::{Literal, Type};
+//! use rnr::intrinsics::IntrinsicRegistry;
+//! use rnr::vm::{Values, VmErr};
+//!
+//! let mut registry = IntrinsicRegistry::new();
+//! registry
+//!     .register("double", vec![Type::I32], Type::I32, |args| match &args[..] {
+//!         [Values::Lit(Literal::Int(i))] => Ok(Values::Lit(Literal::Int(2 * i))),
+//!         args => Err(VmErr::Err(format!("Invalid arguments {args:?} to double"))),
+//!     })
+//!     .unwrap();
+//! registry.register_fn("triple", |a: i32| 3 * a).unwrap();
+//! assert!(registry.get("double").is_some());
+//! assert_eq!(registry.get("triple").unwrap().ty, Type::I32);
+//! ```
+use super::{is_intrinsic, quote, unquote};
+use crate::ast::{Literal, Type};
+use crate::vm::{Values, VmErr};
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The implementation of a host function, it gets the values of the arguments.
+pub type HostImpl = Rc<dyn Fn(Vec<Values>) -> Result<Values, VmErr>>;
+
+/// A registered host function along with its signature.
+#[derive(Clone)]
+pub struct HostFn {
+    pub args: Vec<Type>,
+    pub ty: Type,
+    f: HostImpl,
+}
+
+impl HostFn {
+    /// Calls the host function with the values of the arguments.
+    pub fn call(&self, args: Vec<Values>) -> Result<Values, VmErr> {
+        (self.f)(args)
+    }
+}
+
+impl std::fmt::Debug for HostFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|arg| arg.to_string()).collect();
+        write!(f, "fn({}) -> {}", args.join(", "), self.ty)
+    }
+}
+
+/// The host functions available to a program.
+#[derive(Debug, Clone, Default)]
+pub struct IntrinsicRegistry {
+    fns: HashMap<String, HostFn>,
+}
+
+impl IntrinsicRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the host function `id` taking arguments of type `args` and returning `ty`.
+    ///
+    /// Fails if `id` is not an identifier, if it names one of the built in intrinsics or if it is
+    /// already registered.
+    pub fn register<F>(&mut self, id: &str, args: Vec<Type>, ty: Type, f: F) -> Result<(), String>
+    where
+        F: Fn(Vec<Values>) -> Result<Values, VmErr> + 'static,
+    {
+        if syn::parse_str::<syn::Ident>(id).is_err() {
+            return Err(format!("Invalid host function name `{id}`"));
+        }
+        if is_intrinsic(id) {
+            return Err(format!("`{id}` is a built in intrinsic"));
+        }
+        if self.fns.contains_key(id) {
+            return Err(format!("Host function `{id}` is already registered"));
+        }
+        self.fns.insert(
+            id.to_owned(),
+            HostFn {
+                args,
+                ty,
+                f: Rc::new(f),
+            },
+        );
+        Ok(())
+    }
+
+    /// Registers the closure `f` as the host function `id`, the signature is derived from the
+    /// [`HostType`]s of its arguments and its result.
+    ///
+    /// Fails like [`register`](Self::register).
+    pub fn register_fn<Args, F>(&mut self, id: &str, f: F) -> Result<(), String>
+    where
+        F: IntoHostFn<Args>,
+    {
+        let (args, ty) = F::signature();
+        let f = f.into_impl();
+        self.register(id, args, ty, move |args| f(args))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&HostFn> {
+        self.fns.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &HostFn)> {
+        self.fns.iter()
+    }
+}
+
+/// A Rust type that can be passed to and returned from a host function registered with
+/// [`register_fn`](IntrinsicRegistry::register_fn).
+pub trait HostType: Sized {
+    /// The type of the values in RnR.
+    fn ty() -> Type;
+    /// Converts the value of an argument, `None` if it is not of the type.
+    fn from_value(value: Values) -> Option<Self>;
+    fn into_value(self) -> Values;
+}
+
+impl HostType for () {
+    fn ty() -> Type {
+        Type::Unit
+    }
+    fn from_value(value: Values) -> Option<Self> {
+        matches!(value, Values::Lit(Literal::Unit)).then_some(())
+    }
+    fn into_value(self) -> Values {
+        Values::Lit(Literal::Unit)
+    }
+}
+
+impl HostType for i32 {
+    fn ty() -> Type {
+        Type::I32
+    }
+    fn from_value(value: Values) -> Option<Self> {
+        match value {
+            Values::Lit(Literal::Int(i)) => Some(i),
+            _ => None,
+        }
+    }
+    fn into_value(self) -> Values {
+        Values::Lit(Literal::Int(self))
+    }
+}
+
+/// A `usize` is an integer in the vm, it fails to convert if it does not fit in an `i32`.
+impl HostType for usize {
+    fn ty() -> Type {
+        Type::Usize
+    }
+    fn from_value(value: Values) -> Option<Self> {
+        i32::from_value(value).and_then(|i| i.try_into().ok())
+    }
+    fn into_value(self) -> Values {
+        Values::Lit(Literal::Int(self as i32))
+    }
+}
+
+impl HostType for bool {
+    fn ty() -> Type {
+        Type::Bool
+    }
+    fn from_value(value: Values) -> Option<Self> {
+        match value {
+            Values::Lit(Literal::Bool(b)) => Some(b),
+            _ => None,
+        }
+    }
+    fn into_value(self) -> Values {
+        Values::Lit(Literal::Bool(self))
+    }
+}
+
+/// Strings are passed without the quotes of the string literal.
+impl HostType for String {
+    fn ty() -> Type {
+        Type::String
+    }
+    fn from_value(value: Values) -> Option<Self> {
+        match value {
+            Values::Lit(Literal::String(s)) => Some(unquote(&s)),
+            _ => None,
+        }
+    }
+    fn into_value(self) -> Values {
+        Values::Lit(Literal::String(quote(&self)))
+    }
+}
+
+impl<T: HostType> HostType for Option<T> {
+    fn ty() -> Type {
+        Type::Option(Box::new(T::ty()))
+    }
+    fn from_value(value: Values) -> Option<Self> {
+        match value {
+            Values::Option(Some(value)) => T::from_value(*value).map(Some),
+            Values::Option(None) => Some(None),
+            _ => None,
+        }
+    }
+    fn into_value(self) -> Values {
+        Values::Option(self.map(|value| Box::new(value.into_value())))
+    }
+}
+
+impl<T: HostType> HostType for Vec<T> {
+    fn ty() -> Type {
+        Type::Vec(Box::new(T::ty()))
+    }
+    fn from_value(value: Values) -> Option<Self> {
+        match value {
+            Values::Vec(elements) => elements.into_iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+    fn into_value(self) -> Values {
+        Values::Vec(self.into_iter().map(T::into_value).collect())
+    }
+}
+
+/// A closure that can be registered as a host function, `Args` are the types of its arguments.
+pub trait IntoHostFn<Args> {
+    /// The types of the arguments and the return type.
+    fn signature() -> (Vec<Type>, Type);
+    fn into_impl(self) -> HostImpl;
+}
+
+macro_rules! into_host_fn {
+    ($($arg:ident: $ty:ident),*) => {
+        impl<F, R, $($ty),*> IntoHostFn<($($ty,)*)> for F
+        where
+            F: Fn($($ty),*) -> R + 'static,
+            R: HostType,
+            $($ty: HostType),*
+        {
+            fn signature() -> (Vec<Type>, Type) {
+                (vec![$($ty::ty()),*], R::ty())
+            }
+
+            fn into_impl(self) -> HostImpl {
+                Rc::new(move |args: Vec<Values>| {
+                    #[allow(unused_mut, unused_variables)]
+                    let mut args = args.into_iter();
+                    $(
+                        let $arg = args.next().and_then($ty::from_value).ok_or_else(|| {
+                            VmErr::Err(format!("Expected an argument of type {}", $ty::ty()))
+                        })?;
+                    )*
+                    Ok(self($($arg),*).into_value())
+                })
+            }
+        }
+    };
+}
+
+into_host_fn!();
+into_host_fn!(a: A);
+into_host_fn!(a: A, b: B);
+into_host_fn!(a: A, b: B, c: C);
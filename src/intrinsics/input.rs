@@ -0,0 +1,43 @@
+//! Program input, `read_line()`, `read_i32()` and `args()`.
+//!
+//! The vm reads from the [`Io`](crate::vm::Io) of the program, the MIPS backend lowers these to
+//! the SPIM read syscalls.
+use crate::ast::Type;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputIntrinsic {
+    /// `read_line()`, the next line of stdin without the newline, empty at the end of the input
+    ReadLine,
+    /// `read_i32()`, parses the next line of stdin as an integer, panics if it is not one
+    ReadI32,
+    /// `args()`, the arguments passed after `--` on the command line
+    Args,
+}
+
+impl InputIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "read_line" => Some(Self::ReadLine),
+            "read_i32" => Some(Self::ReadI32),
+            "args" => Some(Self::Args),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::ReadLine => "read_line",
+            Self::ReadI32 => "read_i32",
+            Self::Args => "args",
+        }
+    }
+
+    /// The type returned by the intrinsic, none of them take any arguments.
+    pub fn ty(&self) -> Type {
+        match self {
+            Self::ReadLine => Type::String,
+            Self::ReadI32 => Type::I32,
+            Self::Args => Type::Vec(Box::new(Type::String)),
+        }
+    }
+}
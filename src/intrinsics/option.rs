@@ -0,0 +1,85 @@
+//! The `Option<T>` and `Result<T, E>` intrinsics, the `Some`, `None`, `Ok` and `Err` constructors
+//! along with `unwrap`, `expect`, `is_some`, `is_none`, `is_ok` and `is_err`.
+//!
+//! `None` is parsed as a call without arguments so that it is handled like the other
+//! constructors. The parser does not know the type of a receiver, so `unwrap` and `expect` are
+//! always desugared to the `Option` path, both paths accept an `Option` as well as a `Result`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionIntrinsic {
+    /// `Some(value)`
+    Some,
+    /// `None`
+    None,
+    /// `Ok(value)`
+    Ok,
+    /// `Err(error)`
+    Err,
+    /// `Option::unwrap(value)`, the contained value or a panic
+    Unwrap,
+    /// `Option::expect(value, msg)`, the contained value or a panic with the message `msg`
+    Expect,
+    /// `Option::is_some(&value)`
+    IsSome,
+    /// `Option::is_none(&value)`
+    IsNone,
+    /// `Result::is_ok(&value)`
+    IsOk,
+    /// `Result::is_err(&value)`
+    IsErr,
+}
+
+impl OptionIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "Some" => Some(Self::Some),
+            "None" => Some(Self::None),
+            "Ok" => Some(Self::Ok),
+            "Err" => Some(Self::Err),
+            "Option::unwrap" | "Result::unwrap" => Some(Self::Unwrap),
+            "Option::expect" | "Result::expect" => Some(Self::Expect),
+            "Option::is_some" => Some(Self::IsSome),
+            "Option::is_none" => Some(Self::IsNone),
+            "Result::is_ok" => Some(Self::IsOk),
+            "Result::is_err" => Some(Self::IsErr),
+            _ => None,
+        }
+    }
+
+    /// Returns the intrinsic called by the method `name`.
+    pub fn from_method(name: &str) -> Option<Self> {
+        match name {
+            "unwrap" => Some(Self::Unwrap),
+            "expect" => Some(Self::Expect),
+            "is_some" => Some(Self::IsSome),
+            "is_none" => Some(Self::IsNone),
+            "is_ok" => Some(Self::IsOk),
+            "is_err" => Some(Self::IsErr),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Some => "Some",
+            Self::None => "None",
+            Self::Ok => "Ok",
+            Self::Err => "Err",
+            Self::Unwrap => "Option::unwrap",
+            Self::Expect => "Option::expect",
+            Self::IsSome => "Option::is_some",
+            Self::IsNone => "Option::is_none",
+            Self::IsOk => "Result::is_ok",
+            Self::IsErr => "Result::is_err",
+        }
+    }
+
+    /// Returns `Some(mutable)` if the receiver is borrowed, methods that take the receiver by
+    /// value return `None`.
+    pub fn receiver(&self) -> Option<bool> {
+        match self {
+            Self::IsSome | Self::IsNone | Self::IsOk | Self::IsErr => Some(false),
+            _ => None,
+        }
+    }
+}
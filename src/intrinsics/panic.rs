@@ -0,0 +1,78 @@
+//! The panicking intrinsics, `panic!`, `assert!`, `assert_eq!`, `assert_ne!`, `unreachable!`
+//! and `todo!`.
+//!
+//! The operands, the condition of `assert!` and the two values of `assert_eq!` and
+//! `assert_ne!`, are followed by an optional format string and its arguments, e.g.
+//! `assert!(a < 10, "a was {}", a)`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicIntrinsic {
+    /// `panic!(msg, args..)`
+    Panic,
+    /// `assert!(cond, msg, args..)`
+    Assert,
+    /// `assert_eq!(left, right, msg, args..)`
+    AssertEq,
+    /// `assert_ne!(left, right, msg, args..)`
+    AssertNe,
+    /// `unreachable!(msg, args..)`
+    Unreachable,
+    /// `todo!(msg, args..)`
+    Todo,
+}
+
+impl PanicIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "panic!" => Some(Self::Panic),
+            "assert!" => Some(Self::Assert),
+            "assert_eq!" => Some(Self::AssertEq),
+            "assert_ne!" => Some(Self::AssertNe),
+            "unreachable!" => Some(Self::Unreachable),
+            "todo!" => Some(Self::Todo),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Panic => "panic!",
+            Self::Assert => "assert!",
+            Self::AssertEq => "assert_eq!",
+            Self::AssertNe => "assert_ne!",
+            Self::Unreachable => "unreachable!",
+            Self::Todo => "todo!",
+        }
+    }
+
+    /// Returns the number of arguments that precede the optional message.
+    pub fn operands(&self) -> usize {
+        match self {
+            Self::Panic | Self::Unreachable | Self::Todo => 0,
+            Self::Assert => 1,
+            Self::AssertEq | Self::AssertNe => 2,
+        }
+    }
+
+    /// Returns true if the intrinsic always panics, these never return.
+    pub fn diverges(&self) -> bool {
+        self.operands() == 0
+    }
+
+    /// Returns the panic message given the formatted message of the call, if any.
+    pub fn message(&self, msg: Option<String>) -> String {
+        let default = match self {
+            Self::Panic => "explicit panic",
+            Self::Assert => "assertion failed",
+            Self::AssertEq => "assertion `left == right` failed",
+            Self::AssertNe => "assertion `left != right` failed",
+            Self::Unreachable => "internal error: entered unreachable code",
+            Self::Todo => "not yet implemented",
+        };
+        match (self, msg) {
+            (Self::Panic | Self::Assert, Some(msg)) => msg,
+            (_, Some(msg)) => format!("{default}: {msg}"),
+            (_, None) => default.to_owned(),
+        }
+    }
+}
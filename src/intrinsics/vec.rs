@@ -0,0 +1,62 @@
+//! The growable array intrinsics, `Vec::new`, `vec![..]`, `push`, `pop` and `len`.
+//!
+//! Method calls such as `v.push(1)` are desugared by the parser in to calls on the path,
+//! `Vec::push(&mut v, 1)`, so each pass only has to handle function calls. The first argument
+//! of a method is the receiver, borrowed as required by the method.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VecIntrinsic {
+    /// `Vec::new()`, an empty vector
+    New,
+    /// `vec![a, b, c]`, a vector of the given elements
+    From,
+    /// `Vec::push(&mut v, value)`, appends a value
+    Push,
+    /// `Vec::pop(&mut v)`, removes the last value
+    Pop,
+    /// `Vec::len(&v)`, the number of elements
+    Len,
+}
+
+impl VecIntrinsic {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "Vec::new" => Some(Self::New),
+            "vec!" => Some(Self::From),
+            "Vec::push" => Some(Self::Push),
+            "Vec::pop" => Some(Self::Pop),
+            "Vec::len" => Some(Self::Len),
+            _ => None,
+        }
+    }
+
+    /// Returns the intrinsic called by the method `name`.
+    pub fn from_method(name: &str) -> Option<Self> {
+        match name {
+            "push" => Some(Self::Push),
+            "pop" => Some(Self::Pop),
+            "len" => Some(Self::Len),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::New => "Vec::new",
+            Self::From => "vec!",
+            Self::Push => "Vec::push",
+            Self::Pop => "Vec::pop",
+            Self::Len => "Vec::len",
+        }
+    }
+
+    /// Returns `Some(mutable)` if the first argument is a receiver, borrowed mutably if
+    /// `mutable` is true.
+    pub fn receiver(&self) -> Option<bool> {
+        match self {
+            Self::New | Self::From => None,
+            Self::Push | Self::Pop => Some(true),
+            Self::Len => Some(false),
+        }
+    }
+}
@@ -4,6 +4,7 @@
 
 use ast::{HirNode, Type};
 use prelude::TypeCheck;
+use std::path::Path;
 use syn::parse::Parse;
 use vm::Eval;
 
@@ -21,6 +22,10 @@ pub mod ast;
 pub mod borrow_checker;
 pub mod climb;
 pub mod parse;
+// name resolution for modules
+pub mod resolve;
+// compile time evaluation of constants
+pub mod const_eval;
 // type generic environment
 //pub mod env;
 // intrinsic functions
@@ -31,18 +36,29 @@ pub mod type_check;
 // natural interpretation
 pub mod codegen;
 pub mod vm;
+// embedding in other applications
+pub mod engine;
+pub use engine::{Engine, EngineErr};
 // borrow checking
 // pub mod bc;
 pub mod prelude {
     pub use super::ast::Prog;
     pub use super::borrow_checker::{BCError, Env, Linearize, PreDeclareTop};
+    pub use super::intrinsics::IntrinsicRegistry;
     pub use super::type_check::{TypeCheck, TypeEnv};
     pub use super::vm::{Eval, VarEnv};
     pub use super::Ast;
+    pub use super::Engine;
     pub use super::{borrow_check, check, eval, parse};
 }
 
-pub trait AstNode: Eval + TypeCheck + std::fmt::Debug {}
+pub trait AstNode: Eval + TypeCheck + std::fmt::Debug {
+    /// Loads file backed modules relative to `source` and resolves all paths, see
+    /// [`resolve`](crate::resolve).
+    fn resolve_modules(&mut self, _source: Option<&Path>) -> Result<(), String> {
+        Ok(())
+    }
+}
 
 /// Ast wrapper for improved error messages
 #[derive(Clone, Debug)]
@@ -63,154 +79,176 @@ impl<T: ast::HIR> ast::HIR for HIR<T> {
 
 impl<T: AstNode + Parse> From<String> for Ast<T> {
     fn from(value: String) -> Self {
-        let map = |el: Vec<String>,
-                   r: std::ops::Range<usize>,
-                   line: usize,
-                   end_line: usize,
-                   cols: (usize, usize)| {
-            let r_clone = r.clone();
-            let intermediate = el
-                .iter()
-                .enumerate()
-                .map(|(idx, el)| match r.contains(&idx) {
-                    true => Some(el),
-                    false => None,
-                });
-            let mut ret = Vec::with_capacity(el.len());
-            for (el, idx) in intermediate.zip(r_clone.into_iter()) {
-                if el.is_some() {
-                    let el = el.unwrap();
-                    match (idx > line, idx < end_line, idx == line, idx == end_line) {
-                        (true, true, _, _) => {
-                            ret.push(format!("{idx}|\t{}", error(el.clone(), true, 0, None)))
-                            // Add some cool new processing to highlight errors on multiple lines
-                        }
-                        (_, _, true, _) => {
-                            ret.push(format!(
-                                "{idx}|\t{}<-- Occured here",
-                                error(
-                                    el.clone(),
-                                    true,
-                                    cols.0,
-                                    match line == end_line {
-                                        true => Some(cols.1),
-                                        false => None,
-                                    }
-                                )
-                            ));
-                        }
-                        (_, _, _, true) => {
-                            ret.push(format!(
-                                "{idx}|\t{}<-- Occured here",
-                                error(el.clone(), true, 0, Some(cols.1))
-                            ));
-                        }
-                        //(_, _, true, err) => {
-                        //    let str = format!("{idx}|\t{}", el.unwrap().clone());
-                        //    ret.push(error())
-                        //}
-                        _ => ret.push(format!("{idx}|\t{}", el.clone())),
-                    }
-                }
-            }
-            ret.join("\n")
-        };
-        // This would be quite easy to re write to be cleaner, but I do not have time to spend on
-        // that
-        fn rec_sub(el: usize, target: usize) -> usize {
-            if target == 0 {
-                return el;
-            }
-            match el.checked_sub(target) {
-                Some(value) => value,
-                _ => rec_sub(el, target - 1),
+        match Self::from_source(value, None) {
+            Ok(ast) => ast,
+            Err(e) => {
+                eprintln!("{e}");
+                panic!("Invalid input");
             }
         }
+    }
+}
 
-        let ts: proc_macro2::TokenStream = match value.parse() {
-            Ok(ts) => ts,
-            Err(e) => {
-                let line = e.span().start().line;
-                let cols = (e.span().start().column, e.span().end().column);
-                let line_offset = rec_sub(line, 4);
-                let rel_line = match line.checked_sub(line_offset) {
-                    Some(e) => e,
-                    None => 0,
-                };
-                let rel_line = match rel_line.checked_sub(1) {
-                    Some(e) => e,
-                    None => 0,
-                };
-                let end_line = e.span().end().line;
-                let line_offset_end = rec_sub(end_line, 4);
-                let rel_line_end = match end_line.checked_sub(line_offset_end) {
-                    Some(e) => e,
-                    None => 0,
-                };
-                let rel_line_end = match rel_line_end.checked_sub(1) {
-                    Some(e) => e,
-                    None => 0,
-                };
+impl<T: AstNode + Parse> Ast<T> {
+    /// Parses `value` and resolves all module paths, file backed modules are read relative
+    /// to `path`.
+    pub fn from_source(value: String, path: Option<&Path>) -> Result<Self, String> {
+        let mut t: T = parse_source(&value, path)?;
+        t.resolve_modules(path)
+            .map_err(|e| format!("Error {e} ocurred while resolving names"))?;
+        Ok(Self { t })
+    }
+}
 
-                let lines = map(
-                    value
-                        .lines()
-                        .map(|el| el.to_string())
-                        .collect::<Vec<String>>(),
-                    line_offset..line + 5,
-                    rel_line,
-                    rel_line_end,
-                    cols,
-                );
+/// Parses `value`, the error shows the offending lines and the file they came from.
+pub(crate) fn parse_source<T: Parse>(value: &str, path: Option<&Path>) -> Result<T, String> {
+    let file = match path {
+        Some(path) => format!(" {}", path.display()),
+        None => "".to_owned(),
+    };
+    let map = |el: Vec<String>,
+               r: std::ops::Range<usize>,
+               line: usize,
+               end_line: usize,
+               cols: (usize, usize)| {
+        let r_clone = r.clone();
+        let intermediate = el
+            .iter()
+            .enumerate()
+            .map(|(idx, el)| match r.contains(&idx) {
+                true => Some(el),
+                false => None,
+            });
+        let mut ret = Vec::with_capacity(el.len());
+        for (el, idx) in intermediate.zip(r_clone.into_iter()) {
+            if el.is_some() {
+                let el = el.unwrap();
+                match (idx > line, idx < end_line, idx == line, idx == end_line) {
+                    (true, true, _, _) => {
+                        ret.push(format!("{idx}|\t{}", error(el.clone(), true, 0, None)))
+                        // Add some cool new processing to highlight errors on multiple lines
+                    }
+                    (_, _, true, _) => {
+                        ret.push(format!(
+                            "{idx}|\t{}<-- Occured here",
+                            error(
+                                el.clone(),
+                                true,
+                                cols.0,
+                                match line == end_line {
+                                    true => Some(cols.1),
+                                    false => None,
+                                }
+                            )
+                        ));
+                    }
+                    (_, _, _, true) => {
+                        ret.push(format!(
+                            "{idx}|\t{}<-- Occured here",
+                            error(el.clone(), true, 0, Some(cols.1))
+                        ));
+                    }
+                    //(_, _, true, err) => {
+                    //    let str = format!("{idx}|\t{}", el.unwrap().clone());
+                    //    ret.push(error())
+                    //}
+                    _ => ret.push(format!("{idx}|\t{}", el.clone())),
+                }
+            }
+        }
+        ret.join("\n")
+    };
+    // This would be quite easy to re write to be cleaner, but I do not have time to spend on
+    // that
+    fn rec_sub(el: usize, target: usize) -> usize {
+        if target == 0 {
+            return el;
+        }
+        match el.checked_sub(target) {
+            Some(value) => value,
+            _ => rec_sub(el, target - 1),
+        }
+    }
 
-                eprintln!("Error {e} ocurred while parsing \n{lines}");
+    let ts: proc_macro2::TokenStream = match value.parse() {
+        Ok(ts) => ts,
+        Err(e) => {
+            let line = e.span().start().line;
+            let cols = (e.span().start().column, e.span().end().column);
+            let line_offset = rec_sub(line, 4);
+            let rel_line = match line.checked_sub(line_offset) {
+                Some(e) => e,
+                None => 0,
+            };
+            let rel_line = match rel_line.checked_sub(1) {
+                Some(e) => e,
+                None => 0,
+            };
+            let end_line = e.span().end().line;
+            let line_offset_end = rec_sub(end_line, 4);
+            let rel_line_end = match end_line.checked_sub(line_offset_end) {
+                Some(e) => e,
+                None => 0,
+            };
+            let rel_line_end = match rel_line_end.checked_sub(1) {
+                Some(e) => e,
+                None => 0,
+            };
 
-                panic!("Invalid input");
-            }
-        };
-        let t = match syn::parse2(ts) {
-            Ok(ts) => ts,
-            Err(e) => {
-                let line = e.span().start().line;
-                let cols = (e.span().start().column, e.span().end().column);
-                let line_offset = rec_sub(line, 4);
-                let rel_line = match line.checked_sub(line_offset) {
-                    Some(e) => e,
-                    None => 0,
-                };
-                let rel_line = match rel_line.checked_sub(1) {
-                    Some(e) => e,
-                    None => 0,
-                };
-                let end_line = e.span().end().line;
-                let line_offset_end = rec_sub(end_line, 4);
-                let rel_line_end = match end_line.checked_sub(line_offset_end) {
-                    Some(e) => e,
-                    None => 0,
-                };
-                let rel_line_end = match rel_line_end.checked_sub(1) {
-                    Some(e) => e,
-                    None => 0,
-                };
-                let lines = map(
-                    value
-                        .lines()
-                        .map(|el| el.to_string())
-                        .collect::<Vec<String>>(),
-                    line_offset..line + 5,
-                    rel_line,
-                    rel_line_end,
-                    cols,
-                );
+            let lines = map(
+                value
+                    .lines()
+                    .map(|el| el.to_string())
+                    .collect::<Vec<String>>(),
+                line_offset..line + 5,
+                rel_line,
+                rel_line_end,
+                cols,
+            );
 
-                eprintln!("Error {e} ocurred while parsing \n{lines}");
+            return Err(format!("Error {e} ocurred while parsing{file} \n{lines}"));
+        }
+    };
+    let t = match syn::parse2(ts) {
+        Ok(ts) => ts,
+        Err(e) => {
+            let line = e.span().start().line;
+            let cols = (e.span().start().column, e.span().end().column);
+            let line_offset = rec_sub(line, 4);
+            let rel_line = match line.checked_sub(line_offset) {
+                Some(e) => e,
+                None => 0,
+            };
+            let rel_line = match rel_line.checked_sub(1) {
+                Some(e) => e,
+                None => 0,
+            };
+            let end_line = e.span().end().line;
+            let line_offset_end = rec_sub(end_line, 4);
+            let rel_line_end = match end_line.checked_sub(line_offset_end) {
+                Some(e) => e,
+                None => 0,
+            };
+            let rel_line_end = match rel_line_end.checked_sub(1) {
+                Some(e) => e,
+                None => 0,
+            };
+            let lines = map(
+                value
+                    .lines()
+                    .map(|el| el.to_string())
+                    .collect::<Vec<String>>(),
+                line_offset..line + 5,
+                rel_line,
+                rel_line_end,
+                cols,
+            );
 
-                panic!("Invalid input");
-            }
-        };
+            return Err(format!("Error {e} ocurred while parsing{file} \n{lines}"));
+        }
+    };
 
-        Self { t }
-    }
+    Ok(t)
 }
 
 impl<T: AstNode> Eval for Ast<T> {
@@ -272,11 +310,12 @@ macro_rules! check {
 #[macro_export]
 macro_rules! borrow_check {
     ($id:ident) => {{
+        let mut env = Env::new();
+        $crate::borrow_check!($id, env)
+    }};
+    ($id:ident, $env:ident) => {{
         match $id.pre_declare_top(&mut 0, &mut 0) {
-            Ok(_) => {
-                let mut env = Env::new();
-                $id.linearize(&mut env)
-            }
+            Ok(_) => $id.linearize(&mut $env),
             Err(e) => Err(BCError::EnvError(e)),
         }
     }};
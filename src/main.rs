@@ -1,5 +1,6 @@
 use rnr::codegen::CompileTarget;
 use rnr::prelude::*;
+use rnr::vm::{BorrowStacks, Io, Verbosity};
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::PathBuf;
@@ -22,6 +23,16 @@ struct Opt {
     #[structopt(short, long)]
     type_check: bool,
 
+    /// Print where each borrow is created, last used and what it conflicts with, per function,
+    /// implies `--type-check`
+    #[structopt(long)]
+    explain_borrows: bool,
+
+    /// Write the graph from the borrowers to what they borrow to FILE as Graphviz DOT, implies
+    /// `--type-check`
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    borrow_graph: Option<PathBuf>,
+
     /// Maximum number of statements to execute
     #[structopt(short, long, default_value = "100")]
     max_iter: usize,
@@ -38,6 +49,37 @@ struct Opt {
     /// emulator
     #[structopt(short, long)]
     asm_sim: bool,
+
+    /// Print the input, the parsed program and the progress of each pass, the vm traces every
+    /// binary operation
+    #[structopt(long)]
+    verbose: bool,
+
+    /// Do not report runtime errors of the vm with the statement that caused them
+    #[structopt(short, long)]
+    quiet: bool,
+
+    /// Check every access through a reference in the vm against the stacked borrows aliasing
+    /// rules, reporting undefined behavior that the borrow checker did not catch
+    #[structopt(long)]
+    check_aliasing: bool,
+
+    /// Allow the program run by the vm to read and write files in DIR, may be repeated
+    #[structopt(long = "allow-fs", value_name = "DIR", parse(from_os_str))]
+    allow_fs: Vec<PathBuf>,
+
+    /// Arguments passed to the program, after `--`
+    #[structopt(last = true)]
+    args: Vec<String>,
+}
+
+// Compiler chatter, only printed with `--verbose` so that the output of the program is clean
+macro_rules! chatter {
+    ($opt:ident, $($arg:tt)*) => {
+        if $opt.verbose {
+            print!($($arg)*);
+        }
+    };
 }
 
 fn main() {
@@ -59,34 +101,77 @@ fn main() {
         panic!("couldn't read {}: {}", opt.path.display(), why)
     }
 
-    print!("rnr input:\n{}", s);
-    print!("rnr parsing: ");
-    let mut prog: Ast<Prog> = s.into();
-    println!("\nrnr prog:\n{}", prog);
-    if opt.type_check {
-        print!("rnr type checking: ");
+    chatter!(opt, "rnr input:\n{}", s);
+    chatter!(opt, "rnr parsing: ");
+    let mut prog: Ast<Prog> = match Ast::from_source(s, Some(&opt.path)) {
+        Ok(prog) => prog,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return;
+        }
+    };
+    chatter!(opt, "\nrnr prog:\n{}\n", prog);
+    let explain = opt.explain_borrows || opt.borrow_graph.is_some();
+    if opt.type_check || explain {
+        chatter!(opt, "rnr type checking: ");
         match check!(prog) {
-            Ok(_) => println!("passed"),
+            Ok(_) => chatter!(opt, "passed\n"),
             Err(err) => {
                 eprintln!("error: {}", err);
                 return;
             }
         }
-        match borrow_check!(prog) {
-            Ok(_) => println!("Borrow checker passed"),
+        let (res, explanations, graph) = {
+            let mut env = match explain {
+                true => Env::new().explaining(),
+                false => Env::new(),
+            };
+            let res = borrow_check!(prog, env);
+            (res, env.explanations(), env.borrow_graph())
+        };
+        if opt.explain_borrows {
+            for explanation in explanations {
+                print!("{explanation}");
+            }
+        }
+        if let Some(path) = &opt.borrow_graph {
+            let plain_bytes = strip_ansi_escapes::strip(graph.as_bytes());
+            if let Err(e) = std::fs::write(path, plain_bytes) {
+                eprintln!(
+                    "Cannot write the borrow graph to {}, error {e}",
+                    path.display()
+                );
+            }
+        }
+        match res {
+            Ok(_) => chatter!(opt, "Borrow checker passed\n"),
             Err(e) => {
-                eprintln!("Error : {:?} occured while borrowchecking", e);
+                eprintln!("Error : {} occured while borrowchecking", e);
                 return;
             }
         }
-        println!("Program after linearization : \n{prog}");
+        chatter!(opt, "Program after linearization : \n{prog}\n");
     }
 
     if opt.vm {
-        println!("rnr evaluating");
-        let iter = opt.max_iter;
-        match eval!(prog, iter) {
-            Ok(_) => println!("rnr evaluating done"),
+        chatter!(opt, "rnr evaluating\n");
+        let verbosity = match (opt.quiet, opt.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, true) => Verbosity::Verbose,
+            (false, false) => Verbosity::Normal,
+        };
+        let mut env = VarEnv::new();
+        env.io = Io::default()
+            .with_args(opt.args.clone())
+            .with_verbosity(verbosity);
+        for dir in &opt.allow_fs {
+            env.io = env.io.with_allowed_dir(dir);
+        }
+        if opt.check_aliasing {
+            env.stacks = Some(BorrowStacks::new());
+        }
+        match prog.eval(&mut env, 0, opt.max_iter, &mut 0) {
+            Ok(_) => chatter!(opt, "rnr evaluating done\n"),
             Err(err) => {
                 eprintln!("error: {}", err);
                 return;
@@ -119,5 +204,5 @@ fn main() {
     };
     let plain_bytes = strip_ansi_escapes::strip(format!("{asm}").as_bytes());
     file.write_all(&plain_bytes).unwrap();
-    println!("Done! :)")
+    chatter!(opt, "Done! :)\n")
 }
@@ -1,17 +1,21 @@
 pub mod block;
+pub mod closure;
 pub mod expr;
 pub mod func;
 pub mod globals;
 pub mod literal;
+pub mod module;
 pub mod op;
 pub mod program;
 pub mod statement;
 pub mod types;
 
 pub use block::*;
+pub use closure::*;
 pub use expr::*;
 pub use func::*;
 pub use literal::*;
+pub use module::*;
 pub use op::*;
 pub use program::*;
 pub use statement::*;
@@ -221,6 +225,39 @@ mod test {
         assert_eq!(e, Type::Unit);
     }
 
+    #[test]
+    fn test_type_fn_ptr() {
+        let ts: proc_macro2::TokenStream = "fn(i32, bool) -> i32".parse().unwrap();
+        let e: Type = syn::parse2(ts).unwrap();
+        assert_eq!(
+            e,
+            Type::FnPtr(vec![Type::I32, Type::Bool], Box::new(Type::I32))
+        );
+    }
+
+    #[test]
+    fn test_module() {
+        let ts: proc_macro2::TokenStream = "
+        pub mod a {
+            pub fn f() -> i32 { 1 }
+        }
+        use a::{f as g, self};
+        fn main() { a::f(); }
+        "
+        .parse()
+        .unwrap();
+        let prog: crate::ast::Prog = syn::parse2(ts).unwrap();
+        assert_eq!(prog.statements.len(), 4);
+        assert_eq!(
+            prog.statements
+                .iter()
+                .map(|el| el.to_string())
+                .filter(|el| el.starts_with("use"))
+                .collect::<Vec<String>>(),
+            vec!["use a::f as g;", "use a;"]
+        );
+    }
+
     #[test]
     fn test_type_fail() {
         let ts: proc_macro2::TokenStream = "u32".parse().unwrap();
@@ -319,4 +356,70 @@ mod test {
 
         assert!(bl.is_err());
     }
+
+    #[test]
+    fn test_lifetimes() {
+        let ts: proc_macro2::TokenStream =
+            "fn f<'a, 'b>(x: &'a mut i32, y: &'_ i32, z: &'b bool) -> &'a i32 { x }"
+                .parse()
+                .unwrap();
+        let f: crate::ast::Func = syn::parse2(ts).unwrap();
+        let lifetime = |ty: &Type| match ty {
+            Type::Ref(crate::ast::Ref(_, lifetime))
+            | Type::MutRef(crate::ast::Ref(_, lifetime)) => lifetime.clone(),
+            _ => Some("not a reference".to_owned()),
+        };
+        assert_eq!(f.lifetimes, vec!["'a".to_owned(), "'b".to_owned()]);
+        assert!(matches!(f.args[0].ty, Type::MutRef(_)));
+        assert_eq!(lifetime(&f.args[0].ty), Some("'a".to_owned()));
+        assert_eq!(lifetime(&f.args[1].ty), None);
+        assert_eq!(lifetime(&f.args[2].ty), Some("'b".to_owned()));
+        assert_eq!(lifetime(&f.ty), Some("'a".to_owned()));
+        // Lifetimes are not part of the type
+        assert_eq!(f.ty, Type::Ref(Type::I32.into()));
+
+        let ts: proc_macro2::TokenStream = "fn f<'a, 'a>(x: &'a i32) {}".parse().unwrap();
+        assert!(syn::parse2::<crate::ast::Func>(ts).is_err());
+    }
+
+    #[test]
+    fn test_cast_unary() {
+        let cast = |e: Expr| Expr::Cast(Box::new(e), Type::ConstPtr(Box::new(Type::I32)));
+        let borrow = |e: Expr| Expr::UnOp(UnaryOp::Borrow, Box::new(e));
+
+        let ts: proc_macro2::TokenStream = "&0 as *const i32".parse().unwrap();
+        let e: Expr = syn::parse2(ts).unwrap();
+        assert_eq!(e, cast(borrow(Expr::Lit(Literal::Int(0)))));
+
+        let ts: proc_macro2::TokenStream = "&a as *const i32".parse().unwrap();
+        let e: Expr = syn::parse2(ts).unwrap();
+        assert_eq!(e, cast(borrow(Expr::Ident("a".to_owned()))));
+    }
+
+    #[test]
+    fn test_cast_binop() {
+        let cast = |e: Expr| Box::new(Expr::Cast(Box::new(e), Type::I32));
+        let int = |i: i32| Box::new(Expr::Lit(Literal::Int(i)));
+
+        let ts: proc_macro2::TokenStream = "1 as i32 + 2".parse().unwrap();
+        let e: Expr = syn::parse2(ts).unwrap();
+        assert_eq!(e, Expr::BinOp(BinaryOp::Add, cast(*int(1)), int(2)));
+
+        let ts: proc_macro2::TokenStream = "2 * 3 as i32 + 1".parse().unwrap();
+        let e: Expr = syn::parse2(ts).unwrap();
+        let mul = Expr::BinOp(BinaryOp::Mul, int(2), cast(*int(3)));
+        assert_eq!(e, Expr::BinOp(BinaryOp::Add, Box::new(mul), int(1)));
+
+        let ts: proc_macro2::TokenStream = "-a as i32 + 1".parse().unwrap();
+        let e: Expr = syn::parse2(ts).unwrap();
+        let neg = Expr::UnOp(UnaryOp::Subtract, Box::new(Expr::Ident("a".to_owned())));
+        assert_eq!(e, Expr::BinOp(BinaryOp::Add, cast(neg), int(1)));
+
+        let ts: proc_macro2::TokenStream = "v.len() as i32 + 1".parse().unwrap();
+        let e: Expr = syn::parse2(ts).unwrap();
+        assert!(matches!(
+            e,
+            Expr::BinOp(BinaryOp::Add, len, one) if matches!(*len, Expr::Cast(_, Type::I32)) && one == int(1)
+        ));
+    }
 }
@@ -0,0 +1,64 @@
+use crate::ast::{Closure, ClosureArg, Expr, Type};
+use syn::parse::{Parse, ParseStream, Result};
+use syn::Token;
+
+impl Parse for Closure {
+    /// Parses the input stream in to a [closure](Closure)
+    ///
+    /// Both `|a, b: i32| a + b` and `move |a| -> i32 { a }` are accepted, a return type
+    /// requires the body to be a block.
+    fn parse(input: ParseStream) -> Result<Self> {
+        let move_semantics = match input.peek(Token![move]) {
+            true => {
+                let _: Token![move] = input.parse()?;
+                true
+            }
+            f => f,
+        };
+
+        let mut args = vec![];
+        if input.peek(Token![||]) {
+            // `||` is lexed as a single token, this is a closure without arguments
+            let _: Token![||] = input.parse()?;
+        } else {
+            let _: Token![|] = input.parse()?;
+            while !input.peek(Token![|]) {
+                let id: syn::Ident = input.parse()?;
+                let ty: Option<Type> = match input.peek(Token![:]) {
+                    true => {
+                        let _: Token![:] = input.parse()?;
+                        Some(input.parse()?)
+                    }
+                    false => None,
+                };
+                args.push(ClosureArg {
+                    id: Expr::Ident(id.to_string()),
+                    ty,
+                });
+                if !input.peek(Token![|]) {
+                    let _: Token![,] = input.parse()?;
+                }
+            }
+            let _: Token![|] = input.parse()?;
+        }
+
+        let ty: Option<Type> = match input.peek(Token![->]) {
+            true => {
+                let _: Token![->] = input.parse()?;
+                Some(input.parse()?)
+            }
+            false => None,
+        };
+        let body: Expr = match ty {
+            Some(_) => Expr::Block(input.parse()?),
+            None => input.parse()?,
+        };
+
+        Ok(Closure {
+            args,
+            ty,
+            body: Box::new(body),
+            move_semantics,
+        })
+    }
+}
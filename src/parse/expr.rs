@@ -1,20 +1,85 @@
 //! Defines parsing rules for [expressions](crate::ast::Expr)
 //!
 
-use crate::{ast::FuncCall, climb::climb, parse::Peek};
+use crate::{
+    ast::{Closure, FuncCall},
+    climb::climb,
+    intrinsics::{CellIntrinsic, OptionIntrinsic, VecIntrinsic},
+    parse::Peek,
+};
 
 use super::{
-    BinaryOp, Block, Expr, Literal, Parse, ParseStream, Result, Statement, Token, UnaryOp,
+    BinaryOp, Block, Expr, Literal, Parse, ParseStream, Result, Statement, Token, Type, UnaryOp,
 };
 impl Expr {
+    /// Parses any number of casts of `left`, e.g. `&mut a as *mut i32 as *const i32`.
+    fn parse_casts(mut left: Expr, input: ParseStream) -> Result<Self> {
+        while input.peek(Token![as]) {
+            let _: Token![as] = input.parse()?;
+            let ty: Type = input.parse()?;
+            left = Expr::Cast(Box::new(left), ty);
+        }
+        Ok(left)
+    }
+
     fn parse_internal(input: ParseStream) -> Result<Self> {
         //println!("{:?}", input);
-        let left = if input.peek(syn::token::Paren) {
+        // Set when the operand of a dereference is parsed, the method calls on it are applied
+        // first
+        let mut deref = false;
+        let mut left = if input.peek(syn::token::Paren) {
             // we have a left (Expr), e.g., "(1 + 2)"
             let content;
             let _ = syn::parenthesized!(content in input);
             let e: Expr = content.parse()?;
             Expr::Par(Box::new(e))
+        } else if (input.peek(syn::Ident)
+            || input.peek(Token![crate])
+            || input.peek(Token![self])
+            || input.peek(Token![super]))
+            && input.peek2(Token![::])
+        {
+            // A path to an item in some module, e.g., "a::b::c"
+            let path = input.call(syn::Path::parse_mod_style)?;
+            let segments: Vec<String> = path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect();
+            Expr::Ident(segments.join("::"))
+        } else if input.peek(syn::Ident)
+            && input.peek2(Token![!])
+            && input.peek3(syn::token::Bracket)
+        {
+            // A macro invoked with brackets, e.g. `vec![1, 2, 3]` or `vec![0; 3]`
+            let ident: syn::Ident = input.parse()?;
+            let _: Token![!] = input.parse()?;
+            let content;
+            syn::bracketed!(content in input);
+            let mut args = vec![];
+            if !content.is_empty() {
+                let first: Expr = content.parse()?;
+                if content.peek(Token![;]) {
+                    let _: Token![;] = content.parse()?;
+                    match content.parse::<Literal>()? {
+                        Literal::Int(len) => args = vec![first; len as usize],
+                        _ => return Err(content.error("expected the number of elements")),
+                    }
+                } else {
+                    args.push(first);
+                    while content.peek(Token![,]) {
+                        let _: Token![,] = content.parse()?;
+                        if content.is_empty() {
+                            break;
+                        }
+                        args.push(content.parse()?);
+                    }
+                }
+            }
+            Expr::FuncCall(FuncCall {
+                id: Box::new(Expr::Ident(format!("{ident}!"))),
+                args: Box::new(args),
+            })
         } else if input.peek(syn::Ident)
             && (input.peek2(syn::token::Paren)
                 || (input.peek2(Token![!]) && input.peek3(syn::token::Paren)))
@@ -44,28 +109,57 @@ impl Expr {
         } else if input.peek(syn::Ident) {
             // we have a left Ident, e.g, "my_best_ident_ever"
             let ident: syn::Ident = input.parse()?;
-            Expr::Ident(ident.to_string())
+            match ident.to_string().as_str() {
+                // `None` is a constructor like `Some(..)`, but without arguments
+                "None" => Expr::FuncCall(FuncCall {
+                    id: Box::new(Expr::Ident(ident.to_string())),
+                    args: Box::default(),
+                }),
+                _ => Expr::Ident(ident.to_string()),
+            }
         } else if input.peek(syn::token::If) {
             //println!("Parsing an if statement");
             // we have a left conditional, e.g., "if true {1} else {2}" or
             // if true { 5 }
             let IfThenOptElse(c, t, e) = input.parse()?;
             Expr::IfThenElse(Box::new(c), t, e)
+        } else if input.peek(Token![|]) || input.peek(Token![||]) || input.peek(Token![move]) {
+            // The closure body consumes the rest of the expression
+            let closure: Closure = input.parse()?;
+            return Ok(Expr::Closure(closure));
         } else if input.peek(Token![*]) && input.peek2(syn::Ident) {
+            // Method calls bind tighter than the dereference, `*c.borrow()` is `*(c.borrow())`
             let _: Token![*] = input.parse()?;
             let id: syn::Ident = input.parse()?;
-            Expr::UnOp(UnaryOp::Dereff, Box::new(Expr::Ident(id.to_string())))
+            deref = true;
+            Expr::Ident(id.to_string())
         } else if UnaryOp::peek::<1>(input) {
             // We have a UnaryOp
             let op: UnaryOp = input.parse()?;
 
             let operand: Expr = if input.peek(syn::Ident) {
                 let id: syn::Ident = input.parse()?;
-                Expr::Ident(id.to_string())
+                let id = Expr::Ident(id.to_string());
+                // A borrow of an element, `&mut v[i]`
+                if input.peek(syn::token::Bracket) {
+                    let content;
+                    syn::bracketed!(content in input);
+                    let idx: Expr = content.parse()?;
+                    match op {
+                        UnaryOp::BorrowMut => Expr::IndexMut(Box::new(id), Box::new(idx)),
+                        _ => Expr::Index(Box::new(id), Box::new(idx)),
+                    }
+                } else {
+                    id
+                }
             } else {
-                input.parse()?
+                // The climber applies the casts and operators after the operand to the unary
+                // expression, `&0 as *const i32` is `(&0) as *const i32`
+                return Ok(Expr::UnOp(op, Box::new(input.parse()?)));
             };
-            return Ok(Expr::UnOp(op, Box::new(operand)));
+            // The operand is cast after the operator is applied, `&mut a as *mut i32`
+            let left = Self::parse_casts(Expr::UnOp(op, Box::new(operand)), input)?;
+            return Self::parse_binop(left, input);
         } else if input.peek(syn::token::Bracket) {
             //println!("Parsing an array decleration");
             // This is an array
@@ -100,6 +194,10 @@ impl Expr {
                 }
             };
             Expr::Array(bl)
+        } else if input.peek(Token![unsafe]) && input.peek2(syn::token::Brace) {
+            let _: Token![unsafe] = input.parse()?;
+            let bl: Block = input.parse()?;
+            Expr::Unsafe(bl)
         } else if input.peek(syn::token::Brace) {
             let bl: Block = input.parse()?;
             Expr::Block(bl)
@@ -108,6 +206,73 @@ impl Expr {
             let e: Expr = input.parse::<crate::ast::Literal>()?.into();
             e
         };
+        // Calls on anything that evaluates to a function, e.g. `(f)(1)`, `make()(1)` or `a::f(1)`
+        while (matches!(&left, Expr::Ident(path) if path.contains("::"))
+            || matches!(left, Expr::Par(_) | Expr::FuncCall(_)))
+            && input.peek(syn::token::Paren)
+        {
+            let content;
+            syn::parenthesized!(content in input);
+            let args = content.parse_terminated(Expr::parse, Token![,])?;
+            left = Expr::FuncCall(FuncCall {
+                id: Box::new(left),
+                args: Box::new(args.into_iter().collect()),
+            });
+        }
+        // Method calls, e.g. `v.push(1)`, are desugared in to calls on the path with the borrowed
+        // receiver as the first argument, `Vec::push(&mut v, 1)`, `?` may follow any of them
+        loop {
+            if input.peek(Token![?]) {
+                let _: Token![?] = input.parse()?;
+                left = Expr::Try(Box::new(left));
+                continue;
+            }
+            if !(input.peek(Token![.]) && input.peek2(syn::Ident) && input.peek3(syn::token::Paren))
+            {
+                break;
+            }
+            let _: Token![.] = input.parse()?;
+            let method: syn::Ident = input.parse()?;
+            let name = method.to_string();
+            let (id, receiver) = match (
+                VecIntrinsic::from_method(&name),
+                OptionIntrinsic::from_method(&name),
+                CellIntrinsic::from_method(&name),
+            ) {
+                (Some(intrinsic), _, _) => (intrinsic.id(), intrinsic.receiver()),
+                (_, Some(intrinsic), _) => (intrinsic.id(), intrinsic.receiver()),
+                (_, _, Some(intrinsic)) => (intrinsic.id(), intrinsic.receiver()),
+                (None, None, None) => {
+                    return Err(syn::Error::new(
+                        method.span(),
+                        format!("no method named `{method}`"),
+                    ))
+                }
+            };
+            let content;
+            syn::parenthesized!(content in input);
+            let rest = content.parse_terminated(Expr::parse, Token![,])?;
+            let mut args = vec![match receiver {
+                Some(true) => Expr::UnOp(UnaryOp::BorrowMut, Box::new(left)),
+                Some(false) => Expr::UnOp(UnaryOp::Borrow, Box::new(left)),
+                // The receiver is taken by value
+                None => left,
+            }];
+            args.extend(rest);
+            left = Expr::FuncCall(FuncCall {
+                id: Box::new(Expr::Ident(id.to_owned())),
+                args: Box::new(args),
+            });
+        }
+        if deref {
+            left = Expr::UnOp(UnaryOp::Dereff, Box::new(left));
+        }
+        let left = Self::parse_casts(left, input)?;
+        Self::parse_binop(left, input)
+    }
+
+    /// Parses the binary operator and the right hand side that may follow `left`.
+    fn parse_binop(left: Expr, input: ParseStream) -> Result<Self> {
         // now check if right is an Op Expr
         match (BinaryOp::peek::<1>(input), input.peek2(Token![=])) {
             (true, false) => {
@@ -1,4 +1,5 @@
-use crate::ast::{Arg, Block, Expr, Func, FuncCall, Type};
+use crate::ast::{Arg, Block, Expr, Func, FuncCall, Literal, Type};
+use crate::intrinsics::{inline_named, quote, unquote, FormatIntrinsic, PanicIntrinsic};
 use syn::parse::{Parse, ParseStream, Result};
 use syn::Token;
 
@@ -29,9 +30,32 @@ impl Parse for Func {
     /// Parses the input stream in to a [function definition](Func)
     ///
     fn parse(input: ParseStream) -> Result<Self> {
+        let unsafety = input.peek(Token![unsafe]);
+        if unsafety {
+            let _: Token![unsafe] = input.parse()?;
+        }
         let _: Token![fn] = input.parse()?;
         let ident: syn::Ident = input.parse()?;
         let ident = Expr::Ident(ident.to_string());
+        // Lifetime parameters, there are no other generics
+        let mut lifetimes = vec![];
+        if input.peek(Token![<]) {
+            let _: Token![<] = input.parse()?;
+            while !input.peek(Token![>]) {
+                let lifetime: syn::Lifetime = input.parse()?;
+                if lifetimes.contains(&lifetime.to_string()) {
+                    return Err(syn::Error::new(
+                        lifetime.span(),
+                        format!("lifetime {lifetime} is declared twice"),
+                    ));
+                }
+                lifetimes.push(lifetime.to_string());
+                if !input.peek(Token![>]) {
+                    let _: Token![,] = input.parse()?;
+                }
+            }
+            let _: Token![>] = input.parse()?;
+        }
         let content;
         syn::parenthesized!(content in input);
         let args = content.parse_terminated(Arg::parse, syn::token::Comma)?;
@@ -45,8 +69,11 @@ impl Parse for Func {
         Ok(Func {
             id: ident,
             ty,
+            lifetimes,
             body,
             args: args.into_iter().collect(),
+            public: false,
+            unsafety,
         })
     }
 }
@@ -71,14 +98,36 @@ impl Parse for FuncCall {
             inner.push('!');
         }
 
+        // The named arguments of a format string read the variables, `println!("{a}")` is
+        // `println!("{0}", a)`
+        let fmt = match (
+            FormatIntrinsic::from_id(&inner),
+            PanicIntrinsic::from_id(&inner),
+        ) {
+            (Some(_), _) => Some(0),
+            (_, Some(intrinsic)) => Some(intrinsic.operands()),
+            (None, None) => None,
+        };
         let ident = Expr::Ident(inner);
 
         let content;
         syn::parenthesized!(content in input);
-        let args = content.parse_terminated(Expr::parse, syn::token::Comma)?;
+        let mut args: Vec<Expr> = content
+            .parse_terminated(Expr::parse, syn::token::Comma)?
+            .into_iter()
+            .collect();
+        if let Some(idx) = fmt.filter(|idx| *idx < args.len()) {
+            if let Expr::Lit(Literal::String(lit)) = &args[idx] {
+                let mut named = vec![];
+                let given = args.len() - idx - 1;
+                let lit = quote(&inline_named(&unquote(lit), given, &mut named));
+                args[idx] = Expr::Lit(Literal::String(lit));
+                args.extend(named);
+            }
+        }
         let ret = FuncCall {
             id: Box::new(ident),
-            args: Box::new(args.into_iter().collect()),
+            args: Box::new(args),
         };
         //println!("Parsing for {ret} completed");
         Ok(ret)
@@ -3,12 +3,16 @@ use syn::{parse::Parse, Token};
 use crate::ast::globals::Static;
 impl Parse for Static {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        if !input.peek(Token![static]) {
-            return Err(input.error("Expected static"));
+        let constant = input.peek(Token![const]);
+        if constant {
+            let _: Token![const] = input.parse().unwrap();
+        } else if input.peek(Token![static]) {
+            let _: Token![static] = input.parse().unwrap();
+        } else {
+            return Err(input.error("Expected static or const"));
         }
-        let _: Token![static] = input.parse().unwrap();
 
-        let mutable = match input.peek(Token![mut]) {
+        let mutable = match !constant && input.peek(Token![mut]) {
             true => {
                 let _: Token![mut] = input.parse().unwrap();
                 true
@@ -41,6 +45,8 @@ impl Parse for Static {
             ty,
             mutable,
             value,
+            public: false,
+            constant,
         })
     }
 }
@@ -0,0 +1,95 @@
+//! Defines parsing rules for [modules](crate::ast::Module) and [imports](crate::ast::Use)
+
+use syn::{
+    parse::{Parse, ParseStream},
+    Result, Token,
+};
+
+use super::program::parse_items;
+use crate::ast::{Module, TopLevel, Use};
+
+/// The contents of a file backed module.
+pub struct Items(pub Vec<Box<dyn TopLevel>>);
+
+impl Parse for Items {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(Self(parse_items(input)?))
+    }
+}
+
+impl Parse for Module {
+    /// Parses `mod a;` or `mod a { .. }`, the visibility is parsed by the caller.
+    fn parse(input: ParseStream) -> Result<Self> {
+        let _: Token![mod] = input.parse()?;
+        let ident: syn::Ident = input.parse()?;
+        let (items, inline) = if input.peek(syn::token::Brace) {
+            let content;
+            syn::braced!(content in input);
+            (parse_items(&content)?, true)
+        } else {
+            let _: Token![;] = input.parse()?;
+            (vec![], false)
+        };
+        Ok(Self {
+            id: ident.to_string(),
+            public: false,
+            items,
+            inline,
+            file: None,
+        })
+    }
+}
+
+impl Use {
+    /// Parses a `use` declaration, returning one import per leaf of the use tree.
+    pub(crate) fn parse_tree(input: ParseStream) -> Result<Vec<Self>> {
+        let _: Token![use] = input.parse()?;
+        let tree: syn::UseTree = input.parse()?;
+        let _: Token![;] = input.parse()?;
+        let mut ret = vec![];
+        flatten(&tree, &mut vec![], &mut ret);
+        Ok(ret)
+    }
+}
+
+fn flatten(tree: &syn::UseTree, prefix: &mut Vec<String>, ret: &mut Vec<Use>) {
+    let leaf = |prefix: &Vec<String>, id: &syn::Ident, alias: Option<String>| {
+        let mut path = prefix.clone();
+        path.push(id.to_string());
+        Use {
+            public: false,
+            path,
+            alias,
+            glob: false,
+        }
+    };
+    match tree {
+        syn::UseTree::Path(path) => {
+            prefix.push(path.ident.to_string());
+            flatten(&path.tree, prefix, ret);
+            prefix.pop();
+        }
+        // `use a::b::{self}` imports `b`
+        syn::UseTree::Name(name) if name.ident == "self" && !prefix.is_empty() => ret.push(Use {
+            public: false,
+            path: prefix.clone(),
+            alias: None,
+            glob: false,
+        }),
+        syn::UseTree::Name(name) => ret.push(leaf(prefix, &name.ident, None)),
+        syn::UseTree::Rename(rename) => {
+            ret.push(leaf(prefix, &rename.ident, Some(rename.rename.to_string())))
+        }
+        syn::UseTree::Glob(_) => ret.push(Use {
+            public: false,
+            path: prefix.clone(),
+            alias: None,
+            glob: true,
+        }),
+        syn::UseTree::Group(group) => {
+            for tree in group.items.iter() {
+                flatten(tree, prefix, ret);
+            }
+        }
+    }
+}
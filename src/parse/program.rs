@@ -1,22 +1,55 @@
-use crate::ast::{program::*, Func, Static};
+use crate::ast::{program::*, Func, Module, Static, Use};
 
 use syn::{parse::Parse, Token};
 
+/// Parses top level items until the input stream is empty.
+///
+/// The items are sorted according to their [`Prio`] before being returned.
+pub(crate) fn parse_items(input: syn::parse::ParseStream) -> syn::Result<Vec<Box<dyn TopLevel>>> {
+    let mut statements: Vec<Box<dyn TopLevel>> = vec![];
+    // The things we have that we can parse here are all statements.
+    while !input.is_empty() {
+        let visibility: syn::Visibility = input.parse()?;
+        let public = !matches!(visibility, syn::Visibility::Inherited);
+        if input.peek(Token![fn]) || (input.peek(Token![unsafe]) && input.peek2(Token![fn])) {
+            let mut stmt: Func = input.parse()?;
+            stmt.public = public;
+            statements.push(Box::new(stmt));
+        } else if input.peek(Token![mod]) {
+            let mut stmt: Module = input.parse()?;
+            stmt.public = public;
+            statements.push(Box::new(stmt));
+        } else if input.peek(Token![use]) {
+            for mut stmt in Use::parse_tree(input)? {
+                stmt.public = public;
+                statements.push(Box::new(stmt));
+            }
+        } else {
+            let mut stmt: Static = input.parse()?;
+            stmt.public = public;
+            statements.push(Box::new(stmt));
+        }
+    }
+    // Sort the items to minimize risk of re evalution being required.
+    //
+    // Things like
+    //
+    //
+    // pub static a:i32 = 0;
+    // pub static b:i32 = c + 2;
+    // pub static c:i32 = 2;
+    //
+    // are valid in the rustc compiler, so our language should reflect this.
+    //
+    statements.sort_by(|el1, el2| order(&**el1, &**el2));
+    Ok(statements)
+}
+
 impl Parse for Prog {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         // This is a cool top level thingie, this means that at the end of parsing
         // the input stream should be empty.
-        let mut statements: Vec<Box<dyn TopLevel>> = vec![];
-        // The things we have that we can parse here are all statements.
-        while !input.is_empty() {
-            if input.peek(Token![fn]) {
-                let stmt: Func = input.parse()?;
-                statements.push(Box::new(stmt));
-            } else {
-                let stmt: Static = input.parse()?;
-                statements.push(Box::new(stmt));
-            }
-        }
+        let statements = parse_items(input)?;
 
         let mut main_defined = false;
         for el in statements.iter() {
@@ -25,18 +58,6 @@ impl Parse for Prog {
         if !main_defined {
             return Err(input.error("Failed to parse something".to_string()));
         }
-        // Sort the items to minimize risk of re evalution being required.
-        //
-        // Things like
-        //
-        //
-        // pub static a:i32 = 0;
-        // pub static b:i32 = c + 2;
-        // pub static c:i32 = 2;
-        //
-        // are valid in the rustc compiler, so our language should reflect this.
-        //
-        statements.sort_by(|el1, el2| order(&**el1, &**el2));
         Ok(statements.into())
     }
 }
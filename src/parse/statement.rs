@@ -1,6 +1,6 @@
 use syn::Token;
 
-use crate::ast::{BinaryOp, Func};
+use crate::ast::{BinaryOp, Func, FuncCall, UnaryOp};
 
 use crate::parse::Peek;
 
@@ -38,13 +38,76 @@ fn parse_let(input: ParseStream) -> Result<Statement> {
     };
     Ok(Statement::Let(left, mutable, ty, right))
 }
+// for x in v { body }
+//
+// There is no iterator protocol, iterating over a `Vec` is desugared in to a while loop over the
+// indices, copying each element in to `x`
+//
+// {
+//     let mut #for_x = 0;
+//     while #for_x < Vec::len(&v) {
+//         let x = v[#for_x];
+//         #for_x = #for_x + 1;
+//         { body }
+//     };
+// }
+fn parse_for(input: ParseStream) -> Result<Statement> {
+    let _for: Token![for] = input.parse()?;
+    let id: syn::Ident = input.parse()?;
+    let _in: Token![in] = input.parse()?;
+    let iter: Expr = input.parse()?;
+    let body: Block = input.parse()?;
+
+    let vec = match iter {
+        Expr::Ident(_) => iter,
+        Expr::UnOp(UnaryOp::Borrow, e) if matches!(*e, Expr::Ident(_)) => *e,
+        e => {
+            return Err(syn::Error::new(
+                id.span(),
+                format!("can only iterate over a variable, found {e}"),
+            ))
+        }
+    };
+    let counter = Expr::Ident(format!("#for_{id}"));
+    let len = Expr::FuncCall(FuncCall {
+        id: Box::new(Expr::Ident("Vec::len".to_owned())),
+        args: Box::new(vec![Expr::UnOp(UnaryOp::Borrow, Box::new(vec.clone()))]),
+    });
+    let body = Block {
+        statements: vec![
+            Statement::Let(
+                Expr::Ident(id.to_string()),
+                false,
+                None,
+                Some(Expr::Index(Box::new(vec), Box::new(counter.clone()))),
+            ),
+            Statement::Assign(
+                counter.clone(),
+                Expr::bin_op(BinaryOp::Add, counter.clone(), 1.into()),
+            ),
+            Statement::Block(body),
+        ],
+        semi: true,
+    };
+    Ok(Statement::Block(Block {
+        statements: vec![
+            Statement::Let(counter.clone(), true, None, Some(0.into())),
+            Statement::While(Expr::bin_op(BinaryOp::Lt, counter, len), body),
+        ],
+        semi: true,
+    }))
+}
 impl Statement {
     fn parse_inner(input: ParseStream) -> Result<Statement> {
         if input.peek(syn::token::Let) {
             parse_let(input)
-        } else if input.peek(syn::token::Fn) {
+        } else if input.peek(syn::token::Fn)
+            || (input.peek(Token![unsafe]) && input.peek2(Token![fn]))
+        {
             let func: Func = input.parse()?;
             Ok(Statement::FnDecleration(func))
+        } else if input.peek(Token![for]) {
+            parse_for(input)
         } else if input.peek(syn::token::While) {
             let _while: syn::token::While = input.parse()?;
 
@@ -110,6 +173,11 @@ impl Statement {
                 let _: Token![+=] = input.parse()?;
                 let rhs = input.parse()?;
                 let right = Expr::bin_op(crate::ast::BinaryOp::Add, left.clone(), rhs);
+                // An element is assigned, `v[i] += 1` is `v[i] = v[i] + 1`
+                let left = match left {
+                    Expr::Index(id, idx) => Expr::IndexMut(id, idx),
+                    left => left,
+                };
                 Ok(Statement::Assign(left, right))
             } else {
                 // 1 + 5
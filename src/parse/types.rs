@@ -1,4 +1,4 @@
-use crate::ast::Literal;
+use crate::ast::{Expr, Literal, Ref};
 
 use super::{Parse, ParseStream, Result, Type};
 use quote::quote;
@@ -18,23 +18,94 @@ impl Parse for Type {
             syn::bracketed!(content in input);
             let t: Type = content.parse()?;
             let _: syn::Token![;] = content.parse()?;
-            let count: Literal = content.parse()?;
-            let count = match count {
-                Literal::Int(i) => i as usize,
-                _ => panic!("Expected usize"),
+            // The length is either a literal or a constant expression that is evaluated
+            // once all constants are known
+            let count: Expr = content.parse()?;
+            return match count {
+                Expr::Lit(Literal::Int(i)) => Ok(Type::Array(Box::new(t), i as usize)),
+                count => Ok(Type::ArrayConst(Box::new(t), Box::new(count))),
             };
-            return Ok(Type::Array(Box::new(t), count));
-        } else if input.peek(Token![&]) && input.peek2(syn::token::Mut) {
-            let _: Token![&] = input.parse()?;
-            let _: syn::token::Mut = input.parse()?;
-            let t: Type = input.parse()?;
-            return Ok(Type::MutRef(t.into()));
         } else if input.peek(Token![&]) {
             let _: Token![&] = input.parse()?;
+            // `'_` is the same as leaving the lifetime out
+            let lifetime = match input.peek(syn::Lifetime) {
+                true => Some(input.parse::<syn::Lifetime>()?.to_string()),
+                false => None,
+            }
+            .filter(|lifetime| lifetime != "'_");
+            let mutable = input.peek(syn::token::Mut);
+            if mutable {
+                let _: syn::token::Mut = input.parse()?;
+            }
+            let t: Type = input.parse()?;
+            let r = Ref(Box::new(t), lifetime);
+            return Ok(match mutable {
+                true => Type::MutRef(r),
+                false => Type::Ref(r),
+            });
+        } else if input.peek(Token![*]) {
+            // A raw pointer, `*const T` or `*mut T`
+            let _: Token![*] = input.parse()?;
+            let mutable = match input.peek(Token![mut]) {
+                true => {
+                    let _: Token![mut] = input.parse()?;
+                    true
+                }
+                false => {
+                    let _: Token![const] = input.parse()?;
+                    false
+                }
+            };
             let t: Type = input.parse()?;
-            return Ok(Type::Ref(t.into()));
+            return Ok(match mutable {
+                true => Type::MutPtr(Box::new(t)),
+                false => Type::ConstPtr(Box::new(t)),
+            });
+        } else if input.peek(Token![fn]) {
+            // This is a function pointer type
+            let _: Token![fn] = input.parse()?;
+            let content;
+            syn::parenthesized!(content in input);
+            let args = content.parse_terminated(Type::parse, Token![,])?;
+            let ret: Type = if input.peek(Token![->]) {
+                let _: Token![->] = input.parse()?;
+                input.parse()?
+            } else {
+                Type::Unit
+            };
+            return Ok(Type::FnPtr(args.into_iter().collect(), Box::new(ret)));
+        } else if input.peek(syn::Ident) && input.peek2(Token![<]) {
+            // Generic types, `Box<T>`, `Rc<T>`, `Vec<T>`, `Option<T>`, `Result<T, E>` and the
+            // interior mutability types
+            let id: syn::Ident = input.parse()?;
+            let _: Token![<] = input.parse()?;
+            let t: Type = input.parse()?;
+            let err: Option<Type> = if input.peek(Token![,]) {
+                let _: Token![,] = input.parse()?;
+                Some(input.parse()?)
+            } else {
+                None
+            };
+            let _: Token![>] = input.parse()?;
+            return match (id.to_string().as_str(), err) {
+                ("Box", None) => Ok(Type::Box(Box::new(t))),
+                ("Rc", None) => Ok(Type::Rc(Box::new(t))),
+                ("Vec", None) => Ok(Type::Vec(Box::new(t))),
+                ("Cell", None) => Ok(Type::Cell(Box::new(t))),
+                ("RefCell", None) => Ok(Type::RefCell(Box::new(t))),
+                ("Ref", None) => Ok(Type::CellRef(Box::new(t))),
+                ("RefMut", None) => Ok(Type::CellRefMut(Box::new(t))),
+                ("Option", None) => Ok(Type::Option(Box::new(t))),
+                ("Result", Some(err)) => Ok(Type::Result(Box::new(t), Box::new(err))),
+                ("Result", None) => Err(syn::Error::new(id.span(), "expected Result<T, E>")),
+                _ => Err(syn::Error::new(
+                    id.span(),
+                    "expected Box, Rc, Vec, Cell, RefCell, Ref, RefMut, Option or Result",
+                )),
+            };
         }
-        let t: syn::Type = input.parse()?;
+        // A `+` after the type is the next operator, `a as i32 + 1`, not a bound
+        let t = syn::Type::without_plus(input)?;
 
         let ts = quote! {#t}.to_string();
         match ts.as_str() {
@@ -0,0 +1,383 @@
+//! Name resolution for modules and `use` imports.
+//!
+//! Every item declared inside of a module is renamed to its full path, e.g. `a::b::f`, and every
+//! reference to an item is rewritten to that same path. The later passes can then treat the
+//! program as if all items were declared in the global scope.
+pub mod expr;
+pub mod item;
+pub mod load;
+
+use std::collections::{hash_map::Entry, HashMap};
+use std::path::Path;
+
+use crate::ast::{Prog, Use};
+
+pub type ResolveErr = String;
+
+/// Denotes that a top level item takes part in name resolution.
+pub trait Resolve {
+    /// Reads the items of file backed modules, `dir` is the directory to search for them in
+    /// and `file` the file that the item itself was read from.
+    fn load(&mut self, _dir: &Path, _file: Option<&Path>) -> Result<(), ResolveErr> {
+        Ok(())
+    }
+    /// Registers the items, modules and imports that this node introduces.
+    fn declare(&self, resolver: &mut Resolver);
+    /// Rewrites all paths in the node to fully qualified paths.
+    fn resolve(&mut self, resolver: &mut Resolver) -> Result<(), ResolveErr>;
+}
+
+/// What a name in a module refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    /// A function or a static, identified by its full path.
+    Item(Vec<String>),
+    Module(Vec<String>),
+}
+
+/// A name in a module along with wether or not it is visible outside of the module.
+type Names = HashMap<String, (Binding, bool)>;
+
+#[derive(Debug)]
+pub struct Resolver {
+    /// The names declared in or imported in to each module, indexed by the module path.
+    modules: HashMap<Vec<String>, Names>,
+    /// All imports along with the module they occured in.
+    imports: Vec<(Vec<String>, Use)>,
+    /// The module currently being resolved.
+    current: Vec<String>,
+    /// The local variables currently in scope, these shadow items.
+    locals: Vec<Vec<String>>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            modules: HashMap::from([(vec![], HashMap::new())]),
+            imports: vec![],
+            current: vec![],
+            locals: vec![],
+        }
+    }
+
+    /// Binds `id` in the current module.
+    pub(crate) fn bind(&mut self, id: &str, binding: Binding, public: bool) {
+        self.modules
+            .entry(self.current.clone())
+            .or_default()
+            .insert(id.to_owned(), (binding, public));
+    }
+
+    pub(crate) fn import(&mut self, import: Use) {
+        self.imports.push((self.current.clone(), import));
+    }
+
+    pub(crate) fn enter_module(&mut self, id: &str) {
+        self.current.push(id.to_owned());
+        self.modules.entry(self.current.clone()).or_default();
+    }
+
+    pub(crate) fn exit_module(&mut self) {
+        self.current.pop();
+    }
+
+    /// Returns the full path of an item named `id` declared in the current module.
+    pub(crate) fn path_of(&self, id: &str) -> Vec<String> {
+        let mut path = self.current.clone();
+        path.push(id.to_owned());
+        path
+    }
+
+    pub(crate) fn qualify(&self, id: &str) -> String {
+        self.path_of(id).join("::")
+    }
+
+    /// An item is visible if it is public or if we are inside of the module that declares it.
+    fn visible(&self, module: &[String], public: bool) -> bool {
+        public || self.current.starts_with(module)
+    }
+
+    /// Resolves a path, e.g. `super::a::f`, relative to the current module.
+    pub fn resolve_path(&self, path: &[String]) -> Result<Binding, ResolveErr> {
+        let mut module = self.current.clone();
+        let mut segments = path.iter().peekable();
+        match segments.peek().map(|el| el.as_str()) {
+            Some("crate") => {
+                module.clear();
+                segments.next();
+            }
+            Some("self") => {
+                segments.next();
+            }
+            _ => {}
+        }
+        while segments.peek().map(|el| el.as_str()) == Some("super") {
+            if module.pop().is_none() {
+                return Err("There are too many leading `super` keywords".to_owned());
+            }
+            segments.next();
+        }
+
+        let mut binding = Binding::Module(module);
+        for segment in segments {
+            let module = match binding {
+                Binding::Module(module) => module,
+                Binding::Item(path) => {
+                    return Err(format!("`{}` is not a module", path.join("::")))
+                }
+            };
+            let (next, public) = match self.modules.get(&module).and_then(|el| el.get(segment)) {
+                Some(next) => next.clone(),
+                None => return Err(format!("Cannot find `{segment}` in {}", describe(&module))),
+            };
+            if !self.visible(&module, public) {
+                return Err(format!("`{segment}` is private in {}", describe(&module)));
+            }
+            binding = next;
+        }
+        Ok(binding)
+    }
+
+    /// Adds every import to the module it was declared in.
+    ///
+    /// Imports may refer to other imports so this is repeated until no more imports can be
+    /// resolved.
+    pub(crate) fn resolve_imports(&mut self) -> Result<(), ResolveErr> {
+        let mut imports = std::mem::take(&mut self.imports);
+        // Explicit imports take precedence over glob imports
+        imports.sort_by_key(|(_, import)| import.glob);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (module, import) in imports.iter() {
+                self.current = module.clone();
+                if let Ok(binding) = self.resolve_path(&import.path) {
+                    changed |= self.add_import(import, binding)?;
+                }
+            }
+        }
+        // Anything that can not be resolved at this point is an error.
+        for (module, import) in imports.iter() {
+            self.current = module.clone();
+            if let Err(e) = self.resolve_path(&import.path) {
+                return Err(format!(
+                    "Unresolved import `{}` in {}: {e}",
+                    import.path.join("::"),
+                    describe(module)
+                ));
+            }
+        }
+        self.current.clear();
+        Ok(())
+    }
+
+    /// Returns true if any new names were introduced in to the current module.
+    fn add_import(&mut self, import: &Use, binding: Binding) -> Result<bool, ResolveErr> {
+        if import.glob {
+            let target = match binding {
+                Binding::Module(target) => target,
+                Binding::Item(path) => {
+                    return Err(format!(
+                        "Cannot glob import from item `{}`",
+                        path.join("::")
+                    ))
+                }
+            };
+            let names: Vec<(String, Binding)> = self.modules[&target]
+                .iter()
+                .filter(|(_, (_, public))| self.visible(&target, *public))
+                .map(|(id, (binding, _))| (id.clone(), binding.clone()))
+                .collect();
+            let scope = self.modules.get_mut(&self.current).unwrap();
+            let mut changed = false;
+            for (id, binding) in names {
+                if let Entry::Vacant(entry) = scope.entry(id) {
+                    entry.insert((binding, import.public));
+                    changed = true;
+                }
+            }
+            return Ok(changed);
+        }
+
+        let id = match &import.alias {
+            Some(alias) => alias.clone(),
+            None => import.path.last().cloned().unwrap_or_default(),
+        };
+        let scope = self.modules.get_mut(&self.current).unwrap();
+        match scope.get(&id) {
+            Some((previous, _)) if *previous == binding => Ok(false),
+            Some(_) => Err(format!(
+                "`{id}` is defined multiple times in {}",
+                describe(&self.current)
+            )),
+            None => {
+                scope.insert(id, (binding, import.public));
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn describe(module: &[String]) -> String {
+    match module.is_empty() {
+        true => "the crate root".to_owned(),
+        false => format!("module `{}`", module.join("::")),
+    }
+}
+
+/// Loads all file backed modules relative to `source` and rewrites every path in the program to
+/// a fully qualified path.
+pub fn resolve(prog: &mut Prog, source: Option<&Path>) -> Result<(), ResolveErr> {
+    let dir = source.and_then(|el| el.parent()).unwrap_or(Path::new("."));
+    for item in prog.statements.iter_mut() {
+        item.load(dir, source)?;
+    }
+
+    let mut resolver = Resolver::new();
+    for item in prog.statements.iter() {
+        item.declare(&mut resolver);
+    }
+    resolver.resolve_imports()?;
+    for item in prog.statements.iter_mut() {
+        item.resolve(&mut resolver)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+
+    fn run(prog: &str) -> Result<(), String> {
+        let mut prog: Ast<Prog> = prog.to_string().into();
+        check!(prog)?;
+        let iter = 100;
+        eval!(prog, iter).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_module() {
+        let prog = "
+        mod math {
+            fn helper(a: i32) -> i32 {
+                a
+            }
+            pub fn add(a: i32, b: i32) -> i32 {
+                helper(a) + b
+            }
+        }
+        fn main() {
+            let a = math::add(1, 2);
+        }
+        ";
+        assert!(run(prog).is_ok());
+    }
+
+    #[test]
+    fn test_forward_call() {
+        // The functions of a module are declared before any of them is checked
+        let prog = "
+        mod a {
+            pub fn f() -> i32 {
+                g() + b::h()
+            }
+            fn g() -> i32 {
+                1
+            }
+            mod b {
+                pub fn h() -> i32 {
+                    super::g()
+                }
+            }
+        }
+        fn main() {
+            let x = a::f();
+        }
+        ";
+        assert!(run(prog).is_ok());
+
+        let prog = "
+        mod a {
+            fn g() -> i32 {
+                1
+            }
+            fn g() -> i32 {
+                2
+            }
+        }
+        fn main() {}
+        ";
+        assert!(run(prog).is_err());
+    }
+
+    #[test]
+    fn test_use_and_super() {
+        let prog = "
+        mod a {
+            pub fn one() -> i32 {
+                1
+            }
+            pub mod b {
+                pub fn two() -> i32 {
+                    super::one() + 1
+                }
+            }
+        }
+        use a::b::two as get_two;
+        fn main() {
+            let x = get_two() + crate::a::one();
+        }
+        ";
+        assert!(run(prog).is_ok());
+    }
+
+    #[test]
+    fn test_private_item() {
+        let prog = "
+        mod a {
+            fn secret() -> i32 {
+                1
+            }
+        }
+        fn main() {
+            let x = a::secret();
+        }
+        ";
+        let prog: Result<Ast<Prog>, String> = Ast::from_source(prog.to_string(), None);
+        assert!(prog.is_err());
+    }
+
+    #[test]
+    fn test_file_module() {
+        let dir = std::env::temp_dir().join("rnr_test_file_module");
+        std::fs::create_dir_all(dir.join("util")).unwrap();
+        std::fs::write(
+            dir.join("util.rs"),
+            "pub mod nested; pub fn three() -> i32 { nested::two() + 1 }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("util").join("nested.rs"),
+            "pub fn two() -> i32 { 2 }",
+        )
+        .unwrap();
+        let main = dir.join("main.rs");
+        let prog = "
+        mod util;
+        use util::*;
+        fn main() {
+            let x = three();
+        }
+        ";
+        let mut prog: Ast<Prog> = Ast::from_source(prog.to_string(), Some(&main)).unwrap();
+        assert!(check!(prog).is_ok());
+        assert!(prog.to_string().contains("fn util::nested::two"));
+    }
+}
@@ -0,0 +1,194 @@
+//! Rewrites paths in expressions to fully qualified paths
+
+use super::{Binding, ResolveErr, Resolver};
+use crate::ast::{Block, Expr, Func, Ref, Statement, Type};
+
+fn ident(id: &Expr) -> Option<String> {
+    match id {
+        Expr::Ident(id) => Some(id.clone()),
+        _ => None,
+    }
+}
+
+impl Resolver {
+    fn is_local(&self, id: &str) -> bool {
+        self.locals
+            .iter()
+            .any(|scope| scope.iter().any(|el| el == id))
+    }
+
+    fn declare_local(&mut self, id: &Expr) {
+        if let (Some(id), Some(scope)) = (ident(id), self.locals.last_mut()) {
+            scope.push(id);
+        }
+    }
+
+    pub(crate) fn resolve_fn(&mut self, f: &mut Func) -> Result<(), ResolveErr> {
+        for arg in f.args.iter_mut() {
+            self.resolve_type(&mut arg.ty)?;
+        }
+        self.resolve_type(&mut f.ty)?;
+        self.locals
+            .push(f.args.iter().filter_map(|arg| ident(&arg.id)).collect());
+        let ret = self.resolve_block(&mut f.body);
+        self.locals.pop();
+        ret
+    }
+
+    pub(crate) fn resolve_expr(&mut self, expr: &mut Expr) -> Result<(), ResolveErr> {
+        match expr {
+            Expr::Ident(id) => self.resolve_ident(id),
+            Expr::Lit(_) => Ok(()),
+            Expr::BinOp(_, lhs, rhs) => {
+                self.resolve_expr(lhs)?;
+                self.resolve_expr(rhs)
+            }
+            Expr::UnOp(_, e) | Expr::Par(e) | Expr::Try(e) => self.resolve_expr(e),
+            Expr::IfThenElse(cond, then_block, else_block) => {
+                self.resolve_expr(cond)?;
+                self.resolve_block(then_block)?;
+                match else_block {
+                    Some(else_block) => self.resolve_block(else_block),
+                    None => Ok(()),
+                }
+            }
+            Expr::Array(elements) => {
+                for el in elements.iter_mut() {
+                    self.resolve_expr(el)?;
+                }
+                Ok(())
+            }
+            Expr::Index(id, idx) | Expr::IndexMut(id, idx) => {
+                self.resolve_expr(id)?;
+                self.resolve_expr(idx)
+            }
+            Expr::FuncCall(call) => {
+                self.resolve_expr(&mut call.id)?;
+                for arg in call.args.iter_mut() {
+                    self.resolve_expr(arg)?;
+                }
+                Ok(())
+            }
+            Expr::Block(block) | Expr::Unsafe(block) => self.resolve_block(block),
+            Expr::Cast(e, ty) => {
+                self.resolve_expr(e)?;
+                self.resolve_type(ty)
+            }
+            Expr::Closure(closure) => {
+                for ty in closure
+                    .args
+                    .iter_mut()
+                    .filter_map(|arg| arg.ty.as_mut())
+                    .chain(closure.ty.as_mut())
+                {
+                    self.resolve_type(ty)?;
+                }
+                self.locals.push(
+                    closure
+                        .args
+                        .iter()
+                        .filter_map(|arg| ident(&arg.id))
+                        .collect(),
+                );
+                let ret = self.resolve_expr(&mut closure.body);
+                self.locals.pop();
+                ret
+            }
+        }
+    }
+
+    /// Resolves paths in constant array lengths.
+    pub(crate) fn resolve_type(&mut self, ty: &mut Type) -> Result<(), ResolveErr> {
+        match ty {
+            Type::ArrayConst(ty, len) => {
+                self.resolve_type(ty)?;
+                self.resolve_expr(len)
+            }
+            Type::Array(ty, _)
+            | Type::Ref(Ref(ty, _))
+            | Type::MutRef(Ref(ty, _))
+            | Type::Box(ty)
+            | Type::Rc(ty)
+            | Type::Vec(ty)
+            | Type::Cell(ty)
+            | Type::RefCell(ty)
+            | Type::CellRef(ty)
+            | Type::CellRefMut(ty)
+            | Type::ConstPtr(ty)
+            | Type::MutPtr(ty)
+            | Type::Option(ty) => self.resolve_type(ty),
+            Type::Result(ty, err) => {
+                self.resolve_type(ty)?;
+                self.resolve_type(err)
+            }
+            Type::Closure(args, ret, _) | Type::FnPtr(args, ret) => {
+                for arg in args.iter_mut() {
+                    self.resolve_type(arg)?;
+                }
+                self.resolve_type(ret)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve_ident(&mut self, id: &mut String) -> Result<(), ResolveErr> {
+        // Macros and local variables are left as is
+        if id.ends_with('!') || (!id.contains("::") && self.is_local(id)) {
+            return Ok(());
+        }
+        let path: Vec<String> = id.split("::").map(|el| el.to_owned()).collect();
+        match self.resolve_path(&path) {
+            Ok(Binding::Item(path)) => {
+                *id = path.join("::");
+                Ok(())
+            }
+            Ok(Binding::Module(_)) => Err(format!("Expected value, found module `{id}`")),
+            Err(_) if crate::intrinsics::is_intrinsic(id) => Ok(()),
+            // Leave it to the type checker to report undeclared identifiers in the crate root
+            Err(_) if path.len() == 1 && self.current.is_empty() => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn resolve_block(&mut self, block: &mut Block) -> Result<(), ResolveErr> {
+        self.locals.push(vec![]);
+        let mut ret = Ok(());
+        for statement in block.statements.iter_mut() {
+            ret = self.resolve_statement(statement);
+            if ret.is_err() {
+                break;
+            }
+        }
+        self.locals.pop();
+        ret
+    }
+
+    fn resolve_statement(&mut self, statement: &mut Statement) -> Result<(), ResolveErr> {
+        match statement {
+            Statement::Let(id, _, ty, rhs) => {
+                if let Some(ty) = ty {
+                    self.resolve_type(ty)?;
+                }
+                if let Some(rhs) = rhs {
+                    self.resolve_expr(rhs)?;
+                }
+                self.declare_local(id);
+                Ok(())
+            }
+            Statement::Assign(lhs, rhs) => {
+                self.resolve_expr(rhs)?;
+                self.resolve_expr(lhs)
+            }
+            Statement::While(cond, block) => {
+                self.resolve_expr(cond)?;
+                self.resolve_block(block)
+            }
+            Statement::Expr(e) => self.resolve_expr(e),
+            Statement::Block(block) => self.resolve_block(block),
+            Statement::FnDecleration(f) => {
+                self.declare_local(&f.id);
+                self.resolve_fn(f)
+            }
+        }
+    }
+}
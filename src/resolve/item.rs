@@ -0,0 +1,98 @@
+//! Name resolution for top level items
+
+use std::path::Path;
+
+use super::load::{module_file, read_module};
+use super::{Binding, Resolve, ResolveErr, Resolver};
+use crate::ast::{Expr, Func, Module, Static, Use};
+
+impl Resolve for Func {
+    fn declare(&self, resolver: &mut Resolver) {
+        if let Expr::Ident(id) = &self.id {
+            resolver.bind(id, Binding::Item(resolver.path_of(id)), self.public);
+        }
+    }
+
+    fn resolve(&mut self, resolver: &mut Resolver) -> Result<(), ResolveErr> {
+        if let Expr::Ident(id) = &mut self.id {
+            *id = resolver.qualify(id);
+        }
+        resolver.resolve_fn(self)
+    }
+}
+
+impl Resolve for Static {
+    fn declare(&self, resolver: &mut Resolver) {
+        resolver.bind(
+            &self.id,
+            Binding::Item(resolver.path_of(&self.id)),
+            self.public,
+        );
+    }
+
+    fn resolve(&mut self, resolver: &mut Resolver) -> Result<(), ResolveErr> {
+        self.id = resolver.qualify(&self.id);
+        resolver.resolve_type(&mut self.ty)?;
+        resolver.resolve_expr(&mut self.value)
+    }
+}
+
+impl Resolve for Module {
+    fn load(&mut self, dir: &Path, file: Option<&Path>) -> Result<(), ResolveErr> {
+        match self.inline {
+            true => self.file = file.map(|el| el.to_path_buf()),
+            false => {
+                let file = module_file(dir, &self.id)?;
+                self.items = read_module(&file)?;
+                self.file = Some(file);
+            }
+        }
+        // Modules nested in `a.rs` or `a/mod.rs` are both read from `a/`
+        let dir = dir.join(&self.id);
+        let file = self.file.clone();
+        let mut ret = Ok(());
+        for item in self.items.iter_mut() {
+            ret = item.load(&dir, file.as_deref());
+            if ret.is_err() {
+                break;
+            }
+        }
+        ret.map_err(|e| self.locate(e))
+    }
+
+    fn declare(&self, resolver: &mut Resolver) {
+        resolver.bind(
+            &self.id,
+            Binding::Module(resolver.path_of(&self.id)),
+            self.public,
+        );
+        resolver.enter_module(&self.id);
+        for item in self.items.iter() {
+            item.declare(resolver);
+        }
+        resolver.exit_module();
+    }
+
+    fn resolve(&mut self, resolver: &mut Resolver) -> Result<(), ResolveErr> {
+        resolver.enter_module(&self.id);
+        let mut ret = Ok(());
+        for item in self.items.iter_mut() {
+            ret = item.resolve(resolver);
+            if ret.is_err() {
+                break;
+            }
+        }
+        resolver.exit_module();
+        ret.map_err(|e| self.locate(e))
+    }
+}
+
+impl Resolve for Use {
+    fn declare(&self, resolver: &mut Resolver) {
+        resolver.import(self.clone());
+    }
+
+    fn resolve(&mut self, _resolver: &mut Resolver) -> Result<(), ResolveErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+//! Reads file backed modules from disk
+
+use std::path::{Path, PathBuf};
+
+use super::ResolveErr;
+use crate::ast::TopLevel;
+use crate::parse::Items;
+
+/// Finds the file for `mod id;`, either `dir/id.rs` or `dir/id/mod.rs`.
+pub(crate) fn module_file(dir: &Path, id: &str) -> Result<PathBuf, ResolveErr> {
+    let candidates = [dir.join(format!("{id}.rs")), dir.join(id).join("mod.rs")];
+    match candidates.iter().find(|el| el.is_file()) {
+        Some(file) => Ok(file.clone()),
+        None => Err(format!(
+            "Cannot find file for module `{id}`, expected {} or {}",
+            candidates[0].display(),
+            candidates[1].display()
+        )),
+    }
+}
+
+/// Reads and parses all items in `file`.
+pub(crate) fn read_module(file: &Path) -> Result<Vec<Box<dyn TopLevel>>, ResolveErr> {
+    let source = match std::fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(e) => return Err(format!("Cannot read {}: {e}", file.display())),
+    };
+    let items: Items = crate::parse_source(&source, Some(file))?;
+    Ok(items.0)
+}
@@ -1,40 +1,62 @@
 pub mod block;
+pub mod cell;
+pub mod closure;
 pub mod expr;
+pub mod format;
+pub mod fs;
 pub mod func;
 pub mod globals;
+pub mod host;
+pub mod input;
 pub mod literal;
+pub mod module;
 pub mod op;
+pub mod option;
+pub mod panic;
 pub mod program;
 pub mod statement;
+pub mod vec;
 
 pub use block::*;
+pub use cell::*;
+pub use closure::*;
 pub use expr::*;
+pub use format::*;
+pub use fs::*;
 pub use func::*;
 pub use globals::*;
+pub use host::*;
+pub use input::*;
 pub use literal::*;
+pub use module::*;
 pub use op::*;
+pub use option::*;
+pub use panic::*;
 pub use program::*;
 pub use statement::*;
+pub use vec::*;
 
-use crate::ast::{Expr, Func, Type};
+use crate::ast::{Expr, Func, Type, UnaryOp};
 
 use std::collections::HashMap;
+use std::fmt::Display;
 
 // So let's implement a type checker
 // Here we go!!!!
-#[derive(Debug, Clone, Copy)]
-pub enum Ref {
-    Mutable,
-    Immutable(usize),
-}
 /// Describes all of the needed data for a value.
 #[derive(Debug, Clone)]
 pub struct ValueMeta {
     ty: Option<Type>,
+    /// True if the variable is assigned on every path to this point
     assigned: bool,
+    /// True if the variable is assigned on any path to this point, an immutable variable can only
+    /// be assigned if it is not
+    maybe_assigned: bool,
     mutable: bool,
     shadowable: bool,
-    ref_counter: Option<Ref>,
+    /// Set once the value has been moved out of the variable, e.g. `moved by let b = a`, until
+    /// the variable is assigned again
+    moved: Option<String>,
 }
 #[derive(Debug, Clone)]
 pub struct FunctionMeta {
@@ -42,6 +64,8 @@ pub struct FunctionMeta {
     /// The variable scope, this should include
     /// all arguments and their types
     args: Vec<(Type, bool)>,
+    /// True for an `unsafe fn`, it can only be called in an unsafe context
+    unsafety: bool,
 }
 
 /// Represents the functions accessible in the current scope
@@ -54,12 +78,28 @@ pub type Scope = HashMap<String, ValueMeta>;
 pub type TypeEnv = Vec<(Scope, FunctionScope)>;
 pub type TypeErr = String;
 
+/// The variable holding the return type of the enclosing function or closure, `?` returns to it.
+const RETURN: &str = "#return";
+
+/// Declared in the scope of an `unsafe` block and of the body of an `unsafe fn`, raw pointers
+/// can only be dereferenced, mutable statics accessed and unsafe functions called where it is
+/// visible.
+const UNSAFE: &str = "#unsafe";
+
 /// Denotes that a type is simply TypeCheckable.
 ///
 /// This means that given the current vec of all
 /// [`Scope`]s and the index of the current scope.
 pub trait TypeCheck {
     fn check(&self, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr>;
+
+    /// Declares the signature of the item in scope `idx` before any of the items next to it are
+    /// checked, so they can call it regardless of the order they are written in.
+    ///
+    /// Only functions have anything to declare.
+    fn declare_signature(&self, _env: &mut TypeEnv, _idx: usize) -> Result<(), TypeErr> {
+        Ok(())
+    }
 }
 
 impl From<Func> for FunctionMeta {
@@ -70,7 +110,41 @@ impl From<Func> for FunctionMeta {
             .iter()
             .map(|arg| (arg.ty.clone(), arg.mutable))
             .collect();
-        Self { ty, args }
+        Self {
+            ty,
+            args,
+            unsafety: value.unsafety,
+        }
+    }
+}
+
+impl ValueMeta {
+    /// Returns true if the variable is a `static mut`, it can only be accessed in an unsafe
+    /// context.
+    fn is_static_mut(&self) -> bool {
+        !self.shadowable && self.mutable
+    }
+
+    /// The variable that marks a scope as unsafe, see [`UNSAFE`].
+    fn unsafe_marker() -> Self {
+        Self {
+            ty: Some(Type::Unit),
+            assigned: true,
+            maybe_assigned: true,
+            mutable: false,
+            shadowable: false,
+            moved: None,
+        }
+    }
+}
+
+/// Returns an error if `what` is done outside of an `unsafe` block or function.
+fn require_unsafe(env: &TypeEnv, what: &dyn Display) -> Result<(), TypeErr> {
+    match env.iter().any(|(scope, _)| scope.contains_key(UNSAFE)) {
+        true => Ok(()),
+        false => Err(format!(
+            "{what} is unsafe and requires an unsafe block or function"
+        )),
     }
 }
 
@@ -89,6 +163,159 @@ fn get_meta<'a>(env: &'a mut TypeEnv, expr: &Expr) -> Result<Option<&'a mut Valu
     Ok(env.get_mut(scope).unwrap().0.get_mut(id))
 }
 
+/// Returns an error if the variable `id` in `meta` can not be read, i.e. it has not been
+/// assigned on every path to this point or its value has been moved out of it.
+fn usable(id: &str, meta: &ValueMeta) -> Result<(), TypeErr> {
+    if !meta.assigned {
+        return Err(format!("Use of possibly uninitialized variable {id}"));
+    }
+    match &meta.moved {
+        Some(moved) => Err(format!("Use of {id} after it was {moved}")),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `expr` can be read if it is a variable, see [`usable`].
+fn check_usable(env: &mut TypeEnv, expr: &Expr) -> Result<(), TypeErr> {
+    match expr {
+        Expr::Ident(id) => match get_meta(env, expr)? {
+            Some(meta) => usable(id, meta),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Moves the value of `expr` out of the place it is read from, `by` is the expression that
+/// moves it. Values of [`Copy`](Type::is_copy) types are copied and values behind references
+/// or in arrays can not be moved at all.
+fn consume(env: &mut TypeEnv, expr: &Expr, by: &dyn std::fmt::Display) -> Result<(), TypeErr> {
+    match expr {
+        Expr::Ident(id) => {
+            let meta = match get_meta(env, expr)? {
+                Some(meta) => meta,
+                // Functions
+                None => return Ok(()),
+            };
+            match &meta.ty {
+                Some(ty) if !ty.is_copy() && !meta.shadowable => Err(format!(
+                    "Cannot move out of static {id} of type {ty} in {by}"
+                )),
+                Some(ty) if !ty.is_copy() => {
+                    meta.moved = Some(format!("moved by {by}"));
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+        Expr::UnOp(UnaryOp::Dereff, e) if matches!(**e, Expr::Ident(_)) => {
+            let ty = match get_meta(env, e)?.and_then(|meta| meta.ty.clone()) {
+                Some(ty) => ty,
+                None => return Ok(()),
+            };
+            match ty {
+                Type::Ref(crate::ast::Ref(ty, _))
+                | Type::MutRef(crate::ast::Ref(ty, _))
+                | Type::CellRef(ty)
+                | Type::CellRefMut(ty)
+                    if !ty.is_copy() =>
+                {
+                    Err(format!(
+                        "Cannot move out of {expr} in {by} as it is behind a refference, \
+                         {ty} is not Copy"
+                    ))
+                }
+                Type::Rc(ty) if !ty.is_copy() => Err(format!(
+                    "Cannot move out of {expr} in {by} as it is shared by an Rc, {ty} is not Copy"
+                )),
+                // Moving the value out of the box leaves the box partially moved
+                Type::Box(ty) if !ty.is_copy() => {
+                    if let Some(meta) = get_meta(env, e)? {
+                        meta.moved = Some(format!("partially moved by {by}"));
+                    }
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+        Expr::Index(target, _) | Expr::IndexMut(target, _)
+            if matches!(**target, Expr::Ident(_)) =>
+        {
+            let ty = get_meta(env, target)?.and_then(|meta| meta.ty.clone());
+            match ty {
+                Some(Type::Array(ty, _) | Type::Vec(ty)) if !ty.is_copy() => Err(format!(
+                    "Cannot move out of {expr} in {by}, the elements are of type {ty} which is \
+                     not Copy, borrow the element instead"
+                )),
+                _ => Ok(()),
+            }
+        }
+        Expr::Par(e) | Expr::Try(e) => consume(env, e, by),
+        // Every other expression creates a new value
+        _ => Ok(()),
+    }
+}
+
+/// The part of a [`ValueMeta`] that depends on the path taken through the program.
+#[derive(Debug, Clone, PartialEq)]
+struct Flow {
+    assigned: bool,
+    maybe_assigned: bool,
+    moved: Option<String>,
+}
+
+/// The [`Flow`] of every variable in each scope.
+type FlowState = Vec<HashMap<String, Flow>>;
+
+fn flow(env: &TypeEnv) -> FlowState {
+    env.iter()
+        .map(|(scope, _)| {
+            scope
+                .iter()
+                .map(|(id, meta)| {
+                    let flow = Flow {
+                        assigned: meta.assigned,
+                        maybe_assigned: meta.maybe_assigned,
+                        moved: meta.moved.clone(),
+                    };
+                    (id.clone(), flow)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Resets the variables to `state`, e.g. before checking the else block of an `if` the
+/// assignments and moves in the then block are undone.
+fn restore_flow(env: &mut TypeEnv, state: &FlowState) {
+    for ((scope, _), state) in env.iter_mut().zip(state.iter()) {
+        for (id, meta) in scope.iter_mut() {
+            if let Some(flow) = state.get(id) {
+                meta.assigned = flow.assigned;
+                meta.maybe_assigned = flow.maybe_assigned;
+                meta.moved = flow.moved.clone();
+            }
+        }
+    }
+}
+
+/// Joins the current state of the variables with `state`, the state after another path to the
+/// same point. A variable is only assigned if it is assigned on both paths while it may have
+/// been assigned or moved if it was on either of them.
+fn join_flow(env: &mut TypeEnv, state: &FlowState) {
+    for ((scope, _), state) in env.iter_mut().zip(state.iter()) {
+        for (id, meta) in scope.iter_mut() {
+            if let Some(flow) = state.get(id) {
+                meta.assigned &= flow.assigned;
+                meta.maybe_assigned |= flow.maybe_assigned;
+                if meta.moved.is_none() {
+                    meta.moved = flow.moved.clone();
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -126,10 +353,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: false,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -148,10 +376,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: true,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -170,10 +399,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: true,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -192,10 +422,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: true,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -220,10 +451,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: false,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -257,10 +489,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: false,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -278,10 +511,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: false,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -312,10 +546,11 @@ mod test {
             "a".to_string(),
             ValueMeta {
                 ty: Some(Type::I32),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: false,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
@@ -339,14 +574,583 @@ mod test {
             "b".to_string(),
             ValueMeta {
                 ty: Some(Type::Bool),
-                assigned: false,
+                assigned: true,
+                maybe_assigned: true,
                 mutable: false,
                 shadowable: true,
-                ref_counter: None,
+                moved: None,
             },
         );
         env.push((scope, HashMap::new()));
         let ty = e.check(&mut env, 0).unwrap();
         assert_eq!(ty, Type::Bool);
     }
+
+    #[test]
+    fn test_box_and_rc() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut b: Box<i32> = Box::new(1);
+        *b = 2;
+        let a = Rc::new(Box::new(true));
+        let c = Rc::clone(&a);
+        **c
+    }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        let ty = e.check(&mut env, 0).unwrap();
+        assert_eq!(ty, Type::Bool);
+
+        let ts: proc_macro2::TokenStream = "
+    {
+        let a = Rc::new(1);
+        *a = 2;
+    }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        assert!(e.check(&mut env, 0).is_err());
+    }
+
+    #[test]
+    fn test_vec() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut v = Vec::new();
+        v.push(true);
+        let w: Vec<i32> = vec![1, 2];
+        for x in &w {
+            v.push(x == 1);
+        };
+        v[0] = false;
+        v.pop().unwrap()
+    }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        let ty = e.check(&mut env, 0).unwrap();
+        assert_eq!(ty, Type::Bool);
+
+        for prog in [
+            // pushing requires a mutable vector
+            "{ let v = vec![1]; v.push(2); }",
+            // as does assigning to an element
+            "{ let v = vec![1]; v[0] = 2; }",
+            "{ let mut v = vec![1]; v.push(true); }",
+        ] {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            let mut env = TypeEnv::new();
+            env.push((Scope::new(), HashMap::new()));
+            assert!(e.check(&mut env, 0).is_err(), "{prog}");
+        }
+    }
+
+    #[test]
+    fn test_option_and_try() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn half(x: i32) -> Result<i32, bool> {
+            if x == 4 { Ok(2) } else { Err(false) }
+        };
+        fn quarter(x: i32) -> Result<i32, bool> {
+            let h = half(x)?;
+            half(h + h)
+        };
+        let mut a = None;
+        a = Some(quarter(4).unwrap());
+        let none = a == None || None == a;
+        a.is_some() && !none
+    }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        let ty = e.check(&mut env, 0).unwrap();
+        assert_eq!(ty, Type::Bool);
+
+        for prog in [
+            // `?` requires the function to return an Option or a Result
+            "{ fn f(a: Option<i32>) -> i32 { a? }; }",
+            "{ fn f(a: Option<i32>) -> Result<i32, bool> { Ok(a?) }; }",
+            // with the same error type
+            "{ fn f(a: Result<i32, i32>) -> Result<i32, bool> { Ok(a?) }; }",
+            // and is not allowed outside of a function
+            "{ let a = Some(1); a? }",
+            "{ let a = Some(1); let b: Option<bool> = a; }",
+            "{ let a = Some(1); let b = a == Some(true); }",
+        ] {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            let mut env = TypeEnv::new();
+            env.push((Scope::new(), HashMap::new()));
+            assert!(e.check(&mut env, 0).is_err(), "{prog}");
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let a = 1;
+        let v = vec![true];
+        print!(\"{} {:?} {a:>5} {0:x} {{}}\", &a, &v);
+        eprintln!();
+        format!(\"{:?}\", [1, 2])
+    }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        assert_eq!(e.check(&mut env, 0).unwrap(), Type::String);
+
+        for prog in [
+            // the number of arguments must match the format string
+            "{ println!(\"{} {}\", 1) }",
+            "{ println!(\"{}\", 1, 2) }",
+            "{ println!(\"{1}\", 1, 2) }",
+            // and the arguments must be formattable
+            "{ println!(\"{:x}\", true) }",
+            "{ println!(\"{}\", vec![1]) }",
+            "{ println!(\"{missing}\") }",
+            "{ print!() }",
+            "{ let s: i32 = format!(\"a\"); }",
+        ] {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            let mut env = TypeEnv::new();
+            env.push((Scope::new(), HashMap::new()));
+            assert!(e.check(&mut env, 0).is_err(), "{prog}");
+        }
+    }
+
+    #[test]
+    fn test_assertions() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn sign(a: i32) -> i32 {
+            assert_ne!(a, 0, \"a was {}\", a);
+            if a > 0 { 1 } else if a < 0 { 0 - 1 } else { unreachable!() }
+        };
+        fn later() -> bool { todo!() };
+        assert!(sign(2) == 1);
+        assert_eq!(sign(0 - 2), 0 - 1)
+    }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        assert_eq!(e.check(&mut env, 0).unwrap(), Type::Unit);
+
+        for prog in [
+            "{ assert!(1) }",
+            "{ assert_eq!(1, true) }",
+            "{ assert_ne!(1) }",
+            // the message must be a format string
+            "{ panic!(1) }",
+            "{ assert!(true, \"{}\") }",
+        ] {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            let mut env = TypeEnv::new();
+            env.push((Scope::new(), HashMap::new()));
+            assert!(e.check(&mut env, 0).is_err(), "{prog}");
+        }
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let mut host = crate::intrinsics::IntrinsicRegistry::new();
+        host.register("double", vec![Type::I32], Type::I32, |args| {
+            Ok(args[0].clone())
+        })
+        .unwrap();
+        let ts: proc_macro2::TokenStream = "
+        {
+            let a: i32 = double(2);
+            double(a)
+        }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        assert_eq!(e.check(&mut host.type_env(), 0), Ok(Type::I32));
+
+        for prog in [
+            "{ double(true) }",
+            "{ double(1, 2) }",
+            "{ let a: bool = double(1); }",
+            "{ triple(1) }",
+        ] {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            assert!(e.check(&mut host.type_env(), 0).is_err(), "{prog}");
+        }
+    }
+
+    #[test]
+    fn test_input() {
+        let ts: proc_macro2::TokenStream = "
+        {
+            let line: String = read_line();
+            let args: Vec<String> = args();
+            read_i32() + 1
+        }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        assert_eq!(e.check(&mut env, 0), Ok(Type::I32));
+
+        for prog in ["{ read_line(1) }", "{ let a: bool = read_i32(); }"] {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            let mut env = TypeEnv::new();
+            env.push((Scope::new(), HashMap::new()));
+            assert!(e.check(&mut env, 0).is_err(), "{prog}");
+        }
+    }
+
+    #[test]
+    fn test_fs() {
+        let ts: proc_macro2::TokenStream = "
+        {
+            let path = \"out.txt\";
+            let w: Result<(), String> = fs::write(&path, format!(\"{}\", 1));
+            let r: Result<String, String> = std::fs::read_to_string(path);
+            r
+        }"
+        .parse()
+        .unwrap();
+        let e: Block = syn::parse2(ts).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        assert_eq!(
+            e.check(&mut env, 0),
+            Ok(Type::Result(Box::new(Type::String), Box::new(Type::String)))
+        );
+
+        for prog in [
+            "{ fs::read_to_string() }",
+            "{ fs::read_to_string(1) }",
+            "{ fs::write(\"out.txt\") }",
+        ] {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            let mut env = TypeEnv::new();
+            env.push((Scope::new(), HashMap::new()));
+            assert!(e.check(&mut env, 0).is_err(), "{prog}");
+        }
+    }
+
+    // helper to check a block in an environment with a single scope
+    fn check_src(prog: &str) -> Result<Type, TypeErr> {
+        let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+        let mut env = TypeEnv::new();
+        env.push((Scope::new(), HashMap::new()));
+        e.check(&mut env, 0)
+    }
+
+    // helper to check that each block is rejected with an error containing the message
+    fn assert_rejected(progs: &[(&str, &str)]) {
+        for (prog, err) in progs {
+            match check_src(prog) {
+                Err(e) => assert!(e.contains(err), "{prog}: {e}"),
+                Ok(ty) => unreachable!("{prog} should not type check, got {ty}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        for prog in [
+            "{ let a = 1; let f = |x: i32| a + x; f(1) }",
+            "{ let mut a = 1; let mut f = || { a = a + 1; }; f(); a }",
+            "{ let a = 1; let f = move || { a + 1 }; f() }",
+        ] {
+            assert!(check_src(prog).is_ok(), "{prog}: {:?}", check_src(prog));
+        }
+        assert_rejected(&[
+            (
+                "{ let mut a = 1; let f = || { a = a + 1; }; f(); a }",
+                "Cannot borrow f as mutable, as it is not declared as mutable",
+            ),
+            (
+                "{ let a = 1; let mut f = || { a = a + 1; }; f(); a }",
+                "Closure cannot mutate a since it is not decleared as mutable",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_moves() {
+        for prog in [
+            // Arrays of copy types are copied
+            "{ let a = [1, 2]; let b = a; a[0] + b[0] }",
+            "{ let a = \"a\"; let b = &a; let c = a == *b; a }",
+            "{ let mut a = \"a\"; let b = a; a = \"b\"; a }",
+            "{ let a = \"a\"; println!(\"{}\", a); a }",
+            "{ let a = \"a\"; if true { let b = a; } else { let c = 1; }; 1 }",
+            "{ let mut v = vec![1]; let r = &mut v; fn f(v: &mut Vec<i32>) {}; f(r); f(r); }",
+            "{ let mut i = 0; while i < 2 { let a = \"a\"; let b = a; i = i + 1; }; i }",
+            "{ let b = Box::new(1); let c = *b; b }",
+        ] {
+            assert!(check_src(prog).is_ok(), "{prog}: {:?}", check_src(prog));
+        }
+        assert_rejected(&[
+            (
+                "{ let a = [\"a\"]; let b = a; a[0] }",
+                "Use of a after it was moved by",
+            ),
+            (
+                "{ let a = \"a\"; if true { let b = a; } else { let c = 1; }; a }",
+                "Use of a after it was moved by",
+            ),
+            (
+                "{ let v = vec![1]; fn f(v: Vec<i32>) {}; f(v); Vec::len(&v) }",
+                "Use of v after it was moved by f(v)",
+            ),
+            (
+                "{ let a = \"a\"; let mut i = 0; while i < 2 { let b = a; i = i + 1; }; i }",
+                "in a previous iteration of the loop",
+            ),
+            (
+                "{ let a = \"a\"; let r = &a; let b = *r; b }",
+                "Cannot move out of *r",
+            ),
+            (
+                "{ let a = [\"a\"]; let b = a[0]; b }",
+                "Cannot move out of a[0]",
+            ),
+            (
+                "{ let b = Box::new(\"a\"); let c = *b; b }",
+                "Use of b after it was partially moved by",
+            ),
+            (
+                "{ let a = Some(\"a\"); let f = move || a; a }",
+                "Use of a after it was moved by",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_definite_init() {
+        for prog in [
+            "{ let a; a = 1; a }",
+            "{ let c = true; let a; if c { a = 1; } else { a = 2; }; a }",
+            "{ let c = true; let a: i32; if c { a = 1; }; c }",
+            "{ let c = true; let a; if c { a = 1; } else { panic!() }; a }",
+            "{ let mut a; let mut i = 0; while i < 2 { a = i; i = i + 1; }; a = 0; a }",
+        ] {
+            assert!(check_src(prog).is_ok(), "{prog}: {:?}", check_src(prog));
+        }
+        assert_rejected(&[
+            (
+                "{ let a: i32; a + 1 }",
+                "Use of possibly uninitialized variable a",
+            ),
+            (
+                "{ let c = true; let a; if c { a = 1; }; a }",
+                "Use of possibly uninitialized variable a",
+            ),
+            (
+                "{ let mut a; let mut i = 0; while i < 2 { a = i; i = i + 1; }; a }",
+                "Use of possibly uninitialized variable a",
+            ),
+            (
+                "{ let a: i32; let r = &a; *r }",
+                "Use of possibly uninitialized variable a",
+            ),
+            (
+                "{ let a; a = 1; a = 2; a }",
+                "Cannot assign twice to immutable variable a",
+            ),
+            (
+                "{ let c = true; let a; if c { a = 1; }; a = 2; a }",
+                "Cannot assign twice to immutable variable a",
+            ),
+            (
+                "{ let a; let mut i = 0; while i < 2 { a = i; i = i + 1; }; i }",
+                "Cannot assign twice to immutable variable a",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_reborrows() {
+        for prog in [
+            "{ let mut a = 1; let r = &mut a; let s = &mut *r; *s = 2; let t = &*r; *t }",
+            "{ let mut b = Box::new(1); let r = &mut *b; *r = 2; 1 }",
+            // `&mut T` is coerced to `&T` at call sites
+            "{ fn f(x: &i32) -> i32 { *x }; let mut a = 1; let r = &mut a; f(r) }",
+            "{ let mut a = 1; let r = &mut a; let f = |x: &i32| *x; f(r) }",
+            // Comparisons, method receivers and formatting look through references
+            "{ let a = 1; let r = &a; let rr = &r; let c = r == 1; rr < 2 }",
+            "{ let v = vec![1]; let r = &v; let rr = &r; rr.len() }",
+            "{ let mut v = vec![1]; let mut r = &mut v; let rr = &mut r; rr.push(2); }",
+            "{ let o = Some(1); let r = &o; let rr = &r; rr.is_some() }",
+            "{ let a = 1; let r = &a; let rr = &r; println!(\"{}\", rr); }",
+        ] {
+            assert!(check_src(prog).is_ok(), "{prog}: {:?}", check_src(prog));
+        }
+        assert_rejected(&[
+            (
+                "{ let a = 1; let r = &a; let s = &mut *r; 1 }",
+                "Cannot borrow *r as mutable, as it is behind a &",
+            ),
+            (
+                "{ let b = Box::new(1); let r = &mut *b; 1 }",
+                "b is not declared as mutable",
+            ),
+            (
+                "{ let mut v = vec![1]; let r = &v; let rr = &r; rr.push(2); }",
+                "as it is behind a & reference",
+            ),
+            (
+                "{ fn f(x: &mut i32) {}; let a = 1; let r = &a; f(r) }",
+                "Expected argument nr 0",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_unsafe() {
+        let check = |prog: &str| {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            let mut env = TypeEnv::new();
+            let mut scope = Scope::new();
+            // A `static mut COUNT: i32`
+            scope.insert(
+                "COUNT".to_string(),
+                ValueMeta {
+                    ty: Some(Type::I32),
+                    assigned: true,
+                    maybe_assigned: true,
+                    mutable: true,
+                    shadowable: false,
+                    moved: None,
+                },
+            );
+            env.push((scope, HashMap::new()));
+            e.check(&mut env, 0)
+        };
+        for prog in [
+            "{ let mut a = 1; let p = &mut a as *mut i32; unsafe { *p = 2 }; unsafe { *p } }",
+            "{ let a = 1; let p = &a as *const i32; let q = p as *mut i32; unsafe { *q } }",
+            "{ let mut a = 1; let p = &mut a as *mut i32; let r = unsafe { &mut *p }; *r = 2; a }",
+            "{ unsafe fn f(p: *const i32) -> i32 { *p }; let a = 1; unsafe { f(&a as *const i32) } }",
+            "{ unsafe { COUNT = COUNT + 1 }; let r = unsafe { &mut COUNT }; *r }",
+            "{ let a = true as i32; let b = a as usize; b }",
+        ] {
+            assert!(check(prog).is_ok(), "{prog}: {:?}", check(prog));
+        }
+        for (prog, err) in [
+            (
+                "{ let a = 1; let p = &a as *const i32; *p }",
+                "Dereference of raw pointer p is unsafe",
+            ),
+            (
+                "{ let mut a = 1; let p = &mut a as *mut i32; *p = 2; a }",
+                "Dereference of raw pointer p is unsafe",
+            ),
+            (
+                "{ let a = 1; let p = &a as *const i32; unsafe { *p = 2 }; a }",
+                "as p is a *const pointer",
+            ),
+            ("{ COUNT = 1; 1 }", "Use of mutable static COUNT is unsafe"),
+            ("{ COUNT + 1 }", "Use of mutable static COUNT is unsafe"),
+            (
+                "{ unsafe fn f() {}; f() }",
+                "Call to unsafe function f is unsafe",
+            ),
+            ("{ let a = 1; let p = &a as *mut i32; 1 }", "Cannot cast &a"),
+            (
+                "{ let a = 1; let p = &a as *const bool; 1 }",
+                "Cannot cast &a",
+            ),
+        ] {
+            match check(prog) {
+                Err(e) => assert!(e.contains(err), "{prog}: {e}"),
+                Ok(ty) => unreachable!("{prog} should not type check, got {ty}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_cells() {
+        let check = |prog: &str| {
+            let e: Block = syn::parse2(prog.parse().unwrap()).unwrap();
+            e.check(&mut TypeEnv::new(), 0)
+        };
+        for (prog, ty) in [
+            (
+                "{ let c = Cell::new(1); let r = &c; c.set(2); r.get() }",
+                Type::I32,
+            ),
+            (
+                "{ let c = RefCell::new(1); let mut g = c.borrow_mut(); *g = 2; g }",
+                Type::CellRefMut(Box::new(Type::I32)),
+            ),
+            (
+                "{ let c = Rc::new(RefCell::new(true)); let d = Rc::clone(&c); *d.borrow() }",
+                Type::Bool,
+            ),
+            (
+                "{ let c = RefCell::new(1); *c.borrow_mut() = 2; *c.borrow() }",
+                Type::I32,
+            ),
+            (
+                "{ let c = RefCell::new(vec![1]); c.borrow_mut().push(2); c.borrow().len() }",
+                Type::I32,
+            ),
+            (
+                "{ let c = RefCell::new(vec![1]); let mut g = c.borrow_mut(); g.push(1); g.len() }",
+                Type::I32,
+            ),
+        ] {
+            assert_eq!(check(prog), Ok(ty), "{prog}");
+        }
+        for (prog, err) in [
+            (
+                "{ let c = Cell::new(vec![1]); c.get() }",
+                "Cannot get the value of &c as Vec<i32> is not Copy",
+            ),
+            (
+                "{ let c = Cell::new(1); c.set(true) }",
+                "Expected a value of type i32 in Cell::set but got bool",
+            ),
+            (
+                "{ let c = Cell::new(1); c.borrow() }",
+                "Expected a RefCell in RefCell::borrow but got Cell<i32>",
+            ),
+            (
+                "{ let c = RefCell::new(1); let g = c.borrow(); *g = 2; 1 }",
+                "use borrow_mut instead",
+            ),
+            (
+                "{ let c = RefCell::new(1); let g = c.borrow_mut(); *g = 2; 1 }",
+                "as g is not declared as mutable",
+            ),
+            (
+                "{ let c = RefCell::new(1); *c.borrow() = 2; 1 }",
+                "as it is a Ref, use borrow_mut instead",
+            ),
+            (
+                "{ let c = RefCell::new(vec![1]); let g = c.borrow_mut(); g.push(2); 1 }",
+                "Cannot borrow g as mutable, as it is not declared as mutable",
+            ),
+            (
+                "{ let c = RefCell::new(vec![1]); c.borrow().push(2); 1 }",
+                "as it is behind a & reference",
+            ),
+        ] {
+            match check(prog) {
+                Err(e) => assert!(e.contains(err), "{prog}: {e}"),
+                Ok(ty) => unreachable!("{prog} should not type check, got {ty}"),
+            }
+        }
+    }
 }
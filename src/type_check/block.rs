@@ -7,13 +7,16 @@ impl super::TypeCheck for Block {
         env.push((scope, FunctionScope::new()));
         let len = env.len() - 1;
 
+        for stmt in &self.statements {
+            stmt.declare_signature(env, len)?;
+        }
         let mut return_ty = Type::Unit;
         for stmt in &self.statements {
             // update the return type for each iteration
             return_ty = stmt.check(env, len)?
         }
         for (id, meta) in env.pop().unwrap().0.iter() {
-            match (meta.ty.clone(),meta.assigned)  {
+            match (meta.ty.clone(),meta.maybe_assigned)  {
                 (Some(_),true) => {}
                 _ => {
                     return Err(format!(
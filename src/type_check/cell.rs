@@ -0,0 +1,60 @@
+use super::{option::receiver, TypeCheck, TypeEnv, TypeErr};
+use crate::ast::{Expr, Type};
+use crate::intrinsics::CellIntrinsic;
+
+/// Returns the type of the cell that a receiver refers to, the cell may be shared through an
+/// `Rc` or owned by a `Box`, e.g. `c.borrow()` where `c: Rc<RefCell<i32>>`.
+fn cell(e: &Expr, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+    match receiver(e, env, idx)? {
+        Type::Rc(ty) | Type::Box(ty) => Ok(ty.peel_refs().clone()),
+        ty => Ok(ty),
+    }
+}
+
+impl CellIntrinsic {
+    /// Checks a call to the intrinsic, the cell is only ever borrowed immutably so a `&Cell<T>`
+    /// or a `&RefCell<T>` is enough to change the value in it.
+    pub fn check(&self, args: &[Expr], env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        let expected = match self {
+            Self::Set => 2,
+            _ => 1,
+        };
+        if args.len() != expected {
+            return Err(format!(
+                "Expected {expected} arguments to {} but got {}",
+                self.id(),
+                args.len()
+            ));
+        }
+        match (self, args) {
+            (Self::CellNew, [value]) => Ok(Type::Cell(Box::new(value.check(env, idx)?))),
+            (Self::RefCellNew, [value]) => Ok(Type::RefCell(Box::new(value.check(env, idx)?))),
+            (Self::Get, [receiver]) => match cell(receiver, env, idx)? {
+                Type::Cell(ty) if ty.is_copy() => Ok(*ty),
+                Type::Cell(ty) => Err(format!(
+                    "Cannot get the value of {receiver} as {ty} is not Copy"
+                )),
+                ty => Err(format!("Expected a Cell in {} but got {ty}", self.id())),
+            },
+            (Self::Set, [receiver, value]) => match cell(receiver, env, idx)? {
+                Type::Cell(ty) => {
+                    let got = value.check(env, idx)?;
+                    match ty.accepts(&got) {
+                        true => Ok(Type::Unit),
+                        false => Err(format!(
+                            "Expected a value of type {ty} in {} but got {got}",
+                            self.id()
+                        )),
+                    }
+                }
+                ty => Err(format!("Expected a Cell in {} but got {ty}", self.id())),
+            },
+            (Self::Borrow | Self::BorrowMut, [receiver]) => match cell(receiver, env, idx)? {
+                Type::RefCell(ty) if *self == Self::Borrow => Ok(Type::CellRef(ty)),
+                Type::RefCell(ty) => Ok(Type::CellRefMut(ty)),
+                ty => Err(format!("Expected a RefCell in {} but got {ty}", self.id())),
+            },
+            (_, _) => unreachable!("ICE, argument count checked above"),
+        }
+    }
+}
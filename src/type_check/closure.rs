@@ -0,0 +1,169 @@
+use super::{
+    consume, get_meta, usable, FunctionScope, Scope, TypeCheck, TypeEnv, TypeErr, ValueMeta, RETURN,
+};
+use crate::ast::{BinaryOp, Block, Capture, Closure, Expr, Literal, Statement, Type, UnaryOp};
+
+impl TypeCheck for Closure {
+    fn check(&self, env: &mut TypeEnv, _idx: usize) -> Result<Type, TypeErr> {
+        // All captured variables have to be usable at the point of declaration
+        let mut captures_any = false;
+        let mut mutates = false;
+        for (id, capture) in self.captures() {
+            let meta = match get_meta(env, &Expr::Ident(id.clone()))? {
+                Some(meta) => meta,
+                // This is not a variable, most likely a function that is called
+                None => continue,
+            };
+            captures_any = true;
+            if !meta.assigned {
+                return Err(format!("Closure captures {id} before it has been assigned"));
+            }
+            usable(&id, meta)?;
+            if capture == Capture::MutRef && !meta.mutable {
+                return Err(format!(
+                    "Closure cannot mutate {id} since it is not decleared as mutable"
+                ));
+            }
+            mutates |= capture == Capture::MutRef;
+        }
+
+        let mut scope = Scope::new();
+        let mut args = vec![];
+        for arg in self.args.iter() {
+            let id = match &arg.id {
+                Expr::Ident(i) => i.clone(),
+                e => return Err(format!("Cannot use {e} as a closure argument")),
+            };
+            let ty = match &arg.ty {
+                Some(ty) => ty.clone(),
+                None => match usage_hint(&self.body, &id) {
+                    Some(ty) => ty,
+                    None => {
+                        return Err(format!(
+                            "Cannot infer the type of closure argument {id}, please annotate it"
+                        ))
+                    }
+                },
+            };
+            args.push(ty.clone());
+            scope.insert(
+                id,
+                ValueMeta {
+                    ty: Some(ty),
+                    assigned: true,
+                    maybe_assigned: true,
+                    mutable: false,
+                    shadowable: true,
+                    moved: None,
+                },
+            );
+        }
+
+        // `?` returns from the closure, not the enclosing function
+        scope.insert(
+            RETURN.to_string(),
+            ValueMeta {
+                ty: self.ty.clone(),
+                assigned: true,
+                maybe_assigned: true,
+                mutable: false,
+                shadowable: false,
+                moved: None,
+            },
+        );
+
+        // The closure body can see the enclosing scopes, captures are resolved as normal variables
+        env.push((scope, FunctionScope::new()));
+        let len = env.len() - 1;
+        let ret = self.body.check(env, len);
+        env.pop();
+        let ret = ret?;
+        // A `move` closure takes the values it captures
+        let closure = Expr::Closure(self.clone());
+        for (id, capture) in self.captures() {
+            if capture == Capture::Value {
+                consume(env, &Expr::Ident(id), &closure)?;
+            }
+        }
+
+        if let Some(ty) = &self.ty {
+            if !ty.accepts(&ret) {
+                return Err(format!("Expected closure to return {ty} but got {ret}"));
+            }
+        }
+        // A closure that does not capture anything is just a function
+        match captures_any {
+            true => Ok(Type::Closure(args, Box::new(ret), mutates)),
+            false => Ok(Type::FnPtr(args, Box::new(ret))),
+        }
+    }
+}
+
+fn is_id(expr: &Expr, id: &str) -> bool {
+    match expr {
+        Expr::Ident(i) => i == id,
+        Expr::Par(e) => is_id(e, id),
+        _ => false,
+    }
+}
+
+/// Tries to infer the type of the identifier `id` from how it is used in `expr`.
+fn usage_hint(expr: &Expr, id: &str) -> Option<Type> {
+    match expr {
+        Expr::BinOp(op, lhs, rhs) if is_id(lhs, id) || is_id(rhs, id) => match op {
+            BinaryOp::Add
+            | BinaryOp::Sub
+            | BinaryOp::Mul
+            | BinaryOp::Div
+            | BinaryOp::Lt
+            | BinaryOp::Gt => Some(Type::I32),
+            BinaryOp::And | BinaryOp::Or => Some(Type::Bool),
+            BinaryOp::Eq => {
+                let other = match is_id(lhs, id) {
+                    true => rhs,
+                    false => lhs,
+                };
+                match &**other {
+                    Expr::Lit(Literal::Int(_)) => Some(Type::I32),
+                    Expr::Lit(Literal::Bool(_)) => Some(Type::Bool),
+                    Expr::Lit(Literal::String(_)) => Some(Type::String),
+                    other => usage_hint(other, id),
+                }
+            }
+        },
+        Expr::BinOp(_, lhs, rhs) => usage_hint(lhs, id).or_else(|| usage_hint(rhs, id)),
+        Expr::UnOp(UnaryOp::Not, e) if is_id(e, id) => Some(Type::Bool),
+        Expr::UnOp(UnaryOp::Subtract, e) if is_id(e, id) => Some(Type::I32),
+        Expr::UnOp(_, e) | Expr::Par(e) | Expr::Try(e) | Expr::Cast(e, _) => usage_hint(e, id),
+        Expr::IfThenElse(cond, then_block, else_block) => match is_id(cond, id) {
+            true => Some(Type::Bool),
+            false => usage_hint(cond, id)
+                .or_else(|| block_hint(then_block, id))
+                .or_else(|| else_block.as_ref().and_then(|b| block_hint(b, id))),
+        },
+        Expr::Index(arr, idx) | Expr::IndexMut(arr, idx) => match is_id(idx, id) {
+            true => Some(Type::I32),
+            false => usage_hint(arr, id).or_else(|| usage_hint(idx, id)),
+        },
+        Expr::Array(elements) => elements.iter().find_map(|el| usage_hint(el, id)),
+        Expr::FuncCall(call) => call.args.iter().find_map(|el| usage_hint(el, id)),
+        Expr::Block(b) | Expr::Unsafe(b) => block_hint(b, id),
+        Expr::Ident(_) | Expr::Lit(_) | Expr::Closure(_) => None,
+    }
+}
+
+fn block_hint(block: &Block, id: &str) -> Option<Type> {
+    block
+        .statements
+        .iter()
+        .find_map(|statement| match statement {
+            Statement::Let(_, _, _, Some(e)) | Statement::Expr(e) => usage_hint(e, id),
+            Statement::Assign(lhs, rhs) => usage_hint(lhs, id).or_else(|| usage_hint(rhs, id)),
+            Statement::While(cond, block) => match cond {
+                Expr::Ident(i) if i == id => Some(Type::Bool),
+                cond => usage_hint(cond, id).or_else(|| block_hint(block, id)),
+            },
+            Statement::Block(b) => block_hint(b, id),
+            Statement::Let(_, _, _, None) | Statement::FnDecleration(_) => None,
+        })
+}
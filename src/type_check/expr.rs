@@ -1,5 +1,8 @@
-use super::{get_meta, Operation, Ref, TypeEnv, TypeErr};
-use crate::ast::{Expr, Literal, Type, UnaryOp};
+use super::{
+    check_try, check_usable, flow, get_meta, join_flow, require_unsafe, restore_flow, usable,
+    FunctionScope, Operation, Scope, TypeEnv, TypeErr, ValueMeta, UNSAFE,
+};
+use crate::ast::{BinaryOp, Expr, Literal, Ref, Type, UnaryOp};
 
 impl super::TypeCheck for Expr {
     // check_expr
@@ -21,13 +24,31 @@ impl super::TypeCheck for Expr {
                 };
 
                 let res = scope.0.get(&id);
+                if let Some(t) = res {
+                    // This is an error in any scope
+                    usable(&id, t)?;
+                    if t.is_static_mut() {
+                        require_unsafe(env, &format!("Use of mutable static {id}"))?;
+                    }
+                }
                 match (res, idx) {
                     (Some(t), _) => match &t.ty {
                         Some(t) => Ok(t.clone()),
                         _ => Err(format!("Type of variable {id} must be known at this point")),
                     },
+                    // Functions can be used as values as well
+                    (None, 0) => match env.iter().rev().find_map(|scope| scope.1.get(&id)) {
+                        // Function pointers do not say wether the function is unsafe
+                        Some(fndec) if fndec.unsafety => {
+                            Err(format!("Cannot use unsafe function {id} as a value"))
+                        }
+                        Some(fndec) => Ok(Type::FnPtr(
+                            fndec.args.iter().map(|(ty, _)| ty.clone()).collect(),
+                            Box::new(fndec.ty.clone()),
+                        )),
+                        None => Err(format!("variable {id} not found")),
+                    },
                     // Look for identifier in earlier scopes
-                    (None, 0) => Err(format!("variable {id} not found")),
                     (_, _) => self.check(env, idx - 1),
                 }
             }
@@ -35,6 +56,13 @@ impl super::TypeCheck for Expr {
             Expr::BinOp(op, l, r) => {
                 let lhs = (*l).check(env, env.len() - 1)?;
                 let rhs = (*r).check(env, env.len() - 1)?;
+                // Comparisons look through references, `r == 1` compares the value `r` refers to
+                let (lhs, rhs) = match op {
+                    BinaryOp::Eq | BinaryOp::Lt | BinaryOp::Gt => {
+                        (lhs.peel_refs().clone(), rhs.peel_refs().clone())
+                    }
+                    _ => (lhs, rhs),
+                };
                 let ret_type = op.type_check((lhs.clone(), rhs.clone()));
                 if !ret_type {
                     return Err(format!(
@@ -55,16 +83,31 @@ impl super::TypeCheck for Expr {
                         cond
                     ))
                 } else {
+                    // Either block may have been executed so a variable is only assigned
+                    // afterwards if both of them assign it, and moved if either of them moves it.
+                    // A block that never returns does not reach the code after the if
+                    let before = flow(env);
                     let t = t.check(env, idx)?;
-                    match e {
+                    let then = flow(env);
+                    restore_flow(env, &before);
+                    let otherwise = match e {
+                        Some(b) => Some(b.check(env, idx)?),
+                        None => None,
+                    };
+                    match (&t, &otherwise) {
+                        (Type::Never, _) => {}
+                        (_, Some(Type::Never)) => restore_flow(env, &then),
+                        _ => join_flow(env, &then),
+                    }
+                    match otherwise {
                         Some(b) => {
-                            let b = b.check(env, idx)?;
-                            if t != b {
-                                Err(format!("Else block return type did not match then block, expected : {} got : {}",t,b))
-                            } else {
-                                Ok(t)
+                            match t.unify(&b) {
+                                Some(ty) => Ok(ty),
+                                None => Err(format!("Else block return type did not match then block, expected : {} got : {}",t,b)),
                             }
                         }
+                        // The then block may be skipped
+                        _ if t == Type::Never => Ok(Type::Unit),
                         _ => Ok(t),
                     }
                 }
@@ -76,32 +119,47 @@ impl super::TypeCheck for Expr {
                 // should simply be stored in a temporary variable.
                 let id = match *e.clone() {
                     Expr::Ident(i) => i,
+                    // A reborrow, the value can only be mutated through a mutable reference or
+                    // a box that is declared as mutable
+                    Expr::UnOp(UnaryOp::Dereff, inner) => {
+                        return match inner.check(env, env.len() - 1)? {
+                            Type::MutRef(Ref(ty, _)) => Ok(Type::MutRef(Ref(ty, None))),
+                            Type::MutPtr(ty) => {
+                                require_unsafe(env, &format!("Dereference of raw pointer {inner}"))?;
+                                Ok(Type::MutRef(Ref(ty, None)))
+                            }
+                            Type::Box(ty) => match get_meta(env, &inner)? {
+                                Some(meta) if meta.mutable => Ok(Type::MutRef(Ref(ty, None))),
+                                _ => Err(format!(
+                                    "Cannot borrow {e} as mutable, as {inner} is not declared as mutable"
+                                )),
+                            },
+                            ty => Err(format!(
+                                "Cannot borrow {e} as mutable, as it is behind a {ty}"
+                            )),
+                        };
+                    }
                     e => {
                         // Otherwise we borrow a simple stack allocated value.
                         // This will be introuced at this point in the code.
 
                         return Ok(Type::MutRef(crate::ast::Ref(
                             Box::new(e.check(env, env.len() - 1)?),
-                            idx,
-                            0,
+                            None,
                         )));
                     }
                 };
 
+                if get_meta(env, &e)?.is_some_and(|meta| meta.is_static_mut()) {
+                    require_unsafe(env, &format!("Use of mutable static {id}"))?;
+                }
                 let meta = match get_meta(env, &e)? {
                     Some(meta) => Ok(meta),
                     _ => Err(format!("Cannot locate {id}")),
                 }?;
+                usable(&id, meta)?;
 
-                match meta.ref_counter {
-                    None => {}
-                    Some(_) => {
-                        return Err(format!(
-                            "Cannot borrow {id} mutably as it has a live borrow"
-                        ))
-                    }
-                }
-
+                // Conflicting borrows are rejected by the borrow checker
                 if !meta.mutable {
                     return Err(format!(
                         "For {self} to be valid {e} has to be decleared as mutable"
@@ -116,10 +174,7 @@ impl super::TypeCheck for Expr {
                 let expected = UnaryOp::BorrowMut.return_type(got.clone())?;
 
                 match UnaryOp::BorrowMut.type_check(got.clone()) {
-                    true => {
-                        meta.ref_counter = Some(Ref::Mutable);
-                        Ok(expected)
-                    }
+                    true => Ok(expected),
                     false => Err(format!("Cannot perform {} on {got}", UnaryOp::BorrowMut)),
                 }
             }
@@ -136,21 +191,19 @@ impl super::TypeCheck for Expr {
 
                         return Ok(Type::Ref(crate::ast::Ref(
                             Box::new(e.check(env, env.len() - 1)?),
-                            idx,
-                            0,
+                            None,
                         )));
                     }
                 };
+                if get_meta(env, &e)?.is_some_and(|meta| meta.is_static_mut()) {
+                    require_unsafe(env, &format!("Use of mutable static {id}"))?;
+                }
                 let meta = match get_meta(env, &e)? {
                     Some(meta) => Ok(meta),
                     _ => Err(format!("Cannot locate {id}")),
                 }?;
+                usable(&id, meta)?;
 
-                let counter = match meta.ref_counter {
-                    None => Some(Ref::Immutable(1)),
-                    Some(Ref::Immutable(c)) => Some(Ref::Immutable(c + 1)),
-                    Some(_) => None,
-                };
                 let got = match meta.ty.clone() {
                     Some(ty) => Ok(ty),
                     _ => Err(format!(
@@ -160,16 +213,16 @@ impl super::TypeCheck for Expr {
                 let expected = UnaryOp::Borrow.return_type(got.clone())?;
 
                 match UnaryOp::Borrow.type_check(got.clone()) {
-                    true => {
-                        meta.ref_counter = counter;
-                        Ok(expected)
-                    }
+                    true => Ok(expected),
                     false => Err(format!("Cannot perform {} on {got}", UnaryOp::Borrow)),
                 }
             }
             Expr::UnOp(op, e) => {
                 let got = (*e).check(env, env.len() - 1)?;
                 let expected = op.return_type(got.clone())?;
+                if op == UnaryOp::Dereff && got.is_raw() {
+                    require_unsafe(env, &format!("Dereference of raw pointer {e}"))?;
+                }
 
                 match op.type_check(got.clone()) {
                     true => Ok(expected),
@@ -192,10 +245,33 @@ impl super::TypeCheck for Expr {
                 }
                 ret
             }
-            Expr::Index(id, arr_index) => index(*id, *arr_index, false, env, idx),
-            Expr::IndexMut(id, arr_index) => index(*id, *arr_index, true, env, idx),
+            Expr::Index(id, arr_index) => {
+                check_usable(env, &id)?;
+                index(*id, *arr_index, false, env, idx)
+            }
+            Expr::IndexMut(id, arr_index) => {
+                check_usable(env, &id)?;
+                index(*id, *arr_index, true, env, idx)
+            }
             Expr::FuncCall(fncall) => fncall.check(env, env.len() - 1),
             Expr::Block(b) => b.check(env, env.len() - 1),
+            Expr::Unsafe(b) => {
+                // The block is checked in a scope that marks it as unsafe
+                let mut scope = Scope::new();
+                scope.insert(UNSAFE.to_string(), ValueMeta::unsafe_marker());
+                env.push((scope, FunctionScope::new()));
+                let ty = b.check(env, env.len() - 1);
+                env.pop();
+                ty
+            }
+            Expr::Cast(e, ty) => {
+                let got = (*e).check(env, env.len() - 1)?;
+                cast(&e, got, ty)
+            }
+            Expr::Closure(closure) => closure.check(env, idx),
+            // The operand is already looked up in the outer scopes, retrying would only hide
+            // the error
+            Expr::Try(e) => return check_try(&e, env, idx),
         };
         match (ret, idx) {
             (Ok(value), _) => Ok(value),
@@ -217,6 +293,9 @@ fn index(
     };
     match env.get(idx).unwrap().0.get(&id) {
         Some(meta) => {
+            if meta.is_static_mut() {
+                require_unsafe(env, &format!("Use of mutable static {id}"))?;
+            }
             if !meta.mutable && mutable {
                 return Err("Cannot get a mutable element from immutable value".to_string());
             }
@@ -233,6 +312,8 @@ fn index(
                     }
                     Ok(*ty)
                 }
+                // The length of a vector is only known at runtime
+                Some(Type::Vec(ty)) => Ok(*ty),
                 Some(ty) => Err(format!("{ty} does not implement index")),
                 None => Err("Type must be known at this point".to_string()),
             }
@@ -240,3 +321,22 @@ fn index(
         _ => Err(format!("Usage of undecleared variable {id}")),
     }
 }
+
+/// Checks the cast `e as ty` where `e` is of type `got`. References can be cast to raw pointers
+/// to the same type, raw pointers to other raw pointers to the same type and integers and
+/// booleans to integers.
+fn cast(e: &Expr, got: Type, ty: Type) -> Result<Type, TypeErr> {
+    let valid = match (&got, &ty) {
+        (Type::Ref(Ref(got, _)), Type::ConstPtr(ty)) => got == ty,
+        (Type::MutRef(Ref(got, _)), Type::ConstPtr(ty) | Type::MutPtr(ty)) => got == ty,
+        (Type::ConstPtr(got) | Type::MutPtr(got), Type::ConstPtr(ty) | Type::MutPtr(ty)) => {
+            got == ty
+        }
+        (Type::I32 | Type::Usize | Type::Bool, Type::I32 | Type::Usize) => true,
+        (got, ty) => got == ty,
+    };
+    match valid {
+        true => Ok(ty),
+        false => Err(format!("Cannot cast {e} of type {got} to {ty}")),
+    }
+}
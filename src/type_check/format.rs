@@ -0,0 +1,76 @@
+use super::{TypeCheck, TypeEnv, TypeErr};
+use crate::ast::{Expr, Literal, Ref, Type};
+use crate::intrinsics::{
+    parse_format, positional_args, unquote, Argument, FormatIntrinsic, Kind, Piece,
+};
+
+/// Returns true if a value of type `ty` can be formatted as `kind`.
+fn formattable(ty: &Type, kind: Kind) -> bool {
+    match (ty, kind) {
+        // References and pointers are formatted as the value they point to
+        (Type::Ref(Ref(ty, _)) | Type::MutRef(Ref(ty, _)) | Type::Box(ty) | Type::Rc(ty), kind) => {
+            formattable(ty, kind)
+        }
+        (Type::CellRef(ty) | Type::CellRefMut(ty), kind) => formattable(ty, kind),
+        (Type::I32 | Type::Usize, _) => true,
+        (Type::Bool | Type::String, Kind::Display | Kind::Debug) => true,
+        (Type::Closure(..) | Type::FnPtr(..) | Type::Never, _) => false,
+        (_, Kind::Debug) => true,
+        _ => false,
+    }
+}
+
+/// Checks the format string `fmt` and the arguments that it formats.
+pub fn check_format(
+    fmt: &Expr,
+    args: &[Expr],
+    env: &mut TypeEnv,
+    idx: usize,
+) -> Result<(), TypeErr> {
+    let pieces = match fmt {
+        Expr::Lit(Literal::String(fmt)) => parse_format(&unquote(fmt))?,
+        fmt => return Err(format!("Expected a format string but got {fmt}")),
+    };
+    let count = positional_args(&pieces)?;
+    if count != args.len() {
+        return Err(format!(
+            "The format string {fmt} expects {count} arguments but got {}",
+            args.len()
+        ));
+    }
+    let mut tys = vec![];
+    for arg in args {
+        tys.push(arg.check(env, idx)?);
+    }
+    for piece in pieces {
+        let spec = match piece {
+            Piece::Arg(spec) => spec,
+            Piece::Text(_) => continue,
+        };
+        let ty = match &spec.arg {
+            Argument::Index(n) => tys[*n].clone(),
+            Argument::Named(id) => Expr::Ident(id.clone()).check(env, idx)?,
+        };
+        if !formattable(&ty, spec.kind) {
+            return Err(format!(
+                "A value of type {ty} cannot be formatted with {:?} in {fmt}",
+                spec.kind
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl FormatIntrinsic {
+    pub fn check(&self, args: &[Expr], env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        match args.split_first() {
+            Some((fmt, args)) => check_format(fmt, args, env, idx)?,
+            None if self.newline() => {}
+            None => return Err(format!("{} requires a format string", self.id())),
+        }
+        match self {
+            Self::Format => Ok(Type::String),
+            _ => Ok(Type::Unit),
+        }
+    }
+}
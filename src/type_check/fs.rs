@@ -0,0 +1,30 @@
+use super::{TypeCheck, TypeEnv, TypeErr};
+use crate::ast::{Expr, Ref, Type};
+use crate::intrinsics::FsIntrinsic;
+
+impl FsIntrinsic {
+    pub fn check(&self, args: &[Expr], env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        if args.len() != self.operands() {
+            return Err(format!(
+                "Expected {} arguments to {} but got {}",
+                self.operands(),
+                self.id(),
+                args.len()
+            ));
+        }
+        for arg in args {
+            // The strings are only read, they may be borrowed
+            let ty = match arg.check(env, idx)? {
+                Type::Ref(Ref(ty, _)) | Type::MutRef(Ref(ty, _)) => *ty,
+                ty => ty,
+            };
+            if ty != Type::String {
+                return Err(format!(
+                    "Expected the arguments of {} to be strings but got {ty}",
+                    self.id()
+                ));
+            }
+        }
+        Ok(self.ty())
+    }
+}
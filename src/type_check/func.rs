@@ -1,22 +1,30 @@
 use std::collections::HashMap;
 
-use super::{FunctionMeta, Scope, TypeCheck, TypeEnv, TypeErr, ValueMeta};
+use super::{
+    consume, get_meta, require_unsafe, FunctionMeta, Scope, TypeCheck, TypeEnv, TypeErr, ValueMeta,
+    RETURN, UNSAFE,
+};
 use crate::ast::func::{Arg, Func, FuncCall};
 use crate::ast::{Expr, Type};
+use crate::intrinsics::{
+    CellIntrinsic, FormatIntrinsic, FsIntrinsic, HeapIntrinsic, InputIntrinsic, OptionIntrinsic,
+    PanicIntrinsic, VecIntrinsic,
+};
 
 impl From<Arg> for ValueMeta {
     fn from(value: Arg) -> Self {
         Self {
             ty: Some(value.ty),
             assigned: true,
+            maybe_assigned: true,
             mutable: value.mutable,
             shadowable: true,
-            ref_counter: None,
+            moved: None,
         }
     }
 }
 
-fn reconstruct_evn(env: &TypeEnv, args: Vec<Arg>) -> TypeEnv {
+fn reconstruct_evn(env: &TypeEnv, args: Vec<Arg>, ty: Type) -> TypeEnv {
     let global = env.get(0).unwrap().0.clone();
     let mut local_scope: HashMap<String, ValueMeta> = HashMap::new();
     for arg in args {
@@ -24,6 +32,18 @@ fn reconstruct_evn(env: &TypeEnv, args: Vec<Arg>) -> TypeEnv {
             local_scope.insert(i, arg.into());
         }
     }
+    // Used by `?` to check that the function can return the `None` or the `Err`
+    local_scope.insert(
+        RETURN.to_string(),
+        ValueMeta {
+            ty: Some(ty),
+            assigned: true,
+            maybe_assigned: true,
+            mutable: false,
+            shadowable: false,
+            moved: None,
+        },
+    );
     let blank_scope: Scope = Scope::new();
     let mut new_env: TypeEnv = env
         .iter()
@@ -35,34 +55,124 @@ fn reconstruct_evn(env: &TypeEnv, args: Vec<Arg>) -> TypeEnv {
     new_env.get_mut(len - 1).unwrap().0 = local_scope;
     new_env
 }
+/// Checks the arguments of a call through a closure or a function pointer.
+fn check_args(expected: &[Type], args: &[Type]) -> Result<(), TypeErr> {
+    if expected.len() != args.len() {
+        return Err(format!(
+            "Expected {} arguments but got {}",
+            expected.len(),
+            args.len()
+        ));
+    }
+    for (idx, (expected_ty, got)) in expected.iter().zip(args.iter()).enumerate() {
+        if !expected_ty.coerces(got) {
+            return Err(format!(
+                "Expected argument nr {idx} to be of type {expected_ty} but got {got}"
+            ));
+        }
+    }
+    Ok(())
+}
+
 impl TypeCheck for FuncCall {
     fn check(&self, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        let ty = self.check_call(env, idx)?;
+        // The formatting macros only borrow their arguments
+        if let Expr::Ident(id) = &*self.id {
+            if FormatIntrinsic::from_id(id).is_some() || PanicIntrinsic::from_id(id).is_some() {
+                return Ok(ty);
+            }
+        }
+        let call = Expr::FuncCall(self.clone());
+        for arg in self.args.iter() {
+            // Mutable references are reborrowed rather than moved in to the callee
+            let reborrow = matches!(arg, Expr::Ident(_))
+                && matches!(
+                    get_meta(env, arg)?.and_then(|meta| meta.ty.clone()),
+                    Some(Type::MutRef(_))
+                );
+            if !reborrow {
+                consume(env, arg, &call)?;
+            }
+        }
+        Ok(ty)
+    }
+}
+
+impl FuncCall {
+    fn check_call(&self, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        // The receiver of a method is only borrowed for the duration of the call
+        if let Expr::Ident(id) = &*self.id {
+            if let Some(intrinsic) = VecIntrinsic::from_id(id) {
+                return intrinsic.check(&self.args, env, idx);
+            }
+            if let Some(intrinsic) = OptionIntrinsic::from_id(id) {
+                return intrinsic.check(&self.args, env, idx);
+            }
+            if let Some(intrinsic) = PanicIntrinsic::from_id(id) {
+                return intrinsic.check(&self.args, env, idx);
+            }
+            if let Some(intrinsic) = FormatIntrinsic::from_id(id) {
+                return intrinsic.check(&self.args, env, idx);
+            }
+            if let Some(intrinsic) = InputIntrinsic::from_id(id) {
+                return intrinsic.check(&self.args, env, idx);
+            }
+            if let Some(intrinsic) = FsIntrinsic::from_id(id) {
+                return intrinsic.check(&self.args, env, idx);
+            }
+            if let Some(intrinsic) = CellIntrinsic::from_id(id) {
+                return intrinsic.check(&self.args, env, idx);
+            }
+        }
         let mut args: Vec<Type> = vec![];
         for arg in self.args.iter() {
             args.push(arg.check(env, idx)?)
         }
 
-        let id = match (*self.id).clone() {
-            Expr::Ident(id) => id,
-            e => return Err(format!("Cannot treat {e} as a function identifier.")),
+        if let Expr::Ident(id) = &*self.id {
+            if let Some(intrinsic) = HeapIntrinsic::from_id(id) {
+                return intrinsic.return_type(&args);
+            }
+        }
+
+        // Closures and function pointers live in the variable scopes and shadow functions with
+        // the same name
+        let callee = match &*self.id {
+            Expr::Ident(_) => get_meta(env, &self.id)?.and_then(|meta| meta.ty.clone()),
+            e => Some(e.check(env, idx)?),
+        };
+        let id = match (callee, (*self.id).clone()) {
+            (None, Expr::Ident(id)) => id,
+            // Like an `FnMut` a closure that mutates what it captures is borrowed mutably to call it
+            (Some(Type::Closure(_, _, true)), e @ Expr::Ident(_))
+                if !get_meta(env, &e)?.is_some_and(|meta| meta.mutable) =>
+            {
+                return Err(format!(
+                    "Cannot borrow {e} as mutable, as it is not declared as mutable"
+                ))
+            }
+            (Some(Type::Closure(expected, ret, _)), _) | (Some(Type::FnPtr(expected, ret)), _) => {
+                return check_args(&expected, &args).map(|_| *ret)
+            }
+            (Some(ty), e) => return Err(format!("Cannot call {e} of type {ty}")),
+            (None, e) => return Err(format!("Cannot treat {e} as a function identifier.")),
         };
 
-        let mut index = env.len() - 1;
         let mut fndec = None;
-        while let Some(scope) = env.get(index) {
+        for scope in env.iter().rev() {
             if let Some(dec) = scope.1.get(&id) {
                 fndec = Some(dec.clone());
                 break;
             };
-            index -= 1;
         }
 
         let fndec = match fndec {
             Some(fndec) => fndec,
             _ => return Err(format!("Tried to call undefined function {id}")),
         };
-        if id == *"println!" {
-            return Ok(Type::Unit);
+        if fndec.unsafety {
+            require_unsafe(env, &format!("Call to unsafe function {id}"))?;
         }
         if fndec.args.len() != args.len() {
             return Err(format!(
@@ -76,8 +186,8 @@ impl TypeCheck for FuncCall {
             fndec.args.iter().zip(args.iter()).enumerate().collect();
 
         while let Some((idx, ((expected_ty, _expected_mutable), got))) = args.pop() {
-            // Check them in order
-            if *got != *expected_ty {
+            // Check them in order, a `&mut T` is accepted as a `&T`
+            if !expected_ty.coerces(got) {
                 return Err(format!(
                     "Expected argument nr {idx} to be of type {expected_ty} but got {got}"
                 ));
@@ -96,35 +206,43 @@ impl TypeCheck for Func {
             Expr::Ident(id) => Ok(id),
             exp => Err(format!("Cannot treat {exp} as a function identifier")),
         }?;
-        let args: Vec<(Expr, Type, bool)> = self
-            .args
-            .iter()
-            .map(|arg| (arg.id.clone(), arg.ty.clone(), arg.mutable))
-            .collect();
-
-        if env.get(idx).unwrap().1.get(id).is_some() {
-            return Err(format!("Duplicate definition of function {id}"));
+        // The function is declared before its body is checked unless the surrounding module or
+        // block already declared it
+        if !env.get(idx).unwrap().1.contains_key(id) {
+            self.declare_signature(env, idx)?;
         }
-        // Add in the new function and assume correctly typed for now
-        env.get_mut(idx).unwrap().1.insert(
-            id.clone(),
-            FunctionMeta {
-                ty: self.ty.clone(),
-                args: args
-                    .iter()
-                    .map(|(_id, ty, mutable)| (ty.clone(), *mutable))
-                    .collect(),
-            },
-        );
 
         // Give function scope access to global scope and all of the accessible functions
-        let mut new_env = reconstruct_evn(env, self.args.clone());
+        let mut new_env = reconstruct_evn(env, self.args.clone(), self.ty.clone());
+        // The body of an unsafe function is an unsafe context
+        if self.unsafety {
+            let len = new_env.len();
+            new_env[len - 1]
+                .0
+                .insert(UNSAFE.to_string(), ValueMeta::unsafe_marker());
+        }
         let ret_ty = self.body.check(&mut new_env, idx)?;
         // Allow mutable access to global scope
         env.get_mut(0).unwrap().0 = new_env.get(0).unwrap().0.clone();
-        if ret_ty != self.ty {
+        if !self.ty.accepts(&ret_ty) {
             return Err(format!("Expected {} but got {ret_ty}", self.ty));
         }
         Ok(Type::Unit)
     }
+
+    fn declare_signature(&self, env: &mut TypeEnv, idx: usize) -> Result<(), TypeErr> {
+        let id = match &self.id {
+            Expr::Ident(id) => Ok(id),
+            exp => Err(format!("Cannot treat {exp} as a function identifier")),
+        }?;
+        if env.get(idx).unwrap().1.get(id).is_some() {
+            return Err(format!("Duplicate definition of function {id}"));
+        }
+        // Add in the new function and assume correctly typed for now
+        env.get_mut(idx)
+            .unwrap()
+            .1
+            .insert(id.clone(), FunctionMeta::from(self.clone()));
+        Ok(())
+    }
 }
@@ -1,14 +1,26 @@
-use crate::ast::{Static, Type};
+use crate::ast::{Expr, Literal, Static, Type};
 
 use super::{TypeCheck, ValueMeta};
 
 impl TypeCheck for Static {
-    fn check(&self, env: &mut super::TypeEnv, _idx: usize) -> Result<Type, super::TypeErr> {
+    fn check(&self, env: &mut super::TypeEnv, idx: usize) -> Result<Type, super::TypeErr> {
         // These are quite trivial to check, we just insert the
         // global in to the latest scope
         if env.is_empty() {
             return Err("Cannot declear variables in non existant scope".to_string());
         }
+        // The initializer has already been evaluated to a literal, an integer literal takes the
+        // declared integer type, `const N: usize = 2 + 1;`
+        let ty = match (&self.value, &self.ty) {
+            (Expr::Lit(Literal::Int(i)), Type::Usize) if *i >= 0 => Type::Usize,
+            (value, _) => value.check(env, idx)?,
+        };
+        if ty != self.ty {
+            return Err(format!(
+                "Expected {} to be of type {} but got {ty}",
+                self.id, self.ty
+            ));
+        }
         let last_env = env.len();
         let scope = env.get_mut(last_env - 1).unwrap();
         scope.0.insert(
@@ -16,9 +28,10 @@ impl TypeCheck for Static {
             ValueMeta {
                 ty: Some(self.ty.clone()),
                 assigned: true,
+                maybe_assigned: true,
                 mutable: self.mutable,
                 shadowable: false,
-                ref_counter: None,
+                moved: None,
             },
         );
         Ok(Type::Unit)
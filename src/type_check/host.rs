@@ -0,0 +1,19 @@
+use super::{FunctionMeta, FunctionScope, Scope, TypeEnv};
+use crate::intrinsics::IntrinsicRegistry;
+
+impl IntrinsicRegistry {
+    /// Returns a type environment with the host functions declared in the global scope, the
+    /// program is checked in this scope.
+    pub fn type_env(&self) -> TypeEnv {
+        let fns: FunctionScope = self
+            .iter()
+            .map(|(id, host)| {
+                let args = host.args.iter().map(|ty| (ty.clone(), false)).collect();
+                let ty = host.ty.clone();
+                let unsafety = false;
+                (id.clone(), FunctionMeta { ty, args, unsafety })
+            })
+            .collect();
+        vec![(Scope::new(), fns)]
+    }
+}
@@ -0,0 +1,16 @@
+use super::{TypeEnv, TypeErr};
+use crate::ast::{Expr, Type};
+use crate::intrinsics::InputIntrinsic;
+
+impl InputIntrinsic {
+    pub fn check(&self, args: &[Expr], _env: &mut TypeEnv, _idx: usize) -> Result<Type, TypeErr> {
+        match args.is_empty() {
+            true => Ok(self.ty()),
+            false => Err(format!(
+                "Expected no arguments to {} but got {}",
+                self.id(),
+                args.len()
+            )),
+        }
+    }
+}
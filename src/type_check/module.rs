@@ -0,0 +1,36 @@
+use super::{TypeCheck, TypeEnv, TypeErr};
+use crate::ast::{Module, Type, Use};
+
+impl TypeCheck for Module {
+    fn check(&self, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        // All items have been renamed to their full path, so they can be checked as if they
+        // were declared in the surrounding scope
+        for el in self.items.iter() {
+            match el.check(env, idx).map_err(|e| self.locate(e))? {
+                Type::Unit => {}
+                t => {
+                    return Err(self.locate(format!(
+                        "All top level expressions should return unit type, got {t} when evaluting {el}"
+                    )))
+                }
+            };
+        }
+        Ok(Type::Unit)
+    }
+
+    /// Declares the functions of the module and its submodules, a function may call any other
+    /// function in the program regardless of where it is written.
+    fn declare_signature(&self, env: &mut TypeEnv, idx: usize) -> Result<(), TypeErr> {
+        for el in self.items.iter() {
+            el.declare_signature(env, idx).map_err(|e| self.locate(e))?;
+        }
+        Ok(())
+    }
+}
+
+impl TypeCheck for Use {
+    fn check(&self, _env: &mut TypeEnv, _idx: usize) -> Result<Type, TypeErr> {
+        // Imports are resolved before type checking
+        Ok(Type::Unit)
+    }
+}
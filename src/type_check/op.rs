@@ -25,7 +25,8 @@ impl Operation for BinaryOp {
             Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Lt | Self::Gt => {
                 matches!(operands, (Type::I32, Type::I32))
             }
-            Self::Eq => operands.0 == operands.1,
+            // `None` is an `Option<()>` until it is compared with a known option, `o == None`
+            Self::Eq => operands.0.accepts(&operands.1) || operands.1.accepts(&operands.0),
             Self::And | Self::Or => operands == (Type::Bool, Type::Bool),
         }
     }
@@ -41,8 +42,11 @@ impl Operation for UnaryOp {
             Self::Borrow => Ok(super::Type::Ref(operands.into())),
             Self::BorrowMut => Ok(super::Type::MutRef(operands.into())),
             Self::Dereff => match operands {
-                super::Type::Ref(crate::ast::types::Ref(ty, _, _)) => Ok(*ty),
-                super::Type::MutRef(crate::ast::types::Ref(ty, _, _)) => Ok(*ty),
+                super::Type::Ref(crate::ast::types::Ref(ty, _)) => Ok(*ty),
+                super::Type::MutRef(crate::ast::types::Ref(ty, _)) => Ok(*ty),
+                super::Type::Box(ty) | super::Type::Rc(ty) => Ok(*ty),
+                super::Type::CellRef(ty) | super::Type::CellRefMut(ty) => Ok(*ty),
+                super::Type::ConstPtr(ty) | super::Type::MutPtr(ty) => Ok(*ty),
                 ty => Err(format!("Cannot treat {} as a reference", ty)),
             },
         }
@@ -57,6 +61,12 @@ impl Operation for UnaryOp {
             Self::Dereff => {
                 matches!(operands, super::Type::Ref(_))
                     || matches!(operands, super::Type::MutRef(_))
+                    || operands.is_heap()
+                    || operands.is_raw()
+                    || matches!(
+                        operands,
+                        super::Type::CellRef(_) | super::Type::CellRefMut(_)
+                    )
             }
         }
     }
@@ -0,0 +1,125 @@
+use super::{check_usable, get_meta, TypeCheck, TypeEnv, TypeErr, RETURN};
+use crate::ast::{Expr, Ref, Type, UnaryOp};
+use crate::intrinsics::OptionIntrinsic;
+
+/// Follows the references and the `Ref<T>` and `RefMut<T>` guards of a receiver to the value.
+fn auto_deref(ty: &Type) -> Type {
+    match ty {
+        Type::Ref(Ref(ty, _)) | Type::MutRef(Ref(ty, _)) => auto_deref(ty),
+        Type::CellRef(ty) | Type::CellRefMut(ty) => auto_deref(ty),
+        ty => ty.clone(),
+    }
+}
+
+/// Returns the type of the value that a borrowed receiver refers to.
+///
+/// Like the receivers of the `Vec` methods this is only a temporary borrow, it does not count as
+/// a live borrow of the variable.
+///
+/// The receiver is dereferenced until the value is found, e.g. `o.is_some()` where
+/// `o: &&Option<i32>`.
+pub(super) fn receiver(e: &Expr, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+    match e {
+        Expr::UnOp(UnaryOp::Borrow, inner) if matches!(**inner, Expr::Ident(_)) => {
+            check_usable(env, inner)?;
+            match get_meta(env, inner)? {
+                Some(meta) => match &meta.ty {
+                    Some(ty) => Ok(auto_deref(ty)),
+                    None => Err("Type must be known at this point".to_string()),
+                },
+                None => Err(format!("Usage of undecleared variable {inner}")),
+            }
+        }
+        e => match e.check(env, idx)? {
+            Type::Ref(Ref(ty, _)) | Type::MutRef(Ref(ty, _)) => Ok(auto_deref(&ty)),
+            ty => Err(format!("Expected a reference but got {ty}")),
+        },
+    }
+}
+
+/// Checks `e?`, the value must be an `Option` or a `Result` that can be returned from the
+/// enclosing function.
+pub fn check_try(e: &Expr, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+    let ty = e.check(env, idx)?;
+    let ret = match get_meta(env, &Expr::Ident(RETURN.to_string()))? {
+        Some(meta) => meta.ty.clone(),
+        None => return Err(format!("`?` used outside of a function in {e}?")),
+    };
+    let ret = match ret {
+        Some(ret) => ret,
+        None => {
+            return Err(format!(
+                "`?` used in a closure without an explicit return type in {e}?"
+            ))
+        }
+    };
+    match (ty, ret) {
+        (Type::Option(ty), Type::Option(_)) => Ok(*ty),
+        (Type::Result(ty, err), Type::Result(_, expected)) => match expected.accepts(&err) {
+            true => Ok(*ty),
+            false => Err(format!(
+                "`?` cannot return an error of type {err} from a function returning Result<_, {expected}>"
+            )),
+        },
+        (ty @ (Type::Option(_) | Type::Result(_, _)), ret) => Err(format!(
+            "`?` on a value of type {ty} can only be used in a function that returns a compatible type, not {ret}"
+        )),
+        (ty, _) => Err(format!(
+            "`?` can only be applied to an Option or a Result, not {ty}"
+        )),
+    }
+}
+
+impl OptionIntrinsic {
+    pub fn check(&self, args: &[Expr], env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        let expected = match self {
+            Self::None => 0,
+            Self::Expect => 2,
+            _ => 1,
+        };
+        if args.len() != expected {
+            return Err(format!(
+                "Expected {expected} arguments to {} but got {}",
+                self.id(),
+                args.len()
+            ));
+        }
+        match (self, args) {
+            // The types that are not mentioned are unknown, these are accepted as any type
+            (Self::None, _) => Ok(Type::Option(Box::new(Type::Unit))),
+            (Self::Some, [value]) => Ok(Type::Option(Box::new(value.check(env, idx)?))),
+            (Self::Ok, [value]) => Ok(Type::Result(
+                Box::new(value.check(env, idx)?),
+                Box::new(Type::Unit),
+            )),
+            (Self::Err, [err]) => Ok(Type::Result(
+                Box::new(Type::Unit),
+                Box::new(err.check(env, idx)?),
+            )),
+            (Self::Unwrap | Self::Expect, [value, msg @ ..]) => {
+                if let [msg] = msg {
+                    let msg = msg.check(env, idx)?;
+                    if msg != Type::String {
+                        return Err(format!("Expected the message to be a String but got {msg}"));
+                    }
+                }
+                match value.check(env, idx)? {
+                    Type::Option(ty) | Type::Result(ty, _) => Ok(*ty),
+                    ty => Err(format!(
+                        "Expected an Option or a Result in {} but got {ty}",
+                        self.id()
+                    )),
+                }
+            }
+            (Self::IsSome | Self::IsNone, [value]) => match receiver(value, env, idx)? {
+                Type::Option(_) => Ok(Type::Bool),
+                ty => Err(format!("Expected an Option in {} but got {ty}", self.id())),
+            },
+            (Self::IsOk | Self::IsErr, [value]) => match receiver(value, env, idx)? {
+                Type::Result(_, _) => Ok(Type::Bool),
+                ty => Err(format!("Expected a Result in {} but got {ty}", self.id())),
+            },
+            (_, _) => unreachable!("ICE, argument count checked above"),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use super::{check_format, TypeCheck, TypeEnv, TypeErr};
+use crate::ast::{Expr, Type};
+use crate::intrinsics::PanicIntrinsic;
+
+impl PanicIntrinsic {
+    pub fn check(&self, args: &[Expr], env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        if args.len() < self.operands() {
+            return Err(format!(
+                "Expected at least {} arguments to {} but got {}",
+                self.operands(),
+                self.id(),
+                args.len()
+            ));
+        }
+        let (operands, msg) = args.split_at(self.operands());
+        let mut tys = vec![];
+        for operand in operands {
+            tys.push(operand.check(env, idx)?);
+        }
+        match (self, &tys[..]) {
+            (Self::Assert, [cond]) if *cond != Type::Bool => {
+                return Err(format!(
+                    "Expected the condition of {} to be a bool but got {cond}",
+                    self.id()
+                ))
+            }
+            (Self::AssertEq | Self::AssertNe, [left, right]) if left.unify(right).is_none() => {
+                return Err(format!(
+                    "Cannot compare {left} with {right} in {}",
+                    self.id()
+                ))
+            }
+            _ => {}
+        }
+        if let Some((fmt, args)) = msg.split_first() {
+            check_format(fmt, args, env, idx)?;
+        }
+        match self.diverges() {
+            true => Ok(Type::Never),
+            false => Ok(Type::Unit),
+        }
+    }
+}
@@ -1,20 +1,21 @@
 use super::TypeCheck;
 use crate::ast::{program::Prog, Type};
-use crate::intrinsics::vm_println;
 
 impl TypeCheck for Prog {
     fn check(&self, env: &mut super::TypeEnv, idx: usize) -> Result<Type, super::TypeErr> {
-        let mut global_scope = (
-            crate::type_check::Scope::new(),
-            crate::type_check::FunctionScope::new(),
-        );
-        // Introduce compile builtins
-        let (f, _body) = vm_println();
-        match &f.id {
-            crate::ast::Expr::Ident(id) => global_scope.1.insert(id.clone(), f.into()),
-            e => return Err(format!("Malformed compiler built in {e}")),
-        };
-        env.push(global_scope);
+        // The built in functions are intrinsics, they are not declared in the global scope. Host
+        // functions are, the global scope is only created here if it was not created by
+        // `IntrinsicRegistry::type_env`
+        if env.is_empty() {
+            let global_scope = (
+                crate::type_check::Scope::new(),
+                crate::type_check::FunctionScope::new(),
+            );
+            env.push(global_scope);
+        }
+        for el in self.statements.iter() {
+            el.declare_signature(env, idx)?;
+        }
         for el in self.statements.iter() {
             match el.check(env, idx)?{
                 crate::ast::Type::Unit => {},
@@ -1,7 +1,16 @@
-use super::{TypeEnv, TypeErr, ValueMeta};
+use super::{
+    consume, flow, get_meta, join_flow, require_unsafe, restore_flow, TypeEnv, TypeErr, ValueMeta,
+};
 use crate::ast::{Expr, Statement, Type, UnaryOp};
 
 impl super::TypeCheck for Statement {
+    fn declare_signature(&self, env: &mut TypeEnv, idx: usize) -> Result<(), TypeErr> {
+        match self {
+            Statement::FnDecleration(func) => func.declare_signature(env, idx),
+            _ => Ok(()),
+        }
+    }
+
     fn check(&self, env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
         if env.len() < idx {
             return Err("Trying to read from undecleared scope".to_owned());
@@ -17,10 +26,10 @@ impl super::TypeCheck for Statement {
 
                 let assigned = e.is_some();
 
-                let ty = match (t, e) {
+                let ty = match (t, &e) {
                     (Some(t), Some(e)) => {
                         let expr_ty = e.check(env, last_scope)?;
-                        if t != expr_ty {
+                        if !t.accepts(&expr_ty) {
                             Err(format!(
                                 "Cannot assign expression of type {expr_ty} to value of type {t}"
                             ))
@@ -32,13 +41,20 @@ impl super::TypeCheck for Statement {
                     (Some(t), None) => Ok(Some(t)),
                     (None, None) => Ok(None),
                 }?;
+                match (&id, &e) {
+                    // Temporaries introduced by the pre declaration pass are only borrowed
+                    (Expr::Ident(id), _) if id.starts_with('#') => {}
+                    (_, Some(e)) => consume(env, e, self)?,
+                    (_, None) => {}
+                }
 
                 let meta = ValueMeta {
                     assigned,
+                    maybe_assigned: assigned,
                     ty,
                     mutable,
                     shadowable: true,
-                    ref_counter: None,
+                    moved: None,
                 };
                 let id = match id {
                     Expr::Ident(i) => i,
@@ -62,7 +78,10 @@ impl super::TypeCheck for Statement {
             Statement::Expr(e) => {
                 // the type of an Expr is returned
                 match e.check(env, last_scope) {
-                    Ok(ty) => Ok(Some(ty)),
+                    Ok(ty) => {
+                        consume(env, &e, self)?;
+                        Ok(Some(ty))
+                    }
                     Err(e) => Err(e),
                 }
             }
@@ -70,13 +89,22 @@ impl super::TypeCheck for Statement {
                 // a = 5
                 let ret = match id {
                     Expr::Ident(id) => {
-                        let expected = env.get_mut(idx).unwrap().0.get_mut(&id);
+                        // The variable might live in any of the enclosing scopes
+                        if get_meta(env, &Expr::Ident(id.clone()))?
+                            .is_some_and(|meta| meta.is_static_mut())
+                        {
+                            require_unsafe(env, &format!("Use of mutable static {id}"))?;
+                        }
+                        let expected = get_meta(env, &Expr::Ident(id.clone()))?;
                         match expected {
                             Some(t) => {
-                                if !t.mutable && t.assigned {
-                                    return Err("Cannot assing to a immutable value".to_owned());
+                                // A binding that is declared without a value may be assigned
+                                // once on every path
+                                if !t.mutable && t.maybe_assigned {
+                                    return Err(format!(
+                                        "Cannot assign twice to immutable variable {id}"
+                                    ));
                                 } else {
-                                    t.assigned = true;
                                     Ok((id, t.ty.clone()))
                                 }
                             }
@@ -91,6 +119,19 @@ impl super::TypeCheck for Statement {
                             ty => Err(format!("Cannot use {ty} as identifier")),
                         }
                     }
+                    // The guard or reference returned by a call is a temporary that is assigned
+                    // through directly, e.g. `*c.borrow_mut() = 1`
+                    Expr::UnOp(UnaryOp::Dereff, e) if matches!(*e, Expr::FuncCall(_)) => {
+                        match e.check(env, last_scope)? {
+                            Type::MutRef(crate::ast::types::Ref(ty, _)) | Type::CellRefMut(ty) => {
+                                Ok((e.to_string(), Some(*ty)))
+                            }
+                            Type::CellRef(_) => Err(format!(
+                                "Cannot assign to *{e}, as it is a Ref, use borrow_mut instead"
+                            )),
+                            ty => Err(format!("Cannot assign through {e} of type {ty}")),
+                        }
+                    }
                     Expr::UnOp(UnaryOp::Dereff, e) => {
                         //let ty = e.check(env, env.len() - 1)?;
                         fn get_base_expression(expr: Expr) -> Expr {
@@ -130,7 +171,34 @@ impl super::TypeCheck for Statement {
                         };
                         let ty = e.check(env, last_scope)?;
                         match ty {
-                            Type::MutRef(crate::ast::types::Ref(ty, _, _)) => Ok((id, Some(*ty))),
+                            Type::MutRef(crate::ast::types::Ref(ty, _)) => Ok((id, Some(*ty))),
+                            Type::Box(ty) => match get_meta(env, &Expr::Ident(id.clone()))? {
+                                Some(meta) if meta.mutable => Ok((id, Some(*ty))),
+                                _ => Err(format!(
+                                    "Cannot assign to *{id}, as {id} is not declared as mutable"
+                                )),
+                            },
+                            Type::Rc(_) => Err(format!(
+                                "Cannot assign to data in an Rc, {id} is not mutable"
+                            )),
+                            Type::CellRefMut(ty) => {
+                                match get_meta(env, &Expr::Ident(id.clone()))? {
+                                    Some(meta) if meta.mutable => Ok((id, Some(*ty))),
+                                    _ => Err(format!(
+                                    "Cannot assign to *{id}, as {id} is not declared as mutable"
+                                )),
+                                }
+                            }
+                            Type::CellRef(_) => Err(format!(
+                                "Cannot assign to *{id}, as {id} is a Ref, use borrow_mut instead"
+                            )),
+                            Type::MutPtr(ty) => {
+                                require_unsafe(env, &format!("Dereference of raw pointer {e}"))?;
+                                Ok((id, Some(*ty)))
+                            }
+                            Type::ConstPtr(_) => Err(format!(
+                                "Cannot assign to *{id}, as {id} is a *const pointer"
+                            )),
                             e => Err(format!("Cannot treat {e} as a mutable borrow")),
                         }
                     }
@@ -140,11 +208,30 @@ impl super::TypeCheck for Statement {
                 match ret {
                     Ok((id, mut expected)) => {
                         let rhs = e.check(env, last_scope)?;
+                        consume(env, &e, self)?;
+                        // Assigning a new value to a variable that was moved out of makes it
+                        // usable again
+                        if let Statement::Assign(Expr::Ident(_), _) = self {
+                            if let Some(meta) = get_meta(env, &Expr::Ident(id.clone()))? {
+                                meta.assigned = true;
+                                meta.maybe_assigned = true;
+                                meta.moved = None;
+                            }
+                        }
 
                         match expected {
-                            Some(t) => match rhs == t {
-                                true => Ok(Some(Type::Unit)),
-                                _ => Err(format!(
+                            // A value whose type is partially unknown, e.g. `None`, is refined by
+                            // the assigned value
+                            Some(t) => match t.unify(&rhs) {
+                                Some(ty) => {
+                                    if ty != t && matches!(self, Statement::Assign(Expr::Ident(_), _)) {
+                                        if let Some(meta) = get_meta(env, &Expr::Ident(id))? {
+                                            meta.ty = Some(ty);
+                                        }
+                                    }
+                                    Ok(Some(Type::Unit))
+                                }
+                                None => Err(format!(
                                     "Invalid return type for expression {e} got {rhs} expected {t}\noccured in:\n\t{self}"
                                 )),
                             },
@@ -152,7 +239,9 @@ impl super::TypeCheck for Statement {
                                 expected = Some(rhs.clone());
                                 // Re assign the new expected
                                 // Unwrapping here is ok since the value must exist at this point
-                                env.get_mut(idx).unwrap().0.get_mut(&id).unwrap().ty = expected;
+                                if let Some(meta) = get_meta(env, &Expr::Ident(id))? {
+                                    meta.ty = expected;
+                                }
                                 Ok(Some(Type::Unit))
                             }
                         }
@@ -168,10 +257,27 @@ impl super::TypeCheck for Statement {
                         expr_type
                     ))
                 } else {
-                    match b.check(env, last_scope) {
-                        Ok(ty) => Ok(Some(ty)),
-                        Err(e) => Err(e),
+                    let before = flow(env);
+                    let ty = b.check(env, last_scope)?;
+                    // A variable that is moved or assigned in the body is so for the next
+                    // iteration as well, the body is checked again to find the uses of it
+                    if flow(env) != before {
+                        let mut after = flow(env);
+                        for (scope, before) in after.iter_mut().zip(before.iter()) {
+                            for (id, flow) in scope.iter_mut() {
+                                let moved_before = before.get(id).map(|flow| &flow.moved);
+                                if let (Some(moved), Some(None)) = (&mut flow.moved, moved_before) {
+                                    moved.push_str(" in a previous iteration of the loop");
+                                }
+                            }
+                        }
+                        restore_flow(env, &after);
+                        e.check(env, last_scope)?;
+                        b.check(env, last_scope)?;
                     }
+                    // The body may not be executed at all
+                    join_flow(env, &before);
+                    Ok(Some(ty))
                 }
             }
             Statement::Block(b) => match b.check(env, last_scope) {
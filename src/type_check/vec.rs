@@ -0,0 +1,135 @@
+use super::{check_usable, get_meta, TypeCheck, TypeEnv, TypeErr};
+use crate::ast::{Expr, Ref, Type, UnaryOp};
+use crate::intrinsics::VecIntrinsic;
+
+/// Checks the receiver of a `Vec` method and returns the identifier of the vector, if it is
+/// borrowed directly, along with its element type.
+///
+/// Borrowing the receiver is temporary, it is only checked for mutability like
+/// [`IndexMut`](Expr::IndexMut) and does not count as a live borrow.
+fn receiver(
+    e: &Expr,
+    mutable: bool,
+    env: &mut TypeEnv,
+    idx: usize,
+) -> Result<(Option<String>, Type), TypeErr> {
+    let ty = match e {
+        Expr::UnOp(UnaryOp::Borrow | UnaryOp::BorrowMut, inner)
+            if matches!(**inner, Expr::Ident(_)) =>
+        {
+            check_usable(env, inner)?;
+            let meta = match get_meta(env, inner)? {
+                Some(meta) => meta,
+                None => return Err(format!("Usage of undecleared variable {inner}")),
+            };
+            match meta.ty.clone() {
+                Some(Type::Vec(ty)) => {
+                    let id = match &**inner {
+                        Expr::Ident(id) => id.clone(),
+                        _ => unreachable!("ICE, matched on an identifier above"),
+                    };
+                    if mutable && !meta.mutable {
+                        return Err(format!(
+                            "Cannot borrow {id} as mutable, as it is not declared as mutable"
+                        ));
+                    }
+                    return Ok((Some(id), *ty));
+                }
+                // A guard is dereferenced mutably, so it has to be declared as mutable like
+                // the vector itself
+                Some(Type::CellRefMut(_)) if mutable && !meta.mutable => {
+                    return Err(format!(
+                        "Cannot borrow {inner} as mutable, as it is not declared as mutable"
+                    ));
+                }
+                // Calls through a reference or a guard, e.g. `v.push(1)` where
+                // `v: &mut Vec<i32>`
+                Some(ty) => ty,
+                None => return Err("Type must be known at this point".to_string()),
+            }
+        }
+        e => e.check(env, idx)?,
+    };
+    if !matches!(
+        ty,
+        Type::Ref(_) | Type::MutRef(_) | Type::CellRef(_) | Type::CellRefMut(_)
+    ) {
+        return Err(format!(
+            "Expected a {}Vec but got {ty}",
+            if mutable { "&mut " } else { "&" }
+        ));
+    }
+    // The receiver is dereferenced until the vector is found, e.g. `v.len()` where
+    // `v: &&Vec<i32>` or `c.borrow_mut().push(1)`, it can only be mutated if none of the
+    // references are shared and it is not behind a `Ref<T>`
+    let mut target = &ty;
+    let mut shared = false;
+    while let Type::Ref(Ref(inner, _))
+    | Type::MutRef(Ref(inner, _))
+    | Type::CellRef(inner)
+    | Type::CellRefMut(inner) = target
+    {
+        shared |= matches!(target, Type::Ref(_) | Type::CellRef(_));
+        target = inner;
+    }
+    match target {
+        Type::Vec(_) if mutable && shared => Err(format!(
+            "Cannot borrow the Vec behind {ty} as mutable, as it is behind a & reference"
+        )),
+        Type::Vec(el) => Ok((None, (**el).clone())),
+        target => Err(format!("Expected a Vec but got {target}")),
+    }
+}
+
+impl VecIntrinsic {
+    pub fn check(&self, args: &[Expr], env: &mut TypeEnv, idx: usize) -> Result<Type, TypeErr> {
+        let expected = match self {
+            Self::New => 0,
+            Self::From => args.len(),
+            Self::Push => 2,
+            Self::Pop | Self::Len => 1,
+        };
+        if args.len() != expected {
+            return Err(format!(
+                "Expected {expected} arguments to {} but got {}",
+                self.id(),
+                args.len()
+            ));
+        }
+        match (self, args) {
+            (Self::New, _) => Ok(Type::Vec(Box::new(Type::Unit))),
+            (Self::From, elements) => {
+                let mut ty = Type::Unit;
+                for (idx, el) in elements.iter().enumerate() {
+                    let found = el.check(env, env.len() - 1)?;
+                    if idx != 0 && found != ty {
+                        return Err(format!("Expected {ty} but found {found}"));
+                    }
+                    ty = found;
+                }
+                Ok(Type::Vec(Box::new(ty)))
+            }
+            (Self::Push, [vec, value]) => {
+                let value = value.check(env, idx)?;
+                let (id, ty) = receiver(vec, true, env, idx)?;
+                match (id, ty) {
+                    (_, ty) if ty == value => Ok(Type::Unit),
+                    // The first push decides the element type of an empty vector
+                    (Some(id), Type::Unit) => {
+                        if let Some(meta) = get_meta(env, &Expr::Ident(id))? {
+                            meta.ty = Some(Type::Vec(Box::new(value)));
+                        }
+                        Ok(Type::Unit)
+                    }
+                    (_, ty) => Err(format!(
+                        "Cannot push a value of type {value} to a Vec<{ty}>"
+                    )),
+                }
+            }
+            (Self::Pop, [vec]) => Ok(Type::Option(Box::new(receiver(vec, true, env, idx)?.1))),
+            // Integer literals are always i32, so the length is as well
+            (Self::Len, [vec]) => receiver(vec, false, env, idx).map(|_| Type::I32),
+            (_, _) => unreachable!("ICE, argument count checked above"),
+        }
+    }
+}
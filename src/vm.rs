@@ -1,37 +1,128 @@
 pub mod block;
+pub mod cell;
+pub mod closure;
 pub mod expr;
+pub mod format;
+pub mod frame;
+pub mod fs;
 pub mod func;
 pub mod globals;
+pub mod heap;
+pub mod host;
+pub mod input;
+pub mod io;
+pub mod module;
 pub mod op;
+pub mod option;
+pub mod panic;
 pub mod program;
+pub mod stacked_borrows;
 pub mod statement;
+pub mod vec;
 
 use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+pub use frame::{Frame, Slots};
+pub use heap::{Addr, Heap, Pointer};
+pub use io::{Buffer, Io, Verbosity};
+pub use stacked_borrows::BorrowStacks;
 
 use crate::ast::{
     op::BinaryOp,
     Block,
     Expr::{self},
-    Func, Literal,
+    Func, Literal, Type,
 };
+use crate::intrinsics::IntrinsicRegistry;
 
 #[derive(Debug)]
 pub enum VmErr {
     Err(String),
     Handled(String),
+    /// A panic in the evaluated program, e.g. `unwrap` on a `None`
+    Panic(String),
+    /// Returns a value from the enclosing function, this is how `?` leaves a function early
+    Return(Box<Values>),
+    /// An error returned by a host function, the call is not retried in an enclosing scope
+    Host(String),
+    /// An access that violates the aliasing rules, see [`stacked_borrows`]
+    UndefinedBehavior(String),
+}
+impl VmErr {
+    /// Returns true if the error unwinds the evaluation, a panic, a return, a failed host
+    /// function or undefined behavior can not be retried in an enclosing scope.
+    pub fn unwinds(&self) -> bool {
+        matches!(
+            self,
+            VmErr::Panic(_) | VmErr::Return(_) | VmErr::Host(_) | VmErr::UndefinedBehavior(_)
+        )
+    }
 }
 impl std::fmt::Display for VmErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             VmErr::Err(e) => write!(f, "{}", e),
             VmErr::Handled(e) => write!(f, "{}", e),
+            VmErr::Panic(e) => write!(f, "panicked: {}", e),
+            VmErr::Return(value) => write!(f, "return {}", value),
+            VmErr::Host(e) => write!(f, "{}", e),
+            VmErr::UndefinedBehavior(e) => write!(f, "Undefined behavior: {}", e),
         }
     }
 }
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Values {
     Lit(Literal),
-    Ref((String, usize)),
+    /// A reference, the [`Pointer`] to the place it refers to and the [`Tag`] of the reference.
+    ///
+    /// [`Tag`]: stacked_borrows::Tag
+    Ref(Pointer, stacked_borrows::Tag),
+    /// A raw pointer, `*const T` or `*mut T`, keeps the [`Tag`] of the reference it was cast
+    /// from. Nothing guarantees that the place it points to is still alive.
+    ///
+    /// [`Tag`]: stacked_borrows::Tag
+    Raw(Pointer, stacked_borrows::Tag),
+    Closure(ClosureRecord),
+    /// A function used as a value, it is resolved through the [`FunctionScope`]s when called.
+    Fn(String),
+    /// A `Box<T>`, the address of the allocation in the [`Heap`].
+    Box(Addr),
+    /// An `Rc<T>`, the address of the allocation in the [`Heap`].
+    Rc(Addr),
+    /// A `Vec<T>`, owned by the variable it is stored in.
+    Vec(Vec<Values>),
+    /// An `Option<T>`, `Some(value)` or `None`.
+    Option(Option<Box<Values>>),
+    /// A `Result<T, E>`, `Ok(value)` or `Err(error)`.
+    Result(Result<Box<Values>, Box<Values>>),
+    /// A `Cell<T>`, the value is replaced in place.
+    Cell(Box<Values>),
+    /// A `RefCell<T>` along with the number of live `Ref<T>` guards, `-1` while a `RefMut<T>`
+    /// is live.
+    RefCell(Box<Values>, isize),
+    /// A `Ref<T>` or a `RefMut<T>` if mutable, the [`Pointer`] to the `RefCell<T>` that it
+    /// releases when it is dropped.
+    Guard(Pointer, bool),
+}
+
+/// A captured variable in a closures environment record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Captured {
+    /// Captured by reference, the address of the cell of the variable and wether or not the
+    /// closure may write to it.
+    Ref(Addr, bool),
+    /// Moved in to a cell owned by the closure when it was created, the address of the cell.
+    Value(Addr),
+}
+
+/// The runtime representation of a closure, the code to run along with the captured environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosureRecord {
+    args: Vec<String>,
+    body: Expr,
+    env: Vec<(String, Captured)>,
 }
 
 impl Values {
@@ -41,20 +132,91 @@ impl Values {
             _ => panic!(),
         }
     }
+
+    /// Returns true if reading the value from a variable moves it out of the variable, i.e. the
+    /// value owns a heap allocation, the elements of a vector or a string.
+    ///
+    /// ## Deviations from rust
+    ///
+    /// A closure that captures values is moved even if all of them are `Copy`, the copies would
+    /// otherwise share the cells of the captured values.
+    pub fn moves(&self) -> bool {
+        fn moves(lit: &Literal) -> bool {
+            match lit {
+                Literal::String(_) => true,
+                Literal::Array(elements) => elements.iter().any(|el| moves(el)),
+                _ => false,
+            }
+        }
+        match self {
+            Values::Box(_) | Values::Rc(_) | Values::Vec(_) => true,
+            Values::Cell(_) | Values::RefCell(..) | Values::Guard(..) => true,
+            Values::Closure(closure) => closure.owned().next().is_some(),
+            Values::Lit(lit) => moves(lit),
+            Values::Option(Some(value)) | Values::Result(Ok(value) | Err(value)) => value.moves(),
+            _ => false,
+        }
+    }
+
+    /// Converts the value for `value as ty`, a reference becomes a raw pointer to the same
+    /// place and a boolean an integer.
+    ///
+    /// ## Deviations from rust
+    ///
+    /// Casting a negative integer to `usize` is an error rather than wrapping around.
+    pub fn cast(self, ty: &Type) -> Result<Values, VmErr> {
+        match (self, ty) {
+            (
+                Values::Ref(ptr, tag) | Values::Raw(ptr, tag),
+                Type::ConstPtr(_) | Type::MutPtr(_),
+            ) => Ok(Values::Raw(ptr, tag)),
+            (Values::Lit(Literal::Int(i)), Type::Usize) if i < 0 => Err(VmErr::Err(format!(
+                "Cannot cast {i} to usize as it is negative"
+            ))),
+            (Values::Lit(Literal::Bool(b)), Type::I32 | Type::Usize) => {
+                Ok(Values::Lit(Literal::Int(b as i32)))
+            }
+            (value, _) => Ok(value),
+        }
+    }
 }
 impl std::fmt::Display for Values {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             Values::Lit(l) => l.to_string(),
-            Values::Ref((id, _)) => format!("&{id}"),
+            Values::Ref(ptr, _) => format!("&{ptr}"),
+            Values::Raw(ptr, _) => format!("*{ptr}"),
+            Values::Closure(closure) => format!("|{}| {}", closure.args.join(","), closure.body),
+            Values::Fn(id) => format!("fn {id}"),
+            Values::Box(addr) => format!("Box(#{addr})"),
+            Values::Rc(addr) => format!("Rc(#{addr})"),
+            Values::Vec(elements) => format!(
+                "[{}]",
+                elements
+                    .iter()
+                    .map(|el| el.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Values::Option(Some(value)) => format!("Some({value})"),
+            Values::Option(None) => "None".to_string(),
+            Values::Result(Ok(value)) => format!("Ok({value})"),
+            Values::Result(Err(err)) => format!("Err({err})"),
+            Values::Cell(value) => format!("Cell({value})"),
+            Values::RefCell(value, _) => format!("RefCell({value})"),
+            Values::Guard(ptr, false) => format!("Ref({ptr})"),
+            Values::Guard(ptr, true) => format!("RefMut({ptr})"),
         };
         write!(f, "{}", s)
     }
 }
-/// Describes all of the needed data for a value.
-#[derive(Debug, Clone)]
+/// Describes where the value of a variable is stored.
+#[derive(Debug, Clone, Copy)]
 pub struct ValueMeta {
-    value: Option<Values>,
+    /// The address of the cell of the variable in the [`Heap`]
+    addr: Addr,
+    /// False if the cell belongs to another variable, i.e. a variable captured by reference
+    owned: bool,
 }
 #[derive(Debug, Clone)]
 pub struct FunctionMeta {
@@ -64,11 +226,19 @@ pub struct FunctionMeta {
     /// in the vm all type information is discarded
     args: Vec<String>,
     body: Block,
+    /// The slots of the variables of the function, resolved when it is declared
+    slots: Rc<Slots>,
+}
+impl FunctionMeta {
+    pub fn new(args: Vec<String>, body: Block) -> Self {
+        let slots = Rc::new(Slots::resolve(&args, &body));
+        Self { args, body, slots }
+    }
 }
 impl From<Func> for FunctionMeta {
     fn from(value: Func) -> Self {
-        Self {
-            args: value
+        Self::new(
+            value
                 .args
                 .iter()
                 .map(|el| match &el.id {
@@ -76,8 +246,8 @@ impl From<Func> for FunctionMeta {
                     e => panic!("Cannot treat {e} as an expression"),
                 })
                 .collect(),
-            body: value.body,
-        }
+            value.body,
+        )
     }
 }
 /// Represents the functions accessible in the current scope
@@ -85,9 +255,167 @@ pub type FunctionScope = HashMap<String, FunctionMeta>;
 
 /// Represents a specific scope.
 /// For example a block has it's own scope.
-pub type Scope = HashMap<String, ValueMeta>;
-/// Represents all program [`Scope`]s
-pub type VarEnv = Vec<(Scope, FunctionScope)>;
+///
+/// The variables are bound to the slots of the [`Frame`] until the scope ends, after which their
+/// cells are freed along with the cells of the temporaries. A shadowed variable stays alive until
+/// then as it may still be borrowed.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    /// The slots bound in the scope along with the variables that were bound to them before
+    bindings: Vec<(usize, Option<ValueMeta>)>,
+    temporaries: Vec<Addr>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+/// Represents all program [`Scope`]s along with the [`Heap`], the host functions and the [`Io`]
+/// of the program.
+#[derive(Debug, Clone, Default)]
+pub struct VarEnv {
+    scopes: Vec<(Scope, FunctionScope)>,
+    /// The variables of the current call
+    frame: Frame,
+    /// The variables of the global scope, i.e. the statics, shared by all calls
+    globals: Frame,
+    pub heap: Heap,
+    pub host: IntrinsicRegistry,
+    pub io: Io,
+    /// The borrow stacks of the variables, accesses through references are only checked if set
+    pub stacks: Option<BorrowStacks>,
+}
+
+impl VarEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an environment in which the program may call the host functions in `host`.
+    pub fn with_host(host: IntrinsicRegistry) -> Self {
+        Self {
+            host,
+            ..Self::default()
+        }
+    }
+
+    /// Returns an environment for a call of a function with the variables `slots` from `env`,
+    /// the callee shares the functions, the globals and the memory of the caller.
+    pub fn call(env: &mut VarEnv, slots: Rc<Slots>) -> Self {
+        let mut scopes: Vec<_> = env
+            .iter()
+            .map(|(_, functions)| (Scope::new(), functions.clone()))
+            .collect();
+        scopes.push((Scope::new(), FunctionScope::new()));
+        Self {
+            scopes,
+            frame: Frame::new(slots),
+            globals: env.globals.clone(),
+            heap: std::mem::take(&mut env.heap),
+            host: env.host.clone(),
+            io: env.io.clone(),
+            stacks: env.stacks.take(),
+        }
+    }
+
+    /// Returns the variables that are declared in `scope`, the globals live in the outermost
+    /// scope.
+    fn frame_mut(&mut self, scope: usize) -> &mut Frame {
+        match scope {
+            0 => &mut self.globals,
+            _ => &mut self.frame,
+        }
+    }
+
+    /// Returns the address of the cell of the innermost variable `id`.
+    pub fn var(&self, id: &str) -> Option<Addr> {
+        self.frame
+            .get(id)
+            .or_else(|| self.globals.get(id))
+            .map(|meta| meta.addr)
+    }
+
+    /// Declares the variable `id` in `scope` with a new cell holding `value`, `None` if it is not
+    /// initialized. A variable that is shadowed stays alive until the end of the scope.
+    pub fn declare(
+        &mut self,
+        scope: usize,
+        id: &str,
+        value: Option<Values>,
+    ) -> Result<Addr, VmErr> {
+        let addr = self.heap.alloc_var(id, value);
+        if let Some(stacks) = &mut self.stacks {
+            stacks.declare(addr, id);
+        }
+        self.bind(scope, id, addr, true)?;
+        Ok(addr)
+    }
+
+    /// Binds `id` in `scope` to the existing cell at `addr`, the scope frees it if it is `owned`.
+    pub fn bind(&mut self, scope: usize, id: &str, addr: Addr, owned: bool) -> Result<(), VmErr> {
+        if scope >= self.len() {
+            return Err(VmErr::Err(format!("Cannot declare {id} in scope {scope}")));
+        }
+        let binding = self.frame_mut(scope).bind(id, ValueMeta { addr, owned });
+        self.scopes[scope].0.bindings.push(binding);
+        Ok(())
+    }
+
+    /// Ends the innermost scope, its variables are unbound and the cells it owns are freed in
+    /// the reverse order of their declaration.
+    pub fn end_scope(&mut self) -> Result<(), VmErr> {
+        let scope = match self.scopes.pop() {
+            Some((scope, _)) => scope,
+            None => return Ok(()),
+        };
+        let frame = self.frame_mut(self.scopes.len());
+        let mut owned = vec![];
+        for (slot, previous) in scope.bindings.into_iter().rev() {
+            match frame.restore(slot, previous) {
+                Some(meta) if meta.owned => owned.push(meta.addr),
+                _ => {}
+            }
+        }
+        owned.extend(scope.temporaries.into_iter().rev());
+        for addr in owned {
+            if let Some(stacks) = &mut self.stacks {
+                stacks.forget(addr);
+            }
+            if let Some(value) = self.heap.free(addr)? {
+                self.heap.drop_value(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves `value` to a new cell owned by `scope`, for a borrow of a value that is not stored
+    /// in a variable.
+    pub fn temporary(&mut self, scope: usize, value: Values) -> Result<Addr, VmErr> {
+        let addr = self.heap.alloc(value);
+        if let Some(stacks) = &mut self.stacks {
+            stacks.declare(addr, &format!("#{addr}"));
+        }
+        match self.get_mut(scope) {
+            Some((scope, _)) => scope.temporaries.push(addr),
+            None => return Err(VmErr::Err(format!("Invalid scope {scope} for a temporary"))),
+        }
+        Ok(addr)
+    }
+}
+
+impl Deref for VarEnv {
+    type Target = Vec<(Scope, FunctionScope)>;
+    fn deref(&self) -> &Self::Target {
+        &self.scopes
+    }
+}
+
+impl DerefMut for VarEnv {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.scopes
+    }
+}
 
 pub trait Eval {
     fn eval(
@@ -122,13 +450,16 @@ impl BinaryOp {
         use Literal::{Bool, Int};
         let (left, right) = match (left, right) {
             (Values::Lit(left), Values::Lit(right)) => (left, right),
+            // Options are compared by their contents, `o == None`
+            (left @ Values::Option(_), right @ Values::Option(_)) if *self == Eq => {
+                return Ok(Values::Lit(Bool(left == right)))
+            }
             (l, r) => {
                 return Err(VmErr::Err(format!(
                     "Cannot peform operations on refferences. {l:?} and {r:?} should be literals"
                 )))
             }
         };
-        println!("{left} {self} {right}");
         Ok(Values::Lit(match self {
             Add => Int(left.get_int()? + right.get_int()?),
             Sub => Int(left.get_int()?) - Int(right.get_int()?),
@@ -156,7 +487,7 @@ impl Expr {
 mod test {
 
     use super::*;
-    use crate::ast::Block;
+    use crate::ast::{Block, Type};
     #[test]
     fn test_check_block1() {
         let ts: proc_macro2::TokenStream = "
@@ -283,4 +614,919 @@ mod test {
         println!("l {:?}", l);
         assert_eq!(l.lit().get_int().unwrap(), 2);
     }
+
+    #[test]
+    fn test_closure_capture() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut a: i32 = 1;
+        let b: i32 = 2;
+        let mut f = |c: i32| { a = a + b + c; };
+        f(3);
+        a
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        println!("bl {:?}", bl);
+        let l = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap();
+        println!("l {:?}", l);
+        assert_eq!(l.lit().get_int().unwrap(), 6);
+    }
+
+    #[test]
+    fn test_closure_move_state() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut c = 0;
+        let mut f = move || { c = c + 1; c };
+        f();
+        let g = move || { c };
+        f() * 100 + g() * 10 + c
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let mut env = VarEnv::new();
+        let l = bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+        // The moved in copy of `c` is kept between the calls of `f`
+        assert_eq!(l.lit().get_int().unwrap(), 200);
+        // The captured values are dropped along with the closures
+        assert_eq!(env.heap.live(), 0);
+
+        let prog = "
+        fn main() -> i32 {
+            let mut c = 0;
+            let mut f = move || { c = c + 1; c };
+            f();
+            f()
+        }";
+        let res = crate::Engine::new(prog).unwrap().call("main", &[]).unwrap();
+        assert_eq!(res, Values::Lit(Literal::Int(2)));
+    }
+
+    #[test]
+    fn test_fn_ptr() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn add_one(a: i32) -> i32 { a + 1 };
+        fn apply(f: fn(i32) -> i32, x: i32) -> i32 { f(x) };
+        apply(add_one, 2) + apply(|x: i32| x * 2, 3)
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        println!("bl {:?}", bl);
+        let l = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap();
+        println!("l {:?}", l);
+        assert_eq!(l.lit().get_int().unwrap(), 9);
+    }
+
+    #[test]
+    fn test_box_and_rc() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut b = Box::new(2);
+        *b = *b + 1;
+        let nested = Box::new(Box::new(3));
+        let a = Rc::new(4);
+        let c = Rc::clone(&a);
+        *b + **nested + *a + *c
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let mut env = VarEnv::new();
+        let l = bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+        assert_eq!(l.lit().get_int().unwrap(), 14);
+        // Everything is dropped at the end of the block
+        assert_eq!(env.heap.live(), 0);
+    }
+
+    #[test]
+    fn test_box_double_free() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let b = Box::new(2);
+        let r = &b;
+        let c = *r;
+        0
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let l = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0);
+        assert!(l.unwrap_err().to_string().contains("Double free"));
+    }
+
+    #[test]
+    fn test_nested_box() {
+        // A list of nodes that each box the next one
+        let engine = crate::Engine::new(
+            "
+            fn main() -> i32 {
+                let list = Some(Box::new(Some(Box::new(Some(Box::new(3))))));
+                let first = list.unwrap();
+                let second = (*first).unwrap();
+                let third = (*second).unwrap();
+                let mut b = Box::new(Box::new(4));
+                **b = **b + 1;
+                let r = &**b;
+                let sum = *third + **b + *r;
+                // Moves the inner box out of `b`
+                let c = *b;
+                sum * 10 + *c
+            }",
+        )
+        .unwrap();
+        let mut env = engine.env();
+        let res = engine.call_in(&mut env, "main", &[]).unwrap();
+        assert_eq!(res, Values::Lit(Literal::Int(135)));
+        // Every box is freed exactly once
+        assert_eq!(env.heap.live(), 0);
+    }
+
+    #[test]
+    fn test_vec() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut v = Vec::new();
+        v.push(1);
+        v.push(2);
+        let mut w = vec![10, 20, 30];
+        w[1] = 5;
+        let mut s = 0;
+        for x in &w {
+            s = s + x;
+        };
+        s + v.pop().unwrap() + v.len()
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let l = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap();
+        assert_eq!(l.lit().get_int().unwrap(), 48);
+    }
+
+    #[test]
+    fn test_vec_pop_empty() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut v = vec![1];
+        let one = v.pop();
+        one.unwrap() == 1 && v.pop().is_none()
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let l = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap();
+        assert_eq!(l, Values::Lit(Literal::Bool(true)));
+    }
+
+    #[test]
+    fn test_option_and_result() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn check(x: i32) -> Result<i32, i32> {
+            if x < 10 { Ok(x + 1) } else { Err(x) }
+        };
+        fn sum(a: i32, b: i32) -> Result<i32, i32> {
+            let x = check(a)?;
+            let y = check(b)?;
+            Ok(x + y)
+        };
+        fn first(v: Option<i32>) -> Option<i32> {
+            let x = v?;
+            Some(x + 100)
+        };
+        let mut r = sum(1, 2).unwrap() + first(Some(1)).expect(\"some\");
+        let e = sum(1, 20);
+        if e.is_err() { r = r + 10 };
+        if first(None).is_none() { r = r + 1000 };
+        let o: Option<i32> = None;
+        if o == None { r = r + 10000 };
+        if first(Some(1)) == Some(101) { r = r + 100000 };
+        r
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let l = bl.eval(&mut VarEnv::new(), 0, 1000, &mut 0).unwrap();
+        assert_eq!(l.lit().get_int().unwrap(), 111116);
+    }
+
+    #[test]
+    fn test_unwrap_none_panics() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let a: Option<i32> = None;
+        a.unwrap()
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let err = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap_err();
+        assert!(matches!(err, VmErr::Panic(_)), "{err}");
+        assert!(err.to_string().contains("on a `None` value"));
+    }
+
+    #[test]
+    fn test_assertions() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn check(a: i32) -> i32 {
+            assert!(a < 10, \"a was {}\", a);
+            if a > 5 { a } else { unreachable!(\"small {}\", a) }
+        };
+        assert_eq!(check(6), 6);
+        assert_ne!(check(7), 6);
+        check(3)
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let err = bl.eval(&mut VarEnv::new(), 0, 1000, &mut 0).unwrap_err();
+        assert!(matches!(err, VmErr::Panic(_)), "{err}");
+        let err = err.to_string();
+        assert!(err.contains("entered unreachable code: small 3"), "{err}");
+        assert!(err.contains("in fn check"), "{err}");
+
+        let ts: proc_macro2::TokenStream = "
+    {
+        let a = 1;
+        assert_eq!(a + 1, 3, \"a is {}\", a)
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let err = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("assertion `left == right` failed: a is 1\n  left: 2\n right: 3"));
+
+        // The call is reported with the names of the variables in the source
+        let engine =
+            crate::Engine::new("fn main() { let x = 5; assert!(x < 3, \"x was {}\", x); }")
+                .unwrap();
+        let err = engine.call("main", &[]).unwrap_err().to_string();
+        assert!(err.contains("x was 5\n\tat "), "{err}");
+        assert!(!err.contains('#'), "{err}");
+    }
+
+    #[test]
+    fn test_format() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let a = 10;
+        let v = vec![1, 2];
+        let s = format!(\"{}|{:>4}|{:<6}|{:^5}|{a:#x}|{:08b}|{1:?}|{{}}\", a, true, 5, a, 5);
+        println!(\"{s}\");
+        format!(\"{:?} {:?} {} {:>3}\", v, &s, &a, -1)
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let s = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap();
+        let expected = "[1, 2] \"10|true|5     | 10  |0xa|00000101|true|{}\" 10  -1";
+        assert_eq!(
+            s,
+            Values::Lit(Literal::String(crate::intrinsics::quote(expected)))
+        );
+    }
+
+    #[test]
+    fn test_format_named_args() {
+        // The named arguments are variables read by the call, they are renamed along with them
+        // and checked by the borrow checker
+        let engine = crate::Engine::new(
+            "fn main() -> String {
+                let a = 1;
+                let s = format!(\"x\");
+                let mut b = 2;
+                b = b + a;
+                println!(\"{a} {s}\");
+                format!(\"{a}-{b:>3}-{s}-{a}\")
+            }",
+        )
+        .unwrap();
+        let s = engine.call("main", &[]);
+        let expected = Values::Lit(Literal::String(crate::intrinsics::quote("1-  3-x-1")));
+        assert_eq!(s.unwrap(), expected);
+
+        let err = crate::Engine::new(
+            "fn main() {
+                let mut a = 1;
+                let r = &mut a;
+                println!(\"{a}\");
+                *r = 2;
+            }",
+        );
+        assert!(matches!(err, Err(crate::EngineErr::Borrow(_))), "{err:?}");
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let mut host = crate::intrinsics::IntrinsicRegistry::new();
+        host.register(
+            "double",
+            vec![Type::I32],
+            Type::I32,
+            |args| match &args[..] {
+                [Values::Lit(Literal::Int(i))] => Ok(Values::Lit(Literal::Int(2 * i))),
+                args => Err(VmErr::Err(format!("Invalid arguments {args:?}"))),
+            },
+        )
+        .unwrap();
+        host.register("fail", vec![], Type::Unit, |_| {
+            Err(VmErr::Err("host failure".to_owned()))
+        })
+        .unwrap();
+        assert!(host
+            .register("double", vec![], Type::Unit, |_| Ok(Values::Lit(
+                Literal::Unit
+            )))
+            .is_err());
+        assert!(host
+            .register("Box::new", vec![], Type::Unit, |_| Ok(Values::Lit(
+                Literal::Unit
+            )))
+            .is_err());
+
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn quadruple(a: i32) -> i32 {
+            double(double(a))
+        };
+        let a = 3;
+        quadruple(a) + double(*&a)
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let l = bl.eval(&mut VarEnv::with_host(host.clone()), 0, 100, &mut 0);
+        assert_eq!(l.unwrap(), Values::Lit(Literal::Int(18)));
+
+        let bl: Block = syn::parse2("{ fail() }".parse().unwrap()).unwrap();
+        let err = bl.eval(&mut VarEnv::with_host(host.clone()), 0, 100, &mut 0);
+        assert_eq!(err.unwrap_err().to_string(), "host failure");
+
+        // The result is checked against the declared return type
+        host.register("lie", vec![], Type::Bool, |_| {
+            Ok(Values::Lit(Literal::Int(1)))
+        })
+        .unwrap();
+        let bl: Block = syn::parse2("{ lie() }".parse().unwrap()).unwrap();
+        let err = bl.eval(&mut VarEnv::with_host(host.clone()), 0, 100, &mut 0);
+        assert!(matches!(err, Err(VmErr::Host(_))), "{err:?}");
+
+        // The signature of a typed host function is derived from the closure
+        host.register_fn("repeat", |s: String, n: usize| s.repeat(n))
+            .unwrap();
+        host.register_fn("first", |v: Vec<i32>| v.first().copied())
+            .unwrap();
+        let repeat = host.get("repeat").unwrap();
+        assert_eq!(repeat.args, vec![Type::String, Type::Usize]);
+        assert_eq!(repeat.ty, Type::String);
+        assert_eq!(
+            host.get("first").unwrap().ty,
+            Type::Option(Box::new(Type::I32))
+        );
+        let engine = crate::Engine::with_host(
+            "fn main() -> String {
+                let v = vec![2, 3];
+                let n = first(v).unwrap();
+                repeat(format!(\"ab\"), n as usize)
+            }",
+            host,
+        )
+        .unwrap();
+        let s = engine.call("main", &[]).unwrap();
+        assert_eq!(s, Values::Lit(Literal::String("\"abab\"".to_owned())));
+    }
+
+    #[test]
+    fn test_captured_output() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let a = 2;
+        print!(\"a = \");
+        println!(\"{}\", a);
+        eprintln!(\"done\");
+        let mut v = vec![a + 3];
+        v.pop();
+        v[0]
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        let mut env = VarEnv::new();
+        env.io = Io::default()
+            .with_stdout(out.clone())
+            .with_stderr(err.clone())
+            .with_verbosity(Verbosity::Verbose);
+        assert!(bl.eval(&mut env, 0, 100, &mut 0).is_err());
+        assert_eq!(out.contents(), "a = 2\n");
+        let err = err.contents();
+        assert!(err.starts_with("done\n2 + 3\n"), "{err}");
+        assert!(
+            err.contains("Occured during execution of statement"),
+            "{err}"
+        );
+
+        // Nothing but the output of the program is written when quiet
+        let (out, err) = (Buffer::new(), Buffer::new());
+        env = VarEnv::new();
+        env.io = Io::default()
+            .with_stdout(out.clone())
+            .with_stderr(err.clone())
+            .with_verbosity(Verbosity::Quiet);
+        assert!(bl.eval(&mut env, 0, 100, &mut 0).is_err());
+        assert_eq!(out.contents(), "a = 2\n");
+        assert_eq!(err.contents(), "done\n");
+    }
+
+    #[test]
+    fn test_input() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let name = read_line();
+        let a = read_i32();
+        let b = read_i32();
+        let args = args();
+        println!(\"{name} {} {:?}\", a + b, args);
+        read_line()
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let out = Buffer::new();
+        let mut env = VarEnv::new();
+        env.io = Io::default()
+            .with_stdout(out.clone())
+            .with_stdin("rnr\n1\n -2 \n".as_bytes())
+            .with_args(vec!["a".to_owned()]);
+        let l = bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+        assert_eq!(out.contents(), "rnr -1 [\"a\"]\n");
+        assert_eq!(l, Values::Lit(Literal::String("\"\"".to_owned())));
+
+        let bl: Block = syn::parse2("{ read_i32() }".parse().unwrap()).unwrap();
+        let mut env = VarEnv::new();
+        env.io = Io::default().with_stdin("one\n".as_bytes());
+        let err = bl.eval(&mut env, 0, 100, &mut 0).unwrap_err();
+        assert!(matches!(err, VmErr::Panic(_)), "{err}");
+    }
+
+    #[test]
+    fn test_fs() {
+        let dir = std::env::temp_dir().join(format!("rnr_test_fs_{}", std::process::id()));
+        let allowed = dir.join("allowed");
+        std::fs::create_dir_all(&allowed).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let (allowed_s, dir_s) = (allowed.display(), dir.display());
+
+        let ts: proc_macro2::TokenStream = format!(
+            "
+    {{
+        let w = fs::write(\"{allowed_s}/out.txt\", \"hello\\n\");
+        let r = fs::read_to_string(\"{allowed_s}/out.txt\");
+        let escaped = fs::read_to_string(\"{allowed_s}/../secret.txt\");
+        let outside = fs::write(\"{dir_s}/new.txt\", \"\");
+        let missing = fs::read_to_string(\"{allowed_s}/missing.txt\");
+        println!(\"{{:?}} {{:?}}\", w, r);
+        eprintln!(\"{{:?}}\", escaped);
+        eprintln!(\"{{:?}}\", outside);
+        missing
+    }}
+    "
+        )
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        let mut env = VarEnv::new();
+        env.io = Io::default()
+            .with_stdout(out.clone())
+            .with_stderr(err.clone())
+            .with_allowed_dir(&allowed);
+        let missing = bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+        assert_eq!(out.contents(), "Ok(()) Ok(\"hello\\n\")\n");
+        // Denied accesses are errors returned to the program
+        let denied = err.contents();
+        let denied: Vec<&str> = denied.lines().collect();
+        assert_eq!(denied.len(), 2);
+        assert!(
+            denied.iter().all(|e| e.contains("permission denied")),
+            "{denied:?}"
+        );
+        assert!(!dir.join("new.txt").exists());
+        assert!(matches!(missing, Values::Result(Err(_))));
+
+        // Nothing is allowed by default
+        let bl: Block = syn::parse2(
+            format!("{{ fs::read_to_string(\"{allowed_s}/out.txt\") }}")
+                .parse()
+                .unwrap(),
+        )
+        .unwrap();
+        let r = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap();
+        assert!(matches!(r, Values::Result(Err(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reborrows() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut a = 1;
+        let r = &mut a;
+        let s = &mut *r;
+        *s = 2;
+        let t = &*r;
+        let mut v = vec![1];
+        let mut rv = &mut v;
+        let rrv = &mut rv;
+        rrv.push(2);
+        println!(\"{} {} {} {}\", *t, t == 2, rrv.len(), &t);
+        a
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let out = Buffer::new();
+        let mut env = VarEnv::new();
+        env.io = Io::default().with_stdout(out.clone());
+        let a = bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+        // The reborrows write to `a` rather than to a copy of it
+        assert_eq!(a, Values::Lit(Literal::Int(2)));
+        assert_eq!(out.contents(), "2 true 2 2\n");
+    }
+
+    #[test]
+    fn test_element_refs() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut a = [1, 2, 3];
+        let mut v = vec![4, 5];
+        let j = 2;
+        {
+            let x = &mut a[0];
+            *x = a[j] + 1;
+            let y = &v[1];
+            v[0] += *y;
+        };
+        println!(\"{} {}\", a[0], v[0]);
+        a[0]
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let out = Buffer::new();
+        let mut env = VarEnv::new();
+        env.io = Io::default().with_stdout(out.clone());
+        let a = bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+        assert_eq!(a, Values::Lit(Literal::Int(4)));
+        assert_eq!(out.contents(), "4 9\n");
+    }
+
+    #[test]
+    fn test_stacked_borrows() {
+        let eval = |src: &str| {
+            let bl: Block = syn::parse_str(src).unwrap();
+            let mut env = VarEnv::new();
+            env.io = Io::default().with_stdout(Buffer::new());
+            env.stacks = Some(BorrowStacks::new());
+            bl.eval(&mut env, 0, 100, &mut 0)
+        };
+        let ok = eval(
+            "{
+                let mut a = [1, 2];
+                let x = &mut a[0];
+                let y = &mut a[1];
+                *x = 3;
+                *y = 4;
+                let r = &mut a;
+                let mut v = vec![1];
+                v.push(v.len());
+                let s = &v;
+                a[0] + a[1] + s.len()
+            }",
+        );
+        // The mutable borrows of different elements do not invalidate each other
+        assert!(matches!(ok, Ok(Values::Lit(Literal::Int(9)))), "{ok:?}");
+
+        let err = eval(
+            "{
+                let mut a = 0;
+                let b = &mut a;
+                let c = &a;
+                *b = 4;
+                *c
+            }",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Undefined behavior: write access `*b` to `a` through <1>, but <1> is not in the \
+             borrow stack of `a`\n\t<1> was created by `&mut a`\n\t<1> was invalidated by the \
+             read access `&a`"
+        );
+        let err = eval(
+            "{
+                let mut v = vec![1];
+                let r = &v;
+                v.push(2);
+                r.len()
+            }",
+        )
+        .unwrap_err();
+        assert!(matches!(err, VmErr::UndefinedBehavior(_)), "{err}");
+        assert!(err
+            .to_string()
+            .contains("invalidated by the write access `&mut v`"));
+
+        // Without the borrow stacks the accesses are not checked
+        let bl: Block =
+            syn::parse_str("{ let mut a = 0; let b = &mut a; let c = &a; *b = 4; *c }").unwrap();
+        let l = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap();
+        assert_eq!(l, Values::Lit(Literal::Int(4)));
+    }
+    #[test]
+    fn test_memory_cells() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn swap(a: &mut i32, b: &mut i32) {
+            let t = *a;
+            *a = *b;
+            *b = t;
+        };
+        fn first(v: &mut Vec<i32>) -> &mut i32 { &mut v[0] };
+        let mut a = [1, 2];
+        swap(&mut a[0], &mut a[1]);
+        let mut v = vec![3];
+        let f = first(&mut v);
+        *f = 4;
+        let x = 5;
+        let r = &x;
+        let x = 6;
+        a[0] * 100 + v[0] * 10 + *r + x
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let mut env = VarEnv::new();
+        let l = bl.eval(&mut env, 0, 100, &mut 0).unwrap();
+        // References passed to functions and shadowed variables refer to the same cells
+        assert_eq!(l, Values::Lit(Literal::Int(251)));
+        // The cells of the variables are freed at the end of the block
+        assert_eq!(env.heap.live(), 0);
+
+        let ts: proc_macro2::TokenStream = "
+    {
+        let mut r = &0;
+        {
+            let x = 1;
+            r = &x;
+        };
+        *r
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let err = Buffer::new();
+        let mut env = VarEnv::new();
+        env.io = Io::default().with_stderr(err.clone());
+        assert!(bl.eval(&mut env, 0, 100, &mut 0).is_err());
+        let err = err.contents();
+        assert!(
+            err.contains("Dangling reference to `x`, it was dropped at the end of its scope"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_cell_reuse() {
+        let ts: proc_macro2::TokenStream = "
+    {
+        fn square(x: i32) -> i32 {
+            let a = 1;
+            {
+                let a = x * x;
+                let r = &a;
+            };
+            let y = x * x;
+            y * a
+        };
+        let mut i = 0;
+        let mut s = 0;
+        while i < 50 {
+            let a = i;
+            let b = &a;
+            s = s + square(*b);
+            i = i + 1;
+        };
+        s
+    }
+    "
+        .parse()
+        .unwrap();
+        let bl: Block = syn::parse2(ts).unwrap();
+        let mut env = VarEnv::new();
+        let l = bl.eval(&mut env, 0, 10_000, &mut 0).unwrap();
+        // The shadowed `a` is bound again once the inner block ends
+        assert_eq!(l, Values::Lit(Literal::Int(40425)));
+        // The cells of the variables of each iteration and call are reused
+        assert!(env.heap.cells() < 10, "{}", env.heap.cells());
+        assert_eq!(env.heap.live(), 0);
+
+        // `p` reuses the cell of `a`, the pointer still refers to `a`
+        let bl: Block = syn::parse_str(
+            "{
+                let p = { let a = 1; &a as *const i32 };
+                let b = 2;
+                unsafe { *p } + b
+            }",
+        )
+        .unwrap();
+        let err = bl.eval(&mut VarEnv::new(), 0, 100, &mut 0).unwrap_err();
+        assert!(matches!(err, VmErr::UndefinedBehavior(_)), "{err}");
+        assert!(
+            err.to_string().contains("Dangling reference to `a`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_raw_pointers() {
+        let eval = |prog: &str| {
+            let bl: Block = syn::parse_str(prog).unwrap();
+            bl.eval(&mut VarEnv::new(), 0, 100, &mut 0)
+        };
+        let ok = eval(
+            "{
+                let mut a = 1;
+                let p = &mut a as *mut i32;
+                unsafe { *p = 2 };
+                let q = p as *const i32;
+                unsafe { *q } + a
+            }",
+        );
+        assert!(matches!(ok, Ok(Values::Lit(Literal::Int(4)))), "{ok:?}");
+
+        // The local a pointer points to is dropped when the function returns
+        let err = eval(
+            "{
+                fn dangling() -> *const i32 {
+                    let x = 5;
+                    &x as *const i32
+                };
+                let p = dangling();
+                unsafe { *p }
+            }",
+        )
+        .unwrap_err();
+        assert!(matches!(err, VmErr::UndefinedBehavior(_)), "{err}");
+        assert!(err
+            .to_string()
+            .contains("`*p` dereferences an invalid raw pointer"));
+        assert!(
+            err.to_string().contains("Dangling reference to `x`"),
+            "{err}"
+        );
+
+        // The element is removed after the pointer to it was created
+        let err = eval(
+            "{
+                let mut v = vec![1, 2];
+                let p = &mut v[1] as *mut i32;
+                v.pop();
+                unsafe { *p = 3 };
+            }",
+        )
+        .unwrap_err();
+        assert!(matches!(err, VmErr::UndefinedBehavior(_)), "{err}");
+        assert!(
+            err.to_string()
+                .contains("Cannot access element at index 1 since `v` is of size 1"),
+            "{err}"
+        );
+
+        // The errors refer to the names in the source rather than the linearized identifiers
+        use crate::prelude::*;
+        let mut prog: Ast<Prog> = "
+            fn main() {
+                let mut a = 0;
+                let p = &mut a as *mut i32;
+                let b = &mut a;
+                *b = 1;
+                unsafe { *p = 2 };
+            }"
+        .to_string()
+        .into();
+        check!(prog).unwrap();
+        borrow_check!(prog).unwrap();
+        let mut env = VarEnv::new();
+        env.stacks = Some(BorrowStacks::new());
+        let err = prog.eval(&mut env, 0, 100, &mut 0).unwrap_err().to_string();
+        assert!(
+            err.starts_with("Undefined behavior: write access `*p` to `a` through <1>"),
+            "{err}"
+        );
+        assert!(
+            err.contains("invalidated by the write access `&mut a`"),
+            "{err}"
+        );
+        assert_eq!(
+            stacked_borrows::source_names("*>2#2!1_p > 1 && >10#3!0_b_c"),
+            "*p > 1 && b_c"
+        );
+    }
+
+    #[test]
+    fn test_cells() {
+        let eval = |prog: &str| {
+            let bl: Block = syn::parse_str(prog).unwrap();
+            bl.eval(&mut VarEnv::new(), 0, 100, &mut 0)
+        };
+        let ok = eval(
+            "{
+                let c = Cell::new(1);
+                let r = &c;
+                c.set(r.get() + 1);
+                let s = Rc::new(RefCell::new(10));
+                let t = Rc::clone(&s);
+                {
+                    let mut g = t.borrow_mut();
+                    *g = *g + c.get();
+                };
+                let a = s.borrow();
+                let b = t.borrow();
+                *a + *b
+            }",
+        );
+        assert!(matches!(ok, Ok(Values::Lit(Literal::Int(24)))), "{ok:?}");
+
+        // A temporary guard is released once it is read or written through
+        let ok = eval(
+            "{
+                let c = RefCell::new(0);
+                *c.borrow_mut() = 1;
+                let a = *c.borrow_mut();
+                let mut g = c.borrow_mut();
+                *g = a + 1;
+                *g
+            }",
+        );
+        assert!(matches!(ok, Ok(Values::Lit(Literal::Int(2)))), "{ok:?}");
+
+        // Methods are called through the guards, a guard returned by a call is released after it
+        let engine = crate::Engine::new(
+            "fn main() -> i32 {
+                let c = RefCell::new(vec![1]);
+                c.borrow_mut().push(2);
+                {
+                    let mut g = c.borrow_mut();
+                    g.push(3);
+                };
+                let o = Rc::new(RefCell::new(Some(1)));
+                if o.borrow().is_some() { c.borrow().len() } else { 0 }
+            }",
+        )
+        .unwrap();
+        let ok = engine.call("main", &[]);
+        assert!(matches!(ok, Ok(Values::Lit(Literal::Int(3)))), "{ok:?}");
+
+        for (prog, msg) in [
+            (
+                "{ let c = RefCell::new(1); let a = c.borrow(); let b = c.borrow_mut(); }",
+                "already borrowed: BorrowMutError",
+            ),
+            (
+                "{ let c = RefCell::new(1); let a = c.borrow_mut(); let b = c.borrow(); }",
+                "already mutably borrowed: BorrowError",
+            ),
+        ] {
+            let err = eval(prog).unwrap_err();
+            assert!(matches!(&err, VmErr::Panic(e) if e == msg), "{prog}: {err}");
+        }
+    }
 }
@@ -18,10 +18,18 @@ impl super::Eval for Block {
         let mut return_value = Values::Lit(Literal::Unit);
         for stmt in &self.statements {
             // update the return type for each iteration
-            return_value = stmt.eval(env, len, max_iter, iter_counter)?
+            return_value = match stmt.eval(env, len, max_iter, iter_counter) {
+                Ok(value) => value,
+                // `?` leaves the block early, its locals are dropped on the way out
+                Err(e @ VmErr::Return(_)) => {
+                    env.end_scope()?;
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            };
         }
-        // Instead we simply drop the latest scope
-        let _ = env.pop();
+        // Instead we simply drop the latest scope, along with any heap allocations it owns
+        env.end_scope()?;
 
         if self.semi {
             Ok(Values::Lit(Literal::Unit))
@@ -0,0 +1,109 @@
+//! Evaluation of the `Cell<T>` and `RefCell<T>` intrinsics, the value is changed in place through
+//! the shared reference to the cell. A `RefCell<T>` counts its guards and a borrow that
+//! conflicts with a live guard panics.
+use super::{
+    heap::Pointer,
+    stacked_borrows::{eval_args, AccessKind},
+    Values, VarEnv, VmErr,
+};
+use crate::ast::{Expr, Literal, UnaryOp};
+use crate::intrinsics::CellIntrinsic;
+
+/// Releases the guard that the receiver `arg` borrows if the guard was returned by a call, e.g.
+/// `c.borrow_mut().push(1)`. Like in `*c.borrow()` the guard only lives for the call, the
+/// temporary that held it is left with a unit value.
+pub(super) fn release_receiver(
+    env: &mut VarEnv,
+    arg: &Expr,
+    receiver: &Values,
+) -> Result<(), VmErr> {
+    let ptr = match (arg, receiver) {
+        (Expr::UnOp(UnaryOp::Borrow | UnaryOp::BorrowMut, e), Values::Ref(ptr, _))
+            if matches!(**e, Expr::FuncCall(_)) =>
+        {
+            *ptr
+        }
+        _ => return Ok(()),
+    };
+    match env.heap.load(ptr)? {
+        Values::Guard(..) => env.heap.store(ptr, Values::Lit(Literal::Unit)),
+        _ => Ok(()),
+    }
+}
+
+/// Follows the references in `receiver` to the cell they refer to, the cell may be stored in
+/// an `Rc` or a `Box`.
+///
+/// The cell is only read through the shared references, the aliasing checker allows any number
+/// of them to change the value in it.
+fn cell(env: &mut VarEnv, receiver: Values, intrinsic: &CellIntrinsic) -> Result<Pointer, VmErr> {
+    let mut target = receiver;
+    loop {
+        let ptr = match target {
+            Values::Ref(ptr, tag) => {
+                env.access(ptr, tag, AccessKind::Read, &intrinsic.id())?;
+                ptr
+            }
+            Values::Rc(addr) | Values::Box(addr) => Pointer::to(addr),
+            value => {
+                return Err(VmErr::Err(format!(
+                    "Expected a reference to a cell, got {value}"
+                )))
+            }
+        };
+        match env.heap.load(ptr)? {
+            Values::Cell(_) | Values::RefCell(..) => return Ok(ptr),
+            value => target = value,
+        }
+    }
+}
+
+impl CellIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        let values = eval_args(args, env, env.len() - 1, max_iter, iter_counter)?;
+        let mut values = values.into_iter();
+        let receiver = match values.next() {
+            Some(receiver) => receiver,
+            None => return Err(VmErr::Err(format!("Expected an argument to {}", self.id()))),
+        };
+        let ptr = match self {
+            Self::CellNew => return Ok(Values::Cell(Box::new(receiver))),
+            Self::RefCellNew => return Ok(Values::RefCell(Box::new(receiver), 0)),
+            _ => cell(env, receiver, self)?,
+        };
+        match (self, env.heap.place_mut(ptr)?) {
+            (Self::Get, Values::Cell(value)) => Ok((**value).clone()),
+            (Self::Set, Values::Cell(_)) => match values.next() {
+                Some(value) => {
+                    env.heap.replace(ptr, value)?;
+                    Ok(Values::Lit(Literal::Unit))
+                }
+                None => Err(VmErr::Err(format!("Expected a value to {}", self.id()))),
+            },
+            (Self::Borrow, Values::RefCell(_, -1)) => Err(VmErr::Panic(
+                "already mutably borrowed: BorrowError".to_string(),
+            )),
+            (Self::Borrow, Values::RefCell(_, borrows)) => {
+                *borrows += 1;
+                Ok(Values::Guard(ptr, false))
+            }
+            (Self::BorrowMut, Values::RefCell(_, borrows)) if *borrows == 0 => {
+                *borrows = -1;
+                Ok(Values::Guard(ptr, true))
+            }
+            (Self::BorrowMut, Values::RefCell(..)) => {
+                Err(VmErr::Panic("already borrowed: BorrowMutError".to_string()))
+            }
+            (_, value) => Err(VmErr::Err(format!(
+                "Invalid receiver {value} to {}",
+                self.id()
+            ))),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::{Addr, Captured, ClosureRecord, Eval, FunctionScope, Scope, Values, VarEnv, VmErr};
+use crate::ast::{Capture, Closure, Expr};
+
+impl Eval for Closure {
+    fn eval(
+        &self,
+        env: &mut VarEnv,
+        _scope: usize,
+        _max_iter: usize,
+        _iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        // Build the environment record, by reference captures point to the cells of the variables
+        // while captured values are moved in to cells owned by the closure
+        let mut captured = vec![];
+        for (id, capture) in self.captures() {
+            let addr = match env.var(&id) {
+                Some(addr) => addr,
+                // Not a variable, most likely a function
+                None => continue,
+            };
+            let value = match capture {
+                Capture::Value => {
+                    let cell = env.heap.cell(addr)?;
+                    let value = match cell.as_ref().map(Values::moves) {
+                        Some(true) => cell.take(),
+                        _ => cell.clone(),
+                    };
+                    let value = value.ok_or_else(|| {
+                        VmErr::Err(format!(
+                            "Value of variable {id} must be known when it is moved in to a closure"
+                        ))
+                    })?;
+                    Captured::Value(env.heap.alloc_var(&id, Some(value)))
+                }
+                Capture::Ref => Captured::Ref(addr, false),
+                Capture::MutRef => Captured::Ref(addr, true),
+            };
+            captured.push((id, value));
+        }
+        let mut args = vec![];
+        for arg in self.args.iter() {
+            match &arg.id {
+                Expr::Ident(i) => args.push(i.clone()),
+                e => return Err(VmErr::Err(format!("Cannot use {e} as a closure argument"))),
+            }
+        }
+        Ok(Values::Closure(ClosureRecord {
+            args,
+            body: *self.body.clone(),
+            env: captured,
+        }))
+    }
+}
+
+impl ClosureRecord {
+    /// Returns the addresses of the cells of the values captured by the closure.
+    pub fn owned(&self) -> impl Iterator<Item = Addr> + '_ {
+        self.env.iter().filter_map(|(_, captured)| match captured {
+            Captured::Value(addr) => Some(*addr),
+            Captured::Ref(..) => None,
+        })
+    }
+}
+
+impl ClosureRecord {
+    /// Calls the closure with the given arguments.
+    ///
+    /// Captured values are accessed through the cells owned by the closure, so that changes to
+    /// them are kept between calls, while variables captured by reference are accessed through
+    /// the cells of the variables themselves.
+    pub fn call(
+        &self,
+        env: &mut VarEnv,
+        args: Vec<Values>,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        env.push((Scope::new(), FunctionScope::new()));
+        let len = env.len() - 1;
+        // The captured values are owned by the closure, not the call
+        for (id, captured) in self.env.iter() {
+            let addr = match captured {
+                Captured::Value(addr) => *addr,
+                Captured::Ref(addr, _) => {
+                    // Fails if the variable is no longer alive
+                    env.heap.cell(*addr)?;
+                    *addr
+                }
+            };
+            env.bind(len, id, addr, false)?;
+        }
+        for (id, value) in self.args.iter().zip(args) {
+            env.declare(len, id, Some(value))?;
+        }
+
+        let ret = self.body.eval(env, len, max_iter, iter_counter);
+
+        // Arguments are owned by the call
+        env.end_scope()?;
+        match ret {
+            // `?` returned early from the closure
+            Err(VmErr::Return(value)) => Ok(*value),
+            ret => ret,
+        }
+    }
+}
@@ -1,7 +1,14 @@
-use std::convert::TryInto;
-
-use super::{op::Operation, Eval, ValueMeta, Values, VarEnv, VmErr};
-use crate::ast::{Expr, Literal, UnaryOp};
+use super::{
+    heap::{borrow, deref, element, read, read_var, through_raw, Pointer},
+    op::Operation,
+    stacked_borrows::{eval_args, AccessKind},
+    Eval, Values, VarEnv, VmErr,
+};
+use crate::ast::{BinaryOp, Expr, Literal, UnaryOp};
+use crate::intrinsics::{
+    CellIntrinsic, FormatIntrinsic, FsIntrinsic, HeapIntrinsic, InputIntrinsic, OptionIntrinsic,
+    PanicIntrinsic, VecIntrinsic,
+};
 
 impl super::Eval for Expr {
     //.eval_expr
@@ -24,32 +31,38 @@ impl super::Eval for Expr {
             l => l - 1,
         };
         let ret = match self.clone() {
-            Expr::Ident(id) => {
-                let this_env = match env.get(scope) {
-                    Some(scope) => &scope.0,
-                    _ => {
-                        return Err(VmErr::Err(format!(
-                            "Tried to get scope {scope} when there were only {} scopes",
-                            env.len()
-                        )))
-                    }
-                };
-                match (this_env.get(&id), scope) {
-                    (Some(value), _) => match &value.value {
+            Expr::Ident(id) => match env.var(&id) {
+                Some(addr) => {
+                    // Reading a variable removes the mutable references to it from its stack
+                    env.access(Pointer::to(addr), 0, AccessKind::Read, self)?;
+                    let cell = env.heap.cell(addr)?;
+                    match cell {
+                        // Heap allocations are moved out of the variable
+                        Some(value) if value.moves() => Ok(cell.take().unwrap()),
                         Some(value) => Ok(value.clone()),
-                        _ => Err(VmErr::Err(format!(
+                        None => Err(VmErr::Err(format!(
                             "Value of variable {id} must be known at this point"
                         ))),
-                    },
-                    (None, 0) => Err(VmErr::Err("variable not found".to_string())),
-                    (_, scope) => return self.eval(env, scope - 1, max_iter, iter_counter),
+                    }
                 }
-            }
+                // Functions can be used as values as well
+                None => match env.iter().any(|scope| scope.1.contains_key(&id)) {
+                    true => Ok(Values::Fn(id)),
+                    false => Err(VmErr::Err("variable not found".to_string())),
+                },
+            },
 
             Expr::Lit(l) => Ok(Values::Lit(l)),
             Expr::BinOp(op, l, r) => {
-                let lhs = (*l).eval(env, last_scope, max_iter, iter_counter)?;
-                let rhs = (*r).eval(env, last_scope, max_iter, iter_counter)?;
+                // Operators only read their operands, comparing two strings does not move them
+                let mut lhs = read(env, &l, max_iter, iter_counter)?;
+                let mut rhs = read(env, &r, max_iter, iter_counter)?;
+                // Comparisons look through references
+                if let BinaryOp::Eq | BinaryOp::Lt | BinaryOp::Gt = op {
+                    lhs = deref(env, lhs, self)?;
+                    rhs = deref(env, rhs, self)?;
+                }
+                env.io.trace(format_args!("{lhs} {op} {rhs}"));
                 Ok(op.eval(lhs, rhs)?)
             }
             Expr::Par(e) => (*e).eval(env, last_scope, max_iter, iter_counter),
@@ -69,64 +82,76 @@ impl super::Eval for Expr {
                 }
                 .eval(env, last_scope, max_iter, iter_counter)
             }
-            Expr::UnOp(UnaryOp::BorrowMut, e) | Expr::UnOp(UnaryOp::Borrow, e) => match *e {
-                Expr::Ident(i) => {
-                    let mut last_scope = env.len();
-                    let mut scope = None;
-                    while last_scope.checked_sub(1).is_some() {
-                        last_scope -= 1;
-                        let res = env.get(last_scope).unwrap().0.get(&i);
-                        if res.is_some() {
-                            scope = Some(last_scope);
-                            break;
+            Expr::UnOp(op @ (UnaryOp::BorrowMut | UnaryOp::Borrow), e) => {
+                let mutable = op == UnaryOp::BorrowMut;
+                match *e {
+                    Expr::Ident(i) => match env.var(&i) {
+                        Some(addr) => borrow(env, Pointer::to(addr), 0, mutable, self),
+                        None => Err(VmErr::Err(format!("Cannot find identifier {i}"))),
+                    },
+                    e => {
+                        // A reborrow points to the same place as the reference it is borrowed
+                        // from and a borrow of the value in a box in to its allocation
+                        if let Expr::UnOp(UnaryOp::Dereff, inner) = &e {
+                            if let Expr::Ident(_) | Expr::UnOp(UnaryOp::Dereff, _) = &**inner {
+                                match read(env, inner, max_iter, iter_counter)? {
+                                    Values::Ref(ptr, parent) => {
+                                        return borrow(env, ptr, parent, mutable, self)
+                                    }
+                                    Values::Raw(ptr, parent) => {
+                                        through_raw(env.heap.load(ptr), ptr, self)?;
+                                        return borrow(env, ptr, parent, mutable, self);
+                                    }
+                                    Values::Box(addr) | Values::Rc(addr) => {
+                                        return borrow(env, Pointer::to(addr), 0, mutable, self)
+                                    }
+                                    _ => {}
+                                }
+                            }
                         }
-                    }
-                    if scope.is_none() {
-                        return Err(VmErr::Err(format!("Cannot find identifier {i}")));
-                    }
-                    let scope = scope.unwrap();
-                    Ok(Values::Ref((i, scope)))
-                }
-                e => {
-                    // Evaluate the expression push a new invalid identifier on to the stack and
-                    // then we have our reff.
-                    let val = e.eval(env, env.len() - 1, max_iter, iter_counter)?;
-                    let mut counter: i32 = 0;
-                    let id = |counter| format!("{counter}_borrow");
-                    let local_scope = &mut env.get_mut(scope).unwrap().0;
-                    while local_scope.get(&id(counter)).is_some() {
-                        match counter.checked_add(1) {
-                            Some(c) => counter = c,
-                            _ => {
-                                return Err(VmErr::Err("Cannot borrow more literals.".to_string()))
+                        // A borrow of an element points in to the array or `Vec`
+                        if let Expr::Index(target, idx) | Expr::IndexMut(target, idx) = &e {
+                            if let Expr::Ident(id) = &**target {
+                                return match idx.eval(env, env.len() - 1, max_iter, iter_counter)? {
+                                    Values::Lit(Literal::Int(idx)) if idx >= 0 => {
+                                        let (ptr, parent) = element(env, id, idx as usize, self)?;
+                                        borrow(env, ptr, parent, mutable, self)
+                                    }
+                                    idx => {
+                                        Err(VmErr::Err(format!("Cannot convert {idx} into usize")))
+                                    }
+                                };
                             }
-                        };
+                        }
+                        // Any other value is moved to a temporary that lives until the end of
+                        // the innermost scope
+                        let val = e.eval(env, env.len() - 1, max_iter, iter_counter)?;
+                        let addr = env.temporary(env.len() - 1, val)?;
+                        borrow(env, Pointer::to(addr), 0, mutable, self)
                     }
-                    let meta = ValueMeta { value: Some(val) };
-                    local_scope.insert(id(counter), meta);
-                    Ok(Values::Ref((id(counter), scope)))
-                } //Err(VmErr::Err(format!("Cannot borrow {e} mutably"))),
-            },
+                }
+            }
             Expr::UnOp(UnaryOp::Dereff, e) => {
-                let meta = e.eval(env, env.len() - 1, max_iter, iter_counter)?;
-                let (id, idx) = match meta {
-                    Values::Ref((id, idx)) => Ok((id, idx)),
+                let temporary = matches!(*e, Expr::FuncCall(_));
+                // Dereferencing does not move out of the pointer
+                let value = read(env, &e, max_iter, iter_counter)?;
+                env.access_through(&value, AccessKind::Read, self)?;
+                match value {
+                    Values::Ref(ptr, _) => env.heap.load(ptr),
+                    Values::Raw(ptr, _) => through_raw(env.heap.load(ptr), ptr, self),
+                    // The value is moved out of a box but only read through an `Rc<T>`
+                    Values::Box(addr) => env.heap.move_out(addr),
+                    Values::Rc(addr) => env.heap.get(addr),
+                    Values::Guard(ptr, mutable) => {
+                        let value = env.heap.interior(ptr)?.clone();
+                        // A guard returned by a call, e.g. `*c.borrow()`, is a temporary that
+                        // is released once it is read
+                        if temporary {
+                            env.heap.drop_value(Values::Guard(ptr, mutable))?;
+                        }
+                        Ok(value)
+                    }
                     e => Err(VmErr::Err(format!("Cannot derreference {e}"))),
-                }?;
-
-                let target_env = match env.get(idx) {
-                    Some(env) => Ok(env),
-                    _ => Err(VmErr::Err(format!("Invalid refference {self}"))),
-                }?;
-
-                match target_env.0.get(&id) {
-                    Some(meta) => match &meta.value {
-                        Some(value) => Ok(value.clone()),
-                        _ => Err(VmErr::Err(
-                            "Cannot derreference unassigned value".to_string(),
-                        )),
-                    },
-                    _ => Err(VmErr::Err(format!("Invalid refference {self}"))),
                 }
             }
             Expr::UnOp(op, e) => {
@@ -149,24 +174,22 @@ impl super::Eval for Expr {
                 }
                 return Ok(Values::Lit(Literal::Array(inner)));
             }
-            Expr::Index(id, index) => {
+            Expr::Index(id, index) | Expr::IndexMut(id, index) => {
                 let id = match *id {
                     Expr::Ident(id) => id,
                     ty => return Err(VmErr::Err(format!("{ty} does not implement index"))),
                 };
-                let meta: ValueMeta = (id, &env, last_scope).try_into()?;
-
-                let val = match meta.value.clone() {
-                    Some(Values::Lit(Literal::Array(values))) => Ok(values),
-                    Some(ty) => return Err(VmErr::Err(format!("{ty:?} does not implement index"))),
-                    None => return Err(VmErr::Err("Type must be known at this point".to_owned())),
-                }?;
 
                 let idx = match *index {
                     // If the idx is a constant we can.eval it
                     Expr::Lit(Literal::Int(idx)) => Ok(idx),
-                    idx => match (idx.eval(env, scope - 1, max_iter, iter_counter), scope - 1) {
+                    // The index may use the variables of the innermost scope
+                    idx => match (
+                        idx.eval(env, last_scope, max_iter, iter_counter),
+                        last_scope,
+                    ) {
                         (Ok(Values::Lit(Literal::Int(val))), _) => Ok(val),
+                        (Err(e), _) if e.unwinds() => Err(e),
                         (_, 0) => Err(VmErr::Err(format!("Cannot convert {idx} into usize"))),
                         (_, index) => Ok(match idx.eval(env, index - 1, max_iter, iter_counter) {
                             Ok(Values::Lit(Literal::Int(val))) => Ok(val),
@@ -174,26 +197,67 @@ impl super::Eval for Expr {
                         }?),
                     },
                 }?;
-                if idx as usize >= val.len() {
-                    return Err(VmErr::Err(format!(
-                        "Cannot access element at index {idx} since array is of size {}",
-                        val.len()
-                    )));
+                if idx < 0 {
+                    return Err(VmErr::Err(format!("Cannot access element at index {idx}")));
                 }
-                Ok(Values::Lit(*val[idx as usize].clone()))
+                // Only the element is read, the other elements may be mutably borrowed
+                let (ptr, tag) = element(env, &id, idx as usize, self)?;
+                env.access(ptr, tag, AccessKind::Read, self)?;
+                env.heap.load(ptr)
             }
-            Expr::IndexMut(id, index) => {
-                match Expr::IndexMut(id.clone(), index).as_mut(
-                    env,
-                    scope,
-                    max_iter,
-                    iter_counter,
-                )? {
-                    Some(lit) => Ok(Values::Lit((*lit).clone())),
-                    _ => Err(VmErr::Err(format!("Value {id} is unsagined"))),
+            Expr::FuncCall(mut call) => {
+                if let Expr::Ident(id) = &*call.id {
+                    if let Some(intrinsic) = HeapIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                    if let Some(intrinsic) = VecIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                    if let Some(intrinsic) = OptionIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                    if let Some(intrinsic) = PanicIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                    if let Some(intrinsic) = FormatIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                    if let Some(intrinsic) = InputIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                    if let Some(intrinsic) = FsIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                    if let Some(intrinsic) = CellIntrinsic::from_id(id) {
+                        return intrinsic.eval(&call.args, env, max_iter, iter_counter);
+                    }
+                }
+                // Closures and function values live in the variable scopes and shadow functions
+                // with the same name
+                let callee = match &*call.id {
+                    Expr::Ident(id) => match env.var(id) {
+                        Some(addr) => env.heap.cell(addr)?.clone(),
+                        None => None,
+                    },
+                    e => Some(e.eval(env, last_scope, max_iter, iter_counter)?),
+                };
+                match callee {
+                    Some(Values::Closure(closure)) => {
+                        let mut args = vec![];
+                        for arg in call.args.iter() {
+                            args.push(arg.eval(env, last_scope, max_iter, iter_counter)?);
+                        }
+                        let ret = closure.call(env, args, max_iter, iter_counter);
+                        // A closure that is called where it is declared is dropped after the call
+                        if let Expr::Closure(_) = &*call.id {
+                            env.heap.drop_value(Values::Closure(closure))?;
+                        }
+                        return ret;
+                    }
+                    Some(Values::Fn(id)) => *call.id = Expr::Ident(id),
+                    Some(value) => return Err(VmErr::Err(format!("Cannot call {value}"))),
+                    None => {}
                 }
-            }
-            Expr::FuncCall(call) => {
                 let curr_scope = match env.get(scope) {
                     Some(env) => Ok(env.clone()),
                     _ => Err(VmErr::Err("Invalid scope usage".to_owned())),
@@ -206,98 +270,81 @@ impl super::Eval for Expr {
 
                 let fndec = match (curr_scope.1.get(&id), scope) {
                     (Some(fndec), _) => Ok(fndec),
-                    (_, 0) => Err(VmErr::Err(format!("Cannot find function {id}"))),
+                    // Functions in the program shadow host functions with the same name
+                    (_, 0) => match env.host.get(&id).cloned() {
+                        Some(host) => {
+                            return host.eval(&id, &call.args, env, max_iter, iter_counter)
+                        }
+                        None => Err(VmErr::Err(format!("Cannot find function {id}"))),
+                    },
                     (_, _) => return self.eval(env, scope - 1, max_iter, iter_counter),
                 }?;
-                let mut args = vec![];
-                let mut values = vec![];
-                for (arg, value) in fndec.args.iter().zip(call.args.iter()) {
-                    let intermediate = value.eval(env, last_scope, max_iter, iter_counter)?;
-                    values.push(intermediate.clone());
-                    args.push((arg.clone(), intermediate));
-                }
+                let values = eval_args(&call.args, env, last_scope, max_iter, iter_counter)?;
+                let args: Vec<_> = fndec.args.iter().cloned().zip(values).collect();
 
                 // Give function scope access to global scope and all of the accessible functions
                 // Do not keep it on the stack
-                let mut new_env = Box::<VarEnv>::default();
-                let mut idx = 0;
-                while let Some(env) = env.get(idx) {
-                    let t_env = if idx == 0 {
-                        env.0.clone()
-                    } else {
-                        std::collections::HashMap::new()
-                    };
-                    new_env.push((t_env, env.1.clone()));
-                    idx += 1;
-                }
-                new_env.push((
-                    std::collections::HashMap::new(),
-                    std::collections::HashMap::new(),
-                ));
-                for (id, val) in args {
-                    new_env
-                        .get_mut(last_scope)
-                        .unwrap()
-                        .0
-                        .insert(id.clone(), ValueMeta { value: Some(val) });
+                //
+                // The callee shares the memory, the host functions and the io with the caller so
+                // references passed as arguments point to the same cells
+                let mut new_env = Box::new(VarEnv::call(env, fndec.slots.clone()));
+                // The arguments are declared in the scope of the call
+                let call_scope = new_env.len() - 1;
+                let ret = args
+                    .into_iter()
+                    .try_for_each(|(id, val)| {
+                        new_env.declare(call_scope, &id, Some(val)).map(|_| ())
+                    })
+                    .and_then(|_| {
+                        fndec
+                            .body
+                            .eval(&mut new_env, last_scope, max_iter, iter_counter)
+                    });
+                // Arguments that were not moved are dropped when the function returns
+                let mut dropped = Ok(());
+                while new_env.len() > 1 && dropped.is_ok() {
+                    dropped = new_env.end_scope();
                 }
-
-                // Now we need to check if the call is intrinsic
-                let ret = match func_name == *"println!" {
-                    true => {
-                        let (_f, body) = crate::intrinsics::vm_println();
-                        let args = call
-                            .args
-                            .iter()
-                            .map(|arg| {
-                                arg.eval(env, env.len() - 1, max_iter, iter_counter)
-                                    .unwrap()
-                            })
-                            .collect::<Vec<Values>>();
-                        body(args)
+                env.heap = std::mem::take(&mut new_env.heap);
+                env.stacks = new_env.stacks.take();
+                dropped?;
+                let ret = match ret {
+                    // `?` returned early from the function
+                    Err(VmErr::Return(value)) => *value,
+                    // Record the functions that the panic unwinds through
+                    Err(VmErr::Panic(msg)) => {
+                        return Err(VmErr::Panic(format!("{msg}\n\tin fn {func_name}")))
                     }
-                    false => fndec
-                        .body
-                        .eval(&mut new_env, last_scope, max_iter, iter_counter)?,
+                    ret => ret?,
                 }; //fndec.rec_count -= 1;
-                   // Allow mutable access to global scope
-                env.get_mut(0).unwrap().0 = new_env.get(0).unwrap().0.clone();
                 Ok(ret)
             }
-            Expr::Block(b) => b.eval(env, env.len() - 1, max_iter, iter_counter),
+            Expr::Block(b) | Expr::Unsafe(b) => b.eval(env, env.len() - 1, max_iter, iter_counter),
+            Expr::Cast(e, ty) => {
+                // Casting a reference does not move out of the variable holding it
+                let value = match *e {
+                    Expr::Ident(id) => read_var(env, &id)?,
+                    e => e.eval(env, last_scope, max_iter, iter_counter)?,
+                };
+                value.cast(&ty)
+            }
+            Expr::Closure(closure) => closure.eval(env, scope, max_iter, iter_counter),
+            Expr::Try(e) => match e.eval(env, last_scope, max_iter, iter_counter)? {
+                Values::Option(Some(value)) | Values::Result(Ok(value)) => Ok(*value),
+                value @ (Values::Option(None) | Values::Result(Err(_))) => {
+                    Err(VmErr::Return(Box::new(value)))
+                }
+                value => Err(VmErr::Err(format!("Cannot apply ? to {value}"))),
+            },
         };
         match (ret, scope) {
             (Ok(value), _) => Ok(value),
+            (Err(e), _) if e.unwinds() => Err(e),
             (Err(e), 0) => Err(e),
             (Err(_), idx) => self.eval(env, idx - 1, max_iter, iter_counter),
         }
     }
 }
-impl TryInto<ValueMeta> for (String, &&mut VarEnv, usize) {
-    type Error = VmErr;
-    fn try_into(self) -> Result<ValueMeta, Self::Error> {
-        let (id, env, scope) = self;
-
-        let this_env = match env.get(scope) {
-            Some(scope) => scope.0.clone(),
-            _ => {
-                return Err(VmErr::Err(format!(
-                    "Tried to get scope {scope} when there were only {} scopes",
-                    env.len()
-                )))
-            }
-        };
-        match (this_env.get(&id), scope) {
-            (Some(value), _) => Ok(value.clone()),
-            (None, 0) => Err(VmErr::Err("variable not found".to_string())),
-            (_, scope) => {
-                let new_query = (id, env, scope - 1);
-                new_query.try_into()
-            }
-        }
-    }
-}
-
 impl Expr {
     pub fn assign(
         self,
@@ -308,23 +355,16 @@ impl Expr {
         iter_counter: &mut usize,
     ) -> Result<(), VmErr> {
         match self.clone() {
-            Expr::Ident(i) => {
-                let this = match env.get_mut(scope) {
-                    Some(scope) => &mut scope.0,
-                    _ => return Err(VmErr::Err("Invalid scope".to_owned())),
-                };
-                match (this.get(&i), scope) {
-                    (Some(_meta), _) => {
-                        // Since we assume that the type checker has been ran before this the
-                        // mutability of types is irrelevant, now we just assign the value to the
-                        // variable
-                        this.insert(i, ValueMeta { value: Some(value) });
-                        Ok(())
-                    }
-                    (_, 0) => Err(VmErr::Err(format!("No such variable {i}"))),
-                    (_, _) => self.assign(env, scope - 1, value, max_iter, iter_counter),
+            Expr::Ident(i) => match env.var(&i) {
+                Some(addr) => {
+                    env.access(Pointer::to(addr), 0, AccessKind::Write, &self)?;
+                    // Since we assume that the type checker has been ran before this the
+                    // mutability of types is irrelevant, now we just assign the value to the
+                    // variable, the previous value is dropped
+                    env.heap.set(addr, value)
                 }
-            }
+                None => Err(VmErr::Err(format!("No such variable {i}"))),
+            },
             Expr::IndexMut(id, idx) => {
                 let idx = match *idx {
                     Expr::Lit(Literal::Int(idx)) => idx as usize,
@@ -333,6 +373,7 @@ impl Expr {
                         env.len() - 1,
                     ) {
                         (Ok(Values::Lit(Literal::Int(idx))), _) => idx as usize,
+                        (Err(e), _) if e.unwinds() => return Err(e),
                         (_, 0) => {
                             return Err(VmErr::Err(format!("Cannot convert {expr} into usize")));
                         }
@@ -350,124 +391,37 @@ impl Expr {
                     Expr::Ident(id) => id,
                     el => return Err(VmErr::Err(format!("Cannot use {el} as an identifier"))),
                 };
-                let this = match env.get_mut(scope) {
-                    Some(scope) => &mut scope.0,
-                    _ => return Err(VmErr::Err("Invalid scope".to_owned())),
-                };
                 // Now we can assume that the value is mutable as type checker should gaurantee
                 // this
-                match this.get_mut(&id) {
-                    Some(meta) => match &mut meta.value {
-                        Some(Values::Lit(Literal::Array(el))) => {
-                            let value = match value {
-                                Values::Lit(l) => l,
-                                e => {
-                                    return Err(VmErr::Err(format!(
-                                        "Cannot assign {e} to an array"
-                                    )))
-                                }
-                            };
-                            *el[idx] = value;
-                            Ok(())
-                        }
-                        el => Err(VmErr::Err(format!("{el:?} cannot be indexed"))),
-                    },
-                    _ => Err(VmErr::Err(format!("Use of undecleared variable {id}"))),
-                }
+                let (ptr, tag) = element(env, &id, idx, &self)?;
+                env.access(ptr, tag, AccessKind::Write, &self)?;
+                env.heap.store(ptr, value)
             }
             Expr::UnOp(UnaryOp::Dereff, e) => {
+                let temporary = matches!(*e, Expr::FuncCall(_));
                 // First we have a simple way out, the expression is a mutable borrow
-                let ret = e.eval(env, scope, max_iter, iter_counter)?;
-                match ret {
-                    Values::Ref((id, scope)) => {
-                        let scope = match env.get_mut(scope) {
-                            Some(scope) => scope,
-                            _ => {
-                                return Err(VmErr::Err("Invallid scope for refference".to_string()))
-                            }
-                        };
-                        match scope.0.get(&id) {
-                            Some(meta) => {
-                                if let Some(Values::Ref(_)) = meta.value {
-                                    return Err(VmErr::Err(
-                                        "Cannot assign to a refference".to_string(),
-                                    ));
-                                };
-                            }
-                            _ => return Err(VmErr::Err(format!("Cannot find identifier {id}"))),
-                        };
-                        (scope.0.get_mut(&id).unwrap()).value = Some(value);
-                        Ok(())
-                    }
-                    e => Err(VmErr::Err(format!("Cannot derreference {e}"))),
-                }
-            }
-            expr => Err(VmErr::Err(format!(
-                "Cannot get {expr} as mutable identifiers"
-            ))),
-        }
-    }
-    pub fn as_mut<'a>(
-        self,
-        env: &'a mut VarEnv,
-        scope: usize,
-        max_iter: usize,
-        iter_counter: &'a mut usize,
-    ) -> Result<Option<&'a mut Literal>, VmErr> {
-        match self.clone() {
-            Expr::Ident(i) => {
-                let this = match env.get(scope) {
-                    Some(scope) => &scope.0,
-                    _ => return Err(VmErr::Err("Invalid scope".to_owned())),
+                let ret = match *e {
+                    Expr::Ident(id) => read_var(env, &id)?,
+                    // Assigning through nested dereferences, e.g. `**b = 1`, does not move out
+                    // of the outer box
+                    e @ Expr::UnOp(UnaryOp::Dereff, _) => read(env, &e, max_iter, iter_counter)?,
+                    e => e.eval(env, scope, max_iter, iter_counter)?,
                 };
-                match (this.get(&i), scope) {
-                    (Some(_meta), _) => {
-                        // Now we can get it as immutable as it is no longer
-                        match &mut env.get_mut(scope).unwrap().0.get_mut(&i).unwrap().value {
-                            Some(Values::Lit(val)) => Ok(Some(val)),
-                            _ => Ok(None),
+                env.access_through(&ret, AccessKind::Write, &self)?;
+                match ret {
+                    Values::Box(addr) => env.heap.set(addr, value),
+                    Values::Guard(ptr, true) => {
+                        env.heap.replace(ptr, value)?;
+                        // A guard returned by a call, e.g. `*c.borrow_mut() = 1`, is a temporary
+                        // that is released once it is written through
+                        match temporary {
+                            true => env.heap.drop_value(Values::Guard(ptr, true)),
+                            false => Ok(()),
                         }
                     }
-                    (_, 0) => Err(VmErr::Err(format!("No such variable {i}"))),
-                    (_, _) => self.as_mut(env, scope - 1, max_iter, iter_counter),
-                }
-            }
-            Expr::IndexMut(id, idx) => {
-                let idx = match *idx {
-                    Expr::Lit(Literal::Int(idx)) => idx as usize,
-                    expr => match (
-                        (expr).eval(env, env.len() - 1, max_iter, iter_counter),
-                        env.len() - 1,
-                    ) {
-                        (Ok(Values::Lit(Literal::Int(idx))), _) => idx as usize,
-
-                        (_, 0) => {
-                            return Err(VmErr::Err(format!("Cannot convert {expr} into usize")));
-                        }
-                        (_, idx) => match expr.eval(env, idx - 1, max_iter, iter_counter) {
-                            Ok(Values::Lit(Literal::Int(idx))) => idx as usize,
-                            _ => {
-                                return Err(VmErr::Err(format!(
-                                    "Cannot convert {expr} into usize"
-                                )));
-                            }
-                        },
-                    },
-                };
-                let id = match *id {
-                    Expr::Ident(id) => id,
-                    el => return Err(VmErr::Err(format!("Cannot use {el} as an identifier"))),
-                };
-                let this = match env.get_mut(scope) {
-                    Some(scope) => &mut scope.0,
-                    _ => return Err(VmErr::Err("Invalid scope".to_owned())),
-                };
-                match this.get_mut(&id) {
-                    Some(meta) => match &mut meta.value {
-                        Some(Values::Lit(Literal::Array(el))) => Ok(Some(&mut *el[idx])),
-                        el => Err(VmErr::Err(format!("{el:?} cannot be indexed"))),
-                    },
-                    _ => Err(VmErr::Err(format!("Use of undecleared variable {id}"))),
+                    Values::Ref(ptr, _) => env.heap.store(ptr, value),
+                    Values::Raw(ptr, _) => through_raw(env.heap.store(ptr, value), ptr, &self),
+                    e => Err(VmErr::Err(format!("Cannot derreference {e}"))),
                 }
             }
             expr => Err(VmErr::Err(format!(
@@ -0,0 +1,146 @@
+//! Evaluation of the formatting intrinsics, the format string is parsed by
+//! [`parse_format`](crate::intrinsics::parse_format).
+use super::{
+    heap::{deref, read},
+    Values, VarEnv, VmErr,
+};
+use crate::ast::{Expr, Literal};
+use crate::intrinsics::{parse_format, quote, unquote, Argument, FormatIntrinsic, Kind, Piece};
+
+/// Evaluates an argument that is formatted, variables are only read as the arguments are never
+/// moved. References and pointers are replaced by the values they point to.
+pub(super) fn value(
+    arg: &Expr,
+    env: &mut VarEnv,
+    max_iter: usize,
+    iter_counter: &mut usize,
+) -> Result<Values, VmErr> {
+    let value = read(env, arg, max_iter, iter_counter)?;
+    // A guard returned by a call is a temporary, e.g. `c.borrow()`, the borrow is released once
+    // the value is formatted
+    if let (Values::Guard(..), Expr::FuncCall(_)) = (&value, arg) {
+        let resolved = env.heap.resolve(value.clone())?;
+        env.heap.drop_value(value)?;
+        return Ok(resolved);
+    }
+    let value = deref(env, value, arg)?;
+    env.heap.resolve(value)
+}
+
+/// Formats a value like `{}`, values that do not implement `Display` in rust are formatted
+/// like `{:?}`.
+fn display(value: &Values) -> String {
+    match value {
+        Values::Lit(Literal::Int(i)) => i.to_string(),
+        Values::Lit(Literal::Bool(b)) => b.to_string(),
+        Values::Lit(Literal::Unit) => "()".to_owned(),
+        Values::Lit(Literal::String(s)) => unquote(s),
+        value => debug(value),
+    }
+}
+
+/// Formats a value like `{:?}`.
+pub(super) fn debug(value: &Values) -> String {
+    let list = |elements: Vec<String>| format!("[{}]", elements.join(", "));
+    match value {
+        Values::Lit(Literal::String(s)) => quote(&unquote(s)),
+        Values::Lit(Literal::Array(elements)) => list(
+            elements
+                .iter()
+                .map(|el| debug(&Values::Lit((**el).clone())))
+                .collect(),
+        ),
+        Values::Lit(_) => display(value),
+        Values::Vec(elements) => list(elements.iter().map(debug).collect()),
+        Values::Option(Some(value)) => format!("Some({})", debug(value)),
+        Values::Option(None) => "None".to_owned(),
+        Values::Result(Ok(value)) => format!("Ok({})", debug(value)),
+        Values::Result(Err(err)) => format!("Err({})", debug(err)),
+        Values::Cell(value) => format!("Cell {{ value: {} }}", debug(value)),
+        Values::RefCell(_, -1) => "RefCell { value: <borrowed> }".to_owned(),
+        Values::RefCell(value, _) => format!("RefCell {{ value: {} }}", debug(value)),
+        value => value.to_string(),
+    }
+}
+
+/// Formats an integer as hexadecimal or binary.
+fn radix(i: i32, kind: Kind, alternate: bool) -> String {
+    let (prefix, digits) = match kind {
+        Kind::LowerHex => ("0x", format!("{i:x}")),
+        Kind::UpperHex => ("0x", format!("{i:X}")),
+        Kind::Binary => ("0b", format!("{i:b}")),
+        Kind::Display | Kind::Debug => ("", i.to_string()),
+    };
+    match alternate {
+        true => format!("{prefix}{digits}"),
+        false => digits,
+    }
+}
+
+/// Formats the format string `fmt` with the arguments `args`, named arguments are read from the
+/// variables in scope.
+pub(super) fn format(
+    fmt: &Expr,
+    args: &[Expr],
+    env: &mut VarEnv,
+    max_iter: usize,
+    iter_counter: &mut usize,
+) -> Result<String, VmErr> {
+    let pieces = match fmt {
+        Expr::Lit(Literal::String(fmt)) => parse_format(&unquote(fmt)).map_err(VmErr::Err)?,
+        fmt => return Err(VmErr::Err(format!("Invalid format string {fmt}"))),
+    };
+    let mut values = vec![];
+    for arg in args {
+        values.push(value(arg, env, max_iter, iter_counter)?);
+    }
+    let mut s = String::new();
+    for piece in pieces {
+        let spec = match piece {
+            Piece::Text(text) => {
+                s.push_str(&text);
+                continue;
+            }
+            Piece::Arg(spec) => spec,
+        };
+        let value = match &spec.arg {
+            Argument::Index(idx) => match values.get(*idx) {
+                Some(value) => value.clone(),
+                None => return Err(VmErr::Err(format!("Missing argument {idx} to {fmt}"))),
+            },
+            Argument::Named(id) => value(&Expr::Ident(id.clone()), env, max_iter, iter_counter)?,
+        };
+        let formatted = match (spec.kind, &value) {
+            (Kind::Display, value) => display(value),
+            (Kind::Debug, value) => debug(value),
+            (kind, Values::Lit(Literal::Int(i))) => radix(*i, kind, spec.alternate),
+            (kind, value) => return Err(VmErr::Err(format!("Cannot format {value} as {kind:?}"))),
+        };
+        let numeric = matches!(value, Values::Lit(Literal::Int(_)));
+        s.push_str(&spec.pad(formatted, numeric));
+    }
+    Ok(s)
+}
+
+impl FormatIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        let s = match args.split_first() {
+            Some((fmt, args)) => format(fmt, args, env, max_iter, iter_counter)?,
+            None => String::new(),
+        };
+        match self {
+            Self::Print => env.io.print(&s)?,
+            Self::Println => env.io.print(&format!("{s}\n"))?,
+            Self::Eprint => env.io.eprint(&s)?,
+            Self::Eprintln => env.io.eprint(&format!("{s}\n"))?,
+            Self::Format => return Ok(Values::Lit(Literal::String(quote(&s)))),
+        }
+        Ok(Values::Lit(Literal::Unit))
+    }
+}
@@ -0,0 +1,131 @@
+//! The variables of a function call. Every variable that a function declares is resolved to a
+//! slot of its [`Frame`] when the function is loaded, looking up a variable is a single lookup
+//! in the [`Slots`] of the function rather than a search through every scope.
+//!
+//! Variables with the same name share a slot, a variable that shadows another is bound to the
+//! slot until the end of its scope after which the shadowed variable is bound to it again. The
+//! identifiers are unique once the borrow checker has renamed them.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::ValueMeta;
+use crate::ast::{Block, Expr, Statement};
+
+/// Maps the identifiers of the variables of a function to the slots of its frame.
+#[derive(Debug, Clone, Default)]
+pub struct Slots(HashMap<String, usize>);
+
+impl Slots {
+    /// Resolves the arguments and every variable declared in `body`, including the arguments
+    /// and variables of the closures in it which are called in the frame of the function.
+    pub fn resolve(args: &[String], body: &Block) -> Self {
+        let mut slots = Self::default();
+        for arg in args {
+            slots.slot(arg);
+        }
+        slots.visit_block(body);
+        slots
+    }
+
+    /// Returns the slot of `id`, a new one if it does not have one yet.
+    fn slot(&mut self, id: &str) -> usize {
+        let len = self.0.len();
+        *self.0.entry(id.to_owned()).or_insert(len)
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        for statement in &block.statements {
+            match statement {
+                Statement::Let(id, _, _, rhs) => {
+                    if let Expr::Ident(id) = id {
+                        self.slot(id);
+                    }
+                    if let Some(rhs) = rhs {
+                        self.visit_expr(rhs);
+                    }
+                }
+                Statement::Assign(_, e) | Statement::Expr(e) => self.visit_expr(e),
+                Statement::While(cond, block) => {
+                    self.visit_expr(cond);
+                    self.visit_block(block);
+                }
+                Statement::Block(block) => self.visit_block(block),
+                // Nested functions get frames of their own
+                Statement::FnDecleration(_) => {}
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(_) | Expr::Lit(_) => {}
+            Expr::BinOp(_, lhs, rhs) | Expr::Index(lhs, rhs) | Expr::IndexMut(lhs, rhs) => {
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+            }
+            Expr::UnOp(_, e) | Expr::Par(e) | Expr::Try(e) | Expr::Cast(e, _) => self.visit_expr(e),
+            Expr::IfThenElse(cond, then_block, else_block) => {
+                self.visit_expr(cond);
+                self.visit_block(then_block);
+                if let Some(else_block) = else_block {
+                    self.visit_block(else_block);
+                }
+            }
+            Expr::Array(elements) => elements.iter().for_each(|el| self.visit_expr(el)),
+            Expr::FuncCall(call) => {
+                self.visit_expr(&call.id);
+                call.args.iter().for_each(|arg| self.visit_expr(arg));
+            }
+            Expr::Block(block) | Expr::Unsafe(block) => self.visit_block(block),
+            Expr::Closure(closure) => {
+                for arg in &closure.args {
+                    if let Expr::Ident(id) = &arg.id {
+                        self.slot(id);
+                    }
+                }
+                self.visit_expr(&closure.body);
+            }
+        }
+    }
+}
+
+/// The variables of a call, each slot holds the variable that is currently bound to it.
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    slots: Rc<Slots>,
+    vars: Vec<Option<ValueMeta>>,
+}
+
+impl Frame {
+    pub fn new(slots: Rc<Slots>) -> Self {
+        let vars = vec![None; slots.0.len()];
+        Self { slots, vars }
+    }
+
+    /// Returns the variable `id` if it is bound.
+    pub fn get(&self, id: &str) -> Option<ValueMeta> {
+        let slot = *self.slots.0.get(id)?;
+        self.vars.get(slot).copied().flatten()
+    }
+
+    /// Binds `id` to `meta`, returns the slot and the variable that was bound to it before.
+    ///
+    /// Variables that were not resolved when the function was loaded, such as the variables of
+    /// a block that is evaluated on its own or the arguments of a closure that is called in
+    /// another function, get a new slot.
+    pub fn bind(&mut self, id: &str, meta: ValueMeta) -> (usize, Option<ValueMeta>) {
+        let slot = match self.slots.0.get(id) {
+            Some(slot) => *slot,
+            None => Rc::make_mut(&mut self.slots).slot(id),
+        };
+        if slot >= self.vars.len() {
+            self.vars.resize(slot + 1, None);
+        }
+        (slot, self.vars[slot].replace(meta))
+    }
+
+    /// Binds `slot` to the variable `previous` again, returns the variable that was unbound.
+    pub fn restore(&mut self, slot: usize, previous: Option<ValueMeta>) -> Option<ValueMeta> {
+        std::mem::replace(&mut self.vars[slot], previous)
+    }
+}
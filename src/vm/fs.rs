@@ -0,0 +1,44 @@
+//! Evaluation of the file intrinsics, every access is checked against the allowed directories of
+//! the [`Io`](super::Io).
+use super::{format::value, Values, VarEnv, VmErr};
+use crate::ast::{Expr, Literal};
+use crate::intrinsics::{quote, unquote, FsIntrinsic};
+
+impl FsIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        let mut strings = vec![];
+        for arg in args {
+            match value(arg, env, max_iter, iter_counter)? {
+                Values::Lit(Literal::String(s)) => strings.push(unquote(&s)),
+                value => {
+                    return Err(VmErr::Err(format!(
+                        "Expected a string argument to {} but got {value}",
+                        self.id()
+                    )))
+                }
+            }
+        }
+        let ret = match (self, &strings[..]) {
+            (Self::ReadToString, [path]) => env
+                .io
+                .read_file(path)
+                .map(|contents| Values::Lit(Literal::String(quote(&contents)))),
+            (Self::Write, [path, contents]) => env
+                .io
+                .write_file(path, contents)
+                .map(|_| Values::Lit(Literal::Unit)),
+            _ => return Err(VmErr::Err(format!("Invalid arguments to {}", self.id()))),
+        };
+        // Errors are returned to the program
+        Ok(Values::Result(match ret {
+            Ok(value) => Ok(Box::new(value)),
+            Err(e) => Err(Box::new(Values::Lit(Literal::String(quote(&e))))),
+        }))
+    }
+}
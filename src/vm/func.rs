@@ -26,18 +26,16 @@ impl super::Eval for crate::ast::func::Func {
         let args: Vec<Expr> = self.args.iter().map(|arg| arg.id.clone()).collect();
 
         // Add in the new function and assume correctly typed for now
-        let meta = super::FunctionMeta {
-            args: args
-                .iter()
+        // The variables of the function are resolved to the slots of its frame here
+        let meta = super::FunctionMeta::new(
+            args.iter()
                 .map(|id| match id {
                     Expr::Ident(id) => id.clone(),
                     _ => unreachable!(),
                 })
                 .collect(),
-            // I really do not like this, this should probably be moved to some
-            // external function that pre processes all function declarions in to a jagged array.
-            body: self.body.clone(),
-        };
+            self.body.clone(),
+        );
 
         env.get_mut(scope).unwrap().1.insert(id, meta);
         Ok(Values::Lit(Literal::Unit))
@@ -1,6 +1,6 @@
 use crate::ast::Static;
 
-use super::{Eval, ValueMeta, Values};
+use super::{Eval, Values};
 
 impl Eval for Static {
     fn eval(
@@ -18,11 +18,7 @@ impl Eval for Static {
 
         let value = self.value.eval(env, 0, max_iter, iter_counter)?;
 
-        let scope = env.get_mut(scope).unwrap();
-
-        scope
-            .0
-            .insert(self.id.clone(), ValueMeta { value: Some(value) });
+        env.declare(scope, &self.id, Some(value))?;
         Ok(Values::Lit(crate::ast::Literal::Unit))
     }
 }
@@ -0,0 +1,547 @@
+//! The memory of the VM, every variable lives in a cell of the heap along with the allocations
+//! backing `Box<T>` and `Rc<T>`.
+//!
+//! A [`Scope`] owns the cells of its variables and frees them when it ends. References are
+//! [`Pointer`]s to a cell or to an element of the array or `Vec` in it.
+//!
+//! The value of a `Cell<T>` or a `RefCell<T>` is stored in place, it is replaced through a
+//! shared reference to the cell and a `RefCell<T>` counts the guards that borrow it.
+//!
+//! Freed cells are reused by later allocations. Every [`Addr`] carries the generation of the cell
+//! it was allocated in so any access through a pointer to a freed allocation is still reported,
+//! as a dangling reference for the cell of a variable and as a use after free otherwise.
+use std::fmt::Display;
+
+use super::{
+    stacked_borrows::{source_names, AccessKind, Tag},
+    Eval, Values, VarEnv, VmErr,
+};
+use crate::ast::{Expr, Literal, UnaryOp};
+use crate::intrinsics::HeapIntrinsic;
+
+#[derive(Debug, Clone)]
+struct Allocation {
+    /// `None` once the value is moved out or before a declared variable is assigned
+    value: Option<Values>,
+    /// The number of owners, this is always 1 for a `Box<T>` and the cell of a variable
+    count: usize,
+}
+
+/// The address of a cell, the index of the cell in the [`Heap`] and the generation of the cell
+/// when it was allocated. The generation of a cell is bumped when it is freed, so an address
+/// never refers to the allocation that reuses the cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Addr {
+    idx: usize,
+    generation: usize,
+}
+
+impl Display for Addr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.idx)
+    }
+}
+
+/// A pointer in to the memory, the address of a cell and the index of the element of the array
+/// or `Vec` in it that is pointed to, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pointer {
+    pub addr: Addr,
+    pub offset: Option<usize>,
+}
+
+impl Pointer {
+    /// A pointer to the whole value in the cell at `addr`.
+    pub fn to(addr: Addr) -> Self {
+        Self { addr, offset: None }
+    }
+
+    /// A pointer to the element `idx` of the value in the cell at `addr`.
+    pub fn element(addr: Addr, idx: usize) -> Self {
+        Self {
+            addr,
+            offset: Some(idx),
+        }
+    }
+}
+
+impl Display for Pointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            Some(idx) => write!(f, "#{}[{idx}]", self.addr),
+            None => write!(f, "#{}", self.addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Cell {
+    generation: usize,
+    /// `None` while the cell is free
+    allocation: Option<Allocation>,
+    /// The identifier of the variable that owns the cell, used in errors
+    name: Option<String>,
+    /// The identifier of the variable that owned the cell in the previous generation, to report
+    /// a dangling reference to it after the cell is reused
+    dropped: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Heap {
+    cells: Vec<Cell>,
+    /// The indices of the freed cells
+    free: Vec<usize>,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves `value` to a new allocation and returns its address.
+    pub fn alloc(&mut self, value: Values) -> Addr {
+        self.alloc_cell(None, Some(value))
+    }
+
+    /// Allocates the cell of the variable `id`, the value is `None` for a declaration without an
+    /// initializer.
+    pub fn alloc_var(&mut self, id: &str, value: Option<Values>) -> Addr {
+        self.alloc_cell(Some(id.to_owned()), value)
+    }
+
+    /// Reuses a freed cell if there is one.
+    fn alloc_cell(&mut self, name: Option<String>, value: Option<Values>) -> Addr {
+        let idx = match self.free.pop() {
+            Some(idx) => idx,
+            None => {
+                self.cells.push(Cell::default());
+                self.cells.len() - 1
+            }
+        };
+        let cell = &mut self.cells[idx];
+        cell.allocation = Some(Allocation { value, count: 1 });
+        cell.name = name;
+        Addr {
+            idx,
+            generation: cell.generation,
+        }
+    }
+
+    /// Returns the name of the variable that owns the cell at `addr` or the address itself.
+    pub fn name(&self, addr: Addr) -> String {
+        match self.cells.get(addr.idx) {
+            Some(Cell {
+                generation,
+                name: Some(id),
+                ..
+            }) if *generation == addr.generation => format!("`{id}`"),
+            _ => format!("#{addr}"),
+        }
+    }
+
+    /// Returns true if the allocation at `addr` has not been freed.
+    fn is_live(&self, addr: Addr) -> bool {
+        matches!(
+            self.cells.get(addr.idx),
+            Some(Cell { generation, allocation: Some(_), .. }) if *generation == addr.generation
+        )
+    }
+
+    fn allocation(&mut self, addr: Addr) -> Result<&mut Allocation, VmErr> {
+        let cell = match self.cells.get_mut(addr.idx) {
+            Some(cell) => cell,
+            None => return Err(VmErr::Err(format!("Invalid heap address #{addr}"))),
+        };
+        match (&mut cell.allocation, &cell.dropped) {
+            (Some(allocation), _) if cell.generation == addr.generation => Ok(allocation),
+            // Only the variable that owned the cell before it was last freed is known
+            (_, Some(id)) if cell.generation == addr.generation + 1 => Err(VmErr::Err(format!(
+                "Dangling reference to `{id}`, it was dropped at the end of its scope"
+            ))),
+            _ => Err(VmErr::Err(format!(
+                "Use after free of heap allocation #{addr}"
+            ))),
+        }
+    }
+
+    /// Returns the value in the cell at `addr`, `None` if it was moved out or is not assigned.
+    pub fn cell(&mut self, addr: Addr) -> Result<&mut Option<Values>, VmErr> {
+        Ok(&mut self.allocation(addr)?.value)
+    }
+
+    pub fn get(&mut self, addr: Addr) -> Result<Values, VmErr> {
+        match &self.allocation(addr)?.value {
+            Some(value) => Ok(value.clone()),
+            None => Err(VmErr::Err(format!(
+                "Use of moved value {}",
+                self.name(addr)
+            ))),
+        }
+    }
+
+    /// Returns the value in the cell at `addr`, moving it out of the cell if it owns heap
+    /// allocations, like `*b` moves out of `b: Box<T>`.
+    pub fn move_out(&mut self, addr: Addr) -> Result<Values, VmErr> {
+        match self.allocation(addr)?.value.as_ref().map(Values::moves) {
+            Some(true) => Ok(self.allocation(addr)?.value.take().unwrap()),
+            _ => self.get(addr),
+        }
+    }
+
+    pub fn set(&mut self, addr: Addr, value: Values) -> Result<(), VmErr> {
+        match self.allocation(addr)?.value.replace(value) {
+            Some(old) => self.drop_value(old),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds an owner to a reference counted allocation.
+    pub fn clone_rc(&mut self, addr: Addr) -> Result<(), VmErr> {
+        self.allocation(addr)?.count += 1;
+        Ok(())
+    }
+
+    /// Returns the number of owners of the allocation, i.e. `Rc::strong_count`.
+    pub fn count(&mut self, addr: Addr) -> Result<usize, VmErr> {
+        Ok(self.allocation(addr)?.count)
+    }
+
+    /// Returns the number of allocations that have not been freed.
+    pub fn live(&self) -> usize {
+        self.cells.len() - self.free.len()
+    }
+
+    /// Returns the number of cells, including the freed ones waiting to be reused.
+    pub fn cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Frees the cell at `addr` and returns the value in it without dropping it, the cell is
+    /// reused by a later allocation.
+    pub fn free(&mut self, addr: Addr) -> Result<Option<Values>, VmErr> {
+        self.allocation(addr)?;
+        let cell = &mut self.cells[addr.idx];
+        cell.generation += 1;
+        cell.dropped = cell.name.take();
+        self.free.push(addr.idx);
+        Ok(cell
+            .allocation
+            .take()
+            .and_then(|allocation| allocation.value))
+    }
+
+    /// Reads the value `ptr` points to without moving it.
+    pub fn load(&mut self, ptr: Pointer) -> Result<Values, VmErr> {
+        let idx = match ptr.offset {
+            Some(idx) => idx,
+            None => return self.get(ptr.addr),
+        };
+        let name = self.name(ptr.addr);
+        let (el, len) = match &self.allocation(ptr.addr)?.value {
+            Some(Values::Lit(Literal::Array(elements))) => (
+                elements.get(idx).map(|el| Values::Lit((**el).clone())),
+                elements.len(),
+            ),
+            Some(Values::Vec(elements)) => (elements.get(idx).cloned(), elements.len()),
+            Some(value) => return Err(VmErr::Err(format!("{value} cannot be indexed"))),
+            None => return Err(VmErr::Err(format!("Use of moved value {name}"))),
+        };
+        el.ok_or_else(|| out_of_bounds(&name, idx, len))
+    }
+
+    /// Replaces the value `ptr` points to, the previous value is dropped.
+    pub fn store(&mut self, ptr: Pointer, value: Values) -> Result<(), VmErr> {
+        let idx = match ptr.offset {
+            Some(idx) => idx,
+            None => return self.set(ptr.addr, value),
+        };
+        let name = self.name(ptr.addr);
+        let old = match (&mut self.allocation(ptr.addr)?.value, value) {
+            (Some(Values::Lit(Literal::Array(elements))), Values::Lit(value)) => {
+                let len = elements.len();
+                match elements.get_mut(idx) {
+                    Some(el) => Values::Lit(std::mem::replace(&mut **el, value)),
+                    None => return Err(out_of_bounds(&name, idx, len)),
+                }
+            }
+            (Some(Values::Vec(elements)), value) => {
+                let len = elements.len();
+                match elements.get_mut(idx) {
+                    Some(el) => std::mem::replace(el, value),
+                    None => return Err(out_of_bounds(&name, idx, len)),
+                }
+            }
+            (elements, value) => {
+                return Err(VmErr::Err(format!(
+                    "Cannot assign {value} to an element of {elements:?}"
+                )))
+            }
+        };
+        self.drop_value(old)
+    }
+
+    /// Returns the value `ptr` points to so that it can be modified in place, elements of arrays
+    /// are literals and can only be replaced by [`store`](Self::store).
+    pub fn place_mut(&mut self, ptr: Pointer) -> Result<&mut Values, VmErr> {
+        let name = self.name(ptr.addr);
+        match (&mut self.allocation(ptr.addr)?.value, ptr.offset) {
+            (Some(value), None) => Ok(value),
+            (Some(Values::Vec(elements)), Some(idx)) => {
+                let len = elements.len();
+                elements
+                    .get_mut(idx)
+                    .ok_or_else(|| out_of_bounds(&name, idx, len))
+            }
+            (Some(value), Some(_)) => Err(VmErr::Err(format!(
+                "Cannot modify an element of {value} in place"
+            ))),
+            (None, _) => Err(VmErr::Err(format!("Use of moved value {name}"))),
+        }
+    }
+
+    /// Returns the value in the `Cell<T>` or `RefCell<T>` that `ptr` points to.
+    pub fn interior(&mut self, ptr: Pointer) -> Result<&mut Values, VmErr> {
+        match self.place_mut(ptr)? {
+            Values::Cell(value) | Values::RefCell(value, _) => Ok(value),
+            value => Err(VmErr::Err(format!("Expected a cell but got {value}"))),
+        }
+    }
+
+    /// Replaces the value in the cell that `ptr` points to, dropping the old value.
+    pub fn replace(&mut self, ptr: Pointer, value: Values) -> Result<(), VmErr> {
+        let old = std::mem::replace(self.interior(ptr)?, value);
+        self.drop_value(old)
+    }
+
+    /// Releases the borrow of the `RefCell<T>` that a guard holds. The cell may be freed first
+    /// when both are dropped at the end of the same scope.
+    fn release(&mut self, ptr: Pointer, mutable: bool) -> Result<(), VmErr> {
+        if !self.is_live(ptr.addr) {
+            return Ok(());
+        }
+        match self.place_mut(ptr)? {
+            Values::RefCell(_, borrows) => {
+                *borrows = match mutable {
+                    true => 0,
+                    false => *borrows - 1,
+                };
+                Ok(())
+            }
+            value => Err(VmErr::Err(format!(
+                "Expected a guard to point to a RefCell but got {value}"
+            ))),
+        }
+    }
+
+    /// Drops a value, freeing any allocations that it is the last owner of.
+    pub fn drop_value(&mut self, value: Values) -> Result<(), VmErr> {
+        let addr = match value {
+            Values::Box(addr) | Values::Rc(addr) => addr,
+            Values::Cell(value) | Values::RefCell(value, _) => return self.drop_value(*value),
+            Values::Guard(ptr, mutable) => return self.release(ptr, mutable),
+            Values::Closure(closure) => {
+                for addr in closure.owned() {
+                    if let Some(value) = self.free(addr)? {
+                        self.drop_value(value)?;
+                    }
+                }
+                return Ok(());
+            }
+            Values::Vec(elements) => {
+                for el in elements {
+                    self.drop_value(el)?;
+                }
+                return Ok(());
+            }
+            Values::Option(Some(value)) | Values::Result(Ok(value) | Err(value)) => {
+                return self.drop_value(*value)
+            }
+            _ => return Ok(()),
+        };
+        if !self.is_live(addr) {
+            return Err(VmErr::Err(format!(
+                "Double free of heap allocation #{addr}"
+            )));
+        }
+        let allocation = self.allocation(addr)?;
+        allocation.count -= 1;
+        if allocation.count > 0 {
+            return Ok(());
+        }
+        match self.free(addr)? {
+            Some(value) => self.drop_value(value),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces all pointers in a value with the values they point to, used when printing.
+    pub fn resolve(&mut self, value: Values) -> Result<Values, VmErr> {
+        match value {
+            Values::Box(addr) | Values::Rc(addr) => {
+                let value = self.get(addr)?;
+                self.resolve(value)
+            }
+            Values::Vec(elements) => Ok(Values::Vec(
+                elements
+                    .into_iter()
+                    .map(|el| self.resolve(el))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Values::Option(Some(value)) => {
+                Ok(Values::Option(Some(Box::new(self.resolve(*value)?))))
+            }
+            Values::Result(Ok(value)) => Ok(Values::Result(Ok(Box::new(self.resolve(*value)?)))),
+            Values::Result(Err(err)) => Ok(Values::Result(Err(Box::new(self.resolve(*err)?)))),
+            Values::Cell(value) => Ok(Values::Cell(Box::new(self.resolve(*value)?))),
+            Values::RefCell(value, borrows) => {
+                Ok(Values::RefCell(Box::new(self.resolve(*value)?), borrows))
+            }
+            Values::Guard(ptr, _) => {
+                let value = self.interior(ptr)?.clone();
+                self.resolve(value)
+            }
+            value => Ok(value),
+        }
+    }
+}
+
+fn out_of_bounds(name: &str, idx: usize, len: usize) -> VmErr {
+    VmErr::Err(format!(
+        "Cannot access element at index {idx} since {name} is of size {len}"
+    ))
+}
+
+/// Returns a pointer to the element `idx` of the array or `Vec` in the variable `id` and the tag
+/// it is accessed through. Indexing looks through references, `v[i]` indexes the referent of
+/// `v: &mut Vec<T>`.
+pub(crate) fn element(
+    env: &mut VarEnv,
+    id: &str,
+    idx: usize,
+    what: &dyn Display,
+) -> Result<(Pointer, Tag), VmErr> {
+    let mut ptr = match env.var(id) {
+        Some(addr) => Pointer::to(addr),
+        None => return Err(VmErr::Err(format!("Cannot find identifier {id}"))),
+    };
+    let mut tag = 0;
+    while let Some(Values::Ref(target, target_tag)) = env.heap.cell(ptr.addr)? {
+        let (target, target_tag) = (*target, *target_tag);
+        if target.offset.is_some() {
+            break;
+        }
+        env.access(ptr, tag, AccessKind::Read, what)?;
+        ptr = target;
+        tag = target_tag;
+    }
+    Ok((Pointer::element(ptr.addr, idx), tag))
+}
+
+/// Reads a variable without moving out of it.
+pub(crate) fn read_var(env: &mut VarEnv, id: &str) -> Result<Values, VmErr> {
+    match env.var(id) {
+        Some(addr) => {
+            env.access(Pointer::to(addr), 0, AccessKind::Read, &id)?;
+            match env.heap.cell(addr)? {
+                Some(value) => Ok(value.clone()),
+                None => Err(VmErr::Err(format!(
+                    "Value of variable {id} must be known at this point"
+                ))),
+            }
+        }
+        None => Err(VmErr::Err(format!("Cannot find identifier {id}"))),
+    }
+}
+
+/// Evaluates an operand that is only read, neither a variable nor the value in a box it
+/// dereferences is moved.
+pub(crate) fn read(
+    env: &mut VarEnv,
+    e: &Expr,
+    max_iter: usize,
+    iter_counter: &mut usize,
+) -> Result<Values, VmErr> {
+    // Operands may be evaluated outside of any scope
+    let last_scope = env.len().saturating_sub(1);
+    match e {
+        Expr::Ident(id) => read_var(env, id),
+        Expr::UnOp(UnaryOp::Dereff, inner)
+            if matches!(**inner, Expr::Ident(_) | Expr::UnOp(UnaryOp::Dereff, _)) =>
+        {
+            match read(env, inner, max_iter, iter_counter)? {
+                Values::Box(addr) => env.heap.get(addr),
+                _ => e.eval(env, last_scope, max_iter, iter_counter),
+            }
+        }
+        e => e.eval(env, last_scope, max_iter, iter_counter),
+    }
+}
+
+/// Follows the references in `value` to the value they refer to, `what` is the expression that
+/// reads through them.
+pub(crate) fn deref(env: &mut VarEnv, value: Values, what: &dyn Display) -> Result<Values, VmErr> {
+    let mut value = value;
+    while let Values::Ref(ptr, tag) = value {
+        env.access(ptr, tag, AccessKind::Read, what)?;
+        value = env.heap.load(ptr)?;
+    }
+    Ok(value)
+}
+
+/// Returns a reference to the value `ptr` points to, derived from the reference `parent`.
+pub(crate) fn borrow(
+    env: &mut VarEnv,
+    ptr: Pointer,
+    parent: Tag,
+    mutable: bool,
+    borrow: &dyn Display,
+) -> Result<Values, VmErr> {
+    let tag = env.retag(ptr, parent, mutable, borrow)?;
+    Ok(Values::Ref(ptr, tag))
+}
+
+/// Maps the errors of an access through the raw pointer `ptr` to undefined behavior, nothing
+/// guarantees that the place it points to is alive or that the element exists.
+pub(crate) fn through_raw<T>(
+    result: Result<T, VmErr>,
+    ptr: Pointer,
+    what: &dyn Display,
+) -> Result<T, VmErr> {
+    result.map_err(|e| match e {
+        VmErr::Err(e) => VmErr::UndefinedBehavior(source_names(&format!(
+            "`{what}` dereferences an invalid raw pointer to {ptr}: {e}"
+        ))),
+        e => e,
+    })
+}
+
+impl HeapIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        let arg = match args {
+            [arg] => arg.eval(env, env.len() - 1, max_iter, iter_counter)?,
+            _ => return Err(VmErr::Err(format!("Expected 1 argument to {}", self.id()))),
+        };
+        match (self, arg) {
+            (Self::BoxNew, value) => Ok(Values::Box(env.heap.alloc(value))),
+            (Self::RcNew, value) => Ok(Values::Rc(env.heap.alloc(value))),
+            (Self::RcClone, Values::Ref(ptr, _)) => match env.heap.load(ptr).ok() {
+                Some(Values::Rc(addr)) => {
+                    env.heap.clone_rc(addr)?;
+                    Ok(Values::Rc(addr))
+                }
+                value => Err(VmErr::Err(format!(
+                    "Expected a reference to an Rc in Rc::clone, got {value:?}"
+                ))),
+            },
+            (Self::RcClone, value) => Err(VmErr::Err(format!(
+                "Expected a reference to an Rc in Rc::clone, got {value}"
+            ))),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Calls to host functions, see [`IntrinsicRegistry`](crate::intrinsics::IntrinsicRegistry).
+use super::{heap::deref, Eval, Values, VarEnv, VmErr};
+use crate::ast::{Expr, Literal, Type};
+use crate::intrinsics::HostFn;
+
+/// Returns true if the value returned by a host function is of type `ty`. Host functions return
+/// plain values, they can not allocate on the heap or refer to the places of the program.
+fn conforms(value: &Values, ty: &Type) -> bool {
+    match (value, ty) {
+        (Values::Lit(Literal::Int(_)), Type::I32) => true,
+        (Values::Lit(Literal::Int(i)), Type::Usize) => *i >= 0,
+        (Values::Lit(Literal::Bool(_)), Type::Bool) => true,
+        (Values::Lit(Literal::String(_)), Type::String) => true,
+        (Values::Lit(Literal::Unit), Type::Unit) => true,
+        (Values::Lit(Literal::Array(elements)), Type::Array(ty, len)) => {
+            elements.len() == *len
+                && elements
+                    .iter()
+                    .all(|el| conforms(&Values::Lit((**el).clone()), ty))
+        }
+        (Values::Vec(elements), Type::Vec(ty)) => elements.iter().all(|el| conforms(el, ty)),
+        (Values::Option(None), Type::Option(_)) => true,
+        (Values::Option(Some(value)), Type::Option(ty)) => conforms(value, ty),
+        (Values::Result(Ok(value)), Type::Result(ty, _)) => conforms(value, ty),
+        (Values::Result(Err(err)), Type::Result(_, ty)) => conforms(err, ty),
+        _ => false,
+    }
+}
+
+impl HostFn {
+    /// Evaluates the arguments and calls the host function `id` with their values.
+    pub fn eval(
+        &self,
+        id: &str,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        if args.len() != self.args.len() {
+            return Err(VmErr::Err(format!(
+                "Expected {} arguments to {id} but got {}",
+                self.args.len(),
+                args.len()
+            )));
+        }
+        let mut values = vec![];
+        for arg in args {
+            let value = arg.eval(env, env.len() - 1, max_iter, iter_counter)?;
+            let value = deref(env, value, arg)?;
+            values.push(env.heap.resolve(value)?);
+        }
+        // Errors are reported as is, a host function is never called twice for the same call
+        let value = self.call(values).map_err(|e| match e {
+            VmErr::Err(e) | VmErr::Handled(e) => VmErr::Host(e),
+            e => e,
+        })?;
+        match conforms(&value, &self.ty) {
+            true => Ok(value),
+            false => Err(VmErr::Host(format!(
+                "Host function {id} returned {value} but is declared to return {}",
+                self.ty
+            ))),
+        }
+    }
+}
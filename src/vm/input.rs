@@ -0,0 +1,44 @@
+//! Evaluation of the input intrinsics, the input is read from the [`Io`](super::Io) of the
+//! program.
+use super::{Values, VarEnv, VmErr};
+use crate::ast::{Expr, Literal};
+use crate::intrinsics::{quote, InputIntrinsic};
+
+impl InputIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        _max_iter: usize,
+        _iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        if !args.is_empty() {
+            return Err(VmErr::Err(format!("Invalid arguments to {}", self.id())));
+        }
+        let string = |s: &str| Values::Lit(Literal::String(quote(s)));
+        match self {
+            Self::ReadLine => {
+                let line = env.io.read_line()?.unwrap_or_default();
+                Ok(string(line.trim_end_matches(['\n', '\r'])))
+            }
+            Self::ReadI32 => {
+                let line = match env.io.read_line()? {
+                    Some(line) => line,
+                    None => {
+                        return Err(VmErr::Panic(
+                            "read_i32() reached the end of the input\n\tat read_i32()".to_owned(),
+                        ))
+                    }
+                };
+                match line.trim().parse() {
+                    Ok(i) => Ok(Values::Lit(Literal::Int(i))),
+                    Err(e) => Err(VmErr::Panic(format!(
+                        "read_i32() cannot parse {:?} as an i32: {e}\n\tat read_i32()",
+                        line.trim()
+                    ))),
+                }
+            }
+            Self::Args => Ok(Values::Vec(env.io.args.iter().map(|s| string(s)).collect())),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! The input and output of an evaluated program.
+//!
+//! Everything a program prints goes to the sinks of its [`Io`], `print!` and `println!` to
+//! [`stdout`](Io::with_stdout), `eprint!`, `eprintln!` and the diagnostics of the vm to
+//! [`stderr`](Io::with_stderr). By default these are the streams of the process, a [`Buffer`]
+//! captures them instead.
+//!
+//! Files can only be accessed in the directories allowed by [`with_allowed_dir`](Io::with_allowed_dir),
+//! by default a program can not access any file.
+//!
+//! ```
+//! use rnr::vm::{Buffer, Io};
+//!
+//! let out = Buffer::new();
+//! let io = Io::default().with_stdout(out.clone());
+//! io.print("hello").unwrap();
+//! assert_eq!(out.contents(), "hello");
+//! ```
+use super::VmErr;
+
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// How much the vm reports besides the output of the program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the output of the program
+    Quiet,
+    /// Runtime errors along with the statement that caused them
+    #[default]
+    Normal,
+    /// Every evaluated binary operation as well
+    Verbose,
+}
+
+pub type Sink = Rc<RefCell<dyn Write>>;
+pub type Source = Rc<RefCell<dyn BufRead>>;
+
+/// The streams and arguments of a program, clones share the same streams.
+#[derive(Clone)]
+pub struct Io {
+    stdout: Sink,
+    stderr: Sink,
+    stdin: Source,
+    /// The command line arguments of the program, not including the program itself
+    pub args: Vec<String>,
+    pub verbosity: Verbosity,
+    /// The directories whose files the program may read and write
+    pub allowed_dirs: Vec<PathBuf>,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self {
+            stdout: Rc::new(RefCell::new(std::io::stdout())),
+            stderr: Rc::new(RefCell::new(std::io::stderr())),
+            stdin: Rc::new(RefCell::new(std::io::BufReader::new(std::io::stdin()))),
+            args: vec![],
+            verbosity: Verbosity::default(),
+            allowed_dirs: vec![],
+        }
+    }
+}
+
+impl std::fmt::Debug for Io {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Io")
+            .field("args", &self.args)
+            .field("verbosity", &self.verbosity)
+            .field("allowed_dirs", &self.allowed_dirs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Io {
+    pub fn with_stdout(mut self, stdout: impl Write + 'static) -> Self {
+        self.stdout = Rc::new(RefCell::new(stdout));
+        self
+    }
+
+    pub fn with_stderr(mut self, stderr: impl Write + 'static) -> Self {
+        self.stderr = Rc::new(RefCell::new(stderr));
+        self
+    }
+
+    pub fn with_stdin(mut self, stdin: impl BufRead + 'static) -> Self {
+        self.stdin = Rc::new(RefCell::new(stdin));
+        self
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Allows the program to access the files in `dir` and its subdirectories.
+    pub fn with_allowed_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.allowed_dirs.push(dir.into());
+        self
+    }
+
+    /// Reads the file at `path`, the error is returned to the program.
+    pub fn read_file(&self, path: &str) -> Result<String, String> {
+        let file = self.sandboxed(path, false)?;
+        std::fs::read_to_string(file).map_err(|e| format!("cannot read {path}: {e}"))
+    }
+
+    /// Creates or truncates the file at `path` and writes `contents` to it, the error is returned
+    /// to the program.
+    pub fn write_file(&self, path: &str, contents: &str) -> Result<(), String> {
+        let file = self.sandboxed(path, true)?;
+        std::fs::write(file, contents).map_err(|e| format!("cannot write {path}: {e}"))
+    }
+
+    /// Resolves `path` and checks that it is in one of the allowed directories. Symbolic links
+    /// and `..` are resolved first so they can not be used to leave the directories, a file that
+    /// is created only has to exist once it is written.
+    fn sandboxed(&self, path: &str, create: bool) -> Result<PathBuf, String> {
+        let denied = || format!("permission denied: {path} is outside the allowed directories");
+        if self.allowed_dirs.is_empty() {
+            return Err(denied());
+        }
+        let file = Path::new(path);
+        let resolved = match (file.canonicalize(), file.file_name()) {
+            (Ok(resolved), _) => resolved,
+            (Err(_), Some(name)) if create => {
+                let parent = match file.parent() {
+                    Some(parent) if parent != Path::new("") => parent,
+                    _ => Path::new("."),
+                };
+                parent.canonicalize().map_err(|_| denied())?.join(name)
+            }
+            (Err(e), _) => return Err(format!("cannot access {path}: {e}")),
+        };
+        let allowed = self
+            .allowed_dirs
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .any(|dir| resolved.starts_with(dir));
+        match allowed {
+            true => Ok(resolved),
+            false => Err(denied()),
+        }
+    }
+
+    /// Writes the output of the program.
+    pub fn print(&self, s: &str) -> Result<(), VmErr> {
+        write(&self.stdout, s)
+    }
+
+    /// Writes the error output of the program.
+    pub fn eprint(&self, s: &str) -> Result<(), VmErr> {
+        write(&self.stderr, s)
+    }
+
+    /// Reads a line from stdin including the newline, `None` at the end of the input.
+    pub fn read_line(&self) -> Result<Option<String>, VmErr> {
+        let mut line = String::new();
+        match self.stdin.borrow_mut().read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line)),
+            Err(e) => Err(VmErr::Err(format!("Cannot read from stdin: {e}"))),
+        }
+    }
+
+    /// Reports a runtime error unless the vm is [`Quiet`](Verbosity::Quiet).
+    pub fn diagnostic(&self, msg: std::fmt::Arguments) {
+        if self.verbosity >= Verbosity::Normal {
+            // The diagnostic is lost if stderr can not be written, the error is still returned
+            let _ = write(&self.stderr, &format!("{msg}\n"));
+        }
+    }
+
+    /// Traces the evaluation if the vm is [`Verbose`](Verbosity::Verbose).
+    pub fn trace(&self, msg: std::fmt::Arguments) {
+        if self.verbosity >= Verbosity::Verbose {
+            let _ = write(&self.stderr, &format!("{msg}\n"));
+        }
+    }
+}
+
+fn write(sink: &Sink, s: &str) -> Result<(), VmErr> {
+    let mut sink = sink.borrow_mut();
+    sink.write_all(s.as_bytes())
+        .and_then(|_| sink.flush())
+        .map_err(|e| VmErr::Err(format!("Cannot write output: {e}")))
+}
+
+/// An in memory sink, clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns everything written so far.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
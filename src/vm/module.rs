@@ -0,0 +1,29 @@
+use super::{Eval, Values, VmErr};
+use crate::ast::{Literal, Module, Use};
+
+impl Eval for Module {
+    fn eval(
+        &self,
+        env: &mut super::VarEnv,
+        scope: usize,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        for el in self.items.iter() {
+            el.eval(env, scope, max_iter, iter_counter)?;
+        }
+        Ok(Values::Lit(Literal::Unit))
+    }
+}
+
+impl Eval for Use {
+    fn eval(
+        &self,
+        _env: &mut super::VarEnv,
+        _scope: usize,
+        _max_iter: usize,
+        _iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        Ok(Values::Lit(Literal::Unit))
+    }
+}
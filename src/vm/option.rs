@@ -0,0 +1,78 @@
+//! Evaluation of the `Option<T>` and `Result<T, E>` intrinsics, `unwrap` and `expect` panic on a
+//! `None` or an `Err`.
+use super::{cell::release_receiver, heap::deref, Eval, Values, VarEnv, VmErr};
+use crate::ast::{Expr, Literal};
+use crate::intrinsics::{unquote, OptionIntrinsic};
+
+impl OptionIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        let mut values = vec![];
+        for arg in args {
+            values.push(arg.eval(env, env.len() - 1, max_iter, iter_counter)?);
+        }
+        let bool = |b: bool| Ok(Values::Lit(Literal::Bool(b)));
+        let mut values = values.into_iter();
+        let value = match (self, values.next()) {
+            (Self::None, _) => return Ok(Values::Option(None)),
+            (_, Some(value)) => value,
+            (_, None) => return Err(VmErr::Err(format!("Expected an argument to {}", self.id()))),
+        };
+        match (self, value) {
+            (Self::Some, value) => Ok(Values::Option(Some(Box::new(value)))),
+            (Self::Ok, value) => Ok(Values::Result(Ok(Box::new(value)))),
+            (Self::Err, err) => Ok(Values::Result(Err(Box::new(err)))),
+            (Self::Unwrap | Self::Expect, Values::Option(Some(value)))
+            | (Self::Unwrap | Self::Expect, Values::Result(Ok(value))) => Ok(*value),
+            (Self::Unwrap, Values::Option(None)) => Err(VmErr::Panic(
+                "called `Option::unwrap()` on a `None` value".to_string(),
+            )),
+            (Self::Unwrap, Values::Result(Err(err))) => Err(VmErr::Panic(format!(
+                "called `Result::unwrap()` on an `Err` value: {}",
+                env.heap.resolve(*err)?
+            ))),
+            (Self::Expect, value @ (Values::Option(None) | Values::Result(Err(_)))) => {
+                let msg = match values.next() {
+                    Some(Values::Lit(Literal::String(msg))) => unquote(&msg),
+                    msg => return Err(VmErr::Err(format!("Invalid message {msg:?} to expect"))),
+                };
+                match value {
+                    Values::Result(Err(err)) => {
+                        Err(VmErr::Panic(format!("{msg}: {}", env.heap.resolve(*err)?)))
+                    }
+                    _ => Err(VmErr::Panic(msg)),
+                }
+            }
+            (Self::IsSome | Self::IsNone | Self::IsOk | Self::IsErr, receiver) => {
+                // The receiver was evaluated from the first argument
+                let mut value = deref(env, receiver.clone(), &args[0])?;
+                // A receiver behind a `Ref<T>` or a `RefMut<T>` is read through the guard
+                while let Values::Guard(ptr, _) = value {
+                    let inner = env.heap.interior(ptr)?.clone();
+                    value = deref(env, inner, &args[0])?;
+                }
+                let value = match (self, value) {
+                    (Self::IsSome, Values::Option(value)) => bool(value.is_some()),
+                    (Self::IsNone, Values::Option(value)) => bool(value.is_none()),
+                    (Self::IsOk, Values::Result(value)) => bool(value.is_ok()),
+                    (Self::IsErr, Values::Result(value)) => bool(value.is_err()),
+                    (_, value) => Err(VmErr::Err(format!(
+                        "Invalid receiver {value} to {}",
+                        self.id()
+                    ))),
+                };
+                release_receiver(env, &args[0], &receiver)?;
+                value
+            }
+            (_, value) => Err(VmErr::Err(format!(
+                "Expected an Option or a Result in {}, got {value}",
+                self.id()
+            ))),
+        }
+    }
+}
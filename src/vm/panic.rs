@@ -0,0 +1,68 @@
+//! Evaluation of the panicking intrinsics, a failed assertion or a call to `panic!` aborts the
+//! evaluation with a [`VmErr::Panic`] that records the call, the function calls it unwinds
+//! through are appended to it.
+use super::{
+    format::{debug, format, value},
+    stacked_borrows::source_names,
+    Values, VarEnv, VmErr,
+};
+use crate::ast::{Expr, FuncCall, Literal};
+use crate::intrinsics::PanicIntrinsic;
+
+impl PanicIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        let (operands, msg) = args.split_at(self.operands().min(args.len()));
+        let mut values = vec![];
+        for operand in operands {
+            values.push(value(operand, env, max_iter, iter_counter)?);
+        }
+        let failed = match (self, &values[..]) {
+            (Self::Assert, [Values::Lit(Literal::Bool(cond))]) => !cond,
+            (Self::AssertEq, [left, right]) => left != right,
+            (Self::AssertNe, [left, right]) => left == right,
+            (Self::Panic | Self::Unreachable | Self::Todo, []) => true,
+            (_, values) => {
+                return Err(VmErr::Err(format!(
+                    "Invalid operands {values:?} to {}",
+                    self.id()
+                )))
+            }
+        };
+        if !failed {
+            return Ok(Values::Lit(Literal::Unit));
+        }
+
+        // The message is only formatted once the assertion has failed
+        let msg = match msg.split_first() {
+            Some((fmt, args)) => Some(format(fmt, args, env, max_iter, iter_counter)?),
+            None => None,
+        };
+        let msg = match (self, msg, &values[..]) {
+            (Self::Assert, None, _) => format!(
+                "{}: {}",
+                self.message(None),
+                source_names(&operands[0].to_string())
+            ),
+            (Self::AssertEq | Self::AssertNe, msg, [left, right]) => format!(
+                "{}\n  left: {}\n right: {}",
+                self.message(msg),
+                debug(left),
+                debug(right)
+            ),
+            (_, msg, _) => self.message(msg),
+        };
+        let call = FuncCall {
+            id: Box::new(Expr::Ident(self.id().to_owned())),
+            args: Box::new(args.to_vec()),
+        };
+        // The call is reported with the names of the variables in the source
+        let call = source_names(&call.to_string());
+        Err(VmErr::Panic(format!("{msg}\n\tat {call}")))
+    }
+}
@@ -5,23 +5,18 @@ use crate::ast::program::*;
 use crate::ast::Expr;
 use crate::ast::FuncCall;
 use crate::ast::Literal;
-use crate::intrinsics::vm_println;
 
-impl Eval for Prog {
-    fn eval(
+impl Prog {
+    /// Declares the items of the program in a new global scope without calling `main`.
+    pub fn load(
         &self,
         env: &mut super::VarEnv,
         scope: usize,
         max_iter: usize,
         iter_coutner: &mut usize,
-    ) -> Result<Values, super::VmErr> {
-        let mut global_scope = (crate::vm::Scope::new(), crate::vm::FunctionScope::new());
-        // Introduce compile builtins
-        let (f, _body) = vm_println();
-        match &f.id {
-            crate::ast::Expr::Ident(id) => global_scope.1.insert(id.clone(), f.into()),
-            e => return Err(VmErr::Err(format!("Malformed compiler built in {e}"))),
-        };
+    ) -> Result<(), super::VmErr> {
+        // The built in functions are intrinsics, they are not declared in the global scope
+        let global_scope = (crate::vm::Scope::new(), crate::vm::FunctionScope::new());
         env.push(global_scope);
         for el in self.statements.iter() {
             match el.eval(env, scope,max_iter,iter_coutner)?{
@@ -29,6 +24,19 @@ impl Eval for Prog {
                 t => return Err(VmErr::Err(format!("All top level statements should return unit value, got {t} when evaluting {el}"))),
             };
         }
+        Ok(())
+    }
+}
+
+impl Eval for Prog {
+    fn eval(
+        &self,
+        env: &mut super::VarEnv,
+        scope: usize,
+        max_iter: usize,
+        iter_coutner: &mut usize,
+    ) -> Result<Values, super::VmErr> {
+        self.load(env, scope, max_iter, iter_coutner)?;
         Expr::FuncCall(FuncCall {
             id: Box::new(Expr::Ident("main".to_owned())),
             args: Box::default(),
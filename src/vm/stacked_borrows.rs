@@ -0,0 +1,383 @@
+//! A dynamic aliasing checker in the style of Miri's stacked borrows, enabled by setting
+//! [`VarEnv::stacks`].
+//!
+//! Every reference carries a [`Tag`] and every variable a stack of the tags that may be used to
+//! access it, the variable itself is always at the bottom with tag `0`. Creating a reference is an
+//! access through the tag it is derived from, after which the new tag is pushed on top.
+//!
+//! - A write through a tag removes everything above it from the stack and is only allowed for
+//!   tags of mutable references and the variable itself.
+//! - A read through a tag removes the mutable references above it, shared references stay.
+//!
+//! Using a tag that is no longer on the stack is undefined behavior and is reported along with
+//! the borrow that created the tag and the access that removed it.
+//!
+//! ```text
+//! let b = &mut a; // [a, b]
+//! let c = &a;     // reading `a` removes `b`, [a, c]
+//! *b = 4;         // `b` is not on the stack
+//! ```
+//!
+//! The stacks are kept per cell of the [`Heap`](super::Heap), only the cells of the variables
+//! declared while the checker is enabled are tracked.
+//!
+//! The elements of an array or a `Vec` get their own stacks once they are borrowed or accessed
+//! on their own, starting out as a copy of the stack of the cell. Accesses to the whole cell
+//! apply to all of its elements.
+//!
+//! The errors refer to the variables by their names in the source, see [`source_names`].
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use super::{
+    heap::{Addr, Pointer},
+    Eval, Values, VarEnv, VmErr,
+};
+use crate::ast::{Expr, UnaryOp};
+
+/// Identifies a reference, `0` is the variable itself.
+pub type Tag = usize;
+
+/// Turns the identifiers renamed by the borrow checker back in to the names in the source,
+/// `*>2#2!1_p` is `*p`.
+pub fn source_names(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('>') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match renamed_prefix(rest) {
+            Some(len) => rest = &rest[len..],
+            None => {
+                out.push('>');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Returns the length of the `>count#depth!reassign_` prefix of a renamed identifier at the
+/// start of `s`, if any.
+fn renamed_prefix(s: &str) -> Option<usize> {
+    let mut len = 1;
+    for sep in ['#', '!', '_'] {
+        let digits = s[len..].chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 || !s[len + digits..].starts_with(sep) {
+            return None;
+        }
+        len += digits + 1;
+    }
+    Some(len)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// A mutable reference or the variable itself, may be read and written through
+    Unique,
+    /// A shared reference, may only be read through
+    SharedReadOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+impl Display for AccessKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessKind::Read => write!(f, "read"),
+            AccessKind::Write => write!(f, "write"),
+        }
+    }
+}
+
+impl Values {
+    /// Returns the place a reference or a raw pointer points to along with its tag.
+    pub fn pointer(&self) -> Option<(Pointer, Tag)> {
+        match self {
+            Values::Ref(ptr, tag) | Values::Raw(ptr, tag) => Some((*ptr, *tag)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Item {
+    tag: Tag,
+    perm: Permission,
+}
+
+type Stack = Vec<Item>;
+
+/// The stack of a cell and the stacks of the elements that have been split off from it.
+#[derive(Debug, Clone)]
+struct CellStacks {
+    whole: Stack,
+    elements: HashMap<usize, Stack>,
+}
+
+impl Default for CellStacks {
+    fn default() -> Self {
+        Self {
+            whole: vec![Item {
+                tag: 0,
+                perm: Permission::Unique,
+            }],
+            elements: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BorrowStacks {
+    next_tag: Tag,
+    stacks: HashMap<Addr, CellStacks>,
+    /// The identifiers of the variables that own the tracked cells
+    names: HashMap<Addr, String>,
+    /// The borrow expression that created each tag
+    created: HashMap<Tag, String>,
+    /// The tag each tag was derived from
+    parents: HashMap<Tag, Tag>,
+    /// The access that removed a tag from a stack
+    invalidated: HashMap<Tag, String>,
+}
+
+impl BorrowStacks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the stacks affected by an access to `ptr`, none if the cell is not tracked.
+    fn stacks(&mut self, ptr: Pointer) -> Vec<&mut Stack> {
+        let stacks = match self.stacks.get_mut(&ptr.addr) {
+            Some(stacks) => stacks,
+            None => return vec![],
+        };
+        match ptr.offset {
+            None => std::iter::once(&mut stacks.whole)
+                .chain(stacks.elements.values_mut())
+                .collect(),
+            Some(idx) => {
+                let whole = &stacks.whole;
+                vec![stacks.elements.entry(idx).or_insert_with(|| whole.clone())]
+            }
+        }
+    }
+
+    /// Starts tracking the cell at `addr`, `id` is the variable it is declared for.
+    pub fn declare(&mut self, addr: Addr, id: &str) {
+        self.stacks.insert(addr, CellStacks::default());
+        self.names.insert(addr, source_names(id));
+    }
+
+    /// Stops tracking the cell at `addr` once it is freed.
+    pub fn forget(&mut self, addr: Addr) {
+        self.stacks.remove(&addr);
+        self.names.remove(&addr);
+    }
+
+    /// Describes the place `ptr` points to in errors.
+    fn place(&self, ptr: Pointer) -> String {
+        let id = match self.names.get(&ptr.addr) {
+            Some(id) => id.clone(),
+            None => format!("#{}", ptr.addr),
+        };
+        match ptr.offset {
+            Some(idx) => format!("`{id}[{idx}]`"),
+            None => format!("`{id}`"),
+        }
+    }
+
+    /// Reads or writes `ptr` through `tag`, `what` describes the access in errors.
+    pub fn access(
+        &mut self,
+        ptr: Pointer,
+        tag: Tag,
+        kind: AccessKind,
+        what: &dyn Display,
+    ) -> Result<(), VmErr> {
+        let mut removed = vec![];
+        // The error if any, true if the tag is on the stack but only grants read access
+        let mut result = Ok(());
+        for stack in self.stacks(ptr) {
+            let granting = match stack.iter().rposition(|item| item.tag == tag) {
+                Some(granting) => granting,
+                None => {
+                    result = Err(false);
+                    break;
+                }
+            };
+            match (kind, stack[granting].perm) {
+                (AccessKind::Write, Permission::SharedReadOnly) => {
+                    result = Err(true);
+                    break;
+                }
+                (AccessKind::Write, _) => {
+                    removed.extend(stack.drain(granting + 1..).map(|item| item.tag));
+                }
+                (AccessKind::Read, _) => {
+                    let above = stack.split_off(granting + 1);
+                    for item in above {
+                        match item.perm {
+                            Permission::SharedReadOnly => stack.push(item),
+                            Permission::Unique => removed.push(item.tag),
+                        }
+                    }
+                }
+            }
+        }
+        let what = source_names(&what.to_string());
+        for tag in removed {
+            self.invalidated
+                .entry(tag)
+                .or_insert_with(|| format!("{kind} access `{what}`"));
+        }
+        let created = match self.created.get(&tag) {
+            Some(created) => format!("\n\t<{tag}> was created by `{created}`"),
+            None => String::new(),
+        };
+        let loc = self.place(ptr);
+        match result {
+            Ok(()) => Ok(()),
+            Err(true) => Err(VmErr::UndefinedBehavior(format!(
+                "{kind} access `{what}` to {loc} through <{tag}>, but <{tag}> only grants shared \
+                 read access{created}"
+            ))),
+            Err(false) => {
+                let invalidated = match self.invalidated.get(&tag) {
+                    Some(access) => format!("\n\t<{tag}> was invalidated by the {access}"),
+                    None => String::new(),
+                };
+                Err(VmErr::UndefinedBehavior(format!(
+                    "{kind} access `{what}` to {loc} through <{tag}>, but <{tag}> is not in the \
+                     borrow stack of {loc}{created}{invalidated}"
+                )))
+            }
+        }
+    }
+
+    /// Creates a new reference to `ptr` derived from `parent`, `borrow` is the expression that
+    /// creates it.
+    pub fn retag(
+        &mut self,
+        ptr: Pointer,
+        parent: Tag,
+        mutable: bool,
+        borrow: &dyn Display,
+    ) -> Result<Tag, VmErr> {
+        let (kind, perm) = match mutable {
+            true => (AccessKind::Write, Permission::Unique),
+            false => (AccessKind::Read, Permission::SharedReadOnly),
+        };
+        self.access(ptr, parent, kind, borrow)?;
+        self.next_tag += 1;
+        let tag = self.next_tag;
+        for stack in self.stacks(ptr) {
+            stack.push(Item { tag, perm });
+        }
+        self.created.insert(tag, source_names(&borrow.to_string()));
+        self.parents.insert(tag, parent);
+        Ok(tag)
+    }
+
+    /// Activates a two-phase borrow, the shared reference `tag` that reserved it becomes a
+    /// mutable one as if it was created by `borrow` now.
+    pub fn activate(&mut self, ptr: Pointer, tag: Tag, borrow: &dyn Display) -> Result<(), VmErr> {
+        // The reservation has to be valid until it is activated
+        self.access(ptr, tag, AccessKind::Read, borrow)?;
+        for stack in self.stacks(ptr) {
+            stack.retain(|item| item.tag != tag);
+        }
+        let parent = self.parents.get(&tag).copied().unwrap_or_default();
+        self.access(ptr, parent, AccessKind::Write, borrow)?;
+        for stack in self.stacks(ptr) {
+            stack.push(Item {
+                tag,
+                perm: Permission::Unique,
+            });
+        }
+        self.created.insert(tag, source_names(&borrow.to_string()));
+        Ok(())
+    }
+}
+
+/// Evaluates the arguments of a call in `scope`. Mutable borrows are two-phase, they are
+/// reserved as shared borrows while the other arguments are evaluated and activated once all of
+/// them are, so `v.push(v.len())` does not invalidate the borrow of `v`.
+pub(crate) fn eval_args(
+    args: &[Expr],
+    env: &mut VarEnv,
+    scope: usize,
+    max_iter: usize,
+    iter_counter: &mut usize,
+) -> Result<Vec<Values>, VmErr> {
+    let mut values = vec![];
+    let mut reserved = vec![];
+    for arg in args {
+        let value = match arg {
+            Expr::UnOp(UnaryOp::BorrowMut, place) => {
+                let shared = Expr::UnOp(UnaryOp::Borrow, place.clone());
+                let value = shared.eval(env, scope, max_iter, iter_counter)?;
+                reserved.push((value.pointer(), arg));
+                value
+            }
+            arg => arg.eval(env, scope, max_iter, iter_counter)?,
+        };
+        values.push(value);
+    }
+    if let Some(stacks) = &mut env.stacks {
+        for (reserved, arg) in reserved {
+            if let Some((ptr, tag)) = reserved {
+                stacks.activate(ptr, tag, arg)?;
+            }
+        }
+    }
+    Ok(values)
+}
+
+impl VarEnv {
+    /// Checks an access to `ptr` through `tag` if aliasing is checked.
+    pub(crate) fn access(
+        &mut self,
+        ptr: Pointer,
+        tag: Tag,
+        kind: AccessKind,
+        what: &dyn Display,
+    ) -> Result<(), VmErr> {
+        match &mut self.stacks {
+            Some(stacks) => stacks.access(ptr, tag, kind, what),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks an access through the reference `value`, other values are not checked.
+    pub(crate) fn access_through(
+        &mut self,
+        value: &Values,
+        kind: AccessKind,
+        what: &dyn Display,
+    ) -> Result<(), VmErr> {
+        match value.pointer() {
+            Some((ptr, tag)) => self.access(ptr, tag, kind, what),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the tag of a new reference to `ptr`, `0` if aliasing is not checked.
+    pub(crate) fn retag(
+        &mut self,
+        ptr: Pointer,
+        parent: Tag,
+        mutable: bool,
+        borrow: &dyn Display,
+    ) -> Result<Tag, VmErr> {
+        match &mut self.stacks {
+            Some(stacks) => stacks.retag(ptr, parent, mutable, borrow),
+            None => Ok(0),
+        }
+    }
+}
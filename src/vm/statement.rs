@@ -1,4 +1,4 @@
-use super::{Eval, ValueMeta, Values, VarEnv, VmErr};
+use super::{Eval, Values, VarEnv, VmErr};
 use crate::ast::{Expr, Literal, Statement};
 impl Statement {
     fn eval_internal(
@@ -27,12 +27,11 @@ impl Statement {
                     },
                     _ => None,
                 };
-                let meta = ValueMeta { value: expr_type };
                 let id = match id {
                     Expr::Ident(i) => i,
                     e => return Err(VmErr::Err(format!("Cannot use {e} as an identifier"))),
                 };
-                env.get_mut(scope).unwrap().0.insert(id, meta.clone());
+                env.declare(scope, &id, expr_type)?;
                 Ok(Values::Lit(Literal::Unit))
             }
             Statement::Expr(e) => {
@@ -44,6 +43,7 @@ impl Statement {
                 let rhs = match (e.eval(env, len - 1, max_iter, iter_counter), scope) {
                     // If we can't eval in this scope go one lower
                     (Ok(val), _) => Ok(val),
+                    (Err(e), _) if e.unwinds() => Err(e),
                     (Err(e), 0) => Err(e),
                     (_, idx) => e.eval(env, idx - 1, max_iter, iter_counter),
                 }?;
@@ -55,6 +55,7 @@ impl Statement {
                 ) {
                     // If we can't eval in this scope go one lower
                     (Ok(_), _) => Ok(()),
+                    (Err(e), _) if e.unwinds() => Err(e),
                     (Err(e), 0) => Err(e),
                     (_, idx) => id.clone().assign(env, idx - 1, rhs, max_iter, iter_counter),
                 }?;
@@ -72,6 +73,7 @@ impl Statement {
                 {
                     ret = match b.eval(env, env.len() - 1, max_iter, iter_counter) {
                         Ok(v) => v,
+                        Err(e) if e.unwinds() => return Err(e),
                         Err(e) => {
                             return Err(VmErr::Err(
                                 format!(
@@ -93,6 +95,7 @@ impl Statement {
         };
         match (ret, scope) {
             (Ok(value), _) => Ok(value),
+            (Err(e), _) if e.unwinds() => Err(e),
             (Err(e), 0) => Err(e),
             (Err(_), scope) => self.eval(env, scope - 1, max_iter, iter_counter),
         }
@@ -119,7 +122,7 @@ impl super::Eval for Statement {
                 } else {
                     format!("Occured during execution of statement {self}")
                 };
-                eprintln!("{}", pretty);
+                env.io.diagnostic(format_args!("{}", pretty));
                 // This is a bit ugly, ideally we should have some queue here.
                 // But this interface makes for a nice stack trace
                 Err(VmErr::Handled("".to_string()))
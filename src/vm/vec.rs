@@ -0,0 +1,94 @@
+//! Evaluation of the `Vec<T>` intrinsics, the elements are stored in the cell that owns the
+//! vector and are modified through the borrowed receiver.
+use super::{
+    cell::release_receiver,
+    stacked_borrows::{eval_args, AccessKind},
+    Values, VarEnv, VmErr,
+};
+use crate::ast::{Expr, Literal};
+use crate::intrinsics::VecIntrinsic;
+
+/// Follows the references in `receiver` to the elements of the vector they refer to, the vector
+/// is accessed as `kind` by `intrinsic`.
+fn elements<'a>(
+    env: &'a mut VarEnv,
+    receiver: Values,
+    kind: AccessKind,
+    intrinsic: &VecIntrinsic,
+) -> Result<&'a mut Vec<Values>, VmErr> {
+    let mut target = receiver;
+    // The vector is either the value `ptr` points to or in the `RefCell<T>` a guard borrows
+    let (ptr, guarded) = loop {
+        let (ptr, tag) = match target {
+            Values::Ref(ptr, tag) => (ptr, tag),
+            value => {
+                return Err(VmErr::Err(format!(
+                    "Expected a reference to a Vec, got {value}"
+                )))
+            }
+        };
+        match env.heap.load(ptr)? {
+            Values::Vec(_) => {
+                env.access(ptr, tag, kind, &intrinsic.id())?;
+                break (ptr, false);
+            }
+            Values::Guard(cell, _) => {
+                env.access(ptr, tag, AccessKind::Read, &intrinsic.id())?;
+                match env.heap.interior(cell)? {
+                    Values::Vec(_) => break (cell, true),
+                    value => return Err(VmErr::Err(format!("Expected a Vec, got {value}"))),
+                }
+            }
+            value @ Values::Ref(..) => {
+                env.access(ptr, tag, AccessKind::Read, &intrinsic.id())?;
+                target = value
+            }
+            value => return Err(VmErr::Err(format!("Expected a Vec, got {value}"))),
+        }
+    };
+    let place = match guarded {
+        true => env.heap.interior(ptr)?,
+        false => env.heap.place_mut(ptr)?,
+    };
+    match place {
+        Values::Vec(elements) => Ok(elements),
+        _ => unreachable!("ICE, the target was found above"),
+    }
+}
+
+impl VecIntrinsic {
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        env: &mut VarEnv,
+        max_iter: usize,
+        iter_counter: &mut usize,
+    ) -> Result<Values, VmErr> {
+        let values = eval_args(args, env, env.len() - 1, max_iter, iter_counter)?;
+        let mut values = values.into_iter();
+        let receiver = match (self, values.next()) {
+            (Self::New, _) => return Ok(Values::Vec(vec![])),
+            (Self::From, first) => {
+                return Ok(Values::Vec(first.into_iter().chain(values).collect()))
+            }
+            (_, Some(receiver)) => receiver,
+            (_, None) => return Err(VmErr::Err(format!("Expected a receiver for {}", self.id()))),
+        };
+        let kind = match self {
+            Self::Len => AccessKind::Read,
+            _ => AccessKind::Write,
+        };
+        let elements = elements(env, receiver.clone(), kind, self)?;
+        let value = match (self, values.next()) {
+            (Self::Push, Some(value)) => {
+                elements.push(value);
+                Ok(Values::Lit(Literal::Unit))
+            }
+            (Self::Pop, _) => Ok(Values::Option(elements.pop().map(Box::new))),
+            (Self::Len, _) => Ok(Values::Lit(Literal::Int(elements.len() as i32))),
+            (_, _) => Err(VmErr::Err(format!("Invalid arguments to {}", self.id()))),
+        };
+        release_receiver(env, &args[0], &receiver)?;
+        value
+    }
+}